@@ -200,6 +200,13 @@ impl {{enum.name}} {
             _ => Err(AmqpParseError::UnknownEnumOption("{{enum.name}}"))
         }
     }
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            {{#each enum.items as |item|}}
+            {{enum.name}}::{{item.name}} => "{{item.value}}",
+            {{/each}}
+        }
+    }
 }
 impl DecodeFormatted for {{enum.name}} {
     fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
@@ -109,7 +109,13 @@ mod tests {
     use crate::codec::{Decode, Encode};
     use crate::errors::AmqpCodecError;
     use crate::framing::{AmqpFrame, SaslFrame};
-    use crate::protocol::SaslFrameBody;
+    use crate::protocol::{
+        Accepted, AmqpError, Attach, AttachTarget, ConnectionError, Coordinator, Declared,
+        DeliveryState, Disposition, Error, ErrorCondition, FilterSet, Frame, LinkError,
+        ReceiverSettleMode, Rejected, Role, SaslFrameBody, SenderSettleMode, SessionError, Source,
+        TerminusDurability, TerminusExpiryPolicy, TransactionalState,
+    };
+    use crate::types::{Descriptor, Symbol, Variant};
 
     #[test]
     fn test_sasl_mechanisms() -> Result<(), AmqpCodecError> {
@@ -147,4 +153,209 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_transactional_disposition() -> Result<(), AmqpCodecError> {
+        let txn_id = Bytes::from_static(b"txn-1");
+        for state in vec![
+            DeliveryState::Declared(Declared {
+                txn_id: txn_id.clone(),
+            }),
+            DeliveryState::TransactionalState(TransactionalState {
+                txn_id: txn_id.clone(),
+                outcome: None,
+            }),
+            DeliveryState::Accepted(Accepted {}).into_transactional(txn_id.clone()),
+        ] {
+            let frame = AmqpFrame::new(
+                0,
+                Frame::Disposition(Disposition {
+                    role: Role::Receiver,
+                    first: 1,
+                    last: None,
+                    settled: true,
+                    state: Some(state.clone()),
+                    batchable: false,
+                }),
+            );
+
+            let mut buf = BytesMut::new();
+            buf.reserve(frame.encoded_size());
+            frame.encode(&mut buf);
+            buf.advance(4);
+
+            let (remainder, frame) = AmqpFrame::decode(&buf)?;
+            assert!(remainder.is_empty());
+            match frame.performative() {
+                Frame::Disposition(disp) => assert_eq!(disp.state(), Some(&state)),
+                _ => panic!("error"),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_attach_coordinator() -> Result<(), AmqpCodecError> {
+        let target = AttachTarget::Coordinator(Coordinator {
+            capabilities: Some(vec![Symbol::from("amqp:local-transactions")].into()),
+        });
+        let frame = AmqpFrame::new(
+            0,
+            Frame::Attach(Attach {
+                name: "txn".into(),
+                handle: 0,
+                role: Role::Sender,
+                snd_settle_mode: SenderSettleMode::Mixed,
+                rcv_settle_mode: ReceiverSettleMode::First,
+                source: None,
+                target: Some(target.clone()),
+                unsettled: None,
+                incomplete_unsettled: false,
+                initial_delivery_count: None,
+                max_message_size: None,
+                offered_capabilities: None,
+                desired_capabilities: None,
+                properties: None,
+            }),
+        );
+
+        let mut buf = BytesMut::new();
+        buf.reserve(frame.encoded_size());
+        frame.encode(&mut buf);
+        buf.advance(4);
+
+        let (remainder, frame) = AmqpFrame::decode(&buf)?;
+        assert!(remainder.is_empty());
+        match frame.performative() {
+            Frame::Attach(attach) => {
+                assert_eq!(attach.target(), Some(&target));
+                assert!(attach.target().unwrap().address().is_none());
+            }
+            _ => panic!("error"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_filter() -> Result<(), AmqpCodecError> {
+        let mut filter = FilterSet::default();
+        filter.insert(
+            Symbol::from("selector"),
+            Some(Variant::Described((
+                Descriptor::Ulong(0x0000_468C_0000_0004),
+                Box::new(Variant::from("color = 'red'")),
+            ))),
+        );
+        filter.insert(Symbol::from("empty"), None);
+        let source = Source {
+            address: Some("topic".into()),
+            durable: TerminusDurability::UnsettledState,
+            expiry_policy: TerminusExpiryPolicy::Never,
+            timeout: 0,
+            dynamic: false,
+            dynamic_node_properties: None,
+            distribution_mode: None,
+            filter: Some(filter),
+            default_outcome: None,
+            outcomes: None,
+            capabilities: Some(vec![Symbol::from("shared")].into()),
+        };
+        let frame = AmqpFrame::new(
+            0,
+            Frame::Attach(Attach {
+                name: "sub".into(),
+                handle: 0,
+                role: Role::Receiver,
+                snd_settle_mode: SenderSettleMode::Mixed,
+                rcv_settle_mode: ReceiverSettleMode::First,
+                source: Some(source.clone()),
+                target: None,
+                unsettled: None,
+                incomplete_unsettled: false,
+                initial_delivery_count: None,
+                max_message_size: None,
+                offered_capabilities: None,
+                desired_capabilities: None,
+                properties: None,
+            }),
+        );
+
+        let mut buf = BytesMut::new();
+        buf.reserve(frame.encoded_size());
+        frame.encode(&mut buf);
+        buf.advance(4);
+
+        let (remainder, frame) = AmqpFrame::decode(&buf)?;
+        assert!(remainder.is_empty());
+        match frame.performative() {
+            Frame::Attach(attach) => assert_eq!(attach.source(), Some(&source)),
+            _ => panic!("error"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delivery_state_variant() {
+        let states = vec![
+            DeliveryState::Accepted(Accepted {}),
+            DeliveryState::Declared(Declared {
+                txn_id: Bytes::from_static(b"txn-1"),
+            }),
+        ];
+        for state in states {
+            let value = state.to_variant();
+            assert!(matches!(value, Variant::Described(_)));
+            assert_eq!(DeliveryState::from_variant(&value), Some(state));
+        }
+        assert_eq!(DeliveryState::from_variant(&Variant::Null), None);
+    }
+
+    #[test]
+    fn test_rejected_condition() {
+        let state = DeliveryState::Rejected(Rejected {
+            error: Some(Error {
+                condition: AmqpError::ResourceLimitExceeded.into(),
+                description: Some("queue is full".into()),
+                info: None,
+            }),
+        });
+        assert_eq!(
+            state.rejection_condition(),
+            Some(&ErrorCondition::AmqpError(AmqpError::ResourceLimitExceeded))
+        );
+        assert_eq!(
+            state.rejection_condition().unwrap().to_symbol().as_str(),
+            "amqp:resource-limit-exceeded"
+        );
+        if let DeliveryState::Rejected(ref rejected) = state {
+            assert_eq!(rejected.description().unwrap(), "queue is full");
+            assert!(rejected.info().is_none());
+        }
+
+        let state = DeliveryState::Rejected(Rejected { error: None });
+        assert!(state.rejection_condition().is_none());
+        assert!(DeliveryState::Accepted(Accepted {})
+            .rejection_condition()
+            .is_none());
+    }
+
+    #[test]
+    fn test_error_condition_symbol() {
+        let condition: ErrorCondition = LinkError::Stolen.into();
+        assert_eq!(condition.to_symbol().as_str(), "amqp:link:stolen");
+        let condition: ErrorCondition = AmqpError::ResourceLimitExceeded.into();
+        assert_eq!(
+            condition.to_symbol().as_str(),
+            "amqp:resource-limit-exceeded"
+        );
+        let condition: ErrorCondition = ConnectionError::Redirect.into();
+        assert_eq!(condition.to_symbol().as_str(), "amqp:connection:redirect");
+        let condition: ErrorCondition = SessionError::HandleInUse.into();
+        assert_eq!(condition.to_symbol().as_str(), "amqp:session:handle-in-use");
+        let condition = ErrorCondition::Custom(Symbol::from("custom:error"));
+        assert_eq!(condition.to_symbol().as_str(), "custom:error");
+    }
 }
@@ -30,6 +30,9 @@ pub enum AmqpParseError {
 #[derive(Debug, Display, From)]
 pub enum AmqpCodecError {
     ParseError(AmqpParseError),
+    #[from(ignore)]
+    #[display(fmt = "Cannot decode frame on channel {}: {}", _0, _1)]
+    FrameDecode(u16, AmqpParseError),
     #[display(fmt = "bytes left unparsed at the frame trail")]
     UnparsedBytesLeft,
     #[display(fmt = "max inbound frame size exceeded")]
@@ -42,6 +45,9 @@ impl Clone for AmqpCodecError {
     fn clone(&self) -> AmqpCodecError {
         match self {
             AmqpCodecError::ParseError(err) => AmqpCodecError::ParseError(err.clone()),
+            AmqpCodecError::FrameDecode(channel, err) => {
+                AmqpCodecError::FrameDecode(*channel, err.clone())
+            }
             AmqpCodecError::UnparsedBytesLeft => AmqpCodecError::UnparsedBytesLeft,
             AmqpCodecError::MaxSizeExceeded => AmqpCodecError::MaxSizeExceeded,
             AmqpCodecError::Io(_) => AmqpCodecError::Io(None),
@@ -84,12 +84,20 @@ impl<T: Decode + Encode> Decoder for AmqpCodec<T> {
                     }
 
                     let frame_buf = src.split_to(size);
-                    let (remainder, frame) = T::decode(frame_buf.as_ref())?;
+                    self.state = DecodeState::FrameHeader;
+
+                    // doff, type and channel precede frame body
+                    let channel = if frame_buf.len() >= 4 {
+                        BigEndian::read_u16(&frame_buf[2..4])
+                    } else {
+                        0
+                    };
+                    let (remainder, frame) = T::decode(frame_buf.as_ref())
+                        .map_err(|err| AmqpCodecError::FrameDecode(channel, err))?;
                     if !remainder.is_empty() {
                         // todo: could it really happen?
                         return Err(AmqpCodecError::UnparsedBytesLeft);
                     }
-                    self.state = DecodeState::FrameHeader;
                     return Ok(Some(frame));
                 }
             }
@@ -14,7 +14,36 @@ pub struct MessageBody {
     pub value: Option<Variant>,
 }
 
+/// Message body sections
+///
+/// Body consists of one or more data sections, one or more sequence
+/// sections or a single value section.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BodyKind<'a> {
+    Data(&'a [Bytes]),
+    Sequence(&'a [List]),
+    Value(&'a Variant),
+    /// Nested messages, each one is encoded as data section
+    Messages(&'a [TransferBody]),
+    Empty,
+}
+
 impl MessageBody {
+    /// Body sections
+    pub fn kind(&self) -> BodyKind<'_> {
+        if let Some(ref value) = self.value {
+            BodyKind::Value(value)
+        } else if !self.sequence.is_empty() {
+            BodyKind::Sequence(&self.sequence)
+        } else if !self.data.is_empty() {
+            BodyKind::Data(&self.data)
+        } else if !self.messages.is_empty() {
+            BodyKind::Messages(&self.messages)
+        } else {
+            BodyKind::Empty
+        }
+    }
+
     pub fn data(&self) -> Option<&Bytes> {
         if self.data.is_empty() {
             None
@@ -27,10 +56,35 @@ impl MessageBody {
         self.value.as_ref()
     }
 
+    pub fn sequence(&self) -> Option<&List> {
+        self.sequence.first()
+    }
+
+    /// Set data section, other body sections are removed
     pub fn set_data(&mut self, data: Bytes) {
-        self.data.clear();
+        self.clear();
         self.data.push(data);
     }
+
+    /// Set sequence section, other body sections are removed
+    pub fn set_sequence(&mut self, sequence: List) {
+        self.clear();
+        self.sequence.push(sequence);
+    }
+
+    /// Set value section, other body sections are removed
+    pub fn set_value<V: Into<Variant>>(&mut self, value: V) {
+        self.clear();
+        self.value = Some(value.into());
+    }
+
+    /// Remove all body sections
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.sequence.clear();
+        self.messages.clear();
+        self.value = None;
+    }
 }
 
 impl Encode for MessageBody {
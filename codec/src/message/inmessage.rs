@@ -8,7 +8,7 @@ use crate::errors::AmqpParseError;
 use crate::protocol::{
     Annotations, Header, MessageFormat, Properties, Section, StringVariantMap, TransferBody,
 };
-use crate::types::{Descriptor, Str, Variant};
+use crate::types::{Descriptor, Str, Symbol, Variant};
 
 use super::body::MessageBody;
 use super::outmessage::OutMessage;
@@ -35,6 +35,13 @@ impl InMessage {
         msg
     }
 
+    /// Create new message and set value as body
+    pub fn with_value<V: Into<Variant>>(value: V) -> InMessage {
+        let mut msg = InMessage::default();
+        msg.body.value = Some(value.into());
+        msg
+    }
+
     /// Create new message and set messages as body
     pub fn with_messages(messages: Vec<TransferBody>) -> InMessage {
         let mut msg = InMessage::default();
@@ -98,6 +105,16 @@ impl InMessage {
         }
     }
 
+    /// Get message annotations
+    pub fn message_annotations(&self) -> Option<&Annotations> {
+        self.message_annotations.as_ref()
+    }
+
+    /// Get delivery annotations
+    pub fn delivery_annotations(&self) -> Option<&Annotations> {
+        self.delivery_annotations.as_ref()
+    }
+
     /// Add application property
     pub fn set_app_property<K: Into<Str>, V: Into<Variant>>(mut self, key: K, value: V) -> Self {
         if let Some(ref mut props) = self.application_properties {
@@ -111,6 +128,32 @@ impl InMessage {
         self
     }
 
+    /// Add message annotation
+    pub fn set_message_annotation<K: Into<Symbol>, V: Into<Variant>>(
+        mut self,
+        key: K,
+        value: V,
+    ) -> Self {
+        self.message_annotations
+            .get_or_insert_with(Annotations::default)
+            .insert(key.into(), value.into());
+        self.size.set(0);
+        self
+    }
+
+    /// Add delivery annotation
+    pub fn set_delivery_annotation<K: Into<Symbol>, V: Into<Variant>>(
+        mut self,
+        key: K,
+        value: V,
+    ) -> Self {
+        self.delivery_annotations
+            .get_or_insert_with(Annotations::default)
+            .insert(key.into(), value.into());
+        self.size.set(0);
+        self
+    }
+
     /// Call closure with message reference
     pub fn update<F>(self, f: F) -> Self
     where
@@ -145,7 +188,8 @@ impl InMessage {
 
     /// Set message body value
     pub fn set_value<V: Into<Variant>>(mut self, v: V) -> Self {
-        self.body.value = Some(v.into());
+        self.body.set_value(v);
+        self.size.set(0);
         self
     }
 
@@ -160,14 +204,38 @@ impl InMessage {
     }
 
     /// Create new message and set `correlation_id` property
+    ///
+    /// Correlation id is taken from `message_id`, or from `correlation_id`
+    /// if message id is not set.
     pub fn reply_message(&self) -> OutMessage {
         let mut msg = OutMessage::default().if_some(&self.properties, |mut msg, data| {
-            msg.set_properties(|props| props.correlation_id = data.message_id.clone());
+            msg.set_properties(|props| {
+                props.correlation_id = data
+                    .message_id
+                    .clone()
+                    .or_else(|| data.correlation_id.clone())
+            });
             msg
         });
         msg.message_format = self.message_format;
         msg
     }
+
+    /// Encode message into provided buffer
+    ///
+    /// Encoded message is appended to `dst`, buffer capacity is reserved
+    /// for the encoded size of the message.
+    pub fn serialize_into(&self, dst: &mut BytesMut) {
+        dst.reserve(self.encoded_size());
+        self.encode(dst);
+    }
+
+    /// Encode message to bytes
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        self.serialize_into(&mut buf);
+        buf.freeze()
+    }
 }
 
 impl Decode for InMessage {
@@ -299,10 +367,44 @@ mod tests {
 
     use crate::codec::{Decode, Encode};
     use crate::errors::AmqpCodecError;
-    use crate::protocol::Header;
+    use crate::protocol::{Header, MessageId};
     use crate::types::Variant;
 
-    use super::InMessage;
+    use super::{InMessage, OutMessage};
+
+    #[test]
+    fn test_sections() -> Result<(), AmqpCodecError> {
+        let msg = InMessage::with_body(Bytes::from_static(b"Hello world"))
+            .set_header(Header {
+                durable: true,
+                priority: 4,
+                ttl: None,
+                first_acquirer: false,
+                delivery_count: 0,
+            })
+            .set_delivery_annotation("x-opt-delivery", 1)
+            .set_message_annotation("x-opt-partition-key", ByteString::from("key"))
+            .set_properties(|props| props.message_id = Some(1.into()))
+            .set_app_property(ByteString::from("test"), 1);
+
+        let mut buf = BytesMut::with_capacity(msg.encoded_size());
+        msg.encode(&mut buf);
+        assert_eq!(buf.len(), msg.encoded_size());
+
+        let msg2 = InMessage::decode(&buf)?.1;
+        assert_eq!(msg2.header(), msg.header());
+        assert_eq!(msg2.delivery_annotations(), msg.delivery_annotations());
+        assert_eq!(msg2.message_annotations(), msg.message_annotations());
+        assert_eq!(msg2.properties(), msg.properties());
+        assert_eq!(msg2.app_properties(), msg.app_properties());
+        assert_eq!(msg2.body(), msg.body());
+        assert_eq!(
+            msg2.message_annotation("x-opt-partition-key"),
+            Some(&Variant::from(ByteString::from("key")))
+        );
+        assert!(msg2.delivery_annotations().is_some());
+        Ok(())
+    }
 
     #[test]
     fn test_properties() -> Result<(), AmqpCodecError> {
@@ -327,6 +429,35 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_reply_message() -> Result<(), AmqpCodecError> {
+        let id = MessageId::Uuid(uuid::Uuid::new_v4());
+        let mut msg = OutMessage::default();
+        msg.set_message_id(id.clone()).set_reply_to("reply-queue");
+
+        let mut buf = BytesMut::with_capacity(msg.encoded_size());
+        msg.encode(&mut buf);
+
+        let msg2 = InMessage::decode(&buf)?.1;
+        let props = msg2.properties().unwrap();
+        assert_eq!(props.message_id(), Some(&id));
+        assert_eq!(props.reply_to().map(|v| v.as_ref()), Some("reply-queue"));
+
+        let reply = msg2.reply_message();
+        assert_eq!(reply.properties().unwrap().correlation_id(), Some(&id));
+
+        // correlation id is passed through if message id is not set
+        let msg = InMessage::default().set_properties(|props| {
+            props.correlation_id = Some(ByteString::from("request-1").into());
+        });
+        let reply = msg.reply_message();
+        assert_eq!(
+            reply.properties().unwrap().correlation_id(),
+            Some(&ByteString::from("request-1").into())
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_app_properties() -> Result<(), AmqpCodecError> {
         let msg = InMessage::default().set_app_property(ByteString::from("test"), 1);
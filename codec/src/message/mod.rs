@@ -2,7 +2,7 @@ mod body;
 mod inmessage;
 mod outmessage;
 
-pub use self::body::MessageBody;
+pub use self::body::{BodyKind, MessageBody};
 pub use self::inmessage::InMessage;
 pub use self::outmessage::OutMessage;
 
@@ -1,10 +1,14 @@
 use std::cell::Cell;
 
 use bytes::{BufMut, Bytes, BytesMut};
+use bytestring::ByteString;
 
 use crate::codec::{Decode, Encode, FORMATCODE_BINARY8};
 use crate::errors::AmqpParseError;
-use crate::protocol::{Annotations, Header, MessageFormat, Properties, Section, TransferBody};
+use crate::protocol::{
+    Address, Annotations, Header, MessageFormat, MessageId, Milliseconds, Properties, Section,
+    TransferBody,
+};
 use crate::types::{Descriptor, Str, Symbol, Variant, VecStringMap, VecSymbolMap};
 
 use super::body::MessageBody;
@@ -33,6 +37,33 @@ impl OutMessage {
         msg
     }
 
+    /// Create new message and set value as body
+    pub fn with_value<V: Into<Variant>>(value: V) -> OutMessage {
+        let mut msg = OutMessage::default();
+        msg.body.value = Some(value.into());
+        msg.message_format = Some(0);
+        msg
+    }
+
+    /// Create new text message
+    ///
+    /// Text is sent as amqp-value body, `content-type` is set to `text/plain`
+    pub fn text<T: Into<ByteString>>(text: T) -> OutMessage {
+        let mut msg = OutMessage::with_value(text.into());
+        msg.set_content_type(Symbol::from_static("text/plain"));
+        msg
+    }
+
+    /// Create new binary message
+    ///
+    /// Bytes are sent as single data section, `content-type` is set
+    /// to `application/octet-stream`
+    pub fn bytes(body: Bytes) -> OutMessage {
+        let mut msg = OutMessage::with_body(body);
+        msg.set_content_type(Symbol::from_static("application/octet-stream"));
+        msg
+    }
+
     /// Create new message and set messages as body
     pub fn with_messages(messages: Vec<TransferBody>) -> OutMessage {
         let mut msg = OutMessage::default();
@@ -53,6 +84,55 @@ impl OutMessage {
         self
     }
 
+    /// Mutable reference to header
+    ///
+    /// Header with default values is created if message does not have one,
+    /// default priority is 4.
+    pub fn header_mut(&mut self) -> &mut Header {
+        if self.header.is_none() {
+            self.header = Some(Header {
+                durable: false,
+                priority: 4,
+                ttl: None,
+                first_acquirer: false,
+                delivery_count: 0,
+            });
+        }
+
+        self.size.set(0);
+        self.header.as_mut().unwrap()
+    }
+
+    /// Set `durable` header field
+    pub fn set_durable(&mut self, durable: bool) -> &mut Self {
+        self.header_mut().durable = durable;
+        self
+    }
+
+    /// Set `priority` header field
+    pub fn set_priority(&mut self, priority: u8) -> &mut Self {
+        self.header_mut().priority = priority;
+        self
+    }
+
+    /// Set `ttl` header field, time to live in milliseconds
+    pub fn set_ttl(&mut self, ttl: Milliseconds) -> &mut Self {
+        self.header_mut().ttl = Some(ttl);
+        self
+    }
+
+    /// Set `first-acquirer` header field
+    pub fn set_first_acquirer(&mut self, first_acquirer: bool) -> &mut Self {
+        self.header_mut().first_acquirer = first_acquirer;
+        self
+    }
+
+    /// Set `delivery-count` header field
+    pub fn set_delivery_count(&mut self, count: u32) -> &mut Self {
+        self.header_mut().delivery_count = count;
+        self
+    }
+
     /// Message properties
     pub fn properties(&self) -> Option<&Properties> {
         self.properties.as_ref()
@@ -84,6 +164,78 @@ impl OutMessage {
         self
     }
 
+    /// Set `message-id` property
+    pub fn set_message_id<T>(&mut self, value: T) -> &mut Self
+    where
+        T: Into<MessageId>,
+    {
+        self.properties_mut().message_id = Some(value.into());
+        self
+    }
+
+    /// Set `user-id` property
+    pub fn set_user_id<T>(&mut self, value: T) -> &mut Self
+    where
+        T: Into<Bytes>,
+    {
+        self.properties_mut().user_id = Some(value.into());
+        self
+    }
+
+    /// Set `to` property
+    pub fn set_to<T>(&mut self, value: T) -> &mut Self
+    where
+        T: Into<Address>,
+    {
+        self.properties_mut().to = Some(value.into());
+        self
+    }
+
+    /// Set `subject` property
+    pub fn set_subject<T>(&mut self, value: T) -> &mut Self
+    where
+        T: Into<ByteString>,
+    {
+        self.properties_mut().subject = Some(value.into());
+        self
+    }
+
+    /// Set `reply-to` property
+    pub fn set_reply_to<T>(&mut self, value: T) -> &mut Self
+    where
+        T: Into<Address>,
+    {
+        self.properties_mut().reply_to = Some(value.into());
+        self
+    }
+
+    /// Set `correlation-id` property
+    pub fn set_correlation_id<T>(&mut self, value: T) -> &mut Self
+    where
+        T: Into<MessageId>,
+    {
+        self.properties_mut().correlation_id = Some(value.into());
+        self
+    }
+
+    /// Set `content-type` property
+    pub fn set_content_type<T>(&mut self, value: T) -> &mut Self
+    where
+        T: Into<Symbol>,
+    {
+        self.properties_mut().content_type = Some(value.into());
+        self
+    }
+
+    /// Set `content-encoding` property
+    pub fn set_content_encoding<T>(&mut self, value: T) -> &mut Self
+    where
+        T: Into<Symbol>,
+    {
+        self.properties_mut().content_encoding = Some(value.into());
+        self
+    }
+
     /// Get application property
     pub fn app_properties(&self) -> Option<&VecStringMap> {
         self.application_properties.as_ref()
@@ -123,11 +275,29 @@ impl OutMessage {
         self
     }
 
+    /// Message annotations
+    pub fn message_annotations(&self) -> Option<&VecSymbolMap> {
+        self.message_annotations.as_ref()
+    }
+
     /// Delivery annotations
     pub fn delivery_annotations(&self) -> Option<&Annotations> {
         self.delivery_annotations.as_ref()
     }
 
+    /// Add delivery annotation
+    pub fn add_delivery_annotation<K, V>(&mut self, key: K, value: V) -> &mut Self
+    where
+        K: Into<Symbol>,
+        V: Into<Variant>,
+    {
+        self.delivery_annotations
+            .get_or_insert_with(Annotations::default)
+            .insert(key.into(), value.into());
+        self.size.set(0);
+        self
+    }
+
     /// Call closure with message reference
     pub fn update<F>(self, f: F) -> Self
     where
@@ -161,8 +331,11 @@ impl OutMessage {
     }
 
     /// Set message body value
+    ///
+    /// Other body sections are removed
     pub fn set_value<V: Into<Variant>>(&mut self, v: V) -> &mut Self {
-        self.body.value = Some(v.into());
+        self.body.set_value(v);
+        self.size.set(0);
         self
     }
 
@@ -177,12 +350,36 @@ impl OutMessage {
     }
 
     /// Create new message and set `correlation_id` property
+    ///
+    /// Correlation id is taken from `message_id`, or from `correlation_id`
+    /// if message id is not set.
     pub fn reply_message(&self) -> OutMessage {
         OutMessage::default().if_some(&self.properties, |mut msg, data| {
-            msg.set_properties(|props| props.correlation_id = data.message_id.clone());
+            msg.set_properties(|props| {
+                props.correlation_id = data
+                    .message_id
+                    .clone()
+                    .or_else(|| data.correlation_id.clone())
+            });
             msg
         })
     }
+
+    /// Encode message into provided buffer
+    ///
+    /// Encoded message is appended to `dst`, buffer capacity is reserved
+    /// for the encoded size of the message.
+    pub fn serialize_into(&self, dst: &mut BytesMut) {
+        dst.reserve(self.encoded_size());
+        self.encode(dst);
+    }
+
+    /// Encode message to bytes
+    pub fn serialize(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        self.serialize_into(&mut buf);
+        buf.freeze()
+    }
 }
 
 impl From<InMessage> for OutMessage {
@@ -337,14 +534,164 @@ impl Encode for OutMessage {
 mod tests {
     use bytes::{Bytes, BytesMut};
     use bytestring::ByteString;
+    use fxhash::FxHashMap;
 
     use crate::codec::{Decode, Encode};
     use crate::errors::AmqpCodecError;
-    use crate::protocol::Header;
-    use crate::types::Variant;
+    use crate::message::BodyKind;
+    use crate::protocol::{Header, MessageId};
+    use crate::types::{List, Variant, VariantMap};
 
     use super::OutMessage;
 
+    #[test]
+    fn test_body_value() -> Result<(), AmqpCodecError> {
+        let msg = OutMessage::with_value("test value");
+        let mut buf = BytesMut::with_capacity(msg.encoded_size());
+        msg.encode(&mut buf);
+
+        let msg2 = OutMessage::decode(&buf)?.1;
+        assert_eq!(
+            msg2.body().kind(),
+            BodyKind::Value(&Variant::from("test value"))
+        );
+
+        let mut map = FxHashMap::default();
+        map.insert(Variant::from("key"), Variant::from(1));
+        let value = Variant::Map(VariantMap::new(map));
+
+        // value replaces data section
+        let mut msg = OutMessage::with_body(Bytes::from_static(b"data"));
+        msg.set_value(value.clone());
+        let mut buf = BytesMut::with_capacity(msg.encoded_size());
+        msg.encode(&mut buf);
+        assert_eq!(buf.len(), msg.encoded_size());
+
+        let msg2 = OutMessage::decode(&buf)?.1;
+        assert_eq!(msg2.body().kind(), BodyKind::Value(&value));
+        assert!(msg2.body().data().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_and_bytes() -> Result<(), AmqpCodecError> {
+        let msg = OutMessage::text("Hello world");
+        let mut buf = BytesMut::with_capacity(msg.encoded_size());
+        msg.encode(&mut buf);
+        assert_eq!(buf.len(), msg.encoded_size());
+
+        let msg2 = OutMessage::decode(&buf)?.1;
+        assert_eq!(
+            msg2.body().kind(),
+            BodyKind::Value(&Variant::from("Hello world"))
+        );
+        assert_eq!(
+            msg2.properties()
+                .and_then(|p| p.content_type.as_ref())
+                .map(|v| v.as_str()),
+            Some("text/plain")
+        );
+
+        let msg = OutMessage::bytes(Bytes::from_static(b"\x00\x01"));
+        let mut buf = BytesMut::with_capacity(msg.encoded_size());
+        msg.encode(&mut buf);
+        assert_eq!(buf.len(), msg.encoded_size());
+
+        let msg2 = OutMessage::decode(&buf)?.1;
+        assert_eq!(msg2.body().data(), Some(&Bytes::from_static(b"\x00\x01")));
+        assert_eq!(
+            msg2.properties()
+                .and_then(|p| p.content_type.as_ref())
+                .map(|v| v.as_str()),
+            Some("application/octet-stream")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_body_sequence() -> Result<(), AmqpCodecError> {
+        let seq = List(vec![Variant::from(1), Variant::from("two")]);
+        let mut msg = OutMessage::default();
+        msg.set_body(|body| body.set_sequence(seq.clone()));
+        let mut buf = BytesMut::with_capacity(msg.encoded_size());
+        msg.encode(&mut buf);
+
+        let msg2 = OutMessage::decode(&buf)?.1;
+        assert_eq!(msg2.body().kind(), BodyKind::Sequence(&[seq]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_properties() -> Result<(), AmqpCodecError> {
+        let id = uuid::Uuid::new_v4();
+        let mut msg = OutMessage::default();
+        msg.set_message_id(id)
+            .set_correlation_id("request-1")
+            .set_user_id(Bytes::from_static(b"user"))
+            .set_to("queue")
+            .set_reply_to("reply-queue")
+            .set_subject("rpc")
+            .set_content_type("application/json")
+            .set_content_encoding("gzip");
+
+        let mut buf = BytesMut::with_capacity(msg.encoded_size());
+        msg.encode(&mut buf);
+
+        let msg2 = OutMessage::decode(&buf)?.1;
+        let props = msg2.properties().unwrap();
+        assert_eq!(props.message_id, Some(MessageId::Uuid(id)));
+        assert_eq!(
+            props.correlation_id,
+            Some(MessageId::String(ByteString::from("request-1")))
+        );
+        assert_eq!(props.user_id, Some(Bytes::from_static(b"user")));
+        assert_eq!(props.to.as_ref().map(|v| v.as_ref()), Some("queue"));
+        assert_eq!(
+            props.reply_to.as_ref().map(|v| v.as_ref()),
+            Some("reply-queue")
+        );
+        assert_eq!(props.subject.as_ref().map(|v| v.as_ref()), Some("rpc"));
+        assert_eq!(
+            props.content_type.as_ref().map(|v| v.as_str()),
+            Some("application/json")
+        );
+        assert_eq!(
+            props.content_encoding.as_ref().map(|v| v.as_str()),
+            Some("gzip")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sections() -> Result<(), AmqpCodecError> {
+        let mut msg = OutMessage::with_body(Bytes::from_static(b"Hello world"));
+        msg.message_format = None;
+        msg.set_header(Header {
+            durable: true,
+            priority: 4,
+            ttl: None,
+            first_acquirer: false,
+            delivery_count: 0,
+        })
+        .add_delivery_annotation("x-opt-delivery", 1)
+        .add_message_annotation("x-opt-partition-key", ByteString::from("key"))
+        .set_properties(|props| props.message_id = Some(1.into()))
+        .set_app_property(ByteString::from("test"), 1);
+
+        let mut buf = BytesMut::with_capacity(msg.encoded_size());
+        msg.encode(&mut buf);
+        assert_eq!(buf.len(), msg.encoded_size());
+
+        let msg2 = OutMessage::decode(&buf)?.1;
+        assert_eq!(msg2.header(), msg.header());
+        assert_eq!(msg2.delivery_annotations(), msg.delivery_annotations());
+        assert_eq!(msg2.message_annotations(), msg.message_annotations());
+        assert_eq!(msg2.properties(), msg.properties());
+        assert_eq!(msg2.app_properties(), msg.app_properties());
+        assert_eq!(msg2.body(), msg.body());
+        Ok(())
+    }
+
     #[test]
     fn test_properties() -> Result<(), AmqpCodecError> {
         let mut msg = OutMessage::default();
@@ -394,6 +741,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_header_fields() -> Result<(), AmqpCodecError> {
+        let mut msg = OutMessage::with_body(Bytes::from_static(b"test"));
+        msg.set_durable(true)
+            .set_ttl(60_000)
+            .set_first_acquirer(true)
+            .set_properties(|props| props.message_id = Some(1.into()));
+
+        let mut buf = BytesMut::with_capacity(msg.encoded_size());
+        msg.encode(&mut buf);
+        assert_eq!(buf.len(), msg.encoded_size());
+
+        let msg2 = OutMessage::decode(&buf)?.1;
+        let hdr = msg2.header().unwrap();
+        assert!(hdr.durable);
+        assert_eq!(hdr.priority, 4);
+        assert_eq!(hdr.ttl, Some(60_000));
+        assert!(hdr.first_acquirer);
+        assert_eq!(hdr.delivery_count, 0);
+        assert_eq!(msg2.properties(), msg.properties());
+        Ok(())
+    }
+
     #[test]
     fn test_data() -> Result<(), AmqpCodecError> {
         let data = Bytes::from_static(b"test data");
@@ -419,6 +789,30 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_serialize_into() -> Result<(), AmqpCodecError> {
+        let mut msg = OutMessage::default();
+        msg.set_properties(|props| props.message_id = Some(1.into()));
+        msg.set_body(|body| body.set_data(Bytes::from_static(b"data")));
+
+        let mut buf = BytesMut::with_capacity(msg.encoded_size());
+        msg.encode(&mut buf);
+        assert_eq!(msg.serialize(), buf.freeze());
+
+        // message is appended to existing content of the buffer
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"prefix");
+        msg.serialize_into(&mut buf);
+        msg.serialize_into(&mut buf);
+        assert_eq!(&buf[..6], b"prefix");
+        assert_eq!(buf.len(), 6 + msg.encoded_size() * 2);
+
+        let msg2 = OutMessage::decode(&buf[6..6 + msg.encoded_size()])?.1;
+        assert_eq!(msg2.properties, msg.properties);
+        assert_eq!(msg2.body.data(), Some(&Bytes::from_static(b"data")));
+        Ok(())
+    }
+
     #[test]
     fn test_messages() -> Result<(), AmqpCodecError> {
         let mut msg1 = OutMessage::default();
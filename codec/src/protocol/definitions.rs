@@ -134,6 +134,7 @@ pub enum Outcome {
     Rejected(Rejected),
     Released(Released),
     Modified(Modified),
+    Declared(Declared),
 }
 impl DecodeFormatted for Outcome {
     fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
@@ -152,6 +153,9 @@ impl DecodeFormatted for Outcome {
             Descriptor::Ulong(39) => {
                 decode_modified_inner(input).map(|(i, r)| (i, Outcome::Modified(r)))
             }
+            Descriptor::Ulong(51) => {
+                decode_declared_inner(input).map(|(i, r)| (i, Outcome::Declared(r)))
+            }
             Descriptor::Symbol(ref a) if a.as_str() == "amqp:accepted:list" => {
                 decode_accepted_inner(input).map(|(i, r)| (i, Outcome::Accepted(r)))
             }
@@ -164,6 +168,9 @@ impl DecodeFormatted for Outcome {
             Descriptor::Symbol(ref a) if a.as_str() == "amqp:modified:list" => {
                 decode_modified_inner(input).map(|(i, r)| (i, Outcome::Modified(r)))
             }
+            Descriptor::Symbol(ref a) if a.as_str() == "amqp:declared:list" => {
+                decode_declared_inner(input).map(|(i, r)| (i, Outcome::Declared(r)))
+            }
             _ => Err(AmqpParseError::InvalidDescriptor(descriptor)),
         }
     }
@@ -175,6 +182,7 @@ impl Encode for Outcome {
             Outcome::Rejected(ref v) => encoded_size_rejected_inner(v),
             Outcome::Released(ref v) => encoded_size_released_inner(v),
             Outcome::Modified(ref v) => encoded_size_modified_inner(v),
+            Outcome::Declared(ref v) => encoded_size_declared_inner(v),
         }
     }
     fn encode(&self, buf: &mut BytesMut) {
@@ -183,6 +191,7 @@ impl Encode for Outcome {
             Outcome::Rejected(ref v) => encode_rejected_inner(v, buf),
             Outcome::Released(ref v) => encode_released_inner(v, buf),
             Outcome::Modified(ref v) => encode_modified_inner(v, buf),
+            Outcome::Declared(ref v) => encode_declared_inner(v, buf),
         }
     }
 }
@@ -193,6 +202,8 @@ pub enum DeliveryState {
     Rejected(Rejected),
     Released(Released),
     Modified(Modified),
+    Declared(Declared),
+    TransactionalState(TransactionalState),
 }
 impl DecodeFormatted for DeliveryState {
     fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
@@ -214,6 +225,11 @@ impl DecodeFormatted for DeliveryState {
             Descriptor::Ulong(39) => {
                 decode_modified_inner(input).map(|(i, r)| (i, DeliveryState::Modified(r)))
             }
+            Descriptor::Ulong(51) => {
+                decode_declared_inner(input).map(|(i, r)| (i, DeliveryState::Declared(r)))
+            }
+            Descriptor::Ulong(52) => decode_transactional_state_inner(input)
+                .map(|(i, r)| (i, DeliveryState::TransactionalState(r))),
             Descriptor::Symbol(ref a) if a.as_str() == "amqp:received:list" => {
                 decode_received_inner(input).map(|(i, r)| (i, DeliveryState::Received(r)))
             }
@@ -229,6 +245,13 @@ impl DecodeFormatted for DeliveryState {
             Descriptor::Symbol(ref a) if a.as_str() == "amqp:modified:list" => {
                 decode_modified_inner(input).map(|(i, r)| (i, DeliveryState::Modified(r)))
             }
+            Descriptor::Symbol(ref a) if a.as_str() == "amqp:declared:list" => {
+                decode_declared_inner(input).map(|(i, r)| (i, DeliveryState::Declared(r)))
+            }
+            Descriptor::Symbol(ref a) if a.as_str() == "amqp:transactional-state:list" => {
+                decode_transactional_state_inner(input)
+                    .map(|(i, r)| (i, DeliveryState::TransactionalState(r)))
+            }
             _ => Err(AmqpParseError::InvalidDescriptor(descriptor)),
         }
     }
@@ -241,6 +264,8 @@ impl Encode for DeliveryState {
             DeliveryState::Rejected(ref v) => encoded_size_rejected_inner(v),
             DeliveryState::Released(ref v) => encoded_size_released_inner(v),
             DeliveryState::Modified(ref v) => encoded_size_modified_inner(v),
+            DeliveryState::Declared(ref v) => encoded_size_declared_inner(v),
+            DeliveryState::TransactionalState(ref v) => encoded_size_transactional_state_inner(v),
         }
     }
     fn encode(&self, buf: &mut BytesMut) {
@@ -250,6 +275,8 @@ impl Encode for DeliveryState {
             DeliveryState::Rejected(ref v) => encode_rejected_inner(v, buf),
             DeliveryState::Released(ref v) => encode_released_inner(v, buf),
             DeliveryState::Modified(ref v) => encode_modified_inner(v, buf),
+            DeliveryState::Declared(ref v) => encode_declared_inner(v, buf),
+            DeliveryState::TransactionalState(ref v) => encode_transactional_state_inner(v, buf),
         }
     }
 }
@@ -619,6 +646,23 @@ impl AmqpError {
             _ => Err(AmqpParseError::UnknownEnumOption("AmqpError")),
         }
     }
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            AmqpError::InternalError => "amqp:internal-error",
+            AmqpError::NotFound => "amqp:not-found",
+            AmqpError::UnauthorizedAccess => "amqp:unauthorized-access",
+            AmqpError::DecodeError => "amqp:decode-error",
+            AmqpError::ResourceLimitExceeded => "amqp:resource-limit-exceeded",
+            AmqpError::NotAllowed => "amqp:not-allowed",
+            AmqpError::InvalidField => "amqp:invalid-field",
+            AmqpError::NotImplemented => "amqp:not-implemented",
+            AmqpError::ResourceLocked => "amqp:resource-locked",
+            AmqpError::PreconditionFailed => "amqp:precondition-failed",
+            AmqpError::ResourceDeleted => "amqp:resource-deleted",
+            AmqpError::IllegalState => "amqp:illegal-state",
+            AmqpError::FrameSizeTooSmall => "amqp:frame-size-too-small",
+        }
+    }
 }
 impl DecodeFormatted for AmqpError {
     fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
@@ -679,6 +723,13 @@ impl ConnectionError {
             _ => Err(AmqpParseError::UnknownEnumOption("ConnectionError")),
         }
     }
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ConnectionError::ConnectionForced => "amqp:connection:forced",
+            ConnectionError::FramingError => "amqp:connection:framing-error",
+            ConnectionError::Redirect => "amqp:connection:redirect",
+        }
+    }
 }
 impl DecodeFormatted for ConnectionError {
     fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
@@ -721,6 +772,14 @@ impl SessionError {
             _ => Err(AmqpParseError::UnknownEnumOption("SessionError")),
         }
     }
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SessionError::WindowViolation => "amqp:session:window-violation",
+            SessionError::ErrantLink => "amqp:session:errant-link",
+            SessionError::HandleInUse => "amqp:session:handle-in-use",
+            SessionError::UnattachedHandle => "amqp:session:unattached-handle",
+        }
+    }
 }
 impl DecodeFormatted for SessionError {
     fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
@@ -769,6 +828,15 @@ impl LinkError {
             _ => Err(AmqpParseError::UnknownEnumOption("LinkError")),
         }
     }
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LinkError::DetachForced => "amqp:link:detach-forced",
+            LinkError::TransferLimitExceeded => "amqp:link:transfer-limit-exceeded",
+            LinkError::MessageSizeExceeded => "amqp:link:message-size-exceeded",
+            LinkError::Redirect => "amqp:link:redirect",
+            LinkError::Stolen => "amqp:link:stolen",
+        }
+    }
 }
 impl DecodeFormatted for LinkError {
     fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
@@ -949,6 +1017,14 @@ impl TerminusExpiryPolicy {
             _ => Err(AmqpParseError::UnknownEnumOption("TerminusExpiryPolicy")),
         }
     }
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            TerminusExpiryPolicy::LinkDetach => "link-detach",
+            TerminusExpiryPolicy::SessionEnd => "session-end",
+            TerminusExpiryPolicy::ConnectionClose => "connection-close",
+            TerminusExpiryPolicy::Never => "never",
+        }
+    }
 }
 impl DecodeFormatted for TerminusExpiryPolicy {
     fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
@@ -1633,7 +1709,7 @@ pub struct Attach {
     pub snd_settle_mode: SenderSettleMode,
     pub rcv_settle_mode: ReceiverSettleMode,
     pub source: Option<Source>,
-    pub target: Option<Target>,
+    pub target: Option<AttachTarget>,
     pub unsettled: Option<Map>,
     pub incomplete_unsettled: bool,
     pub initial_delivery_count: Option<SequenceNo>,
@@ -1661,7 +1737,7 @@ impl Attach {
     pub fn source(&self) -> Option<&Source> {
         self.source.as_ref()
     }
-    pub fn target(&self) -> Option<&Target> {
+    pub fn target(&self) -> Option<&AttachTarget> {
         self.target.as_ref()
     }
     pub fn unsettled(&self) -> Option<&Map> {
@@ -1750,9 +1826,9 @@ fn decode_attach_inner(input: &[u8]) -> Result<(&[u8], Attach), AmqpParseError>
     } else {
         source = None;
     }
-    let target: Option<Target>;
+    let target: Option<AttachTarget>;
     if count > 0 {
-        let decoded = Option::<Target>::decode(input)?;
+        let decoded = Option::<AttachTarget>::decode(input)?;
         input = decoded.0;
         target = decoded.1;
         count -= 1;
@@ -4659,3 +4735,423 @@ impl Encode for Modified {
         encode_modified_inner(self, buf)
     }
 }
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coordinator {
+    pub capabilities: Option<Symbols>,
+}
+impl Coordinator {
+    pub fn capabilities(&self) -> Option<&Symbols> {
+        self.capabilities.as_ref()
+    }
+    #[allow(clippy::identity_op)]
+    const FIELD_COUNT: usize = 0 + 1;
+}
+#[allow(unused_mut)]
+fn decode_coordinator_inner(input: &[u8]) -> Result<(&[u8], Coordinator), AmqpParseError> {
+    let (input, format) = decode_format_code(input)?;
+    let (input, header) = decode_list_header(input, format)?;
+    let size = header.size as usize;
+    decode_check_len!(input, size);
+    let (mut input, mut remainder) = input.split_at(size);
+    let mut count = header.count;
+    let capabilities: Option<Symbols>;
+    if count > 0 {
+        let decoded = Option::<Symbols>::decode(input)?;
+        input = decoded.0;
+        capabilities = decoded.1;
+        count -= 1;
+    } else {
+        capabilities = None;
+    }
+    Ok((remainder, Coordinator { capabilities }))
+}
+fn encoded_size_coordinator_inner(list: &Coordinator) -> usize {
+    #[allow(clippy::identity_op)]
+    let content_size = 0 + list.capabilities.encoded_size();
+    // header: 0x00 0x53 <descriptor code> format_code size count
+    (if content_size + 1 > u8::MAX as usize {
+        12
+    } else {
+        6
+    }) + content_size
+}
+fn encode_coordinator_inner(list: &Coordinator, buf: &mut BytesMut) {
+    Descriptor::Ulong(48).encode(buf);
+    #[allow(clippy::identity_op)]
+    let content_size = 0 + list.capabilities.encoded_size();
+    if content_size + 1 > u8::MAX as usize {
+        buf.put_u8(codec::FORMATCODE_LIST32);
+        buf.put_u32((content_size + 4) as u32); // +4 for 4 byte count
+        buf.put_u32(Coordinator::FIELD_COUNT as u32);
+    } else {
+        buf.put_u8(codec::FORMATCODE_LIST8);
+        buf.put_u8((content_size + 1) as u8);
+        buf.put_u8(Coordinator::FIELD_COUNT as u8);
+    }
+    list.capabilities.encode(buf);
+}
+impl DecodeFormatted for Coordinator {
+    fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
+        validate_code!(fmt, codec::FORMATCODE_DESCRIBED);
+        let (input, descriptor) = Descriptor::decode(input)?;
+        let is_match = match descriptor {
+            Descriptor::Ulong(val) => val == 48,
+            Descriptor::Symbol(ref sym) => sym.as_bytes() == b"amqp:coordinator:list",
+        };
+        if !is_match {
+            Err(AmqpParseError::InvalidDescriptor(descriptor))
+        } else {
+            decode_coordinator_inner(input)
+        }
+    }
+}
+impl Encode for Coordinator {
+    fn encoded_size(&self) -> usize {
+        encoded_size_coordinator_inner(self)
+    }
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_coordinator_inner(self, buf)
+    }
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct Declare {
+    pub global_id: Option<Bytes>,
+}
+impl Declare {
+    pub fn global_id(&self) -> Option<&Bytes> {
+        self.global_id.as_ref()
+    }
+    #[allow(clippy::identity_op)]
+    const FIELD_COUNT: usize = 0 + 1;
+}
+#[allow(unused_mut)]
+fn decode_declare_inner(input: &[u8]) -> Result<(&[u8], Declare), AmqpParseError> {
+    let (input, format) = decode_format_code(input)?;
+    let (input, header) = decode_list_header(input, format)?;
+    let size = header.size as usize;
+    decode_check_len!(input, size);
+    let (mut input, mut remainder) = input.split_at(size);
+    let mut count = header.count;
+    let global_id: Option<Bytes>;
+    if count > 0 {
+        let decoded = Option::<Bytes>::decode(input)?;
+        input = decoded.0;
+        global_id = decoded.1;
+        count -= 1;
+    } else {
+        global_id = None;
+    }
+    Ok((remainder, Declare { global_id }))
+}
+fn encoded_size_declare_inner(list: &Declare) -> usize {
+    #[allow(clippy::identity_op)]
+    let content_size = 0 + list.global_id.encoded_size();
+    // header: 0x00 0x53 <descriptor code> format_code size count
+    (if content_size + 1 > u8::MAX as usize {
+        12
+    } else {
+        6
+    }) + content_size
+}
+fn encode_declare_inner(list: &Declare, buf: &mut BytesMut) {
+    Descriptor::Ulong(49).encode(buf);
+    #[allow(clippy::identity_op)]
+    let content_size = 0 + list.global_id.encoded_size();
+    if content_size + 1 > u8::MAX as usize {
+        buf.put_u8(codec::FORMATCODE_LIST32);
+        buf.put_u32((content_size + 4) as u32); // +4 for 4 byte count
+        buf.put_u32(Declare::FIELD_COUNT as u32);
+    } else {
+        buf.put_u8(codec::FORMATCODE_LIST8);
+        buf.put_u8((content_size + 1) as u8);
+        buf.put_u8(Declare::FIELD_COUNT as u8);
+    }
+    list.global_id.encode(buf);
+}
+impl DecodeFormatted for Declare {
+    fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
+        validate_code!(fmt, codec::FORMATCODE_DESCRIBED);
+        let (input, descriptor) = Descriptor::decode(input)?;
+        let is_match = match descriptor {
+            Descriptor::Ulong(val) => val == 49,
+            Descriptor::Symbol(ref sym) => sym.as_bytes() == b"amqp:declare:list",
+        };
+        if !is_match {
+            Err(AmqpParseError::InvalidDescriptor(descriptor))
+        } else {
+            decode_declare_inner(input)
+        }
+    }
+}
+impl Encode for Declare {
+    fn encoded_size(&self) -> usize {
+        encoded_size_declare_inner(self)
+    }
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_declare_inner(self, buf)
+    }
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct Discharge {
+    pub txn_id: Bytes,
+    pub fail: Option<bool>,
+}
+impl Discharge {
+    pub fn txn_id(&self) -> &Bytes {
+        &self.txn_id
+    }
+    pub fn fail(&self) -> Option<bool> {
+        self.fail
+    }
+    #[allow(clippy::identity_op)]
+    const FIELD_COUNT: usize = 0 + 1 + 1;
+}
+#[allow(unused_mut)]
+fn decode_discharge_inner(input: &[u8]) -> Result<(&[u8], Discharge), AmqpParseError> {
+    let (input, format) = decode_format_code(input)?;
+    let (input, header) = decode_list_header(input, format)?;
+    let size = header.size as usize;
+    decode_check_len!(input, size);
+    let (mut input, mut remainder) = input.split_at(size);
+    let mut count = header.count;
+    let txn_id: Bytes;
+    if count > 0 {
+        let (in1, decoded) = Bytes::decode(input)?;
+        txn_id = decoded;
+        input = in1;
+        count -= 1;
+    } else {
+        return Err(AmqpParseError::RequiredFieldOmitted("txn_id"));
+    }
+    let fail: Option<bool>;
+    if count > 0 {
+        let decoded = Option::<bool>::decode(input)?;
+        input = decoded.0;
+        fail = decoded.1;
+        count -= 1;
+    } else {
+        fail = None;
+    }
+    Ok((remainder, Discharge { txn_id, fail }))
+}
+fn encoded_size_discharge_inner(list: &Discharge) -> usize {
+    #[allow(clippy::identity_op)]
+    let content_size = 0 + list.txn_id.encoded_size() + list.fail.encoded_size();
+    // header: 0x00 0x53 <descriptor code> format_code size count
+    (if content_size + 1 > u8::MAX as usize {
+        12
+    } else {
+        6
+    }) + content_size
+}
+fn encode_discharge_inner(list: &Discharge, buf: &mut BytesMut) {
+    Descriptor::Ulong(50).encode(buf);
+    #[allow(clippy::identity_op)]
+    let content_size = 0 + list.txn_id.encoded_size() + list.fail.encoded_size();
+    if content_size + 1 > u8::MAX as usize {
+        buf.put_u8(codec::FORMATCODE_LIST32);
+        buf.put_u32((content_size + 4) as u32); // +4 for 4 byte count
+        buf.put_u32(Discharge::FIELD_COUNT as u32);
+    } else {
+        buf.put_u8(codec::FORMATCODE_LIST8);
+        buf.put_u8((content_size + 1) as u8);
+        buf.put_u8(Discharge::FIELD_COUNT as u8);
+    }
+    list.txn_id.encode(buf);
+    list.fail.encode(buf);
+}
+impl DecodeFormatted for Discharge {
+    fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
+        validate_code!(fmt, codec::FORMATCODE_DESCRIBED);
+        let (input, descriptor) = Descriptor::decode(input)?;
+        let is_match = match descriptor {
+            Descriptor::Ulong(val) => val == 50,
+            Descriptor::Symbol(ref sym) => sym.as_bytes() == b"amqp:discharge:list",
+        };
+        if !is_match {
+            Err(AmqpParseError::InvalidDescriptor(descriptor))
+        } else {
+            decode_discharge_inner(input)
+        }
+    }
+}
+impl Encode for Discharge {
+    fn encoded_size(&self) -> usize {
+        encoded_size_discharge_inner(self)
+    }
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_discharge_inner(self, buf)
+    }
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct Declared {
+    pub txn_id: Bytes,
+}
+impl Declared {
+    pub fn txn_id(&self) -> &Bytes {
+        &self.txn_id
+    }
+    #[allow(clippy::identity_op)]
+    const FIELD_COUNT: usize = 0 + 1;
+}
+#[allow(unused_mut)]
+fn decode_declared_inner(input: &[u8]) -> Result<(&[u8], Declared), AmqpParseError> {
+    let (input, format) = decode_format_code(input)?;
+    let (input, header) = decode_list_header(input, format)?;
+    let size = header.size as usize;
+    decode_check_len!(input, size);
+    let (mut input, mut remainder) = input.split_at(size);
+    let mut count = header.count;
+    let txn_id: Bytes;
+    if count > 0 {
+        let (in1, decoded) = Bytes::decode(input)?;
+        txn_id = decoded;
+        input = in1;
+        count -= 1;
+    } else {
+        return Err(AmqpParseError::RequiredFieldOmitted("txn_id"));
+    }
+    Ok((remainder, Declared { txn_id }))
+}
+fn encoded_size_declared_inner(list: &Declared) -> usize {
+    #[allow(clippy::identity_op)]
+    let content_size = 0 + list.txn_id.encoded_size();
+    // header: 0x00 0x53 <descriptor code> format_code size count
+    (if content_size + 1 > u8::MAX as usize {
+        12
+    } else {
+        6
+    }) + content_size
+}
+fn encode_declared_inner(list: &Declared, buf: &mut BytesMut) {
+    Descriptor::Ulong(51).encode(buf);
+    #[allow(clippy::identity_op)]
+    let content_size = 0 + list.txn_id.encoded_size();
+    if content_size + 1 > u8::MAX as usize {
+        buf.put_u8(codec::FORMATCODE_LIST32);
+        buf.put_u32((content_size + 4) as u32); // +4 for 4 byte count
+        buf.put_u32(Declared::FIELD_COUNT as u32);
+    } else {
+        buf.put_u8(codec::FORMATCODE_LIST8);
+        buf.put_u8((content_size + 1) as u8);
+        buf.put_u8(Declared::FIELD_COUNT as u8);
+    }
+    list.txn_id.encode(buf);
+}
+impl DecodeFormatted for Declared {
+    fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
+        validate_code!(fmt, codec::FORMATCODE_DESCRIBED);
+        let (input, descriptor) = Descriptor::decode(input)?;
+        let is_match = match descriptor {
+            Descriptor::Ulong(val) => val == 51,
+            Descriptor::Symbol(ref sym) => sym.as_bytes() == b"amqp:declared:list",
+        };
+        if !is_match {
+            Err(AmqpParseError::InvalidDescriptor(descriptor))
+        } else {
+            decode_declared_inner(input)
+        }
+    }
+}
+impl Encode for Declared {
+    fn encoded_size(&self) -> usize {
+        encoded_size_declared_inner(self)
+    }
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_declared_inner(self, buf)
+    }
+}
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionalState {
+    pub txn_id: Bytes,
+    pub outcome: Option<Outcome>,
+}
+impl TransactionalState {
+    pub fn txn_id(&self) -> &Bytes {
+        &self.txn_id
+    }
+    pub fn outcome(&self) -> Option<&Outcome> {
+        self.outcome.as_ref()
+    }
+    #[allow(clippy::identity_op)]
+    const FIELD_COUNT: usize = 0 + 1 + 1;
+}
+#[allow(unused_mut)]
+fn decode_transactional_state_inner(
+    input: &[u8],
+) -> Result<(&[u8], TransactionalState), AmqpParseError> {
+    let (input, format) = decode_format_code(input)?;
+    let (input, header) = decode_list_header(input, format)?;
+    let size = header.size as usize;
+    decode_check_len!(input, size);
+    let (mut input, mut remainder) = input.split_at(size);
+    let mut count = header.count;
+    let txn_id: Bytes;
+    if count > 0 {
+        let (in1, decoded) = Bytes::decode(input)?;
+        txn_id = decoded;
+        input = in1;
+        count -= 1;
+    } else {
+        return Err(AmqpParseError::RequiredFieldOmitted("txn_id"));
+    }
+    let outcome: Option<Outcome>;
+    if count > 0 {
+        let decoded = Option::<Outcome>::decode(input)?;
+        input = decoded.0;
+        outcome = decoded.1;
+        count -= 1;
+    } else {
+        outcome = None;
+    }
+    Ok((remainder, TransactionalState { txn_id, outcome }))
+}
+fn encoded_size_transactional_state_inner(list: &TransactionalState) -> usize {
+    #[allow(clippy::identity_op)]
+    let content_size = 0 + list.txn_id.encoded_size() + list.outcome.encoded_size();
+    // header: 0x00 0x53 <descriptor code> format_code size count
+    (if content_size + 1 > u8::MAX as usize {
+        12
+    } else {
+        6
+    }) + content_size
+}
+fn encode_transactional_state_inner(list: &TransactionalState, buf: &mut BytesMut) {
+    Descriptor::Ulong(52).encode(buf);
+    #[allow(clippy::identity_op)]
+    let content_size = 0 + list.txn_id.encoded_size() + list.outcome.encoded_size();
+    if content_size + 1 > u8::MAX as usize {
+        buf.put_u8(codec::FORMATCODE_LIST32);
+        buf.put_u32((content_size + 4) as u32); // +4 for 4 byte count
+        buf.put_u32(TransactionalState::FIELD_COUNT as u32);
+    } else {
+        buf.put_u8(codec::FORMATCODE_LIST8);
+        buf.put_u8((content_size + 1) as u8);
+        buf.put_u8(TransactionalState::FIELD_COUNT as u8);
+    }
+    list.txn_id.encode(buf);
+    list.outcome.encode(buf);
+}
+impl DecodeFormatted for TransactionalState {
+    fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
+        validate_code!(fmt, codec::FORMATCODE_DESCRIBED);
+        let (input, descriptor) = Descriptor::decode(input)?;
+        let is_match = match descriptor {
+            Descriptor::Ulong(val) => val == 52,
+            Descriptor::Symbol(ref sym) => sym.as_bytes() == b"amqp:transactional-state:list",
+        };
+        if !is_match {
+            Err(AmqpParseError::InvalidDescriptor(descriptor))
+        } else {
+            decode_transactional_state_inner(input)
+        }
+    }
+}
+impl Encode for TransactionalState {
+    fn encoded_size(&self) -> usize {
+        encoded_size_transactional_state_inner(self)
+    }
+    fn encode(&self, buf: &mut BytesMut) {
+        encode_transactional_state_inner(self, buf)
+    }
+}
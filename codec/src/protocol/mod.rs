@@ -7,7 +7,7 @@ use derive_more::From;
 use fxhash::FxHashMap;
 use uuid::Uuid;
 
-use super::codec::{self, DecodeFormatted, Encode};
+use super::codec::{self, Decode, DecodeFormatted, Encode};
 use super::errors::AmqpParseError;
 use super::message::{InMessage, OutMessage};
 use super::types::*;
@@ -40,7 +40,7 @@ pub enum ProtocolId {
 pub type Map = FxHashMap<Variant, Variant>;
 pub type StringVariantMap = FxHashMap<Str, Variant>;
 pub type Fields = FxHashMap<Symbol, Variant>;
-pub type FilterSet = FxHashMap<Symbol, Option<ByteString>>;
+pub type FilterSet = FxHashMap<Symbol, Option<Variant>>;
 pub type Timestamp = DateTime<Utc>;
 pub type Symbols = Multiple<Symbol>;
 pub type IetfLanguageTags = Multiple<IetfLanguageTag>;
@@ -74,6 +74,18 @@ impl From<i32> for MessageId {
     }
 }
 
+impl From<&str> for MessageId {
+    fn from(id: &str) -> MessageId {
+        MessageId::String(ByteString::from(id))
+    }
+}
+
+impl From<String> for MessageId {
+    fn from(id: String) -> MessageId {
+        MessageId::String(ByteString::from(id))
+    }
+}
+
 impl DecodeFormatted for MessageId {
     fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
         match fmt {
@@ -165,6 +177,19 @@ impl Encode for ErrorCondition {
     }
 }
 
+impl ErrorCondition {
+    /// Error condition symbol, i.e. `amqp:link:stolen`
+    pub fn to_symbol(&self) -> Symbol {
+        match self {
+            ErrorCondition::AmqpError(v) => Symbol::from_static(v.as_str()),
+            ErrorCondition::ConnectionError(v) => Symbol::from_static(v.as_str()),
+            ErrorCondition::SessionError(v) => Symbol::from_static(v.as_str()),
+            ErrorCondition::LinkError(v) => Symbol::from_static(v.as_str()),
+            ErrorCondition::Custom(symbol) => symbol.clone(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum DistributionMode {
     Move,
@@ -202,12 +227,243 @@ impl Encode for DistributionMode {
     }
 }
 
+/// Attach target, regular link target or transaction coordinator
+#[derive(Clone, Debug, PartialEq, From)]
+pub enum AttachTarget {
+    Target(Target),
+    Coordinator(Coordinator),
+}
+
+impl AttachTarget {
+    /// Link target, `None` for transaction coordinator
+    pub fn target(&self) -> Option<&Target> {
+        match self {
+            AttachTarget::Target(target) => Some(target),
+            AttachTarget::Coordinator(_) => None,
+        }
+    }
+
+    /// Mutable link target, `None` for transaction coordinator
+    pub fn target_mut(&mut self) -> Option<&mut Target> {
+        match self {
+            AttachTarget::Target(target) => Some(target),
+            AttachTarget::Coordinator(_) => None,
+        }
+    }
+
+    /// Transaction coordinator target
+    pub fn coordinator(&self) -> Option<&Coordinator> {
+        match self {
+            AttachTarget::Target(_) => None,
+            AttachTarget::Coordinator(coordinator) => Some(coordinator),
+        }
+    }
+
+    /// Target address, coordinator does not have address
+    pub fn address(&self) -> Option<&Address> {
+        self.target().and_then(|target| target.address())
+    }
+}
+
+impl DecodeFormatted for AttachTarget {
+    fn decode_with_format(input: &[u8], fmt: u8) -> Result<(&[u8], Self), AmqpParseError> {
+        match Target::decode_with_format(input, fmt) {
+            Ok((input, target)) => Ok((input, AttachTarget::Target(target))),
+            Err(AmqpParseError::InvalidDescriptor(_)) => {
+                Coordinator::decode_with_format(input, fmt)
+                    .map(|(input, coordinator)| (input, AttachTarget::Coordinator(coordinator)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Encode for AttachTarget {
+    fn encoded_size(&self) -> usize {
+        match *self {
+            AttachTarget::Target(ref v) => v.encoded_size(),
+            AttachTarget::Coordinator(ref v) => v.encoded_size(),
+        }
+    }
+
+    fn encode(&self, buf: &mut BytesMut) {
+        match *self {
+            AttachTarget::Target(ref v) => v.encode(buf),
+            AttachTarget::Coordinator(ref v) => v.encode(buf),
+        }
+    }
+}
+
 impl SaslInit {
     pub fn prepare_response(authz_id: &str, authn_id: &str, password: &str) -> Bytes {
         Bytes::from(format!("{}\x00{}\x00{}", authz_id, authn_id, password))
     }
 }
 
+impl From<Outcome> for DeliveryState {
+    fn from(outcome: Outcome) -> DeliveryState {
+        match outcome {
+            Outcome::Accepted(v) => DeliveryState::Accepted(v),
+            Outcome::Rejected(v) => DeliveryState::Rejected(v),
+            Outcome::Released(v) => DeliveryState::Released(v),
+            Outcome::Modified(v) => DeliveryState::Modified(v),
+            Outcome::Declared(v) => DeliveryState::Declared(v),
+        }
+    }
+}
+
+impl Rejected {
+    /// Error condition reported by remote peer, i.e. `amqp:resource-limit-exceeded`
+    pub fn condition(&self) -> Option<&ErrorCondition> {
+        self.error.as_ref().map(|err| &err.condition)
+    }
+
+    /// Error description reported by remote peer
+    pub fn description(&self) -> Option<&ByteString> {
+        self.error.as_ref().and_then(|err| err.description())
+    }
+
+    /// Error info reported by remote peer
+    pub fn info(&self) -> Option<&Fields> {
+        self.error.as_ref().and_then(|err| err.info())
+    }
+}
+
+impl DeliveryState {
+    /// Delivery is accepted
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, DeliveryState::Accepted(_))
+    }
+
+    /// Delivery is rejected
+    pub fn is_rejected(&self) -> bool {
+        matches!(self, DeliveryState::Rejected(_))
+    }
+
+    /// Delivery is released
+    pub fn is_released(&self) -> bool {
+        matches!(self, DeliveryState::Released(_))
+    }
+
+    /// Delivery is modified
+    pub fn is_modified(&self) -> bool {
+        matches!(self, DeliveryState::Modified(_))
+    }
+
+    /// Error reported by remote peer for rejected delivery
+    pub fn rejection_error(&self) -> Option<&Error> {
+        match self {
+            DeliveryState::Rejected(rejected) => rejected.error(),
+            _ => None,
+        }
+    }
+
+    /// Error condition reported by remote peer for rejected delivery
+    pub fn rejection_condition(&self) -> Option<&ErrorCondition> {
+        match self {
+            DeliveryState::Rejected(rejected) => rejected.condition(),
+            _ => None,
+        }
+    }
+
+    /// Modified outcome, contains delivery-failed and undeliverable-here flags
+    pub fn modified(&self) -> Option<&Modified> {
+        match self {
+            DeliveryState::Modified(modified) => Some(modified),
+            _ => None,
+        }
+    }
+
+    /// Message annotations of modified outcome
+    pub fn modified_annotations(&self) -> Option<&Fields> {
+        self.modified().and_then(|m| m.message_annotations())
+    }
+
+    /// Transaction id of declared transaction
+    pub fn declared(&self) -> Option<&Bytes> {
+        match self {
+            DeliveryState::Declared(declared) => Some(declared.txn_id()),
+            _ => None,
+        }
+    }
+
+    /// Decode delivery state from described value, i.e. value of attach unsettled map
+    pub fn from_variant(value: &Variant) -> Option<DeliveryState> {
+        let mut buf = BytesMut::with_capacity(value.encoded_size());
+        value.encode(&mut buf);
+        DeliveryState::decode(&buf).ok().map(|(_, state)| state)
+    }
+
+    /// Encode delivery state as described value
+    pub fn to_variant(&self) -> Variant {
+        let mut buf = BytesMut::with_capacity(self.encoded_size());
+        self.encode(&mut buf);
+        Variant::decode(&buf)
+            .map(|(_, value)| value)
+            .unwrap_or(Variant::Null)
+    }
+
+    /// Wrap terminal delivery state into transactional state of `txn_id` transaction
+    ///
+    /// Non-terminal states are returned as is.
+    pub fn into_transactional(self, txn_id: Bytes) -> DeliveryState {
+        let outcome = match self {
+            DeliveryState::Accepted(v) => Outcome::Accepted(v),
+            DeliveryState::Rejected(v) => Outcome::Rejected(v),
+            DeliveryState::Released(v) => Outcome::Released(v),
+            DeliveryState::Modified(v) => Outcome::Modified(v),
+            DeliveryState::Declared(v) => Outcome::Declared(v),
+            state => return state,
+        };
+        DeliveryState::TransactionalState(TransactionalState {
+            txn_id,
+            outcome: Some(outcome),
+        })
+    }
+}
+
+impl Disposition {
+    /// Delivery is accepted by remote peer
+    pub fn is_accepted(&self) -> bool {
+        matches!(self.state(), Some(DeliveryState::Accepted(_)))
+    }
+
+    /// Delivery is rejected by remote peer
+    pub fn is_rejected(&self) -> bool {
+        matches!(self.state(), Some(DeliveryState::Rejected(_)))
+    }
+
+    /// Delivery is released by remote peer
+    pub fn is_released(&self) -> bool {
+        matches!(self.state(), Some(DeliveryState::Released(_)))
+    }
+
+    /// Delivery is modified by remote peer
+    pub fn is_modified(&self) -> bool {
+        matches!(self.state(), Some(DeliveryState::Modified(_)))
+    }
+
+    /// Error reported by remote peer for rejected delivery
+    pub fn rejection_error(&self) -> Option<&Error> {
+        self.state().and_then(|s| s.rejection_error())
+    }
+
+    /// Error condition reported by remote peer for rejected delivery
+    pub fn rejection_condition(&self) -> Option<&ErrorCondition> {
+        self.state().and_then(|s| s.rejection_condition())
+    }
+
+    /// Modified outcome reported by remote peer
+    pub fn modified(&self) -> Option<&Modified> {
+        self.state().and_then(|s| s.modified())
+    }
+
+    /// Message annotations of modified outcome
+    pub fn modified_annotations(&self) -> Option<&Fields> {
+        self.state().and_then(|s| s.modified_annotations())
+    }
+}
+
 impl Default for Properties {
     fn default() -> Properties {
         Properties {
@@ -249,6 +505,13 @@ impl TransferBody {
             TransferBody::MessageOut(ref data) => data.message_format,
         }
     }
+
+    /// Encode transfer body into provided buffer
+    #[inline]
+    pub fn serialize_into(&self, dst: &mut BytesMut) {
+        dst.reserve(self.encoded_size());
+        self.encode(dst);
+    }
 }
 
 impl Encode for TransferBody {
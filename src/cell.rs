@@ -46,10 +46,19 @@ impl<T> Cell<T> {
         }
     }
 
+    pub(crate) fn ptr_eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+
     pub(crate) fn get_ref(&self) -> &T {
         unsafe { &*self.inner.as_ref().get() }
     }
 
+    /// Mutable reference to the inner value
+    ///
+    /// Caller must not keep other reference to the same value while
+    /// mutable reference is alive, i.e. link methods called from session
+    /// must not access session through link's own session cell.
     #[allow(clippy::mut_from_ref)]
     pub(crate) fn get_mut(&self) -> &mut T {
         unsafe { &mut *self.inner.as_ref().get() }
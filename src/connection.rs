@@ -4,14 +4,18 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
-use futures::{future, Stream};
+use bytestring::ByteString;
+use futures::Stream;
 use fxhash::FxHashMap;
+#[cfg(feature = "raw-frames")]
+use ntex::channel::mpsc;
 use ntex::channel::oneshot;
 use ntex::codec::{AsyncRead, AsyncWrite, Framed};
+use ntex::rt::time;
 use ntex::task::LocalWaker;
 use ntex::util::time::LowResTimeService;
 
-use ntex_amqp_codec::protocol::{Begin, Close, End, Error, Frame};
+use ntex_amqp_codec::protocol::{AmqpError, Begin, Close, End, Error, Frame};
 use ntex_amqp_codec::{AmqpCodec, AmqpCodecError, AmqpFrame};
 
 use crate::cell::{Cell, WeakCell};
@@ -20,6 +24,9 @@ use crate::hb::{Heartbeat, HeartbeatAction};
 use crate::session::{Session, SessionInner};
 use crate::Configuration;
 
+/// Time remote peer has to respond to `Connection::close()`
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct Connection<T: AsyncRead + AsyncWrite> {
     inner: Cell<ConnectionInner>,
     framed: Framed<T, AmqpCodec<AmqpFrame>>,
@@ -27,7 +34,11 @@ pub struct Connection<T: AsyncRead + AsyncWrite> {
 }
 
 pub(crate) enum ChannelState {
-    Opening(Option<oneshot::Sender<Session>>, WeakCell<ConnectionInner>),
+    Opening(
+        Option<oneshot::Sender<Result<Session, AmqpTransportError>>>,
+        WeakCell<ConnectionInner>,
+        SessionWindow,
+    ),
     Established(Cell<SessionInner>),
     #[allow(dead_code)]
     Closing(Option<oneshot::Sender<Result<(), AmqpTransportError>>>),
@@ -36,20 +47,43 @@ pub(crate) enum ChannelState {
 impl ChannelState {
     fn is_opening(&self) -> bool {
         match self {
-            ChannelState::Opening(_, _) => true,
+            ChannelState::Opening(..) => true,
             _ => false,
         }
     }
 }
 
+/// Direction of traced frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Frame is received from remote peer
+    Incoming,
+    /// Frame is sent to remote peer
+    Outgoing,
+}
+
+/// Local incoming window of opening session
+#[derive(Clone, Copy)]
+pub(crate) struct SessionWindow {
+    pub(crate) incoming: u32,
+    pub(crate) threshold: u32,
+}
+
 pub(crate) struct ConnectionInner {
     local: Configuration,
     remote: Configuration,
     write_queue: VecDeque<AmqpFrame>,
+    // number of frames taken from write queue
+    written_frames: u64,
     write_task: LocalWaker,
     sessions: slab::Slab<ChannelState>,
     sessions_map: FxHashMap<u16, usize>,
     error: Option<AmqpTransportError>,
+    protocol_error: Option<Error>,
+    close_waiters: Vec<oneshot::Sender<Result<(), AmqpTransportError>>>,
+    frame_logger: Option<Box<dyn FnMut(Direction, &AmqpFrame)>>,
+    #[cfg(feature = "raw-frames")]
+    frame_subscribers: Vec<(u16, mpsc::Sender<AmqpFrame>)>,
     state: State,
 }
 
@@ -71,7 +105,7 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
         Connection {
             framed,
             hb: Heartbeat::new(
-                local.timeout().unwrap(),
+                local.timeout(),
                 remote.timeout(),
                 time.unwrap_or_else(|| LowResTimeService::with(Duration::from_secs(1))),
             ),
@@ -84,7 +118,7 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
         inner: Cell<ConnectionInner>,
         time: Option<LowResTimeService>,
     ) -> Connection<T> {
-        let l_timeout = inner.get_ref().local.timeout().unwrap();
+        let l_timeout = inner.get_ref().local.timeout();
         let r_timeout = inner.get_ref().remote.timeout();
         Connection {
             framed,
@@ -108,58 +142,33 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
     }
 
     /// Gracefully close connection
+    ///
+    /// Same as `ConnectionController::close()`, connection is dropped if
+    /// remote peer does not respond with `Close` frame in 10 seconds.
     pub fn close(&mut self) -> impl Future<Output = Result<(), AmqpTransportError>> {
-        future::ok(())
+        self.controller().close(None, CLOSE_TIMEOUT)
     }
 
-    // TODO: implement
     /// Close connection with error
+    ///
+    /// Same as `Connection::close()`, `Close` frame carries `err`.
     pub fn close_with_error(
         &mut self,
-        _err: Error,
+        err: Error,
     ) -> impl Future<Output = Result<(), AmqpTransportError>> {
-        future::ok(())
+        self.controller().close(Some(err), CLOSE_TIMEOUT)
     }
 
     /// Opens the session
+    ///
+    /// Session windows are taken from local configuration.
     pub fn open_session(&mut self) -> impl Future<Output = Result<Session, AmqpTransportError>> {
-        let cell = self.inner.downgrade();
-        let inner = self.inner.clone();
-
-        async move {
-            let inner = inner.get_mut();
-
-            if let Some(ref e) = inner.error {
-                log::error!("Connection is in error state: {:?}", e);
-                Err(e.clone())
-            } else {
-                let (tx, rx) = oneshot::channel();
-
-                let entry = inner.sessions.vacant_entry();
-                let token = entry.key();
-
-                if token >= inner.local.channel_max {
-                    log::trace!("Too many channels: {:?}", token);
-                    Err(AmqpTransportError::TooManyChannels)
-                } else {
-                    entry.insert(ChannelState::Opening(Some(tx), cell));
-
-                    let begin = Begin {
-                        remote_channel: None,
-                        next_outgoing_id: 1,
-                        incoming_window: std::u32::MAX,
-                        outgoing_window: std::u32::MAX,
-                        handle_max: std::u32::MAX,
-                        offered_capabilities: None,
-                        desired_capabilities: None,
-                        properties: None,
-                    };
-                    inner.post_frame(AmqpFrame::new(token as u16, begin.into()));
-
-                    rx.await.map_err(|_| AmqpTransportError::Disconnected)
-                }
-            }
-        }
+        let local = &self.inner.get_ref().local;
+        let window = SessionWindow {
+            incoming: local.session_window,
+            threshold: local.session_window_threshold,
+        };
+        ConnectionController(self.inner.clone()).open_session(window, std::u32::MAX)
     }
 
     /// Get session by remote id. This method panics if session does not exists or in opening/closing state.
@@ -184,22 +193,24 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
         let entry = inner.sessions.vacant_entry();
         let token = entry.key();
 
+        let window = SessionWindow {
+            incoming: inner.local.session_window,
+            threshold: inner.local.session_window_threshold,
+        };
         let session = Cell::new(SessionInner::new(
             token,
-            false,
             ConnectionController(cell),
             token as u16,
-            begin.next_outgoing_id(),
-            begin.incoming_window(),
-            begin.outgoing_window(),
+            begin,
+            window,
         ));
         entry.insert(ChannelState::Established(session));
         inner.sessions_map.insert(channel_id, token);
 
         let begin = Begin {
             remote_channel: Some(channel_id),
-            next_outgoing_id: 1,
-            incoming_window: std::u32::MAX,
+            next_outgoing_id: inner.local.initial_outgoing_id,
+            incoming_window: inner.local.session_window,
             outgoing_window: begin.incoming_window(),
             handle_max: std::u32::MAX,
             offered_capabilities: None,
@@ -209,6 +220,41 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
         inner.post_frame(AmqpFrame::new(token as u16, begin.into()));
     }
 
+    /// Send heartbeat if connection is idle
+    ///
+    /// Returns `false` if remote peer did not send any frame
+    /// within local idle time-out, connection is closed in that case.
+    pub(crate) fn poll_heartbeat(&mut self, cx: &mut Context<'_>) -> bool {
+        match self.hb.poll(cx) {
+            HeartbeatAction::None => true,
+            HeartbeatAction::Heartbeat => {
+                trace!("Sending hb frame");
+                self.inner
+                    .get_mut()
+                    .post_frame(AmqpFrame::new(0, Frame::Empty));
+                true
+            }
+            HeartbeatAction::Close => {
+                trace!("Heartbeat expired, closing connection");
+                let close = Close {
+                    error: Some(Error {
+                        condition: AmqpError::ResourceLimitExceeded.into(),
+                        description: Some(ByteString::from_static("Idle time-out expired")),
+                        info: None,
+                    }),
+                };
+                self.inner
+                    .get_mut()
+                    .post_frame(AmqpFrame::new(0, close.into()));
+                let _ = self.poll_outgoing(cx);
+                self.inner
+                    .get_mut()
+                    .set_error(AmqpTransportError::IdleTimeout);
+                false
+            }
+        }
+    }
+
     pub(crate) fn register_write_task(&self, cx: &mut Context<'_>) {
         self.inner.write_task.register(cx.waker());
     }
@@ -224,6 +270,9 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
                 if let Some(frame) = inner.pop_next_frame() {
                     #[cfg(feature = "frame-trace")]
                     trace!("outgoing: {:#?}", frame);
+                    if let Some(ref mut logger) = inner.frame_logger {
+                        logger(Direction::Outgoing, &frame);
+                    }
                     update = true;
                     if let Err(e) = self.framed.write(frame) {
                         error!("Cannot encode frame: {:?}", e);
@@ -270,10 +319,32 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
 
         let mut update = false;
         loop {
+            // malformed frame, connection is closing
+            if let Some(AmqpTransportError::DecodeError(_)) = inner.error {
+                return Poll::Pending;
+            }
+
+            // connection is closed by remote peer, reply `Close` is flushed
+            if inner.state == State::RemoteClose {
+                return Poll::Pending;
+            }
+
+            // protocol violation, stop processing incoming frames
+            if let Some(err) = inner.protocol_error.take() {
+                inner.set_error(AmqpTransportError::Protocol(err));
+                inner.state = State::RemoteClose;
+                return Poll::Pending;
+            }
+
             match Pin::new(&mut self.framed).poll_next(cx) {
                 Poll::Ready(Some(Ok(frame))) => {
                     #[cfg(feature = "frame-trace")]
                     trace!("incoming: {:#?}", frame);
+                    if let Some(ref mut logger) = inner.frame_logger {
+                        logger(Direction::Incoming, &frame);
+                    }
+                    #[cfg(feature = "raw-frames")]
+                    inner.notify_frame_subscribers(&frame);
 
                     update = true;
 
@@ -284,6 +355,11 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
 
                     // handle connection close
                     if let Frame::Close(ref close) = frame.performative() {
+                        if inner.state == State::Closing {
+                            for tx in inner.close_waiters.drain(..) {
+                                let _ = tx.send(Ok(()));
+                            }
+                        }
                         inner.set_error(AmqpTransportError::Closed(close.error.clone()));
 
                         if inner.state == State::Closing {
@@ -295,6 +371,7 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
                             let close = Close { error: None };
                             inner.post_frame(AmqpFrame::new(0, close.into()));
                             inner.state = State::RemoteClose;
+                            return Poll::Pending;
                         }
                     }
 
@@ -324,7 +401,7 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
                     // handle session frames
                     if let Some(channel) = inner.sessions.get_mut(channel_id) {
                         match channel {
-                            ChannelState::Opening(_, _) => {
+                            ChannelState::Opening(..) => {
                                 error!("Unexpected opening state: {}", channel_id);
                             }
                             ChannelState::Established(ref mut session) => {
@@ -338,18 +415,11 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
                                     Frame::Flow(_) | Frame::Detach(_) => {
                                         return Poll::Ready(Some(Ok(frame)));
                                     }
-                                    Frame::End(remote_end) => {
-                                        trace!("Remote session end: {}", frame.channel_id());
-                                        let end = End { error: None };
-                                        session.get_mut().set_error(
-                                            AmqpTransportError::SessionEnded(
-                                                remote_end.error.clone(),
-                                            ),
-                                        );
-                                        let id = session.get_mut().id();
-                                        inner.post_frame(AmqpFrame::new(id, end.into()));
+                                    Frame::End(_) => {
+                                        let (remote_channel_id, frame) = frame.into_parts();
+                                        session.get_mut().handle_frame(frame);
                                         if let Some(token) =
-                                            inner.sessions_map.remove(&frame.channel_id())
+                                            inner.sessions_map.remove(&remote_channel_id)
                                         {
                                             inner.sessions.remove(token);
                                         }
@@ -386,11 +456,17 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
                     self.hb.update_local(update);
                     break;
                 }
-                Poll::Ready(Some(Err(e))) => {
+                Poll::Ready(Some(Err(AmqpCodecError::Io(e)))) => {
                     trace!("Error while reading from socket: {:?}", e);
+                    let e = AmqpCodecError::Io(e);
                     inner.set_error(e.clone().into());
                     return Poll::Ready(Some(Err(e)));
                 }
+                Poll::Ready(Some(Err(e))) => {
+                    error!("Cannot decode incoming frame: {}", e);
+                    inner.decode_error(e);
+                    return Poll::Pending;
+                }
             }
         }
 
@@ -401,9 +477,11 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
 impl<T: AsyncRead + AsyncWrite> Drop for Connection<T> {
     fn drop(&mut self) {
         trace!("Connection has been dropped, disconnecting");
-        self.inner
-            .get_mut()
-            .set_error(AmqpTransportError::Disconnected);
+        let inner = self.inner.get_mut();
+        // keep original error, i.e. idle time-out
+        if inner.error.is_none() {
+            inner.set_error(AmqpTransportError::Disconnected);
+        }
     }
 }
 
@@ -412,27 +490,8 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Future for Connection<T> {
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         // connection heartbeat
-        match self.hb.poll(cx) {
-            Ok(act) => match act {
-                HeartbeatAction::None => (),
-                HeartbeatAction::Close => {
-                    trace!("Headerbeat expired");
-                    self.inner.get_mut().set_error(AmqpTransportError::Timeout);
-                    return Poll::Ready(Ok(()));
-                }
-                HeartbeatAction::Heartbeat => {
-                    trace!("Sending hb frame");
-                    self.inner
-                        .get_mut()
-                        .write_queue
-                        .push_back(AmqpFrame::new(0, Frame::Empty));
-                }
-            },
-            Err(e) => {
-                trace!("Headerbeat error: {:?}", e);
-                self.inner.get_mut().set_error(e);
-                return Poll::Ready(Ok(()));
-            }
+        if !self.poll_heartbeat(cx) {
+            return Poll::Ready(Ok(()));
         }
 
         loop {
@@ -447,7 +506,7 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Future for Connection<T> {
                 Poll::Ready(Some(Ok(frame))) => {
                     if let Some(channel) = self.inner.sessions.get(frame.channel_id() as usize) {
                         if let ChannelState::Established(ref session) = channel {
-                            session.get_mut().handle_frame(frame.into_parts().1);
+                            handle_session_frame(session, frame.into_parts().1);
                             continue;
                         }
                     }
@@ -461,7 +520,14 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Future for Connection<T> {
                 Poll::Pending => break,
             }
         }
-        let _ = self.poll_outgoing(cx)?;
+        if let Poll::Ready(res) = self.poll_outgoing(cx) {
+            // connection is dropped or closed
+            let inner = self.inner.get_mut();
+            if inner.error.is_none() {
+                inner.set_error(AmqpTransportError::Disconnected);
+            }
+            return Poll::Ready(res);
+        }
         self.register_write_task(cx);
 
         match self.poll_incoming(cx) {
@@ -475,7 +541,7 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Future for Connection<T> {
             Poll::Ready(Some(Ok(frame))) => {
                 if let Some(channel) = self.inner.sessions.get(frame.channel_id() as usize) {
                     if let ChannelState::Established(ref session) = channel {
-                        session.get_mut().handle_frame(frame.into_parts().1);
+                        handle_session_frame(session, frame.into_parts().1);
                         return Poll::Pending;
                     }
                 }
@@ -493,6 +559,17 @@ impl<T: AsyncRead + AsyncWrite + Unpin> Future for Connection<T> {
     }
 }
 
+/// Dispatch frame to established session, remote attach goes to incoming links
+fn handle_session_frame(session: &Cell<SessionInner>, frame: Frame) {
+    if let Frame::Attach(attach) = frame {
+        session
+            .get_mut()
+            .handle_remote_attach(attach, session.clone());
+    } else {
+        session.get_mut().handle_frame(frame);
+    }
+}
+
 #[derive(Clone)]
 pub struct ConnectionController(pub(crate) Cell<ConnectionInner>);
 
@@ -502,10 +579,16 @@ impl ConnectionController {
             local,
             remote: Configuration::default(),
             write_queue: VecDeque::new(),
+            written_frames: 0,
             write_task: LocalWaker::new(),
             sessions: slab::Slab::with_capacity(8),
             sessions_map: FxHashMap::default(),
             error: None,
+            protocol_error: None,
+            close_waiters: Vec::new(),
+            frame_logger: None,
+            #[cfg(feature = "raw-frames")]
+            frame_subscribers: Vec::new(),
             state: State::Normal,
         }))
     }
@@ -520,6 +603,95 @@ impl ConnectionController {
         &self.0.get_ref().remote
     }
 
+    #[inline]
+    /// Get local connection configuration
+    pub(crate) fn local_config(&self) -> &Configuration {
+        &self.0.get_ref().local
+    }
+
+    /// Gracefully close connection
+    ///
+    /// Established sessions are ended and `Close` frame is sent to remote peer.
+    /// Resolves once remote peer responds with `Close` frame, if remote peer
+    /// does not respond in time, connection get dropped and future resolves
+    /// with `AmqpTransportError::Timeout` error.
+    pub fn close(
+        &self,
+        error: Option<Error>,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<(), AmqpTransportError>> {
+        let (tx, rx) = oneshot::channel();
+        let inner = self.0.get_mut();
+
+        if let Some(ref err) = inner.error {
+            let _ = tx.send(Err(err.clone()));
+        } else if inner.state == State::Closing {
+            inner.close_waiters.push(tx);
+        } else {
+            trace!("Closing connection with error {:?}", error);
+            for (_, channel) in inner.sessions.iter_mut() {
+                if let ChannelState::Established(ref mut session) = channel {
+                    session.get_mut().end(None);
+                }
+            }
+            inner.post_frame(AmqpFrame::new(0, Close { error }.into()));
+            inner.state = State::Closing;
+            inner.close_waiters.push(tx);
+        }
+
+        let mut controller = self.clone();
+        async move {
+            match time::timeout(timeout, rx).await {
+                Ok(Ok(res)) => res,
+                Ok(Err(_)) => Err(AmqpTransportError::Disconnected),
+                Err(_) => {
+                    trace!("Remote peer did not close connection in {:?}", timeout);
+                    controller.drop_connection();
+                    Err(AmqpTransportError::Timeout)
+                }
+            }
+        }
+    }
+
+    /// Set frame tracing hook
+    ///
+    /// Hook is called for every frame received from remote peer and for
+    /// every frame written to the transport, in the order frames are
+    /// processed. Hook must not use connection controller.
+    pub fn set_frame_logger<F>(&self, logger: F)
+    where
+        F: FnMut(Direction, &AmqpFrame) + 'static,
+    {
+        self.0.get_mut().frame_logger = Some(Box::new(logger));
+    }
+
+    /// Remove frame tracing hook
+    pub fn remove_frame_logger(&self) {
+        self.0.get_mut().frame_logger = None;
+    }
+
+    /// Send raw frame to remote peer
+    ///
+    /// Frame is sent as is, session and link state is not updated.
+    /// This is an unstable api, intended for testing and extensions.
+    #[cfg(feature = "raw-frames")]
+    pub fn inject_frame(&mut self, frame: AmqpFrame) {
+        self.post_frame(frame)
+    }
+
+    /// Subscribe to raw frames received on channel
+    ///
+    /// Copies of incoming frames with specified remote channel id are sent
+    /// to returned stream, frames are processed by connection as usual.
+    /// Subscription is removed once stream is dropped.
+    /// This is an unstable api, intended for testing and extensions.
+    #[cfg(feature = "raw-frames")]
+    pub fn subscribe_frames(&self, channel_id: u16) -> mpsc::Receiver<AmqpFrame> {
+        let (tx, rx) = mpsc::channel();
+        self.0.get_mut().frame_subscribers.push((channel_id, tx));
+        rx
+    }
+
     #[inline]
     /// Drop connection
     pub fn drop_connection(&mut self) {
@@ -532,6 +704,102 @@ impl ConnectionController {
         self.0.get_mut().post_frame(frame)
     }
 
+    /// Sequence number of last queued frame
+    ///
+    /// Frame is written to transport once `written_frames()` reaches it.
+    pub(crate) fn last_queued_frame(&self) -> u64 {
+        self.0.written_frames + self.0.write_queue.len() as u64
+    }
+
+    /// Number of frames written to transport
+    pub(crate) fn written_frames(&self) -> u64 {
+        self.0.written_frames
+    }
+
+    /// Queue frame, frame is sent with next non-batchable frame or flush
+    pub(crate) fn post_batchable_frame(&mut self, frame: AmqpFrame) {
+        self.0.get_mut().write_queue.push_back(frame);
+    }
+
+    /// Send queued frames
+    pub(crate) fn flush(&self) {
+        if !self.0.write_queue.is_empty() {
+            self.0.write_task.wake();
+        }
+    }
+
+    /// Close connection because of protocol violation
+    pub(crate) fn protocol_error(&mut self, err: Error) {
+        self.0.get_mut().protocol_error(err)
+    }
+
+    /// Begin new session with specified incoming and outgoing windows
+    ///
+    /// Incoming window is replenished once half of it is used.
+    /// Resolves once remote peer responds with `Begin` frame.
+    pub fn begin_session(
+        &self,
+        incoming_window: u32,
+        outgoing_window: u32,
+    ) -> impl Future<Output = Result<Session, AmqpTransportError>> {
+        let window = SessionWindow {
+            incoming: incoming_window,
+            threshold: incoming_window / 2,
+        };
+        self.open_session(window, outgoing_window)
+    }
+
+    fn open_session(
+        &self,
+        window: SessionWindow,
+        outgoing_window: u32,
+    ) -> impl Future<Output = Result<Session, AmqpTransportError>> {
+        let cell = self.0.downgrade();
+        let inner = self.0.clone();
+
+        async move {
+            let inner = inner.get_mut();
+
+            if let Some(ref e) = inner.error {
+                log::error!("Connection is in error state: {:?}", e);
+                Err(e.clone())
+            } else if inner.state == State::Closing {
+                log::error!("Connection is closing");
+                Err(AmqpTransportError::Closed(None))
+            } else {
+                let (tx, rx) = oneshot::channel();
+
+                let entry = inner.sessions.vacant_entry();
+                let token = entry.key();
+
+                // remote peer may limit channels below local channel max
+                if token >= inner.local.channel_max || token > inner.remote.channel_max {
+                    log::trace!("Too many channels: {:?}", token);
+                    Err(AmqpTransportError::TooManyChannels)
+                } else {
+                    entry.insert(ChannelState::Opening(Some(tx), cell, window));
+
+                    let begin = Begin {
+                        remote_channel: None,
+                        next_outgoing_id: inner.local.initial_outgoing_id,
+                        incoming_window: window.incoming,
+                        outgoing_window,
+                        handle_max: std::u32::MAX,
+                        offered_capabilities: None,
+                        desired_capabilities: None,
+                        properties: None,
+                    };
+                    inner.post_frame(AmqpFrame::new(token as u16, begin.into()));
+
+                    match rx.await {
+                        Ok(res) => res,
+                        Err(_) => Err(AmqpTransportError::Disconnected),
+                    }
+                }
+            }
+        }
+    }
+
     pub(crate) fn drop_session_copy(&mut self, _id: usize) {}
 }
 
@@ -541,32 +809,91 @@ impl ConnectionInner {
             local,
             remote,
             write_queue: VecDeque::new(),
+            written_frames: 0,
             write_task: LocalWaker::new(),
             sessions: slab::Slab::with_capacity(8),
             sessions_map: FxHashMap::default(),
             error: None,
+            protocol_error: None,
+            close_waiters: Vec::new(),
+            frame_logger: None,
+            #[cfg(feature = "raw-frames")]
+            frame_subscribers: Vec::new(),
             state: State::Normal,
         }
     }
 
+    #[cfg(feature = "raw-frames")]
+    fn notify_frame_subscribers(&mut self, frame: &AmqpFrame) {
+        self.frame_subscribers.retain(|(channel_id, tx)| {
+            *channel_id != frame.channel_id() || tx.send(frame.clone()).is_ok()
+        });
+    }
+
+    /// Send `Close` frame with error, connection state is updated
+    /// before processing next incoming frame
+    fn protocol_error(&mut self, err: Error) {
+        if self.error.is_none() && self.protocol_error.is_none() {
+            error!("Protocol error, closing connection: {:?}", err);
+            self.post_frame(AmqpFrame::new(
+                0,
+                Close {
+                    error: Some(err.clone()),
+                }
+                .into(),
+            ));
+            self.protocol_error = Some(err);
+        }
+    }
+
+    /// Send `Close` frame with decode error, stop reading incoming frames
+    fn decode_error(&mut self, err: AmqpCodecError) {
+        let close = Close {
+            error: Some(Error {
+                condition: AmqpError::DecodeError.into(),
+                description: Some(ByteString::from(format!("{}", err))),
+                info: None,
+            }),
+        };
+        self.post_frame(AmqpFrame::new(0, close.into()));
+        self.set_error(AmqpTransportError::DecodeError(err));
+        self.state = State::RemoteClose;
+    }
+
     fn set_error(&mut self, err: AmqpTransportError) {
         log::trace!("Set connection error: {:?}", err);
         for (_, channel) in self.sessions.iter_mut() {
             match channel {
-                ChannelState::Opening(_, _) | ChannelState::Closing(_) => (),
+                ChannelState::Opening(ref mut tx, _, _) => {
+                    if let Some(tx) = tx.take() {
+                        let _ = tx.send(Err(err.clone()));
+                    }
+                }
                 ChannelState::Established(ref mut ses) => {
                     ses.get_mut().set_error(err.clone());
                 }
+                ChannelState::Closing(ref mut tx) => {
+                    if let Some(tx) = tx.take() {
+                        let _ = tx.send(Err(err.clone()));
+                    }
+                }
             }
         }
         self.sessions.clear();
         self.sessions_map.clear();
 
+        for tx in self.close_waiters.drain(..) {
+            let _ = tx.send(Err(err.clone()));
+        }
         self.error = Some(err);
     }
 
     fn pop_next_frame(&mut self) -> Option<AmqpFrame> {
-        self.write_queue.pop_front()
+        let frame = self.write_queue.pop_front();
+        if frame.is_some() {
+            self.written_frames += 1;
+        }
+        frame
     }
 
     fn post_frame(&mut self, frame: AmqpFrame) {
@@ -585,34 +912,72 @@ impl ConnectionInner {
 
         if let Some(channel) = self.sessions.get_mut(id) {
             if channel.is_opening() {
-                if let ChannelState::Opening(tx, cell) = channel {
+                if let ChannelState::Opening(tx, cell, window) = channel {
+                    // remote channel must be in local channel range and not used
+                    if channel_id as usize >= self.local.channel_max
+                        || self.sessions_map.contains_key(&channel_id)
+                    {
+                        error!(
+                            "Begin is mapped to invalid remote channel: local {:?} remote {:?}",
+                            id, channel_id
+                        );
+                        let _ = tx
+                            .take()
+                            .unwrap()
+                            .send(Err(AmqpTransportError::InvalidRemoteChannel(channel_id)));
+                        self.sessions.remove(id);
+                        self.post_frame(AmqpFrame::new(
+                            id as u16,
+                            End {
+                                error: Some(Error {
+                                    condition: AmqpError::NotAllowed.into(),
+                                    description: Some(ByteString::from(format!(
+                                        "Remote channel is not allowed: {}",
+                                        channel_id
+                                    ))),
+                                    info: None,
+                                }),
+                            }
+                            .into(),
+                        ));
+                        return;
+                    }
+
                     let cell = cell.upgrade().unwrap();
                     let session = Cell::new(SessionInner::new(
                         id,
-                        true,
                         ConnectionController(cell),
                         channel_id,
-                        begin.next_outgoing_id(),
-                        begin.incoming_window(),
-                        begin.outgoing_window(),
+                        begin,
+                        *window,
                     ));
                     self.sessions_map.insert(channel_id, id);
 
-                    if tx
-                        .take()
-                        .unwrap()
-                        .send(Session::new(session.clone()))
-                        .is_err()
+                    if let Err(Ok(session)) =
+                        tx.take().unwrap().send(Ok(Session::owned(session.clone())))
                     {
-                        // todo: send end session
+                        // session is not needed anymore
+                        trace!("Session is dropped before open, ending: {}", id);
+                        session.disown();
+                        *channel = ChannelState::Closing(None);
+                        self.post_frame(AmqpFrame::new(id as u16, End { error: None }.into()));
+                    } else {
+                        session.get_mut().local = true;
+                        *channel = ChannelState::Established(session)
                     }
-                    *channel = ChannelState::Established(session)
                 }
-            } else {
-                // send error response
+                return;
             }
-        } else {
-            // todo: rogue begin right now - do nothing. in future might indicate incoming attach
         }
+
+        // begin does not match any opening session
+        self.protocol_error(Error {
+            condition: AmqpError::IllegalState.into(),
+            description: Some(ByteString::from(format!(
+                "Unexpected begin for channel: {}",
+                id
+            ))),
+            info: None,
+        });
     }
 }
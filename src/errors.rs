@@ -1,19 +1,109 @@
 use bytestring::ByteString;
 use either::Either;
+use ntex_amqp_codec::protocol::{Handle, MessageFormat};
+use ntex_amqp_codec::types::Symbol;
 use ntex_amqp_codec::{protocol, AmqpCodecError, ProtocolIdError};
 
+use crate::sasl::SaslMechanism;
+
 #[derive(Debug, Display, Clone)]
 pub enum AmqpTransportError {
     Codec(AmqpCodecError),
+    #[display(fmt = "Malformed frame: {}", _0)]
+    DecodeError(AmqpCodecError),
     TooManyChannels,
     Disconnected,
     Timeout,
+    #[display(fmt = "Idle time-out expired")]
+    IdleTimeout,
     #[display(fmt = "Connection closed, error: {:?}", _0)]
     Closed(Option<protocol::Error>),
     #[display(fmt = "Session ended, error: {:?}", _0)]
     SessionEnded(Option<protocol::Error>),
+    #[display(fmt = "Session ended by remote peer, error: {:?}", _0)]
+    SessionRemoteEnded(Option<protocol::Error>),
     #[display(fmt = "Link detached, error: {:?}", _0)]
     LinkDetached(Option<protocol::Error>),
+    #[display(fmt = "Message size exceeds link max message size")]
+    MessageTooLarge,
+    #[display(
+        fmt = "Message format {} does not match link message format {}",
+        _0,
+        _1
+    )]
+    MessageFormatMismatch(MessageFormat, MessageFormat),
+    #[display(fmt = "Transfers queue is full, queued transfers: {}", _0)]
+    Full(usize),
+    #[display(fmt = "Delivery is aborted")]
+    Aborted,
+    #[display(fmt = "Session handle-max {} is exceeded", _0)]
+    HandleMaxExceeded(Handle),
+    #[display(fmt = "Link with name {:?} already exists", _0)]
+    LinkNameInUse(ByteString),
+    #[display(fmt = "Begin is mapped to invalid remote channel: {}", _0)]
+    InvalidRemoteChannel(u16),
+    #[display(fmt = "Protocol error: {:?}", _0)]
+    Protocol(protocol::Error),
+    #[display(fmt = "Remote peer error: {}, {:?}", condition, description)]
+    Remote {
+        condition: String,
+        description: Option<String>,
+    },
+}
+
+impl AmqpTransportError {
+    /// Link is detached by remote peer, error of `Detach` frame is
+    /// reported as `Remote` error
+    pub(crate) fn detached(error: Option<protocol::Error>) -> Self {
+        error
+            .map(AmqpTransportError::remote)
+            .unwrap_or(AmqpTransportError::LinkDetached(None))
+    }
+
+    /// Session is ended by remote peer, error of `End` frame is
+    /// reported as `Remote` error
+    pub(crate) fn ended(error: Option<protocol::Error>) -> Self {
+        error
+            .map(AmqpTransportError::remote)
+            .unwrap_or(AmqpTransportError::SessionRemoteEnded(None))
+    }
+
+    fn remote(err: protocol::Error) -> Self {
+        AmqpTransportError::Remote {
+            condition: err.condition.to_symbol().as_str().to_string(),
+            description: err.description.map(|desc| desc.to_string()),
+        }
+    }
+
+    /// Error carried by `Detach`, `End` or `Close` frame
+    pub fn remote_error(&self) -> Option<&protocol::Error> {
+        match self {
+            AmqpTransportError::Closed(err)
+            | AmqpTransportError::SessionRemoteEnded(err)
+            | AmqpTransportError::LinkDetached(err) => err.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Error condition symbol, i.e. `amqp:link:stolen` or `amqp:resource-limit-exceeded`
+    pub fn remote_condition(&self) -> Option<Symbol> {
+        if let AmqpTransportError::Remote { condition, .. } = self {
+            Some(Symbol::from(condition.clone()))
+        } else {
+            self.remote_error().map(|err| err.condition.to_symbol())
+        }
+    }
+
+    /// Error description reported by remote peer
+    pub fn remote_description(&self) -> Option<&str> {
+        if let AmqpTransportError::Remote { description, .. } = self {
+            description.as_deref()
+        } else {
+            self.remote_error()
+                .and_then(|err| err.description.as_ref())
+                .map(|desc| desc.as_ref())
+        }
+    }
 }
 
 impl From<AmqpCodecError> for AmqpTransportError {
@@ -28,7 +118,14 @@ pub enum SaslConnectError {
     AmqpError(AmqpCodecError),
     #[display(fmt = "Sasl error code: {:?}", _0)]
     Sasl(protocol::SaslCode),
+    #[display(fmt = "Sasl mechanisms are not supported by server: {:?}", _0)]
+    #[from(ignore)]
+    MechanismNotSupported(Vec<SaslMechanism>),
+    ExpectedMechanismsFrame,
     ExpectedOpenFrame,
+    #[display(fmt = "Remote max frame size is too small: {}", _0)]
+    #[from(ignore)]
+    InvalidMaxFrameSize(u32),
     Disconnected,
 }
 
@@ -6,8 +6,6 @@ use std::time::Duration;
 use ntex::rt::time::{delay_until, Delay, Instant};
 use ntex::util::time::LowResTimeService;
 
-use crate::errors::AmqpTransportError;
-
 pub(crate) enum HeartbeatAction {
     None,
     Heartbeat,
@@ -17,33 +15,33 @@ pub(crate) enum HeartbeatAction {
 pub(crate) struct Heartbeat {
     expire_local: Instant,
     expire_remote: Instant,
-    local: Duration,
+    local: Option<Duration>,
     remote: Option<Duration>,
     time: LowResTimeService,
-    delay: Delay,
+    delay: Option<Delay>,
 }
 
 impl Heartbeat {
-    pub(crate) fn new(local: Duration, remote: Option<Duration>, time: LowResTimeService) -> Self {
+    pub(crate) fn new(
+        local: Option<Duration>,
+        remote: Option<Duration>,
+        time: LowResTimeService,
+    ) -> Self {
         let now = Instant::from_std(time.now());
-        let delay = if let Some(remote) = remote {
-            delay_until(now + std::cmp::min(local, remote))
-        } else {
-            delay_until(now + local)
-        };
-
-        Heartbeat {
+        let mut hb = Heartbeat {
             expire_local: now,
             expire_remote: now,
             local,
             remote,
             time,
-            delay,
-        }
+            delay: None,
+        };
+        hb.delay = hb.next_expire().map(delay_until);
+        hb
     }
 
     pub(crate) fn update_local(&mut self, update: bool) {
-        if update {
+        if update && self.local.is_some() {
             self.expire_local = Instant::from_std(self.time.now());
         }
     }
@@ -54,43 +52,48 @@ impl Heartbeat {
         }
     }
 
-    fn next_expire(&self) -> Instant {
-        if let Some(remote) = self.remote {
-            let t1 = self.expire_local + self.local;
-            let t2 = self.expire_remote + remote;
-            if t1 < t2 {
-                t1
-            } else {
-                t2
-            }
-        } else {
-            self.expire_local + self.local
+    fn next_expire(&self) -> Option<Instant> {
+        let t1 = self.local.map(|local| self.expire_local + local);
+        let t2 = self.remote.map(|remote| self.expire_remote + remote);
+        match (t1, t2) {
+            (Some(t1), Some(t2)) => Some(std::cmp::min(t1, t2)),
+            (t1, t2) => t1.or(t2),
         }
     }
 
-    pub(crate) fn poll(
-        &mut self,
-        cx: &mut Context<'_>,
-    ) -> Result<HeartbeatAction, AmqpTransportError> {
-        match Pin::new(&mut self.delay).poll(cx) {
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> HeartbeatAction {
+        let delay = if let Some(ref mut delay) = self.delay {
+            delay
+        } else {
+            // idle timeout is not negotiated
+            return HeartbeatAction::None;
+        };
+
+        match Pin::new(&mut *delay).poll(cx) {
             Poll::Ready(_) => {
                 let mut act = HeartbeatAction::None;
-                let dl = self.delay.deadline();
-                if dl >= self.expire_local + self.local {
-                    // close connection
-                    return Ok(HeartbeatAction::Close);
+                let dl = delay.deadline();
+                if let Some(local) = self.local {
+                    if dl >= self.expire_local + local {
+                        // close connection
+                        return HeartbeatAction::Close;
+                    }
                 }
                 if let Some(remote) = self.remote {
                     if dl >= self.expire_remote + remote {
                         // send heartbeat
                         act = HeartbeatAction::Heartbeat;
+                        self.expire_remote = Instant::from_std(self.time.now());
                     }
                 }
-                self.delay.reset(self.next_expire());
-                let _ = Pin::new(&mut self.delay).poll(cx);
-                Ok(act)
+                if let Some(expire) = self.next_expire() {
+                    let delay = self.delay.as_mut().unwrap();
+                    delay.reset(expire);
+                    let _ = Pin::new(delay).poll(cx);
+                }
+                act
             }
-            Poll::Pending => Ok(HeartbeatAction::None),
+            Poll::Pending => HeartbeatAction::None,
         }
     }
 }
@@ -14,45 +14,102 @@ use std::time::Duration;
 use bytestring::ByteString;
 use ntex::channel::oneshot;
 pub use ntex_amqp_codec::protocol::Error;
-use ntex_amqp_codec::protocol::{Disposition, Handle, Milliseconds, Open};
+use ntex_amqp_codec::protocol::{
+    DeliveryState, Disposition, Fields, Handle, Milliseconds, Open, Received, TransferNumber,
+};
 use uuid::Uuid;
 
+use crate::cell::Cell;
+
 mod cell;
 pub mod client;
 mod connection;
 pub mod error_code;
 mod errors;
 mod hb;
+mod management;
 mod rcvlink;
+mod reconnect;
+mod rpc;
 pub mod sasl;
 pub mod server;
 mod service;
 mod session;
 mod sndlink;
+#[cfg(feature = "rustls")]
+pub mod tls;
+mod transaction;
 
-pub use self::connection::{Connection, ConnectionController};
+pub use self::connection::{Connection, ConnectionController, Direction};
 pub use self::errors::{AmqpError, AmqpTransportError, LinkError};
-pub use self::rcvlink::{ReceiverLink, ReceiverLinkBuilder};
-pub use self::session::Session;
-pub use self::sndlink::{SenderLink, SenderLinkBuilder};
+pub use self::management::ManagementClient;
+pub use self::rcvlink::{
+    Deliveries, ReceivedDelivery, ReceiverLink, ReceiverLinkBuilder, SharedCredit,
+};
+pub use self::reconnect::{
+    ReconnectPolicy, ReconnectingConnection, ReconnectingReceiver, ReconnectingSender,
+};
+pub use self::rpc::RequestReply;
+pub use self::session::{
+    AcceptedLink, DeliveryView, IncomingLink, LinkInfo, Session, SessionEvent, SessionStats,
+};
+pub use self::sndlink::{
+    DeliveryTagStrategy, RetryPolicy, SenderLink, SenderLinkBuilder, SenderLinkSink,
+    SenderLinkStats,
+};
+pub use self::transaction::Transaction;
 
 pub mod codec {
     pub use ntex_amqp_codec::*;
 }
 
-pub enum Delivery {
+/// Outgoing delivery
+///
+/// Resolves once delivery is settled by remote peer.
+pub struct Delivery {
+    inner: DeliveryInner,
+    state: Option<Cell<Option<DeliveryState>>>,
+}
+
+enum DeliveryInner {
     Resolved(Result<Disposition, AmqpTransportError>),
     Pending(oneshot::Receiver<Result<Disposition, AmqpTransportError>>),
     Gone,
 }
 
-type DeliveryPromise = oneshot::Sender<Result<Disposition, AmqpTransportError>>;
+impl Delivery {
+    pub(crate) fn resolved(res: Result<Disposition, AmqpTransportError>) -> Delivery {
+        Delivery {
+            inner: DeliveryInner::Resolved(res),
+            state: None,
+        }
+    }
+
+    /// Last non-terminal delivery state reported by remote peer
+    ///
+    /// Remote peer could report delivery state before delivery get settled.
+    pub fn state(&self) -> Option<DeliveryState> {
+        self.state
+            .as_ref()
+            .and_then(|state| state.get_ref().clone())
+    }
+
+    /// Received state reported by remote peer for partially received delivery
+    ///
+    /// Contains section number and section offset, could be used for resuming delivery.
+    pub fn received(&self) -> Option<Received> {
+        match self.state() {
+            Some(DeliveryState::Received(received)) => Some(received),
+            _ => None,
+        }
+    }
+}
 
 impl Future for Delivery {
     type Output = Result<Disposition, AmqpTransportError>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        if let Delivery::Pending(ref mut receiver) = *self {
+        if let DeliveryInner::Pending(ref mut receiver) = self.inner {
             return match Pin::new(receiver).poll(cx) {
                 Poll::Ready(Ok(r)) => Poll::Ready(r.map(|state| state)),
                 Poll::Pending => Poll::Pending,
@@ -63,8 +120,8 @@ impl Future for Delivery {
             };
         }
 
-        let old_v = ::std::mem::replace(&mut *self, Delivery::Gone);
-        if let Delivery::Resolved(r) = old_v {
+        let old_v = ::std::mem::replace(&mut self.inner, DeliveryInner::Gone);
+        if let DeliveryInner::Resolved(r) = old_v {
             return match r {
                 Ok(state) => Poll::Ready(Ok(state)),
                 Err(e) => Poll::Ready(Err(e)),
@@ -74,6 +131,50 @@ impl Future for Delivery {
     }
 }
 
+pub(crate) struct DeliveryPromise {
+    tx: oneshot::Sender<Result<Disposition, AmqpTransportError>>,
+    state: Cell<Option<DeliveryState>>,
+}
+
+impl DeliveryPromise {
+    pub(crate) fn new() -> (DeliveryPromise, Delivery) {
+        let (tx, rx) = oneshot::channel();
+        let state = Cell::new(None);
+        let delivery = Delivery {
+            inner: DeliveryInner::Pending(rx),
+            state: Some(state.clone()),
+        };
+        (DeliveryPromise { tx, state }, delivery)
+    }
+
+    /// Delivery future is dropped
+    pub(crate) fn is_canceled(&self) -> bool {
+        self.tx.is_canceled()
+    }
+
+    /// Promise resolves this delivery future
+    pub(crate) fn is_for(&self, delivery: &Delivery) -> bool {
+        matches!(delivery.state, Some(ref state) if state.ptr_eq(&self.state))
+    }
+
+    /// Last recorded intermediate delivery state
+    pub(crate) fn state(&self) -> Option<DeliveryState> {
+        self.state.get_ref().clone()
+    }
+
+    /// Record intermediate delivery state
+    pub(crate) fn set_state(&self, state: DeliveryState) {
+        *self.state.get_mut() = Some(state);
+    }
+
+    /// Resolve delivery future, returns `false` if future is dropped
+    pub(crate) fn send(self, res: Result<Disposition, AmqpTransportError>) -> bool {
+        // delivery is complete, intermediate state is not relevant anymore
+        *self.state.get_mut() = None;
+        self.tx.send(res).is_ok()
+    }
+}
+
 /// Amqp1 transport configuration.
 #[derive(Debug, Clone)]
 pub struct Configuration {
@@ -81,6 +182,12 @@ pub struct Configuration {
     pub channel_max: usize,
     pub idle_time_out: Option<Milliseconds>,
     pub hostname: Option<ByteString>,
+    pub container_id: Option<ByteString>,
+    pub properties: Option<Fields>,
+    pub session_window: u32,
+    pub session_window_threshold: u32,
+    pub max_pending_transfers: Option<usize>,
+    pub initial_outgoing_id: TransferNumber,
 }
 
 impl Default for Configuration {
@@ -97,6 +204,12 @@ impl Configuration {
             channel_max: 1024,
             idle_time_out: Some(120_000),
             hostname: None,
+            container_id: None,
+            properties: None,
+            session_window: std::u32::MAX,
+            session_window_threshold: std::u32::MAX / 2,
+            max_pending_transfers: None,
+            initial_outgoing_id: session::INITIAL_OUTGOING_ID,
         }
     }
 
@@ -139,10 +252,61 @@ impl Configuration {
         self
     }
 
+    /// Set connection container id
+    ///
+    /// Random uuid is used as container id by default
+    pub fn container_id(&mut self, id: &str) -> &mut Self {
+        self.container_id = Some(ByteString::from(id));
+        self
+    }
+
+    /// Set connection properties
+    ///
+    /// Properties are sent to remote peer with `Open` frame
+    pub fn properties(&mut self, properties: Fields) -> &mut Self {
+        self.properties = Some(properties);
+        self
+    }
+
+    /// Set session incoming window
+    ///
+    /// Window is the number of transfer frames remote peer may send
+    /// before session issues new window. New window is issued once
+    /// remaining window drops to `threshold`.
+    ///
+    /// By default incoming window is not limited
+    pub fn session_window(&mut self, window: u32, threshold: u32) -> &mut Self {
+        self.session_window = window;
+        self.session_window_threshold = std::cmp::min(threshold, window);
+        self
+    }
+
+    /// Set max number of transfers queued while waiting for link credit
+    /// or session window
+    ///
+    /// Once queue is full, sending fails with `AmqpTransportError::Full` error.
+    /// By default queue is not limited
+    pub fn max_pending_transfers(&mut self, max: usize) -> &mut Self {
+        self.max_pending_transfers = Some(max);
+        self
+    }
+
+    /// Set initial outgoing transfer id of new sessions
+    ///
+    /// Transfer and delivery ids are sequence numbers that wrap around `u32::MAX`.
+    /// By default initial id is set to 0
+    pub fn initial_outgoing_id(&mut self, id: TransferNumber) -> &mut Self {
+        self.initial_outgoing_id = id;
+        self
+    }
+
     /// Create `Open` performative for this configuration.
     pub fn to_open(&self) -> Open {
         Open {
-            container_id: ByteString::from(Uuid::new_v4().to_simple().to_string()),
+            container_id: self
+                .container_id
+                .clone()
+                .unwrap_or_else(|| ByteString::from(Uuid::new_v4().to_simple().to_string())),
             hostname: self.hostname.clone(),
             max_frame_size: self.max_frame_size,
             channel_max: self.channel_max as u16,
@@ -151,12 +315,14 @@ impl Configuration {
             incoming_locales: None,
             offered_capabilities: None,
             desired_capabilities: None,
-            properties: None,
+            properties: self.properties.clone(),
         }
     }
 
     pub(crate) fn timeout(&self) -> Option<Duration> {
+        // zero idle time-out means no time-out
         self.idle_time_out
+            .filter(|v| *v > 0)
             .map(|v| Duration::from_millis(((v as f32) * 0.8) as u64))
     }
 }
@@ -168,6 +334,12 @@ impl<'a> From<&'a Open> for Configuration {
             channel_max: open.channel_max as usize,
             idle_time_out: open.idle_time_out,
             hostname: open.hostname.clone(),
+            container_id: Some(open.container_id.clone()),
+            properties: open.properties.clone(),
+            session_window: std::u32::MAX,
+            session_window_threshold: std::u32::MAX / 2,
+            max_pending_transfers: None,
+            initial_outgoing_id: session::INITIAL_OUTGOING_ID,
         }
     }
 }
@@ -0,0 +1,97 @@
+use std::future::Future;
+
+use bytestring::ByteString;
+use ntex_amqp_codec::protocol::{AmqpError, Error};
+use ntex_amqp_codec::{InMessage, OutMessage};
+use uuid::Uuid;
+
+use crate::errors::AmqpTransportError;
+use crate::rpc::RequestReply;
+use crate::session::Session;
+
+/// Client of amqp management node
+///
+/// Requests are sent to management node over sender link, responses are
+/// received from dynamic reply node and matched to requests by correlation id.
+#[derive(Clone, Debug)]
+pub struct ManagementClient {
+    rpc: RequestReply,
+    reply_to: ByteString,
+}
+
+impl ManagementClient {
+    /// Open request and reply links to management node
+    pub(crate) fn open(
+        session: &mut Session,
+        node: ByteString,
+    ) -> impl Future<Output = Result<ManagementClient, AmqpTransportError>> {
+        let name = Uuid::new_v4().to_simple().to_string();
+        let sender = session
+            .build_sender_link(format!("mgmt-{}-sender", name), node.clone())
+            .open();
+        let receiver = session
+            .build_receiver_link(format!("mgmt-{}-receiver", name), node)
+            .dynamic(None)
+            .open();
+
+        async move {
+            let sender = sender.await?;
+            let receiver = receiver.await?;
+            let reply_to = if let Some(addr) = receiver.address() {
+                addr.clone()
+            } else {
+                let _ = receiver.close().await;
+                let _ = sender.close().await;
+                return Err(AmqpTransportError::Protocol(Error {
+                    condition: AmqpError::IllegalState.into(),
+                    description: Some(ByteString::from_static(
+                        "Reply node address is not assigned",
+                    )),
+                    info: None,
+                }));
+            };
+
+            Ok(ManagementClient {
+                rpc: RequestReply::new(sender, receiver),
+                reply_to,
+            })
+        }
+    }
+
+    /// Address of reply node
+    pub fn reply_to(&self) -> &ByteString {
+        &self.reply_to
+    }
+
+    /// Send management request
+    ///
+    /// `operation`, `type` and optional `name` application properties are added
+    /// to the message. Resolves with response message, status of the operation
+    /// is reported with `statusCode` application property of the response.
+    pub fn request<T, U>(
+        &self,
+        operation: T,
+        entity_type: U,
+        name: Option<ByteString>,
+        mut message: OutMessage,
+    ) -> impl Future<Output = Result<InMessage, AmqpTransportError>>
+    where
+        T: Into<ByteString>,
+        U: Into<ByteString>,
+    {
+        message
+            .set_app_property("operation", operation.into())
+            .set_app_property("type", entity_type.into());
+        if let Some(name) = name {
+            message.set_app_property("name", name);
+        }
+        self.rpc.request(message)
+    }
+
+    /// Close request and reply links
+    ///
+    /// Pending requests fail with `AmqpTransportError::Disconnected` error.
+    pub async fn close(&self) -> Result<(), AmqpTransportError> {
+        self.rpc.close().await
+    }
+}
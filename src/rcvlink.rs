@@ -1,23 +1,40 @@
 use std::collections::VecDeque;
 use std::future::Future;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::u32;
 
+use bytes::Bytes;
 use bytestring::ByteString;
 use futures::Stream;
+use fxhash::FxHashSet;
 use ntex::channel::oneshot;
+use ntex::rt::time;
 use ntex::task::LocalWaker;
 use ntex_amqp_codec::protocol::{
-    Attach, DeliveryNumber, Disposition, Error, Handle, LinkError, ReceiverSettleMode, Role,
-    SenderSettleMode, Source, TerminusDurability, TerminusExpiryPolicy, Transfer,
+    Accepted, AmqpError, Attach, DeliveryNumber, DeliveryState, Disposition, Error, Fields,
+    FilterSet, Flow, Handle, LinkError, MessageFormat, Modified, Received, ReceiverSettleMode,
+    Rejected, Released, Role, SenderSettleMode, Source, Symbols, TerminusDurability,
+    TerminusExpiryPolicy, Transfer, TransferBody,
 };
+use ntex_amqp_codec::types::{Descriptor, Symbol, Variant};
+use ntex_amqp_codec::{AmqpCodecError, Decode, InMessage};
 
 use crate::cell::Cell;
 use crate::errors::AmqpTransportError;
-use crate::session::{Session, SessionInner};
+use crate::session::{OpeningLink, Session, SessionInner};
+use crate::sndlink::has_capability;
 use crate::Configuration;
 
+/// Selector filter, apache.org:selector-filter:string
+const SELECTOR_FILTER: &str = "apache.org:selector-filter:string";
+const SELECTOR_FILTER_CODE: u64 = 0x0000_468C_0000_0004;
+
+/// Topic binding filter, apache.org:legacy-amqp-topic-binding:string
+const TOPIC_BINDING_FILTER: &str = "apache.org:legacy-amqp-topic-binding:string";
+const TOPIC_BINDING_FILTER_CODE: u64 = 0x0000_468C_0000_0001;
+
 #[derive(Clone, Debug)]
 pub struct ReceiverLink {
     pub(crate) inner: Cell<ReceiverLinkInner>,
@@ -36,6 +53,19 @@ impl ReceiverLink {
         self.inner.get_ref().credit
     }
 
+    /// Delivery count of the link
+    ///
+    /// Incremented for each received transfer, remote sender could
+    /// advance delivery count with flow frame.
+    pub fn delivery_count(&self) -> u32 {
+        self.inner.get_ref().delivery_count
+    }
+
+    /// Number of messages available at remote sender, as reported by last flow
+    pub fn available(&self) -> u32 {
+        self.inner.get_ref().available
+    }
+
     pub fn session(&self) -> &Session {
         &self.inner.get_ref().session
     }
@@ -48,6 +78,40 @@ impl ReceiverLink {
         &self.inner.get_ref().attach
     }
 
+    /// Capabilities offered by remote peer on attach
+    pub fn remote_offered_capabilities(&self) -> Option<&Symbols> {
+        self.inner.get_ref().remote_offered_capabilities.as_ref()
+    }
+
+    /// Capabilities desired by remote peer on attach
+    pub fn remote_desired_capabilities(&self) -> Option<&Symbols> {
+        self.inner.get_ref().remote_desired_capabilities.as_ref()
+    }
+
+    /// Check if remote peer offers capability
+    pub fn is_capability_offered(&self, cap: &str) -> bool {
+        has_capability(self.remote_offered_capabilities(), cap)
+    }
+
+    /// Source terminus returned by remote peer on attach
+    ///
+    /// Remote peer omits filters it does not support.
+    pub fn remote_source(&self) -> Option<&Source> {
+        self.inner.get_ref().remote_source.as_ref()
+    }
+
+    /// Source address
+    ///
+    /// For dynamic link it is the address assigned by remote peer.
+    pub fn address(&self) -> Option<&ByteString> {
+        self.inner
+            .get_ref()
+            .attach
+            .source
+            .as_ref()
+            .and_then(|source| source.address.as_ref())
+    }
+
     pub fn open(&mut self) {
         let inner = self.inner.get_mut();
         inner
@@ -57,8 +121,80 @@ impl ReceiverLink {
             .confirm_receiver_link(inner.handle, &inner.attach);
     }
 
+    /// Set link credit
+    ///
+    /// Credit is the number of transfers remote peer is allowed to send,
+    /// new value replaces previously issued credit.
     pub fn set_link_credit(&mut self, credit: u32) {
-        self.inner.get_mut().set_link_credit(credit);
+        let inner = self.inner.get_mut();
+        if let Some(ref shared) = inner.shared_credit {
+            // issued credit is accounted in shared budget even if budget is used up
+            if credit > inner.credit {
+                shared.acquire(credit - inner.credit);
+            } else {
+                shared.release(inner.credit - credit);
+            }
+        }
+        inner.set_link_credit(credit);
+    }
+
+    /// Keep link credit topped up to `credit`
+    ///
+    /// More credit is issued as transfers are consumed from the link stream,
+    /// so number of prefetched transfers stays close to `credit`.
+    pub fn set_auto_credit(&mut self, credit: u32) {
+        let inner = self.inner.get_mut();
+        inner.auto_credit = Some(credit);
+        inner.replenish_credit();
+    }
+
+    /// Keep link credit topped up to `credit` from shared credit budget
+    ///
+    /// Works as `set_auto_credit()`, but credit is allocated from budget
+    /// shared with other links. Once budget is used up, link waits until
+    /// transfers of other links get consumed. Outstanding credit of the link
+    /// is accounted in the budget.
+    pub fn set_shared_credit(&mut self, shared: &SharedCredit, credit: u32) {
+        let inner = self.inner.get_mut();
+        inner.release_shared_credit();
+        shared.join(inner.credit.saturating_add(inner.queue.len() as u32));
+        inner.shared_credit = Some(shared.clone());
+        inner.auto_credit = Some(credit);
+        inner.replenish_credit();
+    }
+
+    /// Remember delivery tags of last `window` transfers
+    ///
+    /// Transfer with already seen delivery tag is released and is not
+    /// delivered to the link stream. Zero window disables de-duplication,
+    /// by default de-duplication is disabled.
+    pub fn set_dedup_window(&mut self, window: usize) {
+        let inner = self.inner.get_mut();
+        inner.recent_tags = if window == 0 {
+            None
+        } else {
+            Some(RecentTags::new(window))
+        };
+    }
+
+    /// Take delivery tags remembered by de-duplication
+    pub(crate) fn take_recent_tags(&mut self) -> Option<RecentTags> {
+        self.inner.get_mut().recent_tags.take()
+    }
+
+    /// Continue de-duplication with delivery tags remembered by other link
+    pub(crate) fn set_recent_tags(&mut self, tags: RecentTags) {
+        self.inner.get_mut().recent_tags = Some(tags);
+    }
+
+    /// Settle expired messages on receipt
+    ///
+    /// Message is expired once its `absolute-expiry-time` property has passed,
+    /// or `creation-time` property plus header `ttl` has passed. Expired
+    /// delivery is settled with `Modified` state, marked as undeliverable here,
+    /// and is not delivered to the link stream. By default expiry is not checked.
+    pub fn set_expire_messages(&mut self, expire: bool) {
+        self.inner.get_mut().expire_messages = expire;
     }
 
     /// Send disposition frame
@@ -71,6 +207,101 @@ impl ReceiverLink {
             .post_frame(disp.into());
     }
 
+    /// Settle incoming delivery
+    ///
+    /// Delivery is identified by transfer's `delivery_id`. Deliveries that are
+    /// settled by remote sender do not require disposition and are ignored.
+    pub fn settle_message(&mut self, id: DeliveryNumber, state: DeliveryState) {
+        self.inner.get_mut().settle_message(id, state, None)
+    }
+
+    /// Settle range of incoming deliveries
+    ///
+    /// Range is inclusive, `first` and `last` are transfer's `delivery_id`s.
+    /// Only unsettled deliveries of this link are settled, one disposition
+    /// is sent for each contiguous run of them. Range that spans half of
+    /// delivery id space or more, i.e. reversed range, is ignored.
+    pub fn settle_range(
+        &mut self,
+        first: DeliveryNumber,
+        last: DeliveryNumber,
+        state: DeliveryState,
+    ) {
+        self.inner.get_mut().settle_range(first, last, state, None)
+    }
+
+    /// Accept incoming delivery
+    pub fn accept(&mut self, id: DeliveryNumber) {
+        self.settle_message(id, DeliveryState::Accepted(Accepted {}))
+    }
+
+    /// Reject incoming delivery
+    pub fn reject(&mut self, id: DeliveryNumber, error: Option<Error>) {
+        self.settle_message(id, DeliveryState::Rejected(Rejected { error }))
+    }
+
+    /// Release incoming delivery, remote peer could redeliver it
+    pub fn release(&mut self, id: DeliveryNumber) {
+        self.settle_message(id, DeliveryState::Released(Released {}))
+    }
+
+    /// Settle incoming delivery with modified state
+    pub fn modify(&mut self, id: DeliveryNumber, modified: Modified) {
+        self.settle_message(id, DeliveryState::Modified(modified))
+    }
+
+    /// Settle incoming delivery with modified state
+    ///
+    /// `delivery_failed` asks remote peer to increment delivery count,
+    /// `undeliverable_here` asks not to redeliver message to this link.
+    /// Annotations are merged into message annotations by remote peer.
+    pub fn modify_with(
+        &mut self,
+        id: DeliveryNumber,
+        delivery_failed: bool,
+        undeliverable_here: bool,
+        annotations: Option<Fields>,
+    ) {
+        self.modify(
+            id,
+            Modified {
+                delivery_failed: Some(delivery_failed),
+                undeliverable_here: Some(undeliverable_here),
+                message_annotations: annotations,
+            },
+        )
+    }
+
+    /// Report partially received delivery
+    ///
+    /// Sends unsettled disposition with `Received` state, remote sender
+    /// could use section number and offset for resuming delivery.
+    pub fn report_received(
+        &mut self,
+        id: DeliveryNumber,
+        section_number: u32,
+        section_offset: u64,
+    ) {
+        self.inner
+            .get_mut()
+            .report_received(id, section_number, section_offset)
+    }
+
+    /// Drain link credit
+    ///
+    /// Remote sender sends available messages and uses up the rest of link credit.
+    /// Returned future resolves once remote sender confirms that credit is drained.
+    pub fn drain(&mut self) -> impl Future<Output = Result<(), AmqpTransportError>> {
+        let rx = self.inner.get_mut().drain();
+
+        async move {
+            match rx.await {
+                Ok(res) => res,
+                Err(_) => Err(AmqpTransportError::Disconnected),
+            }
+        }
+    }
+
     /// Wait for disposition with specified number
     pub fn wait_disposition(
         &mut self,
@@ -96,12 +327,32 @@ impl ReceiverLink {
         &self.inner.session.remote_config()
     }
 
+    /// Stream of received deliveries with decoded messages
+    ///
+    /// Stream ends once link is detached and fails if session
+    /// or connection fails.
+    pub fn deliveries(&self) -> Deliveries {
+        Deliveries { link: self.clone() }
+    }
+
+    pub(crate) fn session_failed(&self, err: AmqpTransportError) {
+        trace!("Receiver link session is failed: {:?}", err);
+        let inner = self.inner.get_mut();
+        inner.closed = true;
+        inner.failure = Some(err);
+        inner.release_shared_credit();
+        inner.reader_task.wake();
+        inner.drop_drain_waiters();
+    }
+
     pub(crate) fn remote_closed(&self, error: Option<Error>) {
         trace!("Receiver link has been closed remotely");
         let inner = self.inner.get_mut();
         inner.closed = true;
         inner.error = error;
+        inner.release_shared_credit();
         inner.reader_task.wake();
+        inner.drop_drain_waiters();
     }
 }
 
@@ -112,20 +363,248 @@ impl Stream for ReceiverLink {
         let inner = self.inner.get_mut();
 
         if let Some(tr) = inner.queue.pop_front() {
+            if let Some(ref shared) = inner.shared_credit {
+                shared.release(1);
+            }
+            inner.replenish_credit();
             Poll::Ready(Some(Ok(tr)))
         } else if inner.closed {
-            if let Some(err) = inner.error.take() {
-                Poll::Ready(Some(Err(AmqpTransportError::LinkDetached(Some(err)))))
+            if let Some(err) = inner.failure.take() {
+                Poll::Ready(Some(Err(err)))
+            } else if let Some(err) = inner.error.take() {
+                Poll::Ready(Some(Err(AmqpTransportError::detached(Some(err)))))
             } else {
                 Poll::Ready(None)
             }
         } else {
             inner.reader_task.register(cx.waker());
+            if inner.shared_credit.is_some() {
+                // credit could be returned to shared budget by other links
+                inner.replenish_credit();
+                if inner.credit == 0 {
+                    if let Some(ref shared) = inner.shared_credit {
+                        shared.register_waiter(cx.waker());
+                    }
+                }
+            }
             Poll::Pending
         }
     }
 }
 
+/// Credit budget shared by receiver links
+///
+/// Sum of outstanding link credit and prefetched transfers of all links
+/// using the budget does not exceed budget max, each link gets up to equal
+/// share of the budget. Credit returns to the budget as transfers are consumed
+/// from link streams, links waiting for credit get woken up.
+#[derive(Clone, Debug)]
+pub struct SharedCredit(Cell<SharedCreditInner>);
+
+#[derive(Debug)]
+struct SharedCreditInner {
+    max: u32,
+    used: u32,
+    links: u32,
+    waiters: Vec<Waker>,
+}
+
+impl SharedCredit {
+    /// Create credit budget with max number of outstanding transfers
+    pub fn new(max: u32) -> Self {
+        SharedCredit(Cell::new(SharedCreditInner {
+            max,
+            used: 0,
+            links: 0,
+            waiters: Vec::new(),
+        }))
+    }
+
+    /// Max number of outstanding transfers
+    pub fn max(&self) -> u32 {
+        self.0.get_ref().max
+    }
+
+    /// Credit available for allocation
+    pub fn available(&self) -> u32 {
+        let inner = self.0.get_ref();
+        inner.max.saturating_sub(inner.used)
+    }
+
+    /// Max credit of one link
+    fn share(&self) -> u32 {
+        let inner = self.0.get_ref();
+        std::cmp::max(1, inner.max / std::cmp::max(1, inner.links))
+    }
+
+    /// Link starts using the budget with outstanding credit
+    fn join(&self, credit: u32) {
+        self.0.get_mut().links += 1;
+        self.acquire(credit);
+    }
+
+    /// Link stops using the budget
+    fn leave(&self, credit: u32) {
+        let inner = self.0.get_mut();
+        inner.links = inner.links.saturating_sub(1);
+        self.release(credit);
+    }
+
+    /// Allocate up to `credit` from the budget
+    fn reserve(&self, credit: u32) -> u32 {
+        let credit = std::cmp::min(credit, self.available());
+        self.acquire(credit);
+        credit
+    }
+
+    /// Account credit that is already issued
+    fn acquire(&self, credit: u32) {
+        let inner = self.0.get_mut();
+        inner.used = inner.used.saturating_add(credit);
+    }
+
+    /// Return credit to the budget, wake up links waiting for credit
+    fn release(&self, credit: u32) {
+        let inner = self.0.get_mut();
+        inner.used = inner.used.saturating_sub(credit);
+        for waker in inner.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn register_waiter(&self, waker: &Waker) {
+        let waiters = &mut self.0.get_mut().waiters;
+        if !waiters.iter().any(|w| w.will_wake(waker)) {
+            waiters.push(waker.clone());
+        }
+    }
+}
+
+/// Stream of received deliveries
+///
+/// Link credit is replenished as stream is polled in auto credit mode.
+/// Deliveries with malformed message are rejected and yield `DecodeError`.
+#[derive(Debug)]
+pub struct Deliveries {
+    link: ReceiverLink,
+}
+
+impl Stream for Deliveries {
+    type Item = Result<ReceivedDelivery, AmqpTransportError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match Pin::new(&mut self.link).poll_next(cx) {
+            Poll::Ready(Some(Ok(transfer))) => {
+                let link = self.link.clone();
+                Poll::Ready(Some(ReceivedDelivery::new(link, transfer)))
+            }
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Received delivery
+///
+/// Unsettled delivery must be settled with one of settle methods.
+#[derive(Debug)]
+pub struct ReceivedDelivery {
+    link: ReceiverLink,
+    transfer: Transfer,
+    message: InMessage,
+}
+
+impl ReceivedDelivery {
+    fn new(mut link: ReceiverLink, transfer: Transfer) -> Result<Self, AmqpTransportError> {
+        let message = match transfer.body {
+            Some(TransferBody::Data(ref data)) => match InMessage::decode(data) {
+                Ok((_, msg)) => msg,
+                Err(e) => {
+                    // malformed message could not be delivered to user code
+                    if let Some(id) = transfer.delivery_id {
+                        link.reject(
+                            id,
+                            Some(Error {
+                                condition: AmqpError::DecodeError.into(),
+                                description: Some(ByteString::from(format!("{}", e))),
+                                info: None,
+                            }),
+                        );
+                    }
+                    return Err(AmqpTransportError::DecodeError(AmqpCodecError::from(e)));
+                }
+            },
+            Some(TransferBody::MessageIn(ref msg)) => msg.clone(),
+            _ => InMessage::default(),
+        };
+        Ok(ReceivedDelivery {
+            link,
+            transfer,
+            message,
+        })
+    }
+
+    /// Delivery id
+    pub fn id(&self) -> Option<DeliveryNumber> {
+        self.transfer.delivery_id
+    }
+
+    /// Delivery is settled by remote sender
+    pub fn is_settled(&self) -> bool {
+        self.transfer.settled == Some(true)
+    }
+
+    /// Transfer frame of the delivery
+    pub fn transfer(&self) -> &Transfer {
+        &self.transfer
+    }
+
+    /// Message format of the delivery
+    ///
+    /// `None` means standard amqp message format
+    pub fn message_format(&self) -> Option<MessageFormat> {
+        self.transfer.message_format
+    }
+
+    /// Received message
+    pub fn message(&self) -> &InMessage {
+        &self.message
+    }
+
+    /// Take received message
+    pub fn into_message(self) -> InMessage {
+        self.message
+    }
+
+    /// Settle delivery with provided state
+    pub fn settle(mut self, state: DeliveryState) {
+        if let Some(id) = self.transfer.delivery_id {
+            self.link.settle_message(id, state)
+        }
+    }
+
+    /// Accept delivery
+    pub fn accept(self) {
+        self.settle(DeliveryState::Accepted(Accepted {}))
+    }
+
+    /// Reject delivery
+    pub fn reject(self, error: Option<Error>) {
+        self.settle(DeliveryState::Rejected(Rejected { error }))
+    }
+
+    /// Release delivery, remote peer could redeliver it
+    pub fn release(self) {
+        self.settle(DeliveryState::Released(Released {}))
+    }
+
+    /// Settle delivery with modified state
+    pub fn modify(self, modified: Modified) {
+        self.settle(DeliveryState::Modified(modified))
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct ReceiverLinkInner {
     handle: Handle,
@@ -134,9 +613,60 @@ pub(crate) struct ReceiverLinkInner {
     closed: bool,
     reader_task: LocalWaker,
     queue: VecDeque<Transfer>,
+    unsettled: FxHashSet<DeliveryNumber>,
     credit: u32,
+    auto_credit: Option<u32>,
+    shared_credit: Option<SharedCredit>,
+    available: u32,
+    drain_waiters: Vec<oneshot::Sender<Result<(), AmqpTransportError>>>,
     delivery_count: u32,
+    recent_tags: Option<RecentTags>,
+    expire_messages: bool,
+    remote_offered_capabilities: Option<Symbols>,
+    remote_desired_capabilities: Option<Symbols>,
+    remote_source: Option<Source>,
     error: Option<Error>,
+    failure: Option<AmqpTransportError>,
+}
+
+/// Delivery tags of recently received transfers
+#[derive(Debug)]
+pub(crate) struct RecentTags {
+    max: usize,
+    order: VecDeque<Bytes>,
+    tags: FxHashSet<Bytes>,
+}
+
+impl RecentTags {
+    pub(crate) fn new(max: usize) -> Self {
+        RecentTags {
+            max,
+            order: VecDeque::with_capacity(max),
+            tags: FxHashSet::default(),
+        }
+    }
+
+    /// Remember delivery tag, returns `false` if tag is seen already
+    fn insert(&mut self, tag: &Bytes) -> bool {
+        if self.tags.contains(tag) {
+            return false;
+        }
+        if self.order.len() >= self.max {
+            if let Some(tag) = self.order.pop_front() {
+                self.tags.remove(&tag);
+            }
+        }
+        self.order.push_back(tag.clone());
+        self.tags.insert(tag.clone());
+        true
+    }
+
+    /// Forget delivery tag
+    pub(crate) fn remove(&mut self, tag: &Bytes) {
+        if self.tags.remove(tag) {
+            self.order.retain(|t| t != tag);
+        }
+    }
 }
 
 impl ReceiverLinkInner {
@@ -151,9 +681,21 @@ impl ReceiverLinkInner {
             closed: false,
             reader_task: LocalWaker::new(),
             queue: VecDeque::with_capacity(4),
+            unsettled: FxHashSet::default(),
             credit: 0,
+            auto_credit: None,
+            shared_credit: None,
+            available: 0,
+            drain_waiters: Vec::new(),
             error: None,
+            failure: None,
             delivery_count: attach.initial_delivery_count().unwrap_or(0),
+            recent_tags: None,
+            expire_messages: false,
+            // replaced by remote peer's capabilities for locally opened link
+            remote_offered_capabilities: attach.offered_capabilities.clone(),
+            remote_desired_capabilities: attach.desired_capabilities.clone(),
+            remote_source: attach.source.clone(),
             attach,
         }
     }
@@ -162,10 +704,41 @@ impl ReceiverLinkInner {
     //     &self.attach.name
     // }
 
+    /// Remote peer confirmed locally opened link
+    pub(crate) fn remote_attached(&mut self, attach: &Attach) {
+        self.remote_offered_capabilities = attach.offered_capabilities.clone();
+        self.remote_desired_capabilities = attach.desired_capabilities.clone();
+        self.remote_source = attach.source.clone();
+        if let Some(ref mut source) = self.attach.source {
+            if source.dynamic {
+                source.address = attach
+                    .source
+                    .as_ref()
+                    .and_then(|source| source.address.clone());
+            }
+        }
+    }
+
     pub(crate) fn detached(&mut self) {
         // drop pending transfers
+        self.release_shared_credit();
         self.queue.clear();
         self.closed = true;
+        self.drop_drain_waiters();
+    }
+
+    /// Return outstanding credit and prefetched transfers to shared budget
+    fn release_shared_credit(&mut self) {
+        if let Some(shared) = self.shared_credit.take() {
+            shared.leave(self.credit.saturating_add(self.queue.len() as u32));
+        }
+    }
+
+    fn drop_drain_waiters(&mut self) {
+        let err = AmqpTransportError::detached(self.error.clone());
+        for tx in self.drain_waiters.drain(..) {
+            let _ = tx.send(Err(err.clone()));
+        }
     }
 
     pub(crate) fn close(
@@ -173,6 +746,7 @@ impl ReceiverLinkInner {
         error: Option<Error>,
     ) -> impl Future<Output = Result<(), AmqpTransportError>> {
         let (tx, rx) = oneshot::channel();
+        self.release_shared_credit();
         if self.closed {
             let _ = tx.send(Ok(()));
         } else {
@@ -193,11 +767,292 @@ impl ReceiverLinkInner {
     }
 
     pub(crate) fn set_link_credit(&mut self, credit: u32) {
-        self.credit += credit;
+        self.credit = credit;
         self.session
             .inner
             .get_mut()
-            .rcv_link_flow(self.handle as u32, self.delivery_count, credit);
+            .link_flow(self.handle, self.delivery_count, credit, false);
+    }
+
+    pub(crate) fn drain(&mut self) -> oneshot::Receiver<Result<(), AmqpTransportError>> {
+        let (tx, rx) = oneshot::channel();
+        if self.closed {
+            let _ = tx.send(Err(AmqpTransportError::detached(self.error.clone())));
+        } else if self.credit == 0 {
+            let _ = tx.send(Ok(()));
+        } else {
+            self.drain_waiters.push(tx);
+            self.session.inner.get_mut().link_flow(
+                self.handle,
+                self.delivery_count,
+                self.credit,
+                true,
+            );
+        }
+        rx
+    }
+
+    /// Issue more credit if outstanding credit and queued transfers
+    /// dropped below half of auto credit
+    pub(crate) fn replenish_credit(&mut self) {
+        if let Some(credit) = self.replenished_credit() {
+            self.set_link_credit(credit);
+        }
+    }
+
+    fn replenished_credit(&mut self) -> Option<u32> {
+        let mut target = self.auto_credit?;
+        if let Some(ref shared) = self.shared_credit {
+            target = std::cmp::min(target, shared.share());
+        }
+        let queued = self.queue.len() as u32;
+        if !self.closed
+            && self.drain_waiters.is_empty()
+            && self.credit.saturating_add(queued) <= target / 2
+        {
+            let credit = target.saturating_sub(queued);
+            if let Some(ref shared) = self.shared_credit {
+                // only credit above outstanding credit is taken from the budget
+                let granted = shared.reserve(credit.saturating_sub(self.credit));
+                if granted == 0 {
+                    None
+                } else {
+                    Some(self.credit + granted)
+                }
+            } else {
+                Some(credit)
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Apply link flow received by session
+    ///
+    /// Session is borrowed mutably by the caller, frames are posted
+    /// through provided session instead of link's own session cell.
+    pub(crate) fn apply_flow(&mut self, flow: &Flow, session: &mut SessionInner) {
+        // #2.6.7 sender could advance delivery count, credit limit stays the same
+        if let Some(delivery_count) = flow.delivery_count() {
+            let limit = self.delivery_count.wrapping_add(self.credit);
+            let credit = if (limit.wrapping_sub(delivery_count) as i32) < 0 {
+                0
+            } else {
+                limit.wrapping_sub(delivery_count)
+            };
+            match self.shared_credit {
+                Some(ref shared) if credit < self.credit => shared.release(self.credit - credit),
+                _ => (),
+            }
+            self.credit = credit;
+            self.delivery_count = delivery_count;
+        }
+        if let Some(available) = flow.available() {
+            self.available = available;
+        }
+        trace!(
+            "Apply receiver link flow, credit: {} delivery count: {} available: {}",
+            self.credit,
+            self.delivery_count,
+            self.available
+        );
+
+        // remote sender used up all credit
+        if flow.drain() && self.credit == 0 {
+            for tx in self.drain_waiters.drain(..) {
+                let _ = tx.send(Ok(()));
+            }
+        }
+
+        if flow.echo() {
+            session.link_flow(self.handle, self.delivery_count, self.credit, false);
+        } else if let Some(credit) = self.replenished_credit() {
+            self.credit = credit;
+            session.link_flow(self.handle, self.delivery_count, credit, false);
+        }
+    }
+
+    /// Deliveries settled by remote sender do not require disposition
+    ///
+    /// Delivery ids are sequence numbers, range could wrap around `u32::MAX`.
+    pub(crate) fn remote_settled(&mut self, first: DeliveryNumber, last: DeliveryNumber) {
+        let span = last.wrapping_sub(first);
+        if span >= 1 << 31 {
+            trace!("Invalid settled range {}..={}, skip", first, last);
+            return;
+        }
+        self.unsettled.retain(|id| id.wrapping_sub(first) > span);
+    }
+
+    pub(crate) fn settle_message(
+        &mut self,
+        id: DeliveryNumber,
+        state: DeliveryState,
+        txn_id: Option<Bytes>,
+    ) {
+        if self.unsettled.remove(&id) {
+            let state = if let Some(txn_id) = txn_id {
+                state.into_transactional(txn_id)
+            } else {
+                state
+            };
+            let disp = Disposition {
+                role: Role::Receiver,
+                first: id,
+                last: None,
+                settled: true,
+                state: Some(state),
+                batchable: false,
+            };
+            self.session.inner.get_mut().post_frame(disp.into());
+        } else {
+            trace!("Delivery {} is settled or unknown, skip disposition", id);
+        }
+    }
+
+    pub(crate) fn report_received(
+        &mut self,
+        id: DeliveryNumber,
+        section_number: u32,
+        section_offset: u64,
+    ) {
+        if self.unsettled.contains(&id) {
+            let disp = Disposition {
+                role: Role::Receiver,
+                first: id,
+                last: None,
+                settled: false,
+                state: Some(DeliveryState::Received(Received {
+                    section_number,
+                    section_offset,
+                })),
+                batchable: false,
+            };
+            self.session.inner.get_mut().post_frame(disp.into());
+        } else {
+            trace!("Delivery {} is settled or unknown, skip received state", id);
+        }
+    }
+
+    pub(crate) fn settle_range(
+        &mut self,
+        first: DeliveryNumber,
+        last: DeliveryNumber,
+        state: DeliveryState,
+        txn_id: Option<Bytes>,
+    ) {
+        // delivery ids are serial numbers
+        let span = last.wrapping_sub(first);
+        if span >= 1 << 31 {
+            trace!("Invalid settle range {}..={}, skip", first, last);
+            return;
+        }
+        let state = if let Some(txn_id) = txn_id {
+            state.into_transactional(txn_id)
+        } else {
+            state
+        };
+
+        // delivery ids are assigned per session, range could include
+        // deliveries of other links
+        let mut ids: Vec<_> = self
+            .unsettled
+            .iter()
+            .copied()
+            .filter(|id| id.wrapping_sub(first) <= span)
+            .collect();
+        if ids.is_empty() {
+            trace!(
+                "Deliveries {}-{} are settled or unknown, skip disposition",
+                first,
+                last
+            );
+            return;
+        }
+        ids.sort_by_key(|id| id.wrapping_sub(first));
+        for id in &ids {
+            self.unsettled.remove(id);
+        }
+
+        // one disposition per contiguous run of link's deliveries
+        let mut start = ids[0];
+        let mut end = ids[0];
+        for id in ids.into_iter().skip(1) {
+            if id == end.wrapping_add(1) {
+                end = id;
+            } else {
+                self.post_settled_range(start, end, state.clone());
+                start = id;
+                end = id;
+            }
+        }
+        self.post_settled_range(start, end, state);
+    }
+
+    fn post_settled_range(
+        &mut self,
+        first: DeliveryNumber,
+        last: DeliveryNumber,
+        state: DeliveryState,
+    ) {
+        let disp = Disposition {
+            role: Role::Receiver,
+            first,
+            last: if first == last { None } else { Some(last) },
+            settled: true,
+            state: Some(state),
+            batchable: false,
+        };
+        self.session.inner.get_mut().post_frame(disp.into());
+    }
+
+    /// Transfer received before link is confirmed locally
+    ///
+    /// Transfer is queued without credit check and is delivered
+    /// once link is opened.
+    pub(crate) fn handle_early_transfer(&mut self, transfer: Transfer) {
+        self.delivery_count = self.delivery_count.wrapping_add(1);
+        if transfer.settled != Some(true) {
+            if let Some(id) = transfer.delivery_id {
+                self.unsettled.insert(id);
+            }
+        }
+        self.queue.push_back(transfer);
+    }
+
+    /// Drop transfers queued before link is confirmed,
+    /// returns ids of unsettled deliveries
+    pub(crate) fn drop_early_transfers(&mut self) -> Vec<DeliveryNumber> {
+        self.queue.clear();
+        self.unsettled.drain().collect()
+    }
+
+    fn is_duplicate(&mut self, transfer: &Transfer) -> bool {
+        match (self.recent_tags.as_mut(), transfer.delivery_tag.as_ref()) {
+            (Some(recent), Some(tag)) => !recent.insert(tag),
+            _ => false,
+        }
+    }
+
+    /// Settle transfer that is not delivered to the link stream
+    fn discard(&mut self, transfer: &Transfer, state: DeliveryState) {
+        if let Some(ref shared) = self.shared_credit {
+            shared.release(1);
+        }
+        if transfer.settled != Some(true) {
+            if let Some(id) = transfer.delivery_id {
+                let disp = Disposition {
+                    role: Role::Receiver,
+                    first: id,
+                    last: None,
+                    settled: true,
+                    state: Some(state),
+                    batchable: false,
+                };
+                self.session.inner.get_mut().post_frame(disp.into());
+            }
+        }
+        self.replenish_credit();
     }
 
     pub(crate) fn handle_transfer(&mut self, transfer: Transfer) {
@@ -211,7 +1066,38 @@ impl ReceiverLinkInner {
             let _ = self.close(Some(err));
         } else {
             self.credit -= 1;
-            self.delivery_count += 1;
+            self.delivery_count = self.delivery_count.wrapping_add(1);
+
+            if self.is_duplicate(&transfer) {
+                trace!(
+                    "Duplicate delivery {:?} on link {:?}, releasing",
+                    transfer.delivery_tag,
+                    self.attach.name
+                );
+                self.discard(&transfer, DeliveryState::Released(Released {}));
+                return;
+            }
+            if self.expire_messages && is_expired(&transfer) {
+                trace!(
+                    "Expired delivery {:?} on link {:?}, settling",
+                    transfer.delivery_tag,
+                    self.attach.name
+                );
+                let modified = Modified {
+                    delivery_failed: Some(false),
+                    undeliverable_here: Some(true),
+                    message_annotations: None,
+                };
+                self.discard(&transfer, DeliveryState::Modified(modified));
+                return;
+            }
+
+            // pre-settled deliveries do not require disposition
+            if transfer.settled != Some(true) {
+                if let Some(id) = transfer.delivery_id {
+                    self.unsettled.insert(id);
+                }
+            }
             self.queue.push_back(transfer);
             if self.queue.len() == 1 {
                 self.reader_task.wake()
@@ -220,9 +1106,50 @@ impl ReceiverLinkInner {
     }
 }
 
+/// Message expiry time has passed
+fn is_expired(transfer: &Transfer) -> bool {
+    let decoded;
+    let message = match transfer.body {
+        Some(TransferBody::Data(ref data)) => match InMessage::decode(data) {
+            Ok((_, msg)) => {
+                decoded = msg;
+                &decoded
+            }
+            Err(_) => return false,
+        },
+        Some(TransferBody::MessageIn(ref msg)) => msg,
+        _ => return false,
+    };
+    let props = if let Some(props) = message.properties() {
+        props
+    } else {
+        return false;
+    };
+
+    let expiry = props
+        .absolute_expiry_time()
+        .map(|t| t.timestamp_millis())
+        .or_else(|| {
+            let ttl = message.header().and_then(|h| h.ttl)?;
+            props
+                .creation_time()
+                .map(|t| t.timestamp_millis() + i64::from(ttl))
+        });
+    if let Some(expiry) = expiry {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        expiry <= now
+    } else {
+        false
+    }
+}
+
 pub struct ReceiverLinkBuilder {
     frame: Attach,
     session: Cell<SessionInner>,
+    timeout: Option<Duration>,
 }
 
 impl ReceiverLinkBuilder {
@@ -257,7 +1184,11 @@ impl ReceiverLinkBuilder {
             properties: None,
         };
 
-        ReceiverLinkBuilder { frame, session }
+        ReceiverLinkBuilder {
+            frame,
+            session,
+            timeout: None,
+        }
     }
 
     pub fn max_message_size(mut self, size: u64) -> Self {
@@ -265,13 +1196,155 @@ impl ReceiverLinkBuilder {
         self
     }
 
+    /// Set source terminus durability
+    ///
+    /// By default durability is set to `TerminusDurability::None`
+    pub fn durable(mut self, durability: TerminusDurability) -> Self {
+        if let Some(ref mut source) = self.frame.source {
+            source.durable = durability;
+        }
+        self
+    }
+
+    /// Set source terminus expiry policy
+    ///
+    /// By default expiry policy is set to `TerminusExpiryPolicy::SessionEnd`
+    pub fn expiry_policy(mut self, policy: TerminusExpiryPolicy) -> Self {
+        if let Some(ref mut source) = self.frame.source {
+            source.expiry_policy = policy;
+        }
+        self
+    }
+
+    /// Set source terminus capabilities
+    ///
+    /// For example `shared` and `global` capabilities for shared subscriptions.
+    pub fn source_capabilities(mut self, caps: Symbols) -> Self {
+        if let Some(ref mut source) = self.frame.source {
+            source.capabilities = Some(caps);
+        }
+        self
+    }
+
+    /// Add source filter
+    ///
+    /// `key` is the name of filter in filter set, `value` is
+    /// usually a described filter.
+    pub fn filter(mut self, key: Symbol, value: Option<Variant>) -> Self {
+        if let Some(ref mut source) = self.frame.source {
+            source
+                .filter
+                .get_or_insert_with(FilterSet::default)
+                .insert(key, value);
+        }
+        self
+    }
+
+    /// Add `apache.org:selector-filter:string` source filter
+    pub fn selector<T: Into<ByteString>>(self, selector: T) -> Self {
+        self.filter(
+            Symbol::from_static(SELECTOR_FILTER),
+            Some(Variant::Described((
+                Descriptor::Ulong(SELECTOR_FILTER_CODE),
+                Box::new(Variant::from(selector.into())),
+            ))),
+        )
+    }
+
+    /// Add `apache.org:legacy-amqp-topic-binding:string` source filter
+    pub fn topic_binding<T: Into<ByteString>>(self, pattern: T) -> Self {
+        self.filter(
+            Symbol::from_static(TOPIC_BINDING_FILTER),
+            Some(Variant::Described((
+                Descriptor::Ulong(TOPIC_BINDING_FILTER_CODE),
+                Box::new(Variant::from(pattern.into())),
+            ))),
+        )
+    }
+
+    /// Set capabilities offered by this link
+    pub fn offered_capabilities(mut self, caps: Symbols) -> Self {
+        self.frame.offered_capabilities = Some(caps);
+        self
+    }
+
+    /// Set capabilities desired from remote peer
+    pub fn desired_capabilities(mut self, caps: Symbols) -> Self {
+        self.frame.desired_capabilities = Some(caps);
+        self
+    }
+
+    /// Set unsettled deliveries for link resume
+    ///
+    /// Maps delivery tag to the last known delivery state, unsettled map
+    /// is sent to remote peer with `Attach` frame.
+    pub fn unsettled<I>(mut self, deliveries: I) -> Self
+    where
+        I: IntoIterator<Item = (Bytes, Option<DeliveryState>)>,
+    {
+        let map = deliveries
+            .into_iter()
+            .map(|(tag, state)| {
+                let state = state.map_or(Variant::Null, |state| state.to_variant());
+                (Variant::Binary(tag), state)
+            })
+            .collect();
+        self.frame.unsettled = Some(map);
+        self
+    }
+
+    /// Set link open timeout
+    ///
+    /// If remote peer does not confirm link in time, link get detached
+    /// and `open()` fails with `AmqpTransportError::Timeout` error.
+    /// By default timeout is not set.
+    pub fn open_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Replace source terminus
+    pub(crate) fn source(mut self, source: Source) -> Self {
+        self.frame.source = Some(source);
+        self
+    }
+
+    /// Request dynamic source node
+    ///
+    /// Remote peer creates node and assigns its address,
+    /// address is available via `ReceiverLink::address()`.
+    pub fn dynamic(mut self, properties: Option<Fields>) -> Self {
+        if let Some(ref mut source) = self.frame.source {
+            source.address = None;
+            source.dynamic = true;
+            source.dynamic_node_properties = properties;
+        }
+        self
+    }
+
     pub async fn open(self) -> Result<ReceiverLink, AmqpTransportError> {
         let cell = self.session.clone();
-        let res = self
+        let name = self.frame.name.clone();
+        let fut = self
             .session
             .get_mut()
-            .open_local_receiver_link(cell, self.frame)
-            .await;
+            .open_local_receiver_link(cell, self.frame);
+        let guard = OpeningLink::new(self.session.clone(), name.clone());
+
+        let res = if let Some(timeout) = self.timeout {
+            match time::timeout(timeout, fut).await {
+                Ok(res) => res,
+                Err(_) => {
+                    self.session
+                        .get_mut()
+                        .cancel_opening_link(&name, &self.session, timeout);
+                    return Err(AmqpTransportError::Timeout);
+                }
+            }
+        } else {
+            fut.await
+        };
+        guard.completed();
 
         match res {
             Ok(Ok(res)) => Ok(res),
@@ -0,0 +1,704 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use bytes::Bytes;
+use bytestring::ByteString;
+use futures::StreamExt;
+use fxhash::{FxHashMap, FxHashSet};
+use ntex::channel::oneshot;
+use ntex::codec::{AsyncRead, AsyncWrite};
+use ntex::rt::time::delay_for;
+use ntex_amqp_codec::protocol::{
+    Accepted, DeliveryNumber, DeliveryState, Disposition, Source, Transfer, TransferBody,
+};
+use uuid::Uuid;
+
+use crate::cell::Cell;
+use crate::connection::{Connection, ConnectionController};
+use crate::errors::AmqpTransportError;
+use crate::rcvlink::{ReceiverLink, RecentTags};
+use crate::session::Session;
+use crate::sndlink::SenderLink;
+
+type Establish = Pin<Box<dyn Future<Output = Result<Established, AmqpTransportError>>>>;
+
+/// Reconnect policy
+///
+/// Delay between reconnect attempts doubles after each failed attempt,
+/// up to max backoff.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    backoff: Duration,
+    max_backoff: Duration,
+    max_attempts: Option<usize>,
+    retry_sends: bool,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReconnectPolicy {
+    /// Create reconnect policy
+    ///
+    /// By default initial backoff is set to 100 milliseconds, max backoff
+    /// to 10 seconds, number of attempts is not limited and in-flight
+    /// sends fail on connection loss.
+    pub fn new() -> Self {
+        ReconnectPolicy {
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_attempts: None,
+            retry_sends: false,
+        }
+    }
+
+    /// Set delay before first reconnect attempt and max delay
+    pub fn backoff(mut self, backoff: Duration, max_backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self.max_backoff = std::cmp::max(backoff, max_backoff);
+        self
+    }
+
+    /// Set max number of consecutive failed connect attempts
+    ///
+    /// Once attempts are exhausted, connection fails permanently.
+    pub fn max_attempts(mut self, attempts: usize) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Re-send in-flight messages after reconnect
+    ///
+    /// Message that is unsettled on remote peer is re-sent with `resume`
+    /// flag. Otherwise sends fail with connection error.
+    pub fn retry_sends(mut self, retry: bool) -> Self {
+        self.retry_sends = retry;
+        self
+    }
+
+    fn delay(&self, attempt: usize) -> Duration {
+        let mut delay = self.backoff;
+        for _ in 0..attempt {
+            delay = std::cmp::min(delay * 2, self.max_backoff);
+            if delay == self.max_backoff {
+                break;
+            }
+        }
+        delay
+    }
+}
+
+/// Client connection that re-establishes itself on connection loss
+///
+/// Connection is opened by connect function, i.e. tcp/tls connect,
+/// sasl handshake and `Open` exchange. One session is begun over each
+/// connection, sender and receiver links are re-attached to new session
+/// with their unsettled deliveries. Connection keeps reconnecting until it is closed
+/// with `close()` or reconnect attempts are exhausted.
+#[derive(Clone)]
+pub struct ReconnectingConnection {
+    inner: Cell<ReconnectInner>,
+}
+
+struct ReconnectInner {
+    policy: ReconnectPolicy,
+    session: Option<Session>,
+    controller: Option<ConnectionController>,
+    waiters: Vec<oneshot::Sender<()>>,
+    error: Option<AmqpTransportError>,
+    closed: bool,
+}
+
+struct Established {
+    session: Session,
+    controller: ConnectionController,
+    disconnected: oneshot::Receiver<()>,
+}
+
+impl std::fmt::Debug for ReconnectingConnection {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("ReconnectingConnection")
+            .field("policy", &self.inner.policy)
+            .field("connected", &self.is_connected())
+            .finish()
+    }
+}
+
+impl ReconnectInner {
+    fn notify(&mut self) {
+        for tx in self.waiters.drain(..) {
+            let _ = tx.send(());
+        }
+    }
+
+    /// Established session, if connection is alive
+    fn session(&self) -> Option<Session> {
+        self.session
+            .as_ref()
+            .filter(|session| !session.is_ended())
+            .cloned()
+    }
+}
+
+impl ReconnectingConnection {
+    /// Create connection and start connecting in background
+    ///
+    /// `connect` is called for initial connection and for each reconnect attempt.
+    pub fn new<F, Fut, Io, E>(policy: ReconnectPolicy, connect: F) -> Self
+    where
+        F: Fn() -> Fut + 'static,
+        Fut: Future<Output = Result<Connection<Io>, E>> + 'static,
+        Io: AsyncRead + AsyncWrite + Unpin + 'static,
+        E: std::fmt::Debug + 'static,
+    {
+        let inner = Cell::new(ReconnectInner {
+            policy,
+            session: None,
+            controller: None,
+            waiters: Vec::new(),
+            error: None,
+            closed: false,
+        });
+        let establish = move || -> Establish { Box::pin(establish(connect())) };
+        ntex::rt::spawn(run(inner.clone(), establish));
+
+        ReconnectingConnection { inner }
+    }
+
+    /// Connection is established
+    pub fn is_connected(&self) -> bool {
+        self.inner.session().is_some()
+    }
+
+    /// Wait until connection is established
+    ///
+    /// Fails if reconnect attempts are exhausted or connection is closed.
+    pub async fn connected(&self) -> Result<Session, AmqpTransportError> {
+        loop {
+            let rx = {
+                let inner = self.inner.get_mut();
+                if let Some(ref err) = inner.error {
+                    return Err(err.clone());
+                }
+                if inner.closed {
+                    return Err(AmqpTransportError::Disconnected);
+                }
+                if let Some(session) = inner.session() {
+                    return Ok(session);
+                }
+                let (tx, rx) = oneshot::channel();
+                inner.waiters.push(tx);
+                rx
+            };
+            let _ = rx.await;
+        }
+    }
+
+    /// Sender link that is re-attached after reconnect
+    ///
+    /// Link is attached on first send.
+    pub fn sender_link<T: Into<ByteString>, U: Into<ByteString>>(
+        &self,
+        name: U,
+        address: T,
+    ) -> ReconnectingSender {
+        ReconnectingSender {
+            inner: Cell::new(ReconnectingSenderInner {
+                name: name.into(),
+                address: address.into(),
+                connection: self.clone(),
+                link: None,
+                attaching: false,
+                unsettled: FxHashSet::default(),
+            }),
+        }
+    }
+
+    /// Receiver link that is re-attached after reconnect
+    ///
+    /// Link is attached on first receive, link credit is replenished
+    /// automatically up to `credit`.
+    pub fn receiver_link<T: Into<ByteString>, U: Into<ByteString>>(
+        &self,
+        name: U,
+        address: T,
+        credit: u32,
+    ) -> ReconnectingReceiver {
+        ReconnectingReceiver {
+            inner: Cell::new(ReconnectingReceiverInner {
+                name: name.into(),
+                address: address.into(),
+                credit,
+                connection: self.clone(),
+                link: None,
+                source: None,
+                attaching: false,
+                unsettled: FxHashMap::default(),
+                dedup_window: 0,
+                recent_tags: None,
+            }),
+        }
+    }
+
+    /// Close connection and stop reconnecting
+    ///
+    /// Pending sends fail with `AmqpTransportError::Disconnected` error.
+    pub fn close(&self, timeout: Duration) -> impl Future<Output = Result<(), AmqpTransportError>> {
+        let inner = self.inner.get_mut();
+        inner.closed = true;
+        inner.notify();
+        let controller = inner.controller.take();
+
+        async move {
+            if let Some(controller) = controller {
+                controller.close(None, timeout).await
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Open connection and begin session, connection is driven by spawned task
+async fn establish<Fut, Io, E>(connect: Fut) -> Result<Established, AmqpTransportError>
+where
+    Fut: Future<Output = Result<Connection<Io>, E>>,
+    Io: AsyncRead + AsyncWrite + Unpin + 'static,
+    E: std::fmt::Debug,
+{
+    let mut conn = connect.await.map_err(|e| {
+        trace!("Cannot connect: {:?}", e);
+        AmqpTransportError::Disconnected
+    })?;
+    let controller = conn.controller();
+    let session = conn.open_session();
+
+    let (tx, disconnected) = oneshot::channel();
+    ntex::rt::spawn(async move {
+        if let Err(e) = conn.await {
+            trace!("Connection is failed: {:?}", e);
+        }
+        let _ = tx.send(());
+    });
+
+    Ok(Established {
+        session: session.await?,
+        controller,
+        disconnected,
+    })
+}
+
+async fn run<F>(inner: Cell<ReconnectInner>, establish: F)
+where
+    F: Fn() -> Establish,
+{
+    let mut attempt = 0;
+    loop {
+        match establish().await {
+            Ok(established) => {
+                attempt = 0;
+                trace!("Connection is established");
+                {
+                    let inner = inner.get_mut();
+                    if inner.closed {
+                        // connection is closed while connecting
+                        let close = established.controller.close(None, Duration::from_secs(1));
+                        ntex::rt::spawn(async move {
+                            let _ = close.await;
+                        });
+                        break;
+                    }
+                    inner.session = Some(established.session);
+                    inner.controller = Some(established.controller);
+                    inner.notify();
+                }
+
+                let _ = established.disconnected.await;
+                let inner = inner.get_mut();
+                inner.session = None;
+                inner.controller = None;
+                inner.notify();
+                if inner.closed {
+                    break;
+                }
+                trace!("Connection is lost, reconnecting");
+            }
+            Err(err) => {
+                attempt += 1;
+                let inner = inner.get_mut();
+                if inner.closed {
+                    break;
+                }
+                if matches!(inner.policy.max_attempts, Some(max) if attempt >= max) {
+                    trace!("Reconnect attempts are exhausted: {:?}", err);
+                    inner.error = Some(err);
+                    inner.notify();
+                    break;
+                }
+            }
+        }
+
+        let delay = inner.policy.delay(attempt.saturating_sub(1));
+        delay_for(delay).await;
+        if inner.closed {
+            break;
+        }
+    }
+}
+
+/// Sender link of reconnecting connection
+///
+/// Link is re-attached after reconnect, deliveries in-flight during
+/// connection loss are reported to new link with attach unsettled map.
+#[derive(Clone)]
+pub struct ReconnectingSender {
+    inner: Cell<ReconnectingSenderInner>,
+}
+
+struct ReconnectingSenderInner {
+    name: ByteString,
+    address: ByteString,
+    connection: ReconnectingConnection,
+    link: Option<SenderLink>,
+    attaching: bool,
+    unsettled: FxHashSet<Bytes>,
+}
+
+impl std::fmt::Debug for ReconnectingSender {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("ReconnectingSender")
+            .field("name", &self.inner.name)
+            .field("address", &self.inner.address)
+            .finish()
+    }
+}
+
+impl ReconnectingSender {
+    /// Link name
+    pub fn name(&self) -> &ByteString {
+        &self.inner.name
+    }
+
+    /// Currently attached link
+    pub fn link(&self) -> Option<SenderLink> {
+        self.inner
+            .link
+            .as_ref()
+            .filter(|link| link.inner.get_ref().is_opened())
+            .cloned()
+    }
+
+    /// Send message
+    ///
+    /// Waits until link is attached. If connection is lost before delivery
+    /// is settled, message is re-sent over new link if reconnect policy
+    /// allows it, otherwise delivery fails with connection error.
+    pub fn send<T>(&self, body: T) -> impl Future<Output = Result<Disposition, AmqpTransportError>>
+    where
+        T: Into<TransferBody>,
+    {
+        let body = body.into();
+        let tag = Bytes::copy_from_slice(Uuid::new_v4().as_bytes());
+        let sender = self.clone();
+
+        async move {
+            loop {
+                let link = sender.attached().await?;
+                sender.inner.get_mut().unsettled.insert(tag.clone());
+                let res = link.resume(body.clone(), tag.clone()).await;
+
+                match res {
+                    Err(ref err)
+                        if is_connection_error(err, link.session())
+                            && sender.inner.connection.inner.policy.retry_sends =>
+                    {
+                        trace!("Connection lost during send, retry after reconnect");
+                    }
+                    res => {
+                        sender.inner.get_mut().unsettled.remove(&tag);
+                        return res;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wait for attached link, attach link if needed
+    async fn attached(&self) -> Result<SenderLink, AmqpTransportError> {
+        loop {
+            if let Some(link) = self.link() {
+                return Ok(link);
+            }
+
+            let connection = self.inner.connection.inner.get_mut();
+            if let Some(ref err) = connection.error {
+                return Err(err.clone());
+            }
+            if connection.closed {
+                return Err(AmqpTransportError::Disconnected);
+            }
+
+            match connection.session() {
+                Some(mut session) if !self.inner.attaching => {
+                    // deliveries unsettled during connection loss
+                    let inner = self.inner.get_mut();
+                    let unsettled: Vec<_> = inner
+                        .unsettled
+                        .iter()
+                        .map(|tag| (tag.clone(), None))
+                        .collect();
+                    let builder =
+                        session.build_sender_link(inner.name.clone(), inner.address.clone());
+                    let builder = if unsettled.is_empty() {
+                        builder
+                    } else {
+                        builder.unsettled(unsettled)
+                    };
+
+                    inner.attaching = true;
+                    let res = builder.open().await;
+                    let inner = self.inner.get_mut();
+                    inner.attaching = false;
+                    self.inner.connection.inner.get_mut().notify();
+
+                    match res {
+                        Ok(link) => inner.link = Some(link),
+                        Err(err) if is_connection_error(&err, &session) => continue,
+                        Err(err) => return Err(err),
+                    }
+                }
+                _ => {
+                    // wait for reconnect or for concurrent attach
+                    let (tx, rx) = oneshot::channel();
+                    connection.waiters.push(tx);
+                    let _ = rx.await;
+                }
+            }
+        }
+    }
+}
+
+/// Receiver link of reconnecting connection
+///
+/// Link is re-attached after reconnect with the same source, deliveries
+/// that are received but not settled during connection loss are reported
+/// to new link with attach unsettled map.
+#[derive(Clone)]
+pub struct ReconnectingReceiver {
+    inner: Cell<ReconnectingReceiverInner>,
+}
+
+struct ReconnectingReceiverInner {
+    name: ByteString,
+    address: ByteString,
+    credit: u32,
+    connection: ReconnectingConnection,
+    link: Option<ReceiverLink>,
+    source: Option<Source>,
+    attaching: bool,
+    // delivery tag to delivery id on currently attached link
+    unsettled: FxHashMap<Bytes, Option<DeliveryNumber>>,
+    dedup_window: usize,
+    // delivery tags remembered by lost link
+    recent_tags: Option<RecentTags>,
+}
+
+impl ReconnectingReceiverInner {
+    /// Forget lost link, keep its de-duplication state
+    fn drop_link(&mut self) {
+        if let Some(mut link) = self.link.take() {
+            if let Some(tags) = link.take_recent_tags() {
+                self.recent_tags = Some(tags);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for ReconnectingReceiver {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("ReconnectingReceiver")
+            .field("name", &self.inner.name)
+            .field("address", &self.inner.address)
+            .finish()
+    }
+}
+
+impl ReconnectingReceiver {
+    /// Link name
+    pub fn name(&self) -> &ByteString {
+        &self.inner.name
+    }
+
+    /// Currently attached link
+    pub fn link(&self) -> Option<ReceiverLink> {
+        self.inner
+            .link
+            .as_ref()
+            .filter(|link| !link.session().is_ended())
+            .cloned()
+    }
+
+    /// Remember delivery tags of last `window` transfers
+    ///
+    /// Same as `ReceiverLink::set_dedup_window()`, but remembered tags
+    /// are carried over to re-attached link, so transfers redelivered by
+    /// remote peer after reconnect are released. Tags of unsettled
+    /// deliveries are forgotten, these deliveries are resumed.
+    pub fn set_dedup_window(&self, window: usize) {
+        let inner = self.inner.get_mut();
+        inner.dedup_window = window;
+        inner.recent_tags = None;
+        if let Some(link) = inner.link.as_mut() {
+            link.set_dedup_window(window);
+        }
+    }
+
+    /// Receive transfer
+    ///
+    /// Waits until link is attached. Connection loss is not reported,
+    /// link is re-attached once connection is re-established.
+    pub async fn recv(&self) -> Result<Transfer, AmqpTransportError> {
+        loop {
+            let mut link = self.attached().await?;
+            match link.next().await {
+                Some(Ok(transfer)) => {
+                    if transfer.settled != Some(true) {
+                        if let Some(ref tag) = transfer.delivery_tag {
+                            self.inner
+                                .get_mut()
+                                .unsettled
+                                .insert(tag.clone(), transfer.delivery_id);
+                        }
+                    }
+                    return Ok(transfer);
+                }
+                Some(Err(ref err)) if is_connection_error(err, link.session()) => {
+                    trace!("Connection lost during receive, wait for reconnect");
+                }
+                Some(Err(err)) => return Err(err),
+                None if link.session().is_ended() => (),
+                None => return Err(AmqpTransportError::LinkDetached(None)),
+            }
+            self.inner.get_mut().drop_link();
+        }
+    }
+
+    /// Settle received transfer
+    ///
+    /// Transfers received over link that is lost are forgotten, remote peer
+    /// re-sends them over new link as reported by attach unsettled map.
+    pub fn settle(&self, transfer: &Transfer, state: DeliveryState) {
+        let inner = self.inner.get_mut();
+        let id = transfer
+            .delivery_tag
+            .as_ref()
+            .and_then(|tag| inner.unsettled.remove(tag))
+            .flatten();
+        if let (Some(id), Some(link)) = (id, inner.link.as_mut()) {
+            link.settle_message(id, state);
+        }
+    }
+
+    /// Accept received transfer
+    pub fn accept(&self, transfer: &Transfer) {
+        self.settle(transfer, DeliveryState::Accepted(Accepted {}))
+    }
+
+    /// Wait for attached link, attach link if needed
+    async fn attached(&self) -> Result<ReceiverLink, AmqpTransportError> {
+        loop {
+            if let Some(link) = self.link() {
+                return Ok(link);
+            }
+
+            let connection = self.inner.connection.inner.get_mut();
+            if let Some(ref err) = connection.error {
+                return Err(err.clone());
+            }
+            if connection.closed {
+                return Err(AmqpTransportError::Disconnected);
+            }
+
+            match connection.session() {
+                Some(mut session) if !self.inner.attaching => {
+                    // deliveries unsettled during connection loss,
+                    // delivery ids of lost link are not valid anymore
+                    let inner = self.inner.get_mut();
+                    inner.drop_link();
+                    let unsettled: Vec<_> = inner
+                        .unsettled
+                        .iter_mut()
+                        .map(|(tag, id)| {
+                            *id = None;
+                            (tag.clone(), None)
+                        })
+                        .collect();
+                    let mut builder =
+                        session.build_receiver_link(inner.name.clone(), inner.address.clone());
+                    if let Some(ref source) = inner.source {
+                        builder = builder.source(source.clone());
+                    }
+                    if !unsettled.is_empty() {
+                        builder = builder.unsettled(unsettled);
+                    }
+
+                    inner.attaching = true;
+                    let res = builder.open().await;
+                    let inner = self.inner.get_mut();
+                    inner.attaching = false;
+                    self.inner.connection.inner.get_mut().notify();
+
+                    match res {
+                        Ok(mut link) => {
+                            if inner.dedup_window != 0 {
+                                if let Some(mut tags) = inner.recent_tags.take() {
+                                    for tag in inner.unsettled.keys() {
+                                        tags.remove(tag);
+                                    }
+                                    link.set_recent_tags(tags);
+                                } else {
+                                    link.set_dedup_window(inner.dedup_window);
+                                }
+                            }
+                            link.set_auto_credit(inner.credit);
+                            inner.source = link.frame().source.clone();
+                            inner.link = Some(link);
+                        }
+                        Err(err) if is_connection_error(&err, &session) => continue,
+                        Err(err) => return Err(err),
+                    }
+                }
+                _ => {
+                    // wait for reconnect or for concurrent attach
+                    let (tx, rx) = oneshot::channel();
+                    connection.waiters.push(tx);
+                    let _ = rx.await;
+                }
+            }
+        }
+    }
+}
+
+/// Error is caused by connection loss or by session end
+///
+/// `Remote` error is reported for both `Detach` and `End` frames,
+/// it is caused by session end only if `session` is ended.
+fn is_connection_error(err: &AmqpTransportError, session: &Session) -> bool {
+    match err {
+        AmqpTransportError::Remote { .. } => session.is_ended(),
+        err => matches!(
+            err,
+            AmqpTransportError::Disconnected
+                | AmqpTransportError::IdleTimeout
+                | AmqpTransportError::Codec(_)
+                | AmqpTransportError::DecodeError(_)
+                | AmqpTransportError::Closed(_)
+                | AmqpTransportError::SessionEnded(_)
+                | AmqpTransportError::SessionRemoteEnded(_)
+        ),
+    }
+}
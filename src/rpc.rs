@@ -0,0 +1,236 @@
+use std::future::Future;
+use std::time::Duration;
+
+use bytestring::ByteString;
+use futures::future::{err, Either};
+use futures::StreamExt;
+use ntex::channel::oneshot;
+use ntex::rt::time;
+use ntex_amqp_codec::protocol::{AmqpError, Error, MessageId};
+use ntex_amqp_codec::{InMessage, OutMessage};
+use uuid::Uuid;
+
+use crate::cell::{Cell, WeakCell};
+use crate::errors::AmqpTransportError;
+use crate::rcvlink::{Deliveries, ReceiverLink};
+use crate::sndlink::SenderLink;
+
+/// Number of responses prefetched by reply link
+const REPLY_CREDIT: u32 = 16;
+
+/// Default response timeout
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Request-reply over pair of sender and receiver links
+///
+/// Requests are sent over sender link with `reply-to` property set to
+/// receiver link address. Responses are received from receiver link and
+/// matched to requests by `correlation-id` property, remote peer must
+/// set response `correlation-id` to `message-id` of the request.
+#[derive(Clone)]
+pub struct RequestReply {
+    inner: Cell<RequestReplyInner>,
+}
+
+struct RequestReplyInner {
+    sender: SenderLink,
+    receiver: ReceiverLink,
+    reply_to: Option<ByteString>,
+    timeout: Duration,
+    closed: bool,
+    pending: Vec<(
+        MessageId,
+        oneshot::Sender<Result<InMessage, AmqpTransportError>>,
+    )>,
+}
+
+impl std::fmt::Debug for RequestReply {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("RequestReply")
+            .field("reply_to", &self.inner.reply_to)
+            .field("timeout", &self.inner.timeout)
+            .field("pending", &self.inner.pending.len())
+            .finish()
+    }
+}
+
+impl RequestReply {
+    /// Create request-reply helper for established links
+    ///
+    /// Receiver link credit is managed by helper.
+    pub fn new(sender: SenderLink, mut receiver: ReceiverLink) -> Self {
+        let reply_to = receiver.address().cloned();
+        receiver.set_auto_credit(REPLY_CREDIT);
+
+        let deliveries = receiver.deliveries();
+        let inner = Cell::new(RequestReplyInner {
+            sender,
+            receiver,
+            reply_to,
+            timeout: DEFAULT_TIMEOUT,
+            closed: false,
+            pending: Vec::new(),
+        });
+        ntex::rt::spawn(dispatch(inner.downgrade(), deliveries));
+
+        RequestReply { inner }
+    }
+
+    /// Set response timeout
+    ///
+    /// By default timeout is set to 30 seconds
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.inner.get_mut().timeout = timeout;
+        self
+    }
+
+    /// Address of reply node
+    pub fn reply_to(&self) -> Option<&ByteString> {
+        self.inner.reply_to.as_ref()
+    }
+
+    /// Send request and wait for response
+    ///
+    /// Random `message-id` is generated if request does not have one,
+    /// request with `message-id` of other pending request is rejected.
+    /// Fails with `AmqpTransportError::Timeout` error if response does
+    /// not arrive in time.
+    pub fn request(
+        &self,
+        mut message: OutMessage,
+    ) -> impl Future<Output = Result<InMessage, AmqpTransportError>> {
+        let inner = self.inner.get_mut();
+
+        let id = if let Some(id) = message.properties().and_then(|p| p.message_id.clone()) {
+            id
+        } else {
+            let id = MessageId::Uuid(Uuid::new_v4());
+            message.set_message_id(id.clone());
+            id
+        };
+        if inner.pending.iter().any(|(pid, _)| *pid == id) {
+            trace!("Request with message id {} is pending already", id);
+            return Either::Left(err(AmqpTransportError::Protocol(Error {
+                condition: AmqpError::NotAllowed.into(),
+                description: Some(ByteString::from_static(
+                    "Request with the same message id is pending",
+                )),
+                info: None,
+            })));
+        }
+        if let Some(ref reply_to) = inner.reply_to {
+            message.set_reply_to(reply_to.clone());
+        }
+
+        let (tx, rx) = oneshot::channel();
+        inner.pending.push((id.clone(), tx));
+        let delivery = inner.sender.send(message);
+        let timeout = inner.timeout;
+        let rr = self.clone();
+
+        Either::Right(async move {
+            let res = time::timeout(timeout, async move {
+                delivery.await?;
+                match rx.await {
+                    Ok(res) => res,
+                    Err(_) => Err(AmqpTransportError::Disconnected),
+                }
+            })
+            .await;
+
+            match res {
+                Ok(res) => {
+                    if res.is_err() {
+                        rr.remove(&id);
+                    }
+                    res
+                }
+                Err(_) => {
+                    trace!("Response is not received in {:?}: {}", timeout, id);
+                    rr.remove(&id);
+                    Err(AmqpTransportError::Timeout)
+                }
+            }
+        })
+    }
+
+    /// Close sender and receiver links
+    ///
+    /// Pending requests fail with `AmqpTransportError::Disconnected` error.
+    pub async fn close(&self) -> Result<(), AmqpTransportError> {
+        let (sender, receiver) = {
+            let inner = self.inner.get_mut();
+            inner.closed = true;
+            inner.pending.clear();
+            (inner.sender.clone(), inner.receiver.clone())
+        };
+        sender.close().await?;
+        receiver.close().await
+    }
+
+    fn remove(&self, id: &MessageId) {
+        self.inner.get_mut().pending.retain(|(pid, _)| pid != id);
+    }
+}
+
+impl Drop for RequestReplyInner {
+    fn drop(&mut self) {
+        // detach is sent on close, dispatch task ends once reply link is detached
+        if !self.closed {
+            std::mem::drop(self.sender.close());
+            std::mem::drop(self.receiver.close());
+        }
+    }
+}
+
+impl RequestReplyInner {
+    fn take(
+        &mut self,
+        id: &MessageId,
+    ) -> Option<oneshot::Sender<Result<InMessage, AmqpTransportError>>> {
+        let idx = self.pending.iter().position(|(pid, _)| pid == id)?;
+        Some(self.pending.remove(idx).1)
+    }
+}
+
+/// Route responses to pending requests
+async fn dispatch(rr: WeakCell<RequestReplyInner>, mut deliveries: Deliveries) {
+    while let Some(res) = deliveries.next().await {
+        let inner = if let Some(inner) = rr.upgrade() {
+            inner
+        } else {
+            return;
+        };
+
+        match res {
+            Ok(delivery) => {
+                let message = delivery.message().clone();
+                if !delivery.is_settled() {
+                    delivery.accept();
+                }
+
+                let id = message
+                    .properties()
+                    .and_then(|p| p.correlation_id())
+                    .cloned();
+                if let Some(tx) = id.as_ref().and_then(|id| inner.get_mut().take(id)) {
+                    let _ = tx.send(Ok(message));
+                } else {
+                    trace!("Response for unknown request: {:?}", id);
+                }
+            }
+            Err(err) => {
+                trace!("Reply link is failed: {:?}", err);
+                for (_, tx) in inner.get_mut().pending.drain(..) {
+                    let _ = tx.send(Err(err.clone()));
+                }
+                return;
+            }
+        }
+    }
+
+    // reply link is detached, pending requests fail
+    if let Some(inner) = rr.upgrade() {
+        inner.get_mut().pending.clear();
+    }
+}
@@ -1,3 +1,4 @@
+use bytes::Bytes;
 use bytestring::ByteString;
 use either::Either;
 use futures::future::ok;
@@ -18,6 +19,9 @@ use crate::service::ProtocolNegotiation;
 use super::Configuration;
 pub use crate::errors::SaslConnectError;
 
+/// #2.7.1 smallest max frame size peer may advertise
+const MIN_MAX_FRAME_SIZE: u32 = 512;
+
 #[derive(Debug)]
 /// Sasl connect request
 pub struct SaslConnect {
@@ -27,12 +31,49 @@ pub struct SaslConnect {
     pub time: Option<LowResTimeService>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 /// Sasl authentication parameters
 pub struct SaslAuth {
     pub authz_id: String,
     pub authn_id: String,
     pub password: String,
+    /// Mechanisms in order of preference, `PLAIN` is used if empty
+    pub mechanisms: Vec<SaslMechanism>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Sasl mechanism
+pub enum SaslMechanism {
+    /// Authentication with username and password
+    Plain,
+    /// Anonymous access
+    Anonymous,
+    /// Authentication established by external means, i.e. tls client certificate
+    External,
+}
+
+impl SaslMechanism {
+    /// Mechanism name
+    pub fn name(&self) -> &'static str {
+        match self {
+            SaslMechanism::Plain => "PLAIN",
+            SaslMechanism::Anonymous => "ANONYMOUS",
+            SaslMechanism::External => "EXTERNAL",
+        }
+    }
+
+    fn initial_response(&self, auth: &SaslAuth) -> Option<Bytes> {
+        match self {
+            SaslMechanism::Plain => Some(SaslInit::prepare_response(
+                &auth.authz_id,
+                &auth.authn_id,
+                &auth.password,
+            )),
+            SaslMechanism::Anonymous => None,
+            // authorization identity, empty identity is derived from tls peer identity
+            SaslMechanism::External => Some(Bytes::from(auth.authz_id.clone())),
+        }
+    }
 }
 
 /// Create service that connects to amqp server and authenticate itself via sasl.
@@ -131,6 +172,14 @@ where
 
                 if let Frame::Open(open) = frame.performative() {
                     trace!("Open confirmed: {:?}", open);
+                    if open.max_frame_size < MIN_MAX_FRAME_SIZE {
+                        return Err(Either::Left(SaslConnectError::InvalidMaxFrameSize(
+                            open.max_frame_size,
+                        )));
+                    }
+                    framed
+                        .get_codec_mut()
+                        .max_size(config.get_max_frame_size());
                     Ok(Connection::new(framed, config, open.into(), time))
                 } else {
                     Err(Either::Left(SaslConnectError::ExpectedOpenFrame))
@@ -146,21 +195,40 @@ async fn sasl_connect<Io: AsyncRead + AsyncWrite + Unpin>(
     let mut sasl_io = framed.into_framed(AmqpCodec::<SaslFrame>::new());
 
     // processing sasl-mechanisms
-    let _ = sasl_io
+    let sasl_frame = sasl_io
         .next()
         .await
         .ok_or(SaslConnectError::Disconnected)?
         .map_err(SaslConnectError::from)?;
 
-    let initial_response =
-        SaslInit::prepare_response(&auth.authz_id, &auth.authn_id, &auth.password);
+    let preferred = if auth.mechanisms.is_empty() {
+        vec![SaslMechanism::Plain]
+    } else {
+        auth.mechanisms.clone()
+    };
+
+    // first preferred mechanism offered by server
+    let mechanism = if let SaslFrame {
+        body: SaslFrameBody::SaslMechanisms(mechanisms),
+    } = sasl_frame
+    {
+        let offered = mechanisms.sasl_server_mechanisms();
+        preferred
+            .iter()
+            .find(|m| offered.iter().any(|s| s.as_str() == m.name()))
+            .copied()
+            .ok_or(SaslConnectError::MechanismNotSupported(preferred))?
+    } else {
+        return Err(SaslConnectError::ExpectedMechanismsFrame);
+    };
+    trace!("Use sasl mechanism: {:?}", mechanism);
 
     let hostname = uri.host().map(ByteString::from);
 
     let sasl_init = SaslInit {
         hostname,
-        mechanism: Symbol::from("PLAIN"),
-        initial_response: Some(initial_response),
+        mechanism: Symbol::from(mechanism.name()),
+        initial_response: mechanism.initial_response(&auth),
     };
 
     sasl_io
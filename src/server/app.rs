@@ -5,9 +5,7 @@ use std::task::{Context, Poll};
 use futures::future::{err, ok, Either, Ready};
 use futures::Stream;
 use ntex::service::{boxed, fn_factory_with_config, IntoServiceFactory, Service, ServiceFactory};
-use ntex_amqp_codec::protocol::{
-    DeliveryNumber, DeliveryState, Disposition, Error, Rejected, Role,
-};
+use ntex_amqp_codec::protocol::{DeliveryNumber, DeliveryState, Error, Rejected};
 use ntex_router::{IntoPattern, Router};
 
 use crate::cell::Cell;
@@ -96,7 +94,7 @@ impl<S: 'static> Service for AppService<S> {
             .frame()
             .target
             .as_ref()
-            .and_then(|target| target.address.as_ref().cloned());
+            .and_then(|target| target.address().cloned());
 
         if let Some(path) = path {
             link.path_mut().set(path);
@@ -163,7 +161,7 @@ impl<S> Future for AppServiceResponse<S> {
                                     .frame()
                                     .target
                                     .as_ref()
-                                    .map(|t| t.address.as_ref().map(|s| s.as_ref()).unwrap_or(""))
+                                    .map(|t| t.address().map(|s| s.as_ref()).unwrap_or(""))
                                     .unwrap_or("")
                             );
                             return Poll::Pending;
@@ -243,7 +241,7 @@ impl<S> Future for AppServiceResponse<S> {
                                 .frame()
                                 .target
                                 .as_ref()
-                                .map(|t| t.address.as_ref().map(|s| s.as_ref()).unwrap_or(""))
+                                .map(|t| t.address().map(|s| s.as_ref()).unwrap_or(""))
                                 .unwrap_or("")
                         );
                         this.link.open();
@@ -258,7 +256,7 @@ impl<S> Future for AppServiceResponse<S> {
                                 .frame()
                                 .target
                                 .as_ref()
-                                .map(|t| t.address.as_ref().map(|s| s.as_ref()).unwrap_or(""))
+                                .map(|t| t.address().map(|s| s.as_ref()).unwrap_or(""))
                                 .unwrap_or(""),
                             e
                         );
@@ -293,7 +291,7 @@ impl Future for HandleMessage {
                         .frame()
                         .target
                         .as_ref()
-                        .map(|t| t.address.as_ref().map(|s| s.as_ref()).unwrap_or(""))
+                        .map(|t| t.address().map(|s| s.as_ref()).unwrap_or(""))
                         .unwrap_or("")
                 );
                 let delivery_id = this.delivery_id;
@@ -308,7 +306,7 @@ impl Future for HandleMessage {
                         .frame()
                         .target
                         .as_ref()
-                        .map(|t| t.address.as_ref().map(|s| s.as_ref()).unwrap_or(""))
+                        .map(|t| t.address().map(|s| s.as_ref()).unwrap_or(""))
                         .unwrap_or("")
                 );
 
@@ -325,13 +323,5 @@ impl Future for HandleMessage {
 }
 
 fn settle(link: &mut ReceiverLink, id: DeliveryNumber, state: DeliveryState) {
-    let disposition = Disposition {
-        state: Some(state),
-        role: Role::Receiver,
-        first: id,
-        last: None,
-        settled: true,
-        batchable: false,
-    };
-    link.send_disposition(disposition);
+    link.settle_message(id, state);
 }
@@ -162,8 +162,9 @@ where
                         Frame::Flow(frm) => {
                             // apply flow to specific link
                             if self.control_srv.is_some() {
-                                if let Some(link) =
-                                    session.get_sender_link_by_handle(frm.handle.unwrap())
+                                if let Some(link) = frm
+                                    .handle
+                                    .and_then(|h| session.get_sender_link_by_handle(h))
                                 {
                                     self.control_frame = Some(ControlFrame::new(
                                         self.state.clone(),
@@ -270,6 +271,11 @@ where
     type Output = Result<(), AmqpCodecError>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // connection heartbeat
+        if !self.conn.poll_heartbeat(cx) {
+            return Poll::Ready(Ok(()));
+        }
+
         // process control frame
         if !self.handle_control_fut(cx) {
             return Poll::Pending;
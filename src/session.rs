@@ -1,37 +1,67 @@
 use std::collections::VecDeque;
 use std::future::Future;
+use std::time::Duration;
 
 use bytes::{BufMut, Bytes, BytesMut};
 use bytestring::ByteString;
 use either::Either;
-use futures::future::ok;
+use futures::{future, FutureExt};
 use fxhash::FxHashMap;
-use ntex::channel::oneshot;
+use ntex::channel::{mpsc, oneshot};
+use ntex::rt::time;
 use slab::Slab;
+use uuid::Uuid;
 
 use ntex_amqp_codec::protocol::{
-    Accepted, Attach, DeliveryNumber, DeliveryState, Detach, Disposition, Error, Flow, Frame,
-    Handle, ReceiverSettleMode, Role, SenderSettleMode, Transfer, TransferBody, TransferNumber,
+    Accepted, AmqpError, Attach, Begin, DeliveryNumber, DeliveryState, Detach, Disposition, End,
+    Error, Flow, Frame, Handle, Outcome, ReceiverSettleMode, Rejected, Role, SequenceNo,
+    SessionError, TransactionalState, Transfer, TransferBody, TransferNumber,
 };
-use ntex_amqp_codec::AmqpFrame;
+use ntex_amqp_codec::{AmqpFrame, Encode};
 
 use crate::cell::Cell;
-use crate::connection::ConnectionController;
+use crate::connection::{ConnectionController, SessionWindow};
 use crate::errors::AmqpTransportError;
+use crate::management::ManagementClient;
 use crate::rcvlink::{ReceiverLink, ReceiverLinkBuilder, ReceiverLinkInner};
-use crate::sndlink::{SenderLink, SenderLinkBuilder, SenderLinkInner};
-use crate::{Configuration, DeliveryPromise};
+use crate::sndlink::{SenderLink, SenderLinkBuilder, SenderLinkInner, SenderLinkStats};
+use crate::transaction::Transaction;
+use crate::{Configuration, Delivery, DeliveryPromise};
 
-const INITIAL_OUTGOING_ID: TransferNumber = 0;
+pub(crate) const INITIAL_OUTGOING_ID: TransferNumber = 0;
+
+/// Number of recently settled deliveries kept for `Session::delivery_state()`
+const RECENTLY_SETTLED_MAX: usize = 64;
+const FRAME_HEADER_SIZE: usize = 8;
+/// Time remote peer has to confirm detach of dropped opening link
+const DETACH_TIMEOUT: Duration = Duration::from_secs(10);
 
-#[derive(Clone)]
 pub struct Session {
     pub(crate) inner: Cell<SessionInner>,
+    // handle is owned by user code, counted by `SessionInner::handles`
+    owned: bool,
+}
+
+impl Clone for Session {
+    fn clone(&self) -> Self {
+        if self.owned {
+            self.inner.get_mut().handles += 1;
+        }
+        Session {
+            inner: self.inner.clone(),
+            owned: self.owned,
+        }
+    }
 }
 
 impl Drop for Session {
     fn drop(&mut self) {
-        self.inner.get_mut().drop_session()
+        let inner = self.inner.get_mut();
+        if self.owned {
+            inner.handles -= 1;
+            inner.end_if_unused();
+        }
+        inner.drop_session()
     }
 }
 
@@ -43,7 +73,33 @@ impl std::fmt::Debug for Session {
 
 impl Session {
     pub(crate) fn new(inner: Cell<SessionInner>) -> Session {
-        Session { inner }
+        Session {
+            inner,
+            owned: false,
+        }
+    }
+
+    /// Create session handle owned by user code
+    ///
+    /// Locally opened session get ended once last owned handle is dropped
+    /// and all links are detached.
+    pub(crate) fn owned(inner: Cell<SessionInner>) -> Session {
+        inner.get_mut().handles += 1;
+        Session { inner, owned: true }
+    }
+
+    /// Release owned handle without ending the session
+    pub(crate) fn disown(mut self) {
+        if self.owned {
+            self.owned = false;
+            self.inner.get_mut().handles -= 1;
+        }
+    }
+
+    /// Session is ending or failed
+    pub(crate) fn is_ended(&self) -> bool {
+        let inner = self.inner.get_ref();
+        inner.closing || inner.error.is_some()
     }
 
     #[inline]
@@ -52,8 +108,187 @@ impl Session {
         self.inner.connection.remote_config()
     }
 
-    pub fn close(&self) -> impl Future<Output = Result<(), AmqpTransportError>> {
-        ok(())
+    /// Close session
+    ///
+    /// All established links get detached, `End` frame is sent to remote peer.
+    /// Returned future resolves once remote peer confirms session end.
+    /// Session can not be used after close. New transfers are not accepted
+    /// once close begins, deliveries that are not settled by remote peer
+    /// resolve with `AmqpTransportError::SessionEnded` error.
+    pub fn close(&mut self) -> impl Future<Output = Result<(), AmqpTransportError>> {
+        let rx = self.inner.get_mut().close(None);
+
+        async move {
+            match rx.await {
+                Ok(res) => res,
+                Err(_) => Err(AmqpTransportError::Disconnected),
+            }
+        }
+    }
+
+    /// Close session once outstanding deliveries are settled
+    ///
+    /// New transfers fail with `AmqpTransportError::SessionEnded` error once
+    /// close begins. Queued transfers are still sent, session waits until
+    /// remote peer settles all deliveries or `timeout` expires, and then
+    /// closes as `Session::close()` does.
+    pub fn close_after_settled(
+        &mut self,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<(), AmqpTransportError>> {
+        let inner = self.inner.get_mut();
+        inner.draining = true;
+        let settled = inner.wait_settled();
+        let mut session = self.clone();
+        let guard = DrainingSession(self.inner.clone());
+
+        async move {
+            let _guard = guard;
+            if let Some(rx) = settled {
+                if time::timeout(timeout, rx).await.is_err() {
+                    trace!("Deliveries are not settled in {:?}, closing", timeout);
+                }
+            }
+            session.close().await
+        }
+    }
+
+    /// Detach all established links
+    ///
+    /// `Detach` frames are sent for all links at once. Returned future resolves
+    /// once remote peer confirms all detaches, or fails with
+    /// `AmqpTransportError::Timeout` if confirmations do not arrive in time.
+    /// Links that are already detaching are not awaited.
+    pub fn detach_all(
+        &mut self,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<(), AmqpTransportError>> {
+        let links: Vec<_> = self
+            .inner
+            .get_ref()
+            .links
+            .iter()
+            .filter_map(|(_, link)| match link {
+                Either::Left(SenderLinkState::Established(link)) => {
+                    Some(Either::Left(link.clone()))
+                }
+                Either::Right(ReceiverLinkState::Established(link)) => {
+                    Some(Either::Right(link.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        let detaches: Vec<_> = links
+            .iter()
+            .map(|link| match link {
+                Either::Left(link) => link.close().left_future(),
+                Either::Right(link) => link.close().right_future(),
+            })
+            .collect();
+
+        async move {
+            match time::timeout(timeout, future::join_all(detaches)).await {
+                Ok(results) => results.into_iter().collect(),
+                Err(_) => Err(AmqpTransportError::Timeout),
+            }
+        }
+    }
+
+    /// Flush batched frames
+    ///
+    /// Batchable transfers are not sent until next flush.
+    pub fn flush(&self) {
+        self.inner.connection.flush()
+    }
+
+    /// Advertise session flow state to remote peer
+    ///
+    /// `Flow` frame with current incoming and outgoing windows is sent
+    /// right away.
+    pub fn update_flow(&self) {
+        self.inner.get_mut().send_flow()
+    }
+
+    /// Request flow state of remote peer
+    ///
+    /// `Flow` frame with `echo` flag is sent to remote peer. Resolves with
+    /// next session `Flow` frame received from remote peer after request
+    /// is written to transport, fails with
+    /// `AmqpTransportError::Timeout` error if remote peer does not respond
+    /// in time. Could be used as session liveness probe.
+    pub fn request_flow_echo(
+        &self,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<Flow, AmqpTransportError>> {
+        let inner = self.inner.get_mut();
+        let (tx, rx) = oneshot::channel();
+        if let Some(ref err) = inner.error {
+            let _ = tx.send(Err(err.clone()));
+        } else {
+            inner.post_flow(true);
+            let frame = inner.connection.last_queued_frame();
+            inner.flow_echo_waiters.retain(|(_, tx)| !tx.is_canceled());
+            inner.flow_echo_waiters.push((frame, tx));
+        }
+        let inner = self.inner.clone();
+
+        async move {
+            let res = time::timeout(timeout, rx).await;
+            match res {
+                Ok(Ok(res)) => res,
+                Ok(Err(_)) => Err(AmqpTransportError::Disconnected),
+                Err(_) => {
+                    inner
+                        .get_mut()
+                        .flow_echo_waiters
+                        .retain(|(_, tx)| !tx.is_canceled());
+                    Err(AmqpTransportError::Timeout)
+                }
+            }
+        }
+    }
+
+    /// Set session incoming window
+    ///
+    /// New window is advertised to remote peer right away, later session
+    /// issues new window once remaining window drops to `threshold`.
+    pub fn set_incoming_window(&mut self, window: u32, threshold: u32) {
+        let inner = self.inner.get_mut();
+        inner.window = SessionWindow {
+            incoming: window,
+            threshold: std::cmp::min(threshold, window),
+        };
+        inner.incoming_window = window;
+        inner.send_flow();
+    }
+
+    /// Advertise session flow state on interval
+    ///
+    /// `Flow` frame is sent to remote peer every `interval` until session
+    /// ends, `None` stops periodic flow. By default flow is sent only when
+    /// incoming window drops to threshold.
+    pub fn set_flow_interval(&mut self, interval: Option<Duration>) {
+        let inner = self.inner.get_mut();
+        inner.flow_timer = inner.flow_timer.wrapping_add(1);
+
+        if let Some(interval) = interval {
+            let timer = inner.flow_timer;
+            let session = self.inner.downgrade();
+            ntex::rt::spawn(async move {
+                loop {
+                    time::delay_for(interval).await;
+                    if let Some(session) = session.upgrade() {
+                        let inner = session.get_mut();
+                        if inner.flow_timer != timer || inner.closing || inner.error.is_some() {
+                            break;
+                        }
+                        inner.send_flow();
+                    } else {
+                        break;
+                    }
+                }
+            });
+        }
     }
 
     pub fn get_sender_link(&self, name: &str) -> Option<&SenderLink> {
@@ -87,6 +322,86 @@ impl Session {
         SenderLinkBuilder::new(name, address, self.inner.clone())
     }
 
+    /// Open sender link with default settings
+    ///
+    /// This is a shortcut for `build_sender_link(name, address).open()`.
+    pub fn open_sender_link<T: Into<ByteString>, U: Into<ByteString>>(
+        &mut self,
+        name: U,
+        address: T,
+    ) -> impl Future<Output = Result<SenderLink, AmqpTransportError>> {
+        self.build_sender_link(name, address).open()
+    }
+
+    /// Get sender link to address, link is opened if needed
+    ///
+    /// Established links are cached by address, link is removed
+    /// from cache once it gets detached.
+    pub fn cached_sender_link<T: Into<ByteString>>(
+        &mut self,
+        address: T,
+    ) -> impl Future<Output = Result<SenderLink, AmqpTransportError>> {
+        let address = address.into();
+        let inner = self.inner.clone();
+
+        async move {
+            let link = inner
+                .get_ref()
+                .address_links
+                .get(&address)
+                .filter(|link| link.inner.get_ref().is_opened())
+                .cloned();
+
+            if let Some(link) = link {
+                Ok(link)
+            } else {
+                let name = ByteString::from(format!("{}-{}", address, Uuid::new_v4().to_simple()));
+                let link = SenderLinkBuilder::new(name, address.clone(), inner.clone())
+                    .open()
+                    .await?;
+                inner.get_mut().address_links.insert(address, link.clone());
+                Ok(link)
+            }
+        }
+    }
+
+    /// Send message to address
+    ///
+    /// Sender link is taken from `cached_sender_link()`.
+    pub fn send<T, U>(
+        &mut self,
+        address: T,
+        body: U,
+    ) -> impl Future<Output = Result<Disposition, AmqpTransportError>>
+    where
+        T: Into<ByteString>,
+        U: Into<TransferBody>,
+    {
+        let body = body.into();
+        let link = self.cached_sender_link(address);
+
+        async move { link.await?.send(body).await }
+    }
+
+    /// Declare new transaction
+    ///
+    /// Opens controller link to remote transaction coordinator and
+    /// resolves once coordinator declares the transaction.
+    pub fn transaction(&mut self) -> impl Future<Output = Result<Transaction, AmqpTransportError>> {
+        Transaction::declare(self)
+    }
+
+    /// Open client of amqp management node
+    ///
+    /// Opens sender link to management node, i.e. `$management`, and
+    /// receiver link from dynamic reply node.
+    pub fn management<T: Into<ByteString>>(
+        &mut self,
+        node: T,
+    ) -> impl Future<Output = Result<ManagementClient, AmqpTransportError>> {
+        ManagementClient::open(self, node.into())
+    }
+
     /// Open receiver link
     pub fn build_receiver_link<T: Into<ByteString>, U: Into<ByteString>>(
         &mut self,
@@ -98,6 +413,17 @@ impl Session {
         ReceiverLinkBuilder::new(name, address, self.inner.clone())
     }
 
+    /// Open receiver link with default settings
+    ///
+    /// This is a shortcut for `build_receiver_link(name, address).open()`.
+    pub fn open_receiver_link<T: Into<ByteString>, U: Into<ByteString>>(
+        &mut self,
+        name: U,
+        address: T,
+    ) -> impl Future<Output = Result<ReceiverLink, AmqpTransportError>> {
+        self.build_receiver_link(name, address).open()
+    }
+
     /// Detach receiver link
     pub fn detach_receiver_link(
         &mut self,
@@ -131,12 +457,308 @@ impl Session {
     ) -> impl Future<Output = Result<Disposition, AmqpTransportError>> {
         self.inner.get_mut().wait_disposition(id)
     }
+
+    /// Stream of links attached by remote peer
+    ///
+    /// Each incoming link must be accepted or rejected. Without
+    /// subscribed stream remote attaches get rejected. New stream
+    /// replaces previously returned one.
+    pub fn incoming_links(&mut self) -> mpsc::Receiver<IncomingLink> {
+        let (tx, rx) = mpsc::channel();
+        self.inner.get_mut().incoming_links = Some(tx);
+        rx
+    }
+
+    /// Stream of session lifecycle events
+    ///
+    /// Established session reports `SessionEvent::Begun` right away.
+    /// Stream ends after session is ended or failed. New stream
+    /// replaces previously returned one.
+    pub fn events(&mut self) -> mpsc::Receiver<SessionEvent> {
+        let (tx, rx) = mpsc::channel();
+        let inner = self.inner.get_mut();
+        if inner.error.is_none() {
+            let _ = tx.send(SessionEvent::Begun);
+            inner.events = Some(tx);
+        }
+        rx
+    }
+
+    /// Current state of outgoing delivery
+    ///
+    /// Unsettled deliveries and a number of recently settled
+    /// deliveries are known to session.
+    pub fn delivery_state(&self, id: DeliveryNumber) -> Option<DeliveryView> {
+        self.inner.get_ref().delivery_state(id)
+    }
+
+    /// Delivery counters aggregated across established sender links
+    pub fn sender_link_stats(&self) -> SenderLinkStats {
+        let mut stats = SenderLinkStats::default();
+        for (_, link) in self.inner.get_ref().links.iter() {
+            if let Either::Left(SenderLinkState::Established(ref link)) = link {
+                stats += link.stats();
+            }
+        }
+        stats
+    }
+
+    /// Local channel number of the session
+    pub fn local_channel(&self) -> u16 {
+        self.inner.id as u16
+    }
+
+    /// Channel number of the session assigned by remote peer
+    pub fn remote_channel(&self) -> u16 {
+        self.inner.remote_channel_id
+    }
+
+    /// Snapshot of established links
+    pub fn links(&self) -> Vec<LinkInfo> {
+        let inner = self.inner.get_ref();
+        let remote_handle = |idx: usize| {
+            inner
+                .remote_handles
+                .iter()
+                .find(|(_, v)| **v == idx)
+                .map(|(hnd, _)| *hnd)
+        };
+
+        inner
+            .links
+            .iter()
+            .filter_map(|(idx, link)| match link {
+                Either::Left(SenderLinkState::Established(ref link)) => Some(LinkInfo {
+                    name: link.name().clone(),
+                    handle: idx as Handle,
+                    remote_handle: remote_handle(idx),
+                    role: Role::Sender,
+                    address: link.address().cloned(),
+                }),
+                Either::Right(ReceiverLinkState::Established(ref link)) => Some(LinkInfo {
+                    name: link.frame().name.clone(),
+                    handle: idx as Handle,
+                    remote_handle: remote_handle(idx),
+                    role: Role::Receiver,
+                    address: link.address().cloned(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Snapshot of session flow control state
+    pub fn stats(&self) -> SessionStats {
+        let inner = self.inner.get_ref();
+        SessionStats {
+            next_outgoing_id: inner.next_outgoing_id,
+            next_incoming_id: inner.next_incoming_id,
+            incoming_window: inner.incoming_window,
+            outgoing_window: inner.remote_incoming_window,
+            unsettled_deliveries: inner.unsettled_deliveries.len(),
+            pending_transfers: inner.pending_transfers.len(),
+        }
+    }
+}
+
+/// Leave draining mode if close future is dropped before session is closed
+struct DrainingSession(Cell<SessionInner>);
+
+impl Drop for DrainingSession {
+    fn drop(&mut self) {
+        self.0.get_mut().draining = false;
+    }
+}
+
+/// Detach opening link if open future is dropped before remote peer confirms link
+pub(crate) struct OpeningLink {
+    session: Cell<SessionInner>,
+    name: Option<ByteString>,
+}
+
+impl OpeningLink {
+    pub(crate) fn new(session: Cell<SessionInner>, name: ByteString) -> Self {
+        OpeningLink {
+            session,
+            name: Some(name),
+        }
+    }
+
+    /// Open future is completed, link name could belong to other link
+    pub(crate) fn completed(mut self) {
+        self.name.take();
+    }
+}
+
+impl Drop for OpeningLink {
+    fn drop(&mut self) {
+        if let Some(name) = self.name.take() {
+            self.session
+                .get_mut()
+                .drop_opening_link(&name, &self.session);
+        }
+    }
+}
+
+/// Session lifecycle event
+#[derive(Debug, Clone)]
+pub enum SessionEvent {
+    /// Remote peer responded with `Begin` frame, session is established
+    Begun,
+    /// Session is ended locally, `End` frame is sent to remote peer
+    LocalEnd(Option<Error>),
+    /// Session is ended by remote peer
+    RemoteEnd(Option<Error>),
+    /// Connection is failed
+    Failed(AmqpTransportError),
+}
+
+/// Snapshot of outgoing delivery state
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryView {
+    /// Delivery id
+    pub id: DeliveryNumber,
+    /// Handle of the sender link
+    pub handle: Handle,
+    /// Delivery tag
+    pub tag: Bytes,
+    /// Delivery is settled
+    pub settled: bool,
+    /// Intermediate state of unsettled delivery or outcome of settled delivery
+    pub state: Option<DeliveryState>,
+}
+
+/// Session flow control state
+///
+/// Growing `pending_transfers` indicates that remote peer
+/// does not open incoming window fast enough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionStats {
+    /// Transfer id of next outgoing transfer
+    pub next_outgoing_id: TransferNumber,
+    /// Expected transfer id of next incoming transfer
+    pub next_incoming_id: TransferNumber,
+    /// Number of transfers remote peer may send before session issues new window
+    pub incoming_window: u32,
+    /// Number of transfers session may send before remote peer issues new window
+    pub outgoing_window: u32,
+    /// Number of sent deliveries that are not settled by remote peer
+    pub unsettled_deliveries: usize,
+    /// Number of transfers waiting for remote incoming window
+    pub pending_transfers: usize,
+}
+
+/// Established link of the session
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkInfo {
+    /// Link name
+    pub name: ByteString,
+    /// Local link handle
+    pub handle: Handle,
+    /// Link handle assigned by remote peer
+    pub remote_handle: Option<Handle>,
+    /// Role of local link endpoint
+    pub role: Role,
+    /// Target address of sender link or source address of receiver link
+    pub address: Option<ByteString>,
+}
+
+/// Link established by remote peer
+pub enum AcceptedLink {
+    /// Remote peer attached receiver, local endpoint sends messages
+    Sender(SenderLink),
+    /// Remote peer attached sender, local endpoint receives messages
+    Receiver(ReceiverLink),
+}
+
+/// Link attach initiated by remote peer
+///
+/// Dropping incoming link without accepting it rejects the link.
+pub struct IncomingLink {
+    session: Cell<SessionInner>,
+    attach: Option<Attach>,
+}
+
+impl IncomingLink {
+    fn new(session: Cell<SessionInner>, attach: Attach) -> Self {
+        IncomingLink {
+            session,
+            attach: Some(attach),
+        }
+    }
+
+    /// `Attach` frame sent by remote peer
+    pub fn frame(&self) -> &Attach {
+        self.attach.as_ref().unwrap()
+    }
+
+    /// Link name
+    pub fn name(&self) -> &ByteString {
+        self.frame().name()
+    }
+
+    /// Local endpoint is sender, remote peer attached receiver
+    pub fn is_sender(&self) -> bool {
+        self.frame().role == Role::Receiver
+    }
+
+    /// Accept link, matching `Attach` frame is sent to remote peer
+    pub fn accept(mut self) -> AcceptedLink {
+        let attach = self.attach.take().unwrap();
+        let cell = self.session.clone();
+
+        if attach.role == Role::Receiver {
+            AcceptedLink::Sender(self.session.get_mut().confirm_sender_link(&attach, cell))
+        } else {
+            let mut link = self.session.get_mut().open_receiver_link(cell, attach);
+            link.open();
+            AcceptedLink::Receiver(link)
+        }
+    }
+
+    /// Reject link, `Detach` frame with error is sent to remote peer
+    pub fn reject<E: Into<Error>>(mut self, error: E) {
+        let attach = self.attach.take().unwrap();
+        self.session
+            .get_mut()
+            .detach_unconfirmed_sender_link(&attach, Some(error.into()));
+    }
+}
+
+impl Drop for IncomingLink {
+    fn drop(&mut self) {
+        if let Some(attach) = self.attach.take() {
+            self.session
+                .get_mut()
+                .detach_unconfirmed_sender_link(&attach, Some(incoming_link_rejected()));
+        }
+    }
+}
+
+impl std::fmt::Debug for IncomingLink {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("IncomingLink")
+            .field("attach", &self.attach)
+            .finish()
+    }
+}
+
+fn incoming_link_rejected() -> Error {
+    Error {
+        condition: AmqpError::NotAllowed.into(),
+        description: Some(ByteString::from_static("Link is not accepted")),
+        info: None,
+    }
 }
 
 #[derive(Debug)]
 enum SenderLinkState {
     Established(SenderLink),
-    Opening(Option<oneshot::Sender<Result<SenderLink, AmqpTransportError>>>),
+    /// Local link is opening, initial delivery count of the link
+    Opening(
+        Option<oneshot::Sender<Result<SenderLink, AmqpTransportError>>>,
+        SequenceNo,
+    ),
     Closing(Option<oneshot::Sender<Result<(), AmqpTransportError>>>),
 }
 
@@ -156,7 +778,7 @@ enum ReceiverLinkState {
 impl SenderLinkState {
     fn is_opening(&self) -> bool {
         match self {
-            SenderLinkState::Opening(_) => true,
+            SenderLinkState::Opening(..) => true,
             _ => false,
         }
     }
@@ -175,58 +797,111 @@ pub(crate) struct SessionInner {
     id: usize,
     connection: ConnectionController,
     next_outgoing_id: TransferNumber,
-    local: bool,
 
     remote_channel_id: u16,
     next_incoming_id: TransferNumber,
+    incoming_window: u32,
+    window: SessionWindow,
     remote_outgoing_window: u32,
     remote_incoming_window: u32,
+    handle_max: Handle,
 
-    unsettled_deliveries: FxHashMap<DeliveryNumber, DeliveryPromise>,
+    unsettled_deliveries: FxHashMap<DeliveryNumber, (Handle, Bytes, DeliveryPromise)>,
+    recently_settled: VecDeque<DeliveryView>,
 
     links: Slab<Either<SenderLinkState, ReceiverLinkState>>,
     links_by_name: FxHashMap<ByteString, usize>,
+    address_links: FxHashMap<ByteString, SenderLink>,
     remote_handles: FxHashMap<Handle, usize>,
     pending_transfers: VecDeque<PendingTransfer>,
+    outgoing_partial: Option<OutgoingPartial>,
+    partial_transfers: FxHashMap<Handle, (Transfer, BytesMut)>,
     disposition_subscribers: FxHashMap<DeliveryNumber, oneshot::Sender<Disposition>>,
+    incoming_links: Option<mpsc::Sender<IncomingLink>>,
+    events: Option<mpsc::Sender<SessionEvent>>,
+    flow_timer: usize,
+    pub(crate) local: bool,
+    handles: usize,
     error: Option<AmqpTransportError>,
+    closing: bool,
+    end_waiters: Vec<oneshot::Sender<Result<(), AmqpTransportError>>>,
+    // echo request waiters with sequence number of request frame
+    flow_echo_waiters: Vec<(u64, oneshot::Sender<Result<Flow, AmqpTransportError>>)>,
+    settled_waiters: Vec<oneshot::Sender<()>>,
+    draining: bool,
+}
+
+/// Multi-frame delivery, that is not sent completely
+struct OutgoingPartial {
+    delivery_id: DeliveryNumber,
+    transfer: Transfer,
+    body: Bytes,
+    chunk_size: usize,
+    aborted: bool,
 }
 
 struct PendingTransfer {
     link_handle: Handle,
-    idx: u32,
     body: Option<TransferBody>,
     promise: DeliveryPromise,
-    tag: Option<Bytes>,
-    settled: Option<bool>,
+    opts: TransferOptions,
+}
+
+/// Parameters of outgoing transfer
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TransferOptions {
+    /// Delivery tag, delivery id is used if not set
+    pub(crate) tag: Option<Bytes>,
+    /// Delivery is pre-settled
+    pub(crate) settled: bool,
+    pub(crate) batchable: bool,
+    /// Transaction the delivery belongs to
+    pub(crate) txn_id: Option<Bytes>,
+    /// Delivery is unsettled on remote peer, see `SenderLink::resume()`
+    pub(crate) resume: bool,
 }
 
 impl SessionInner {
     pub(crate) fn new(
         id: usize,
-        local: bool,
         connection: ConnectionController,
         remote_channel_id: u16,
-        next_incoming_id: DeliveryNumber,
-        remote_incoming_window: u32,
-        remote_outgoing_window: u32,
+        begin: &Begin,
+        window: SessionWindow,
     ) -> SessionInner {
+        let next_outgoing_id = connection.local_config().initial_outgoing_id;
         SessionInner {
             id,
-            local,
+            window,
             connection,
-            next_incoming_id,
             remote_channel_id,
-            remote_incoming_window,
-            remote_outgoing_window,
-            next_outgoing_id: INITIAL_OUTGOING_ID,
+            incoming_window: window.incoming,
+            next_incoming_id: begin.next_outgoing_id(),
+            remote_incoming_window: begin.incoming_window(),
+            remote_outgoing_window: begin.outgoing_window(),
+            handle_max: begin.handle_max(),
+            next_outgoing_id,
             unsettled_deliveries: FxHashMap::default(),
+            recently_settled: VecDeque::new(),
             links: Slab::new(),
             links_by_name: FxHashMap::default(),
+            address_links: FxHashMap::default(),
             remote_handles: FxHashMap::default(),
             pending_transfers: VecDeque::new(),
+            outgoing_partial: None,
+            partial_transfers: FxHashMap::default(),
             disposition_subscribers: FxHashMap::default(),
+            incoming_links: None,
+            events: None,
+            flow_timer: 0,
+            local: false,
+            handles: 0,
             error: None,
+            closing: false,
+            end_waiters: Vec::new(),
+            flow_echo_waiters: Vec::new(),
+            settled_waiters: Vec::new(),
+            draining: false,
         }
     }
 
@@ -238,17 +913,45 @@ impl SessionInner {
     /// Set error. New operations will return error.
     pub(crate) fn set_error(&mut self, err: AmqpTransportError) {
         log::trace!("Connection is failed, dropping state: {:?}", err);
+        // dropped links must not end the session
+        self.error = Some(err.clone());
+
+        match err {
+            AmqpTransportError::SessionEnded(_)
+            | AmqpTransportError::SessionRemoteEnded(_)
+            | AmqpTransportError::Remote { .. } => (),
+            ref err => self.session_event(SessionEvent::Failed(err.clone())),
+        }
 
         // drop pending transfers
+        self.outgoing_partial = None;
         for tr in self.pending_transfers.drain(..) {
             let _ = tr.promise.send(Err(err.clone()));
         }
 
+        // drop unsettled deliveries
+        for (_, (_, _, promise)) in self.unsettled_deliveries.drain() {
+            let _ = promise.send(Err(err.clone()));
+        }
+
+        for (_, tx) in self.flow_echo_waiters.drain(..) {
+            let _ = tx.send(Err(err.clone()));
+        }
+
+        // notify close waiters
+        for tx in self.end_waiters.drain(..) {
+            let _ = tx.send(Err(err.clone()));
+        }
+
         // drop links
         self.links_by_name.clear();
         for (_, st) in self.links.iter_mut() {
             match st {
-                Either::Left(SenderLinkState::Opening(_)) => (),
+                Either::Left(SenderLinkState::Opening(ref mut tx, _)) => {
+                    if let Some(tx) = tx.take() {
+                        let _ = tx.send(Err(err.clone()));
+                    }
+                }
                 Either::Left(SenderLinkState::Established(ref mut link)) => {
                     link.inner.get_mut().detached(err.clone())
                 }
@@ -258,14 +961,127 @@ impl SessionInner {
                     }
                 }
                 Either::Right(ReceiverLinkState::Established(ref mut link)) => {
-                    link.remote_closed(None)
+                    link.session_failed(err.clone())
+                }
+                Either::Right(ReceiverLinkState::OpeningLocal(ref mut item)) => {
+                    if let Some((_, tx)) = item.take() {
+                        let _ = tx.send(Err(err.clone()));
+                    }
+                }
+                Either::Right(ReceiverLinkState::Closing(ref mut tx)) => {
+                    if let Some(tx) = tx.take() {
+                        let _ = tx.send(Err(err.clone()));
+                    }
                 }
                 _ => (),
             }
         }
         self.links.clear();
+        self.address_links.clear();
+        self.incoming_links = None;
+        self.events = None;
+    }
+
+    fn session_event(&mut self, event: SessionEvent) {
+        if let Some(ref tx) = self.events {
+            let _ = tx.send(event);
+        }
+    }
+
+    /// End session, all links get detached and new operations return error.
+    pub(crate) fn end(&mut self, error: Option<Error>) {
+        if !self.closing {
+            trace!("Ending session {} with error {:?}", self.id, error);
+            self.closing = true;
+            self.session_event(SessionEvent::LocalEnd(error.clone()));
+            self.post_frame(
+                End {
+                    error: error.clone(),
+                }
+                .into(),
+            );
+            self.set_error(AmqpTransportError::SessionEnded(error));
+        }
+    }
+
+    /// End locally opened session if user code does not own it
+    /// and all links are detached
+    fn end_if_unused(&mut self) {
+        if self.local
+            && self.handles == 0
+            && self.links.is_empty()
+            && !self.closing
+            && self.error.is_none()
+        {
+            trace!("Session {} is dropped, ending", self.id);
+            self.end(None);
+        }
+    }
+
+    /// Close session, detach established links and end session
+    pub(crate) fn close(
+        &mut self,
+        error: Option<Error>,
+    ) -> oneshot::Receiver<Result<(), AmqpTransportError>> {
+        let (tx, rx) = oneshot::channel();
+
+        if self.closing {
+            // end frame is sent already, wait for remote end
+            self.end_waiters.push(tx);
+        } else if let Some(ref err) = self.error {
+            match err {
+                AmqpTransportError::SessionEnded(_) => {
+                    let _ = tx.send(Ok(()));
+                }
+                err => {
+                    let _ = tx.send(Err(err.clone()));
+                }
+            }
+        } else {
+            // detach established links
+            let handles: Vec<_> = self
+                .links
+                .iter()
+                .filter_map(|(token, link)| match link {
+                    Either::Left(SenderLinkState::Established(_))
+                    | Either::Right(ReceiverLinkState::Established(_)) => Some(token as Handle),
+                    _ => None,
+                })
+                .collect();
+            for handle in handles {
+                let detach = Detach {
+                    handle,
+                    closed: true,
+                    error: None,
+                };
+                self.post_frame(detach.into());
+            }
+
+            self.end(error);
+            self.end_waiters.push(tx);
+        }
+        rx
+    }
+
+    /// Handle remote `End` frame
+    fn handle_end(&mut self, end: &End) {
+        if self.closing {
+            trace!("Session end is confirmed: {}", self.id());
+            self.end_confirmed();
+        } else {
+            trace!("Remote session end: {}, error: {:?}", self.id(), end.error);
+            self.session_event(SessionEvent::RemoteEnd(end.error.clone()));
+            self.set_error(AmqpTransportError::ended(end.error.clone()));
+            self.post_frame(End { error: None }.into());
+        }
+    }
 
-        self.error = Some(err);
+    /// Remote peer confirmed session end
+    fn end_confirmed(&mut self) {
+        self.closing = false;
+        for tx in self.end_waiters.drain(..) {
+            let _ = tx.send(Ok(()));
+        }
     }
 
     fn drop_session(&mut self) {
@@ -328,7 +1144,7 @@ impl SessionInner {
             name: attach.name.clone(),
             handle: token as Handle,
             role: Role::Sender,
-            snd_settle_mode: SenderSettleMode::Mixed,
+            snd_settle_mode: attach.snd_settle_mode(),
             rcv_settle_mode: ReceiverSettleMode::First,
             source: attach.source.clone(),
             target: attach.target.clone(),
@@ -369,17 +1185,28 @@ impl SessionInner {
         mut frame: Attach,
     ) -> oneshot::Receiver<Result<ReceiverLink, AmqpTransportError>> {
         let (tx, rx) = oneshot::channel();
+        if let Some(ref err) = self.error {
+            let _ = tx.send(Err(err.clone()));
+            return rx;
+        }
+        if self.links_by_name.contains_key(&frame.name) {
+            let _ = tx.send(Err(AmqpTransportError::LinkNameInUse(frame.name.clone())));
+            return rx;
+        }
+        if let Err(err) = self.check_handle_max() {
+            let _ = tx.send(Err(err));
+            return rx;
+        }
 
         let entry = self.links.vacant_entry();
         let token = entry.key();
+        frame.handle = token as Handle;
 
         let inner = Cell::new(ReceiverLinkInner::new(cell, token as u32, frame.clone()));
         entry.insert(Either::Right(ReceiverLinkState::OpeningLocal(Some((
             inner, tx,
         )))));
 
-        frame.handle = token as Handle;
-
         self.links_by_name.insert(frame.name.clone(), token);
         self.post_frame(Frame::Attach(frame));
         rx
@@ -393,7 +1220,7 @@ impl SessionInner {
                         name: attach.name.clone(),
                         handle: token as Handle,
                         role: Role::Receiver,
-                        snd_settle_mode: SenderSettleMode::Mixed,
+                        snd_settle_mode: attach.snd_settle_mode(),
                         rcv_settle_mode: ReceiverSettleMode::First,
                         source: attach.source.clone(),
                         target: attach.target.clone(),
@@ -423,7 +1250,29 @@ impl SessionInner {
     ) {
         if let Some(Either::Right(link)) = self.links.get_mut(id as usize) {
             match link {
-                ReceiverLinkState::Opening(_inner) => {
+                ReceiverLinkState::Opening(inner) => {
+                    // link is never opened, reject transfers received before attach
+                    if let Some(inner) = inner {
+                        for id in inner.get_mut().drop_early_transfers() {
+                            let disp = Disposition {
+                                role: Role::Receiver,
+                                first: id,
+                                last: None,
+                                settled: true,
+                                state: Some(DeliveryState::Rejected(Rejected {
+                                    error: Some(Error {
+                                        condition: AmqpError::IllegalState.into(),
+                                        description: Some(ByteString::from_static(
+                                            "Link is not attached",
+                                        )),
+                                        info: None,
+                                    }),
+                                })),
+                                batchable: false,
+                            };
+                            self.post_frame(disp.into());
+                        }
+                    }
                     let detach = Detach {
                         handle: id,
                         closed,
@@ -432,6 +1281,9 @@ impl SessionInner {
                     self.post_frame(detach.into());
                     let _ = tx.send(Ok(()));
                     let _ = self.links.remove(id as usize);
+                    // remote handle can be reused by remote peer
+                    self.remote_handles.retain(|_, v| *v != id as usize);
+                    self.end_if_unused();
                 }
                 ReceiverLinkState::Established(_) => {
                     let detach = Detach {
@@ -446,6 +1298,7 @@ impl SessionInner {
                     let _ = tx.send(Ok(()));
                     let _ = self.links.remove(id as usize);
                     error!("Unexpected receiver link state: closing - {}", id);
+                    self.end_if_unused();
                 }
                 ReceiverLinkState::OpeningLocal(_inner) => unimplemented!(),
             }
@@ -464,7 +1317,7 @@ impl SessionInner {
     ) {
         if let Some(Either::Left(link)) = self.links.get_mut(id) {
             match link {
-                SenderLinkState::Opening(_) => {
+                SenderLinkState::Opening(..) => {
                     let detach = Detach {
                         handle: id as u32,
                         closed,
@@ -473,7 +1326,9 @@ impl SessionInner {
                     *link = SenderLinkState::Closing(Some(tx));
                     self.post_frame(detach.into());
                 }
-                SenderLinkState::Established(_) => {
+                SenderLinkState::Established(ref snd) => {
+                    let handle = snd.inner.get_ref().id();
+                    let err = AmqpTransportError::LinkDetached(error.clone());
                     let detach = Detach {
                         handle: id as u32,
                         closed,
@@ -481,6 +1336,8 @@ impl SessionInner {
                     };
                     *link = SenderLinkState::Closing(Some(tx));
                     self.post_frame(detach.into());
+                    self.drop_pending_transfers(handle, &err);
+                    self.uncache_sender_link(id);
                 }
                 SenderLinkState::Closing(_) => {
                     let _ = tx.send(Ok(()));
@@ -493,20 +1350,111 @@ impl SessionInner {
         }
     }
 
-    pub(crate) fn get_sender_link_by_handle(&self, hnd: Handle) -> Option<&SenderLink> {
-        if let Some(id) = self.remote_handles.get(&hnd) {
-            if let Some(Either::Left(SenderLinkState::Established(ref link))) = self.links.get(*id)
-            {
-                return Some(link);
-            }
-        }
-        None
+    /// Remove detached link from address cache
+    fn uncache_sender_link(&mut self, id: usize) {
+        self.address_links
+            .retain(|_, link| link.inner.get_ref().id != id);
     }
 
-    pub(crate) fn get_receiver_link_by_handle(&self, hnd: Handle) -> Option<&ReceiverLink> {
-        if let Some(id) = self.remote_handles.get(&hnd) {
-            if let Some(Either::Right(ReceiverLinkState::Established(ref link))) =
-                self.links.get(*id)
+    /// Link is not confirmed by remote peer in time or open future
+    /// is dropped, detach it
+    ///
+    /// Link slot is released once remote peer confirms detach, or
+    /// after `timeout` if remote peer does not respond.
+    pub(crate) fn cancel_opening_link(
+        &mut self,
+        name: &ByteString,
+        session: &Cell<SessionInner>,
+        timeout: Duration,
+    ) {
+        let idx = if let Some(idx) = self.links_by_name.get(name) {
+            *idx
+        } else {
+            return;
+        };
+
+        match self.links.get_mut(idx) {
+            Some(Either::Left(link)) if link.is_opening() => {
+                *link = SenderLinkState::Closing(None);
+            }
+            Some(Either::Right(link)) if link.is_opening() => {
+                *link = ReceiverLinkState::Closing(None);
+            }
+            _ => return,
+        }
+        trace!("Link {:?} is not confirmed, detaching", name);
+
+        let detach = Detach {
+            handle: idx as Handle,
+            closed: true,
+            error: None,
+        };
+        self.post_frame(detach.into());
+
+        let name = name.clone();
+        let session = session.downgrade();
+        ntex::rt::spawn(async move {
+            time::delay_for(timeout).await;
+            if let Some(session) = session.upgrade() {
+                session.get_mut().release_closing_link(idx, &name);
+            }
+        });
+    }
+
+    /// Free slot of detached link that is not confirmed by remote peer
+    fn release_closing_link(&mut self, idx: usize, name: &ByteString) {
+        if self.links_by_name.get(name) != Some(&idx) {
+            return;
+        }
+        match self.links.get(idx) {
+            Some(Either::Left(SenderLinkState::Closing(None)))
+            | Some(Either::Right(ReceiverLinkState::Closing(None))) => {
+                trace!("Detach of link {:?} is not confirmed, releasing", name);
+                self.links.remove(idx);
+                self.links_by_name.remove(name);
+                let partial_transfers = &mut self.partial_transfers;
+                self.remote_handles.retain(|hnd, v| {
+                    if *v == idx {
+                        partial_transfers.remove(hnd);
+                        false
+                    } else {
+                        true
+                    }
+                });
+                self.end_if_unused();
+            }
+            _ => (),
+        }
+    }
+
+    /// Open future is dropped before completion, detach link
+    fn drop_opening_link(&mut self, name: &ByteString, session: &Cell<SessionInner>) {
+        let link = match self
+            .links_by_name
+            .get(name)
+            .and_then(|idx| self.links.get(*idx))
+        {
+            // confirmed by remote peer, but never delivered to open future
+            Some(Either::Right(ReceiverLinkState::Established(link))) => link.clone(),
+            _ => return self.cancel_opening_link(name, session, DETACH_TIMEOUT),
+        };
+        drop(link.close());
+    }
+
+    pub(crate) fn get_sender_link_by_handle(&self, hnd: Handle) -> Option<&SenderLink> {
+        if let Some(id) = self.remote_handles.get(&hnd) {
+            if let Some(Either::Left(SenderLinkState::Established(ref link))) = self.links.get(*id)
+            {
+                return Some(link);
+            }
+        }
+        None
+    }
+
+    pub(crate) fn get_receiver_link_by_handle(&self, hnd: Handle) -> Option<&ReceiverLink> {
+        if let Some(id) = self.remote_handles.get(&hnd) {
+            if let Some(Either::Right(ReceiverLinkState::Established(ref link))) =
+                self.links.get(*id)
             {
                 return Some(link);
             }
@@ -515,21 +1463,64 @@ impl SessionInner {
     }
 
     pub(crate) fn handle_frame(&mut self, frame: Frame) {
-        if self.error.is_none() {
+        if let Frame::End(ref end) = frame {
+            self.handle_end(end);
+        } else if self.error.is_none() {
             match frame {
                 Frame::Flow(flow) => self.apply_flow(&flow),
                 Frame::Disposition(disp) => {
                     if let Some(sender) = self.disposition_subscribers.remove(&disp.first) {
                         let _ = sender.send(disp);
-                    } else {
+                    } else if disp.role == Role::Receiver {
+                        // remote receiver settles our outgoing deliveries
                         self.settle_deliveries(disp);
+                        self.notify_settled();
+                    } else {
+                        // remote sender settles deliveries we receive
+                        self.settle_incoming_deliveries(&disp);
                     }
                 }
                 Frame::Transfer(transfer) => {
+                    if self.incoming_window == 0 {
+                        error!("Remote peer exceeded session incoming window");
+                        self.end(Some(Error {
+                            condition: SessionError::WindowViolation.into(),
+                            description: None,
+                            info: None,
+                        }));
+                        return;
+                    }
+
+                    // #2.5.6 each incoming transfer frame consumes a transfer-id
+                    self.next_incoming_id = self.next_incoming_id.wrapping_add(1);
+                    self.incoming_window -= 1;
+                    self.remote_outgoing_window = self.remote_outgoing_window.saturating_sub(1);
+
+                    if self.incoming_window <= self.window.threshold {
+                        trace!("Session incoming window is low, issue new window");
+                        self.incoming_window = self.window.incoming;
+                        self.send_flow();
+                    }
+
                     let idx = if let Some(idx) = self.remote_handles.get(&transfer.handle()) {
                         *idx
                     } else {
+                        // #2.7.5 transfer on unattached handle is a session error
                         error!("Transfer's link {:?} is unknown", transfer.handle());
+                        self.end(Some(Error {
+                            condition: SessionError::UnattachedHandle.into(),
+                            description: Some(ByteString::from(format!(
+                                "Link handle is not attached: {}",
+                                transfer.handle()
+                            ))),
+                            info: None,
+                        }));
+                        return;
+                    };
+
+                    let transfer = if let Some(transfer) = self.assemble_transfer(transfer) {
+                        transfer
+                    } else {
                         return;
                     };
 
@@ -537,13 +1528,16 @@ impl SessionInner {
                         match link {
                             Either::Left(_) => error!("Got trasfer from sender link"),
                             Either::Right(link) => match link {
-                                ReceiverLinkState::Opening(_) => {
-                                    error!(
+                                ReceiverLinkState::Opening(Some(link)) => {
+                                    // eager remote sender, keep transfer until link is opened
+                                    trace!(
                                         "Got transfer for opening link: {} -> {}",
                                         transfer.handle(),
                                         idx
                                     );
+                                    link.get_mut().handle_early_transfer(transfer);
                                 }
+                                ReceiverLinkState::Opening(None) => (),
                                 ReceiverLinkState::OpeningLocal(_) => {
                                     error!(
                                         "Got transfer for opening link: {} -> {}",
@@ -552,8 +1546,6 @@ impl SessionInner {
                                     );
                                 }
                                 ReceiverLinkState::Established(link) => {
-                                    // self.outgoing_window -= 1;
-                                    let _ = self.next_incoming_id.wrapping_add(1);
                                     link.inner.get_mut().handle_transfer(transfer);
                                 }
                                 ReceiverLinkState::Closing(_) => (),
@@ -575,42 +1567,119 @@ impl SessionInner {
         }
     }
 
+    /// Accumulate payload of multi-frame delivery.
+    /// Returns transfer once delivery is complete
+    fn assemble_transfer(&mut self, mut transfer: Transfer) -> Option<Transfer> {
+        let handle = transfer.handle();
+
+        if transfer.aborted {
+            // #2.6.14 aborted delivery, drop accumulated payload
+            trace!("Transfer on {} is aborted", handle);
+            self.partial_transfers.remove(&handle);
+            return None;
+        }
+
+        if let Some((_, buf)) = self.partial_transfers.get_mut(&handle) {
+            if let Some(body) = transfer.body.take() {
+                body.serialize_into(buf);
+            }
+            if transfer.more {
+                return None;
+            }
+
+            let (mut first, buf) = self.partial_transfers.remove(&handle).unwrap();
+            first.more = false;
+            first.body = Some(TransferBody::Data(buf.freeze()));
+            if transfer.settled.is_some() {
+                first.settled = transfer.settled;
+            }
+            if transfer.state.is_some() {
+                first.state = transfer.state;
+            }
+            Some(first)
+        } else if transfer.more {
+            let mut buf = BytesMut::new();
+            if let Some(body) = transfer.body.take() {
+                body.serialize_into(&mut buf);
+            }
+            self.partial_transfers.insert(handle, (transfer, buf));
+            None
+        } else {
+            Some(transfer)
+        }
+    }
+
     /// Handle `Attach` frame. return false if attach frame is remote and can not be handled
     pub(crate) fn handle_attach(&mut self, attach: &Attach, cell: Cell<SessionInner>) -> bool {
         let name = attach.name();
 
         if let Some(index) = self.links_by_name.get(name) {
             match self.links.get_mut(*index) {
+                Some(Either::Left(SenderLinkState::Closing(_)))
+                | Some(Either::Right(ReceiverLinkState::Closing(_))) => {
+                    trace!("Link is closed before attach is confirmed: {:?}", name);
+                    // remote detach refers to remote handle
+                    self.remote_handles.insert(attach.handle(), *index);
+                }
                 Some(Either::Left(item)) => {
-                    if item.is_opening() {
-                        trace!(
-                            "Sender link opened: {:?} {} -> {}",
-                            name,
-                            index,
-                            attach.handle()
-                        );
-
-                        self.remote_handles.insert(attach.handle(), *index);
-                        let delivery_count = attach.initial_delivery_count.unwrap_or(0);
-                        let link = Cell::new(SenderLinkInner::new(
-                            *index,
-                            name.clone(),
-                            attach.handle(),
-                            delivery_count,
-                            cell,
-                        ));
-                        let local_sender = std::mem::replace(
-                            item,
-                            SenderLinkState::Established(SenderLink::new(link.clone())),
-                        );
+                    if let SenderLinkState::Opening(_, delivery_count) = *item {
+                        if attach.target.is_none() {
+                            // #2.6.3 null target, remote peer rejects link and detaches it
+                            trace!("Sender link is rejected by remote peer: {:?}", name);
+                            self.remote_handles.insert(attach.handle(), *index);
+                        } else {
+                            trace!(
+                                "Sender link opened: {:?} {} -> {}",
+                                name,
+                                index,
+                                attach.handle()
+                            );
 
-                        if let SenderLinkState::Opening(Some(tx)) = local_sender {
-                            let _ = tx.send(Ok(SenderLink::new(link)));
+                            self.remote_handles.insert(attach.handle(), *index);
+                            let link = Cell::new(SenderLinkInner::new(
+                                *index,
+                                name.clone(),
+                                delivery_count,
+                                attach,
+                                cell,
+                            ));
+                            let local_sender = std::mem::replace(
+                                item,
+                                SenderLinkState::Established(SenderLink::new(link.clone())),
+                            );
+
+                            if let SenderLinkState::Opening(Some(tx), _) = local_sender {
+                                if let Err(Ok(link)) = tx.send(Ok(SenderLink::owned(link))) {
+                                    // open future is dropped, link is not needed anymore,
+                                    // session is borrowed so link could not detach itself
+                                    trace!(
+                                        "Sender link is dropped before open, detaching: {:?}",
+                                        name
+                                    );
+                                    link.disown();
+                                    *item = SenderLinkState::Closing(None);
+                                    let detach = Detach {
+                                        handle: *index as Handle,
+                                        closed: true,
+                                        error: None,
+                                    };
+                                    self.connection.post_frame(AmqpFrame::new(
+                                        self.remote_channel_id,
+                                        detach.into(),
+                                    ));
+                                }
+                            }
                         }
+                    } else {
+                        self.attach_protocol_error(attach);
                     }
                 }
                 Some(Either::Right(item)) => {
-                    if item.is_opening() {
+                    if item.is_opening() && attach.source.is_none() {
+                        // #2.6.3 null source, remote peer rejects link and detaches it
+                        trace!("Receiver link is rejected by remote peer: {:?}", name);
+                        self.remote_handles.insert(attach.handle(), *index);
+                    } else if item.is_opening() {
                         trace!(
                             "Receiver link opened: {:?} {} -> {}",
                             name,
@@ -620,25 +1689,94 @@ impl SessionInner {
                         if let ReceiverLinkState::OpeningLocal(opt_item) = item {
                             let (link, tx) = opt_item.take().unwrap();
                             self.remote_handles.insert(attach.handle(), *index);
-
-                            *item = ReceiverLinkState::Established(ReceiverLink::new(link.clone()));
-                            let _ = tx.send(Ok(ReceiverLink::new(link)));
+                            link.get_mut().remote_attached(attach);
+
+                            if tx.send(Ok(ReceiverLink::new(link.clone()))).is_err() {
+                                // open future is dropped, link is not needed anymore
+                                trace!(
+                                    "Receiver link is dropped before open, detaching: {:?}",
+                                    name
+                                );
+                                *item = ReceiverLinkState::Closing(None);
+                                let detach = Detach {
+                                    handle: *index as Handle,
+                                    closed: true,
+                                    error: None,
+                                };
+                                self.connection.post_frame(AmqpFrame::new(
+                                    self.remote_channel_id,
+                                    detach.into(),
+                                ));
+                            } else {
+                                *item = ReceiverLinkState::Established(ReceiverLink::new(link));
+                            }
                         }
+                    } else {
+                        self.attach_protocol_error(attach);
                     }
                 }
-                _ => {
-                    // TODO: error in proto, have to close connection
-                }
+                None => self.attach_protocol_error(attach),
             }
             true
+        } else if self.remote_handles.contains_key(&attach.handle()) {
+            // #2.7.3 handle is used by other attached link
+            error!("Remote link handle is in use: {}", attach.handle());
+            self.end(Some(Error {
+                condition: SessionError::HandleInUse.into(),
+                description: Some(ByteString::from(format!(
+                    "Link handle is in use: {}",
+                    attach.handle()
+                ))),
+                info: None,
+            }));
+            true
         } else {
             // cannot handle remote attach
             false
         }
     }
 
+    /// Remote attach, pass link to incoming links stream
+    pub(crate) fn handle_remote_attach(&mut self, attach: Attach, cell: Cell<SessionInner>) {
+        if let Some(ref tx) = self.incoming_links {
+            match tx.send(IncomingLink::new(cell, attach)) {
+                Ok(_) => (),
+                Err(err) => {
+                    // stream is dropped, reject link
+                    self.incoming_links = None;
+                    let mut link = err.into_inner();
+                    let attach = link.attach.take().unwrap();
+                    self.detach_unconfirmed_sender_link(&attach, Some(incoming_link_rejected()));
+                }
+            }
+        } else {
+            trace!("Incoming links are not expected: {:?}", attach.name());
+            self.detach_unconfirmed_sender_link(&attach, Some(incoming_link_rejected()));
+        }
+    }
+
+    /// Attach frame for link in unexpected state, close connection
+    fn attach_protocol_error(&mut self, attach: &Attach) {
+        warn!(
+            "Unexpected attach for link {:?} handle {}",
+            attach.name(),
+            attach.handle()
+        );
+        self.connection.protocol_error(Error {
+            condition: AmqpError::IllegalState.into(),
+            description: Some(ByteString::from(format!(
+                "Link is not in opening state: {}",
+                attach.name()
+            ))),
+            info: None,
+        });
+    }
+
     /// Handle `Detach` frame.
     pub(crate) fn handle_detach(&mut self, detach: &mut Detach) {
+        // remote peer attached the link
+        let attached = self.remote_handles.contains_key(&detach.handle());
+
         // get local link instance
         let idx = if let Some(idx) = self.remote_handles.get(&detach.handle()) {
             *idx
@@ -653,54 +1791,62 @@ impl SessionInner {
         let remove = if let Some(link) = self.links.get_mut(idx) {
             match link {
                 Either::Left(link) => match link {
-                    SenderLinkState::Opening(ref mut tx) => {
+                    SenderLinkState::Opening(ref mut tx, _) => {
                         if let Some(tx) = tx.take() {
-                            let err = AmqpTransportError::LinkDetached(detach.error.clone());
+                            let err = AmqpTransportError::detached(detach.error.clone());
                             let _ = tx.send(Err(err));
                         }
+                        if attached {
+                            self.confirm_rejected_link(idx, detach.closed);
+                        }
                         true
                     }
                     SenderLinkState::Established(link) => {
                         // detach from remote endpoint
                         let detach = Detach {
                             handle: link.inner.get_ref().id(),
-                            closed: true,
+                            closed: detach.closed,
                             error: detach.error.clone(),
                         };
-                        let err = AmqpTransportError::LinkDetached(detach.error.clone());
+                        let err = AmqpTransportError::detached(detach.error.clone());
 
                         // remove name
                         self.links_by_name.remove(link.inner.name());
 
-                        // drop pending transfers
-                        let mut idx = 0;
-                        let handle = link.inner.get_ref().remote_handle();
-                        while idx < self.pending_transfers.len() {
-                            if self.pending_transfers[idx].link_handle == handle {
-                                let tr = self.pending_transfers.remove(idx).unwrap();
-                                let _ = tr.promise.send(Err(err.clone()));
-                            } else {
-                                idx += 1;
-                            }
-                        }
-
                         // detach snd link
-                        link.inner.get_mut().detached(err);
+                        let handle = link.inner.get_ref().id();
+                        link.inner.get_mut().detached(err.clone());
+                        self.uncache_sender_link(idx);
                         self.connection
                             .post_frame(AmqpFrame::new(self.remote_channel_id, detach.into()));
+
+                        // drop pending transfers and in-flight deliveries
+                        self.drop_pending_transfers(handle, &err);
+                        self.drop_unsettled_deliveries(handle, &err);
+                        true
+                    }
+                    SenderLinkState::Closing(tx) => {
+                        // detach confirmation
+                        if let Some(tx) = tx.take() {
+                            if let Some(err) = detach.error.clone() {
+                                let _ = tx.send(Err(AmqpTransportError::detached(Some(err))));
+                            } else {
+                                let _ = tx.send(Ok(()));
+                            }
+                        }
+                        let err = AmqpTransportError::detached(detach.error.clone());
+                        self.drop_unsettled_deliveries(idx as Handle, &err);
                         true
                     }
-                    SenderLinkState::Closing(_) => true,
                 },
                 Either::Right(link) => match link {
                     ReceiverLinkState::Opening(_) => false,
                     ReceiverLinkState::OpeningLocal(ref mut item) => {
                         let (inner, tx) = item.take().unwrap();
                         inner.get_mut().detached();
-                        if let Some(err) = detach.error.clone() {
-                            let _ = tx.send(Err(AmqpTransportError::LinkDetached(Some(err))));
-                        } else {
-                            let _ = tx.send(Err(AmqpTransportError::LinkDetached(None)));
+                        let _ = tx.send(Err(AmqpTransportError::detached(detach.error.clone())));
+                        if attached {
+                            self.confirm_rejected_link(idx, detach.closed);
                         }
 
                         true
@@ -711,7 +1857,7 @@ impl SessionInner {
                         // detach from remote endpoint
                         let detach = Detach {
                             handle: link.handle(),
-                            closed: true,
+                            closed: detach.closed,
                             error: None,
                         };
 
@@ -724,7 +1870,7 @@ impl SessionInner {
                         // detach confirmation
                         if let Some(tx) = tx.take() {
                             if let Some(err) = detach.error.clone() {
-                                let _ = tx.send(Err(AmqpTransportError::LinkDetached(Some(err))));
+                                let _ = tx.send(Err(AmqpTransportError::detached(Some(err))));
                             } else {
                                 let _ = tx.send(Ok(()));
                             }
@@ -738,8 +1884,162 @@ impl SessionInner {
         };
 
         if remove {
+            // free link slot, so the handle can be reused
             self.links.remove(idx);
-            self.remote_handles.remove(&detach.handle());
+            self.links_by_name.retain(|_, v| *v != idx);
+            self.remote_handles.retain(|_, v| *v != idx);
+            self.partial_transfers.remove(&detach.handle());
+            self.end_if_unused();
+        }
+    }
+
+    /// Detach link rejected by remote peer with null terminus
+    fn confirm_rejected_link(&mut self, idx: usize, closed: bool) {
+        let detach = Detach {
+            handle: idx as Handle,
+            closed,
+            error: None,
+        };
+        self.connection
+            .post_frame(AmqpFrame::new(self.remote_channel_id, detach.into()));
+    }
+
+    /// Forget unsettled deliveries which futures are dropped
+    pub(crate) fn drop_canceled_deliveries(&mut self) {
+        self.unsettled_deliveries
+            .retain(|_, (_, _, promise)| !promise.is_canceled());
+    }
+
+    /// Fail transfers of the link waiting for session window
+    fn drop_pending_transfers(&mut self, handle: Handle, err: &AmqpTransportError) {
+        if matches!(self.outgoing_partial, Some(ref partial) if partial.transfer.handle == handle) {
+            self.outgoing_partial = None;
+        }
+        let mut idx = 0;
+        while idx < self.pending_transfers.len() {
+            if self.pending_transfers[idx].link_handle == handle {
+                let tr = self.pending_transfers.remove(idx).unwrap();
+                let _ = tr.promise.send(Err(err.clone()));
+            } else {
+                idx += 1;
+            }
+        }
+    }
+
+    /// Tags and last reported states of unsettled deliveries of the link
+    pub(crate) fn unsettled_deliveries(
+        &self,
+        handle: Handle,
+    ) -> FxHashMap<Bytes, Option<DeliveryState>> {
+        self.unsettled_deliveries
+            .values()
+            .filter(|(hnd, _, _)| *hnd == handle)
+            .map(|(_, tag, promise)| (tag.clone(), promise.state()))
+            .collect()
+    }
+
+    pub(crate) fn delivery_state(&self, id: DeliveryNumber) -> Option<DeliveryView> {
+        if let Some((handle, tag, promise)) = self.unsettled_deliveries.get(&id) {
+            Some(DeliveryView {
+                id,
+                handle: *handle,
+                tag: tag.clone(),
+                settled: false,
+                state: promise.state(),
+            })
+        } else {
+            self.recently_settled
+                .iter()
+                .rev()
+                .find(|view| view.id == id)
+                .cloned()
+        }
+    }
+
+    fn record_settled(
+        &mut self,
+        id: DeliveryNumber,
+        handle: Handle,
+        tag: Bytes,
+        state: Option<DeliveryState>,
+    ) {
+        if let Some(Either::Left(SenderLinkState::Established(link))) =
+            self.links.get(handle as usize)
+        {
+            link.inner.get_mut().delivery_settled(state.as_ref());
+        }
+
+        if self.recently_settled.len() >= RECENTLY_SETTLED_MAX {
+            self.recently_settled.pop_front();
+        }
+        self.recently_settled.push_back(DeliveryView {
+            id,
+            handle,
+            tag,
+            settled: true,
+            state,
+        });
+    }
+
+    /// Fail unsettled deliveries of the link
+    fn drop_unsettled_deliveries(&mut self, handle: Handle, err: &AmqpTransportError) {
+        let ids: Vec<_> = self
+            .unsettled_deliveries
+            .iter()
+            .filter(|(_, (hnd, _, _))| *hnd == handle)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            if let Some((_, _, promise)) = self.unsettled_deliveries.remove(&id) {
+                let _ = promise.send(Err(err.clone()));
+            }
+        }
+        self.notify_settled();
+    }
+
+    /// Wait until all outgoing deliveries are sent and settled
+    fn wait_settled(&mut self) -> Option<oneshot::Receiver<()>> {
+        if self.is_settled() {
+            None
+        } else {
+            let (tx, rx) = oneshot::channel();
+            self.settled_waiters.push(tx);
+            Some(rx)
+        }
+    }
+
+    fn is_settled(&self) -> bool {
+        self.unsettled_deliveries.is_empty()
+            && self.pending_transfers.is_empty()
+            && self.outgoing_partial.is_none()
+    }
+
+    /// Notify close waiters once outgoing deliveries are settled
+    fn notify_settled(&mut self) {
+        if !self.settled_waiters.is_empty() && self.is_settled() {
+            for tx in self.settled_waiters.drain(..) {
+                let _ = tx.send(());
+            }
+        }
+    }
+
+    /// Remote sender settled incoming deliveries, forget them on receiver links
+    fn settle_incoming_deliveries(&mut self, disposition: &Disposition) {
+        let from = disposition.first;
+        let to = disposition.last.unwrap_or(from);
+        trace!(
+            "Remote sender settled deliveries {}..={}, settled: {:?}",
+            from,
+            to,
+            disposition.settled
+        );
+
+        if disposition.settled {
+            for (_, link) in self.links.iter() {
+                if let Either::Right(ReceiverLinkState::Established(ref link)) = link {
+                    link.inner.get_mut().remote_settled(from, to);
+                }
+            }
         }
     }
 
@@ -758,32 +2058,103 @@ impl SessionInner {
             );
         }
 
-        if from == to {
-            if let Some(val) = self.unsettled_deliveries.remove(&from) {
-                if !disposition.settled {
-                    let mut disp = disposition.clone();
-                    disp.role = Role::Sender;
-                    disp.settled = true;
-                    disp.state = Some(DeliveryState::Accepted(Accepted {}));
-                    self.post_frame(Frame::Disposition(disp));
-                }
-                let _ = val.send(Ok(disposition));
-            }
+        let (ids, unknown) = if let Some(range) = self.disposition_range(from, to) {
+            range
         } else {
-            if !disposition.settled {
-                let mut disp = disposition.clone();
-                disp.role = Role::Sender;
-                disp.settled = true;
-                disp.state = Some(DeliveryState::Accepted(Accepted {}));
-                self.post_frame(Frame::Disposition(disp));
+            warn!("Disposition range {}..={} is not valid, ignoring", from, to);
+            return;
+        };
+
+        if !disposition.settled {
+            match disposition.state {
+                Some(DeliveryState::Received(_)) | None => {
+                    // intermediate state, delivery is not settled yet
+                    if let Some(ref state) = disposition.state {
+                        for k in &ids {
+                            if let Some((_, _, promise)) = self.unsettled_deliveries.get(k) {
+                                promise.set_state(state.clone());
+                            }
+                        }
+                    }
+                    return;
+                }
+                _ => {
+                    // terminal outcome with rcv-settle-mode second, remote peer
+                    // waits for our settlement before it forgets the delivery
+                    if !ids.is_empty() {
+                        let mut disp = disposition.clone();
+                        disp.role = Role::Sender;
+                        disp.settled = true;
+                        disp.batchable = false;
+                        self.post_frame(Frame::Disposition(disp));
+                    }
+                }
             }
+        }
 
-            for k in from..=to {
-                if let Some(val) = self.unsettled_deliveries.remove(&k) {
-                    let _ = val.send(Ok(disposition.clone()));
+        for k in ids {
+            if let Some((handle, tag, promise)) = self.unsettled_deliveries.remove(&k) {
+                let mut disp = disposition.clone();
+                if disp.state.is_none() {
+                    disp.state = Some(self.default_outcome(handle).into());
                 }
+                self.record_settled(k, handle, tag, disp.state.clone());
+                let _ = promise.send(Ok(disp));
             }
         }
+        if unknown > 0 {
+            // unknown or already settled deliveries, ignore
+            warn!(
+                "Disposition {}..={} refers to {} unknown or settled deliveries",
+                from, to, unknown
+            );
+        }
+    }
+
+    /// Outstanding delivery ids within disposition range and number of unknown ids
+    ///
+    /// Delivery ids are sequence numbers, range could wrap around `u32::MAX`.
+    /// Returns `None` if last id precedes first id.
+    fn disposition_range(
+        &self,
+        from: DeliveryNumber,
+        to: DeliveryNumber,
+    ) -> Option<(Vec<DeliveryNumber>, usize)> {
+        let span = to.wrapping_sub(from);
+        if span >= 1 << 31 {
+            return None;
+        }
+
+        let total = span as usize + 1;
+        let ids: Vec<_> = if total <= self.unsettled_deliveries.len() {
+            (0..=span)
+                .map(|offset| from.wrapping_add(offset))
+                .filter(|k| self.unsettled_deliveries.contains_key(k))
+                .collect()
+        } else {
+            // range is wider than outstanding deliveries
+            let mut ids: Vec<_> = self
+                .unsettled_deliveries
+                .keys()
+                .copied()
+                .filter(|k| k.wrapping_sub(from) <= span)
+                .collect();
+            ids.sort_by_key(|k| k.wrapping_sub(from));
+            ids
+        };
+        let unknown = total - ids.len();
+        Some((ids, unknown))
+    }
+
+    /// Default outcome of the sender link, `Accepted` if link does not exist
+    fn default_outcome(&self, handle: Handle) -> Outcome {
+        if let Some(Either::Left(SenderLinkState::Established(link))) =
+            self.links.get(handle as usize)
+        {
+            link.inner.get_ref().default_outcome().clone()
+        } else {
+            Outcome::Accepted(Accepted {})
+        }
     }
 
     pub(crate) fn apply_flow(&mut self, flow: &Flow) {
@@ -791,11 +2162,29 @@ impl SessionInner {
         self.next_incoming_id = flow.next_outgoing_id();
         self.remote_outgoing_window = flow.outgoing_window();
 
-        self.remote_incoming_window = flow
-            .next_incoming_id()
-            .unwrap_or(INITIAL_OUTGOING_ID)
-            .saturating_add(flow.incoming_window())
-            .saturating_sub(self.next_outgoing_id);
+        // flow state of remote peer is requested, session flow that is
+        // received after echo request is written answers the request
+        if flow.handle().is_none() && !self.flow_echo_waiters.is_empty() {
+            let written = self.connection.written_frames();
+            for (frame, tx) in std::mem::take(&mut self.flow_echo_waiters) {
+                if tx.is_canceled() {
+                    continue;
+                } else if frame <= written {
+                    let _ = tx.send(Ok(flow.clone()));
+                } else {
+                    self.flow_echo_waiters.push((frame, tx));
+                }
+            }
+        }
+
+        // transfer ids are serial numbers, number of transfers that are
+        // not yet seen by peer must not underflow peer's incoming window
+        let in_flight = self.next_outgoing_id.wrapping_sub(
+            flow.next_incoming_id()
+                .unwrap_or(self.connection.local_config().initial_outgoing_id),
+        );
+        let in_flight = if (in_flight as i32) < 0 { 0 } else { in_flight };
+        self.remote_incoming_window = flow.incoming_window().saturating_sub(in_flight);
 
         trace!(
             "Session received credit {:?}. window: {}, pending: {}",
@@ -804,36 +2193,69 @@ impl SessionInner {
             self.pending_transfers.len()
         );
 
-        while let Some(t) = self.pending_transfers.pop_front() {
-            self.send_transfer(t.link_handle, t.idx, t.body, t.promise, t.tag, t.settled);
-            if self.remote_outgoing_window == 0 {
+        // finish in-progress delivery first,
+        // dequeue only if remote peer has window, keep transfers order
+        self.post_partial_transfer();
+        while self.remote_incoming_window > 0 && self.outgoing_partial.is_none() {
+            if let Some(t) = self.pending_transfers.pop_front() {
+                let transfer = self.prepare_transfer(t.link_handle, t.body, t.promise, t.opts);
+                self.post_transfer(transfer);
+            } else {
                 break;
             }
         }
+        self.notify_settled();
 
-        // apply link flow
-        if let Some(Either::Left(link)) = flow.handle().and_then(|h| self.links.get_mut(h as usize))
-        {
-            match link {
-                SenderLinkState::Established(ref mut link) => {
-                    link.inner.get_mut().apply_flow(&flow);
+        // session window is available, wake up senders waiting for capacity
+        if self.queued_transfers().is_none() {
+            for (_, link) in self.links.iter() {
+                if let Either::Left(SenderLinkState::Established(ref link)) = link {
+                    link.inner.get_ref().ready_task.wake();
                 }
-                _ => warn!("Received flow frame"),
             }
         }
-        if flow.echo() {
-            self.send_flow();
+
+        // apply link flow
+        let idx = flow
+            .handle()
+            .and_then(|h| self.remote_handles.get(&h).copied());
+        // link posts frames through this session, link cell is cloned
+        // so the session is not borrowed via links slab and link's own
+        // session cell at the same time
+        let link = match idx.and_then(|idx| self.links.get(idx)) {
+            Some(Either::Left(SenderLinkState::Established(ref link))) => {
+                Some(Either::Left(link.inner.clone()))
+            }
+            Some(Either::Right(ReceiverLinkState::Established(ref link))) => {
+                Some(Either::Right(link.inner.clone()))
+            }
+            Some(_) => {
+                warn!("Received flow frame");
+                None
+            }
+            None => None,
+        };
+        match link {
+            Some(Either::Left(link)) => link.get_mut().apply_flow(flow, self),
+            Some(Either::Right(link)) => link.get_mut().apply_flow(flow, self),
+            None => {
+                // link flow carries session state, echo session flow
+                // only if flow is not handled by link
+                if flow.echo() {
+                    self.send_flow();
+                }
+            }
         }
     }
 
     fn send_flow(&mut self) {
+        self.post_flow(false)
+    }
+
+    fn post_flow(&mut self, echo: bool) {
         let flow = Flow {
-            next_incoming_id: if self.local {
-                Some(self.next_incoming_id)
-            } else {
-                None
-            },
-            incoming_window: std::u32::MAX,
+            next_incoming_id: Some(self.next_incoming_id),
+            incoming_window: self.incoming_window,
             next_outgoing_id: self.next_outgoing_id,
             outgoing_window: self.remote_incoming_window,
             handle: None,
@@ -841,27 +2263,23 @@ impl SessionInner {
             link_credit: None,
             available: None,
             drain: false,
-            echo: false,
+            echo,
             properties: None,
         };
         self.post_frame(flow.into());
     }
 
-    pub(crate) fn rcv_link_flow(&mut self, handle: u32, delivery_count: u32, credit: u32) {
+    pub(crate) fn link_flow(&mut self, handle: u32, delivery_count: u32, credit: u32, drain: bool) {
         let flow = Flow {
-            next_incoming_id: if self.local {
-                Some(self.next_incoming_id)
-            } else {
-                None
-            },
-            incoming_window: std::u32::MAX,
+            next_incoming_id: Some(self.next_incoming_id),
+            incoming_window: self.incoming_window,
             next_outgoing_id: self.next_outgoing_id,
             outgoing_window: self.remote_incoming_window,
             handle: Some(handle),
             delivery_count: Some(delivery_count),
             link_credit: Some(credit),
             available: None,
-            drain: false,
+            drain,
             echo: false,
             properties: None,
         };
@@ -873,15 +2291,42 @@ impl SessionInner {
             .post_frame(AmqpFrame::new(self.remote_channel_id, frame));
     }
 
+    /// Batchable transfer is queued without waking up write task,
+    /// see `Session::flush()`
+    fn post_transfer_frame(&mut self, transfer: Transfer) {
+        let batchable = transfer.batchable;
+        let frame = AmqpFrame::new(self.remote_channel_id, Frame::Transfer(transfer));
+        if batchable {
+            self.connection.post_batchable_frame(frame);
+        } else {
+            self.connection.post_frame(frame);
+        }
+    }
+
     pub(crate) fn open_sender_link(
         &mut self,
         mut frame: Attach,
     ) -> oneshot::Receiver<Result<SenderLink, AmqpTransportError>> {
         let (tx, rx) = oneshot::channel();
+        if let Some(ref err) = self.error {
+            let _ = tx.send(Err(err.clone()));
+            return rx;
+        }
+        if self.links_by_name.contains_key(&frame.name) {
+            let _ = tx.send(Err(AmqpTransportError::LinkNameInUse(frame.name.clone())));
+            return rx;
+        }
+        if let Err(err) = self.check_handle_max() {
+            let _ = tx.send(Err(err));
+            return rx;
+        }
 
         let entry = self.links.vacant_entry();
         let token = entry.key();
-        entry.insert(Either::Left(SenderLinkState::Opening(Some(tx))));
+        entry.insert(Either::Left(SenderLinkState::Opening(
+            Some(tx),
+            frame.initial_delivery_count.unwrap_or(0),
+        )));
 
         frame.handle = token as Handle;
 
@@ -890,37 +2335,324 @@ impl SessionInner {
         rx
     }
 
+    /// Check that next link handle does not exceed handle-max of remote peer
+    ///
+    /// Link handle is released only after detach handshake completes,
+    /// handles of closing links are not reused.
+    fn check_handle_max(&self) -> Result<(), AmqpTransportError> {
+        if self.links.vacant_key() > self.handle_max as usize {
+            log::trace!("No handle is available, handle-max: {}", self.handle_max);
+            Err(AmqpTransportError::HandleMaxExceeded(self.handle_max))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Max number of queued transfers, configured for local connection
+    pub(crate) fn max_pending_transfers(&self) -> Option<usize> {
+        self.connection.local_config().max_pending_transfers
+    }
+
+    /// Number of queued transfers, if new transfer has to be queued
+    pub(crate) fn queued_transfers(&self) -> Option<usize> {
+        if self.remote_incoming_window == 0
+            || !self.pending_transfers.is_empty()
+            || self.outgoing_partial.is_some()
+        {
+            Some(self.pending_transfers.len())
+        } else {
+            None
+        }
+    }
+
     pub(crate) fn send_transfer(
         &mut self,
         link_handle: Handle,
-        idx: u32,
         body: Option<TransferBody>,
         promise: DeliveryPromise,
-        tag: Option<Bytes>,
-        settled: Option<bool>,
+        opts: TransferOptions,
     ) {
-        if self.remote_incoming_window == 0 {
+        if self.draining || self.closing {
+            log::trace!("Session is closing, transfer is not accepted");
+            let _ = promise.send(Err(AmqpTransportError::SessionEnded(None)));
+            return;
+        }
+        if self.remote_incoming_window == 0
+            || !self.pending_transfers.is_empty()
+            || self.outgoing_partial.is_some()
+        {
             log::trace!(
                 "Remote window is 0, push to pending queue, hnd:{:?}",
                 link_handle
             );
             self.pending_transfers.push_back(PendingTransfer {
                 link_handle,
-                idx,
                 body,
                 promise,
-                tag,
-                settled,
+                opts,
             });
             return;
         }
-        let frame = self.prepare_transfer(link_handle, body, promise, tag, settled);
+        let transfer = self.prepare_transfer(link_handle, body, promise, opts);
         log::trace!(
             "Sending transfer over {} window: {}",
             link_handle,
             self.remote_incoming_window
         );
-        self.post_frame(frame);
+        self.post_transfer(transfer);
+    }
+
+    /// Post transfer frame, split it into multiple frames
+    /// if it does not fit into remote max frame size
+    fn post_transfer(&mut self, mut transfer: Transfer) {
+        let max_frame_size = self.connection.remote_config().get_max_frame_size();
+        let frame_size = FRAME_HEADER_SIZE + transfer.encoded_size();
+        if max_frame_size == 0 || frame_size <= max_frame_size {
+            self.post_transfer_frame(transfer);
+            return;
+        }
+
+        // encode message body to raw bytes
+        let mut body = if let Some(body) = transfer.body.take() {
+            let mut buf = BytesMut::new();
+            body.serialize_into(&mut buf);
+            buf.freeze()
+        } else {
+            Bytes::new()
+        };
+
+        // first frame carries delivery id and tag, so its overhead is the largest
+        let overhead = frame_size - body.len();
+        if overhead >= max_frame_size {
+            log::error!(
+                "Transfer frame overhead {} exceeds remote max frame size {}",
+                overhead,
+                max_frame_size
+            );
+            transfer.body = Some(TransferBody::Data(body));
+            self.post_transfer_frame(transfer);
+            return;
+        }
+        let chunk_size = max_frame_size - overhead;
+
+        log::trace!(
+            "Split transfer on {}, size: {} max frame size: {}",
+            transfer.handle,
+            body.len(),
+            max_frame_size
+        );
+
+        // first frame already consumed session window
+        let chunk = body.split_to(std::cmp::min(chunk_size, body.len()));
+        self.post_transfer_frame(Transfer {
+            body: Some(TransferBody::Data(chunk)),
+            more: true,
+            ..transfer.clone()
+        });
+
+        // rest of the delivery is sent as long as remote peer has window
+        self.outgoing_partial = Some(OutgoingPartial {
+            // transfers are prepared by `prepare_transfer()`, delivery id is always set
+            delivery_id: transfer.delivery_id.unwrap_or_default(),
+            transfer: Transfer {
+                delivery_id: None,
+                delivery_tag: None,
+                message_format: None,
+                state: None,
+                ..transfer
+            },
+            body,
+            chunk_size,
+            aborted: false,
+        });
+        self.post_partial_transfer();
+    }
+
+    /// Send continuation frames of in-progress multi-frame delivery
+    fn post_partial_transfer(&mut self) {
+        while self.remote_incoming_window > 0 {
+            let mut partial = if let Some(partial) = self.outgoing_partial.take() {
+                partial
+            } else {
+                break;
+            };
+
+            // each transfer frame consumes session window
+            self.next_outgoing_id = self.next_outgoing_id.wrapping_add(1);
+            self.remote_incoming_window -= 1;
+
+            if partial.aborted {
+                self.post_transfer_frame(Transfer {
+                    body: None,
+                    more: false,
+                    aborted: true,
+                    ..partial.transfer
+                });
+                break;
+            }
+
+            let chunk = partial
+                .body
+                .split_to(std::cmp::min(partial.chunk_size, partial.body.len()));
+            let more = !partial.body.is_empty();
+            self.post_transfer_frame(Transfer {
+                body: Some(TransferBody::Data(chunk)),
+                more,
+                ..partial.transfer.clone()
+            });
+            if more {
+                self.outgoing_partial = Some(partial);
+            }
+        }
+    }
+
+    /// Abort in-progress multi-frame delivery
+    pub(crate) fn abort_transfer(&mut self, handle: Handle, delivery: &Delivery) -> bool {
+        let partial = match self.outgoing_partial {
+            Some(ref partial) if partial.transfer.handle == handle && !partial.aborted => {
+                partial.delivery_id
+            }
+            _ => return false,
+        };
+
+        // only delivery that is in progress could be aborted
+        let id = self
+            .unsettled_deliveries
+            .iter()
+            .find(|(_, (hnd, _, promise))| *hnd == handle && promise.is_for(delivery))
+            .map(|(id, _)| *id)
+            .filter(|id| *id == partial);
+        if let Some(id) = id {
+            trace!("Abort delivery {} on {}", id, handle);
+            if let Some((_, _, promise)) = self.unsettled_deliveries.remove(&id) {
+                let _ = promise.send(Err(AmqpTransportError::Aborted));
+            }
+            // abort frame is sent once remote peer has window
+            if let Some(ref mut partial) = self.outgoing_partial {
+                partial.aborted = true;
+                partial.body = Bytes::new();
+            }
+            self.post_partial_transfer();
+            self.notify_settled();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Settle unsettled delivery without waiting for remote peer
+    pub(crate) fn settle_transfer(
+        &mut self,
+        handle: Handle,
+        delivery: &Delivery,
+        state: DeliveryState,
+    ) -> bool {
+        let id = self
+            .unsettled_deliveries
+            .iter()
+            .find(|(_, (hnd, _, promise))| *hnd == handle && promise.is_for(delivery))
+            .map(|(id, _)| *id);
+        let id = if let Some(id) = id {
+            id
+        } else {
+            return false;
+        };
+
+        // multi-frame delivery in progress, it could be aborted only
+        if matches!(self.outgoing_partial, Some(ref partial) if partial.delivery_id == id) {
+            return false;
+        }
+
+        self.settle_ids(handle, vec![id], state) > 0
+    }
+
+    /// Settle unsettled deliveries of the link without waiting for remote peer
+    ///
+    /// If `deliveries` is `None` all unsettled deliveries of the link get settled.
+    pub(crate) fn settle_transfers(
+        &mut self,
+        handle: Handle,
+        deliveries: Option<&[&Delivery]>,
+        state: DeliveryState,
+    ) -> usize {
+        let ids = self
+            .unsettled_deliveries
+            .iter()
+            .filter(|(_, (hnd, _, promise))| {
+                *hnd == handle
+                    && match deliveries {
+                        Some(deliveries) => deliveries.iter().any(|d| promise.is_for(d)),
+                        None => true,
+                    }
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        self.settle_ids(handle, ids, state)
+    }
+
+    /// Settle deliveries, contiguous delivery ids are settled with single disposition
+    fn settle_ids(
+        &mut self,
+        handle: Handle,
+        mut ids: Vec<DeliveryNumber>,
+        state: DeliveryState,
+    ) -> usize {
+        // multi-frame delivery in progress, it could be aborted only
+        let partial = self
+            .outgoing_partial
+            .as_ref()
+            .map(|partial| partial.delivery_id);
+        let unsettled = &self.unsettled_deliveries;
+        ids.retain(|id| {
+            Some(*id) != partial && matches!(unsettled.get(id), Some((hnd, _, _)) if *hnd == handle)
+        });
+
+        // order ids as sequence numbers, oldest first
+        let base = self.next_outgoing_id;
+        ids.sort_by_key(|id| id.wrapping_sub(base));
+        ids.dedup();
+
+        let mut idx = 0;
+        while idx < ids.len() {
+            let first = ids[idx];
+            let mut last = first;
+            idx += 1;
+            while idx < ids.len() && ids[idx] == last.wrapping_add(1) {
+                last = ids[idx];
+                idx += 1;
+            }
+
+            trace!(
+                "Settle deliveries {}..={} on {} with {:?}",
+                first,
+                last,
+                handle,
+                state
+            );
+            let disp = Disposition {
+                role: Role::Sender,
+                first,
+                last: if last == first { None } else { Some(last) },
+                settled: true,
+                state: Some(state.clone()),
+                batchable: false,
+            };
+            self.post_frame(Frame::Disposition(disp.clone()));
+
+            let mut id = first;
+            loop {
+                if let Some((_, tag, promise)) = self.unsettled_deliveries.remove(&id) {
+                    self.record_settled(id, handle, tag, disp.state.clone());
+                    let _ = promise.send(Ok(disp.clone()));
+                }
+                if id == last {
+                    break;
+                }
+                id = id.wrapping_add(1);
+            }
+        }
+        self.notify_settled();
+        ids.len()
     }
 
     pub(crate) fn prepare_transfer(
@@ -928,12 +2660,11 @@ impl SessionInner {
         link_handle: Handle,
         body: Option<TransferBody>,
         promise: DeliveryPromise,
-        delivery_tag: Option<Bytes>,
-        settled: Option<bool>,
-    ) -> Frame {
+        opts: TransferOptions,
+    ) -> Transfer {
         let delivery_id = self.next_outgoing_id;
 
-        let tag = if let Some(tag) = delivery_tag {
+        let tag = if let Some(tag) = opts.tag {
             tag
         } else {
             let mut buf = BytesMut::new();
@@ -941,8 +2672,8 @@ impl SessionInner {
             buf.freeze()
         };
 
-        self.next_outgoing_id += 1;
-        self.remote_incoming_window -= 1;
+        self.next_outgoing_id = self.next_outgoing_id.wrapping_add(1);
+        self.remote_incoming_window = self.remote_incoming_window.saturating_sub(1);
 
         let message_format = if let Some(ref body) = body {
             body.message_format()
@@ -950,8 +2681,19 @@ impl SessionInner {
             None
         };
 
-        let settled2 = settled.clone().unwrap_or(false);
-        let state = if settled2 {
+        let settled2 = opts.settled;
+        let state = if let Some(txn_id) = opts.txn_id {
+            // transactional transfer, outcome is known only for pre-settled delivery
+            let outcome = if settled2 {
+                Some(Outcome::Accepted(Accepted {}))
+            } else {
+                None
+            };
+            Some(DeliveryState::TransactionalState(TransactionalState {
+                txn_id,
+                outcome,
+            }))
+        } else if settled2 {
             Some(DeliveryState::Accepted(Accepted {}))
         } else {
             None
@@ -959,20 +2701,33 @@ impl SessionInner {
 
         let transfer = Transfer {
             body,
-            settled,
+            settled: Some(settled2),
             message_format,
             handle: link_handle,
             delivery_id: Some(delivery_id),
-            delivery_tag: Some(tag),
+            delivery_tag: Some(tag.clone()),
             more: false,
             rcv_settle_mode: None,
             state, //: Some(DeliveryState::Accepted(Accepted {})),
-            resume: false,
+            resume: opts.resume,
             aborted: false,
-            batchable: false,
+            batchable: opts.batchable,
         };
-        self.unsettled_deliveries.insert(delivery_id, promise);
+        if settled2 {
+            // pre-settled delivery, remote peer does not send disposition
+            let _ = promise.send(Ok(Disposition {
+                role: Role::Receiver,
+                first: delivery_id,
+                last: None,
+                settled: true,
+                state: Some(DeliveryState::Accepted(Accepted {})),
+                batchable: false,
+            }));
+        } else if !promise.is_canceled() {
+            self.unsettled_deliveries
+                .insert(delivery_id, (link_handle, tag, promise));
+        }
 
-        Frame::Transfer(transfer)
+        transfer
     }
 }
@@ -1,23 +1,228 @@
 use std::collections::VecDeque;
 use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 use bytes::Bytes;
 use bytestring::ByteString;
 use futures::future::{ok, Either};
+use futures::stream::FuturesUnordered;
+use futures::{Sink, Stream};
+use fxhash::FxHashMap;
 use ntex::channel::{condition, oneshot};
+use ntex::rt::time;
+use ntex::task::LocalWaker;
 use ntex_amqp_codec::protocol::{
-    Attach, DeliveryNumber, DeliveryState, Disposition, Error, Flow, ReceiverSettleMode, Role,
-    SenderSettleMode, SequenceNo, Target, TerminusDurability, TerminusExpiryPolicy, TransferBody,
+    Accepted, AmqpError, Attach, DeliveryNumber, DeliveryState, Disposition, Error, ErrorCondition,
+    Flow, MessageFormat, Outcome, ReceiverSettleMode, Role, SenderSettleMode, SequenceNo, Symbols,
+    Target, TerminusDurability, TerminusExpiryPolicy, TransferBody,
 };
+use ntex_amqp_codec::types::Variant;
+use uuid::Uuid;
 
 use crate::cell::Cell;
 use crate::errors::AmqpTransportError;
-use crate::session::{Session, SessionInner};
+use crate::session::{OpeningLink, Session, SessionInner, TransferOptions};
 use crate::{Delivery, DeliveryPromise, Handle};
 
+/// #2.8.7 delivery tag may be up to 32 octets of binary data
+const MAX_DELIVERY_TAG_SIZE: usize = 32;
+
+/// Address of target node attached by remote peer
+fn target_address(attach: &Attach) -> Option<ByteString> {
+    attach
+        .target
+        .as_ref()
+        .and_then(|target| target.target())
+        .and_then(|target| target.address.clone())
+}
+
+/// Max message size accepted by remote peer, zero means no limit
+fn max_message_size(attach: &Attach) -> Option<u64> {
+    attach.max_message_size.filter(|size| *size > 0)
+}
+
+/// Check if capability is in the list of capabilities
+pub(crate) fn has_capability(caps: Option<&Symbols>, cap: &str) -> bool {
+    caps.into_iter()
+        .flat_map(|caps| caps.iter())
+        .any(|c| c.as_str() == cap)
+}
+
+/// Unsettled deliveries of remote peer, delivery tag to delivery state
+fn unsettled_map(attach: &Attach) -> FxHashMap<Bytes, Option<DeliveryState>> {
+    let mut unsettled = FxHashMap::default();
+    if let Some(map) = attach.unsettled() {
+        for (tag, state) in map.iter() {
+            if let Variant::Binary(tag) = tag {
+                unsettled.insert(tag.clone(), DeliveryState::from_variant(state));
+            } else {
+                trace!("Unsupported delivery tag in unsettled map: {:?}", tag);
+            }
+        }
+    }
+    unsettled
+}
+
+/// Retry policy for rejected deliveries
+///
+/// Delivery rejected by remote peer with one of retryable error conditions
+/// is re-sent up to `max_retries` times, delay between attempts doubles
+/// after each retry. Delivery resolves once message is accepted
+/// or retries are exhausted.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    conditions: Vec<ErrorCondition>,
+    max_retries: usize,
+    backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Create retry policy with max number of retries
+    ///
+    /// By default initial backoff is set to 100 milliseconds
+    pub fn new(max_retries: usize) -> Self {
+        RetryPolicy {
+            max_retries,
+            conditions: Vec::new(),
+            backoff: Duration::from_millis(100),
+        }
+    }
+
+    /// Add retryable rejection error condition
+    pub fn condition<T: Into<ErrorCondition>>(mut self, condition: T) -> Self {
+        self.conditions.push(condition.into());
+        self
+    }
+
+    /// Set delay before first retry
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    fn is_retryable(&self, disp: &Disposition) -> bool {
+        matches!(disp.rejection_error(), Some(err) if self.conditions.contains(&err.condition))
+    }
+}
+
+/// Delivery tag generation strategy
+///
+/// Strategy is used for transfers sent without explicit delivery tag.
+/// Generated tags must not be longer than 32 bytes, otherwise delivery
+/// fails with `AmqpError::InvalidField` error.
+///
+/// Default strategy is `DeliveryId`, sender links tagged transfers with
+/// session delivery id before strategy became configurable, so default
+/// keeps these 4 byte tags unchanged for existing peers.
 #[derive(Clone)]
+pub enum DeliveryTagStrategy {
+    /// Session delivery id, 4 bytes in network byte order
+    DeliveryId,
+    /// Random v4 uuid, 16 bytes
+    UuidV4,
+    /// Sequential counter starting at provided value, 8 bytes in network byte order
+    Sequential(u64),
+    /// Tag is produced by user closure
+    Custom(Rc<dyn Fn() -> Bytes>),
+}
+
+// `#[default]` enum variants are not supported by minimal rust version
+#[allow(clippy::derivable_impls)]
+impl Default for DeliveryTagStrategy {
+    fn default() -> Self {
+        DeliveryTagStrategy::DeliveryId
+    }
+}
+
+impl std::fmt::Debug for DeliveryTagStrategy {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeliveryTagStrategy::DeliveryId => fmt.write_str("DeliveryId"),
+            DeliveryTagStrategy::UuidV4 => fmt.write_str("UuidV4"),
+            DeliveryTagStrategy::Sequential(next) => {
+                fmt.debug_tuple("Sequential").field(next).finish()
+            }
+            DeliveryTagStrategy::Custom(_) => fmt.write_str("Custom"),
+        }
+    }
+}
+
+impl DeliveryTagStrategy {
+    /// Generate next delivery tag
+    ///
+    /// Returns `None` if tag is assigned by session.
+    fn next_tag(&mut self) -> Option<Bytes> {
+        match self {
+            DeliveryTagStrategy::DeliveryId => None,
+            DeliveryTagStrategy::UuidV4 => Some(Bytes::copy_from_slice(Uuid::new_v4().as_bytes())),
+            DeliveryTagStrategy::Sequential(ref mut next) => {
+                let tag = Bytes::copy_from_slice(&next.to_be_bytes());
+                *next = next.wrapping_add(1);
+                Some(tag)
+            }
+            DeliveryTagStrategy::Custom(ref f) => Some(f()),
+        }
+    }
+}
+
+fn tag_size_error(size: usize) -> AmqpTransportError {
+    AmqpTransportError::Protocol(Error {
+        condition: AmqpError::InvalidField.into(),
+        description: Some(ByteString::from(format!(
+            "Delivery tag size {} exceeds {} bytes",
+            size, MAX_DELIVERY_TAG_SIZE
+        ))),
+        info: None,
+    })
+}
+
+/// Sender link delivery counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SenderLinkStats {
+    /// Number of transfers passed to session
+    pub sent: u64,
+    /// Number of deliveries settled with `Accepted` outcome
+    pub accepted: u64,
+    /// Number of deliveries settled with `Rejected` outcome
+    pub rejected: u64,
+    /// Number of deliveries settled with `Released` outcome
+    pub released: u64,
+    /// Number of deliveries settled with `Modified` outcome
+    pub modified: u64,
+    /// Number of sent deliveries that are not settled yet
+    pub unsettled: usize,
+}
+
+impl std::ops::AddAssign for SenderLinkStats {
+    fn add_assign(&mut self, other: SenderLinkStats) {
+        self.sent += other.sent;
+        self.accepted += other.accepted;
+        self.rejected += other.rejected;
+        self.released += other.released;
+        self.modified += other.modified;
+        self.unsettled += other.unsettled;
+    }
+}
+
 pub struct SenderLink {
     pub(crate) inner: Cell<SenderLinkInner>,
+    // handle is owned by user code, counted by `SenderLinkInner::handles`
+    owned: bool,
+}
+
+impl Clone for SenderLink {
+    fn clone(&self) -> Self {
+        if self.owned {
+            self.inner.get_mut().handles += 1;
+        }
+        SenderLink {
+            inner: self.inner.clone(),
+            owned: self.owned,
+        }
+    }
 }
 
 impl std::fmt::Debug for SenderLink {
@@ -30,29 +235,74 @@ impl std::fmt::Debug for SenderLink {
 
 pub(crate) struct SenderLinkInner {
     pub(crate) id: usize,
-    idx: u32,
     name: ByteString,
+    address: Option<ByteString>,
     session: Session,
     remote_handle: Handle,
     delivery_count: SequenceNo,
     link_credit: u32,
+    default_outcome: Outcome,
+    settle_mode: SenderSettleMode,
+    max_message_size: Option<u64>,
+    message_format: Option<MessageFormat>,
+    remote_unsettled: FxHashMap<Bytes, Option<DeliveryState>>,
+    remote_offered_capabilities: Option<Symbols>,
+    remote_desired_capabilities: Option<Symbols>,
+    retry_policy: Option<RetryPolicy>,
+    tag_strategy: DeliveryTagStrategy,
+    stats: SenderLinkStats,
     pending_transfers: VecDeque<PendingTransfer>,
     error: Option<AmqpTransportError>,
     closed: bool,
+    local: bool,
     on_close: condition::Condition,
+    credit_waiters: Vec<(u32, oneshot::Sender<Result<(), AmqpTransportError>>)>,
+    handles: usize,
+    pub(crate) ready_task: LocalWaker,
 }
 
 struct PendingTransfer {
-    idx: u32,
-    tag: Option<Bytes>,
     body: Option<TransferBody>,
     promise: DeliveryPromise,
-    settle: Option<bool>,
+    opts: TransferOptions,
+}
+
+impl Drop for SenderLink {
+    fn drop(&mut self) {
+        // detach locally opened link if this is the last copy owned by user code
+        if self.owned {
+            let inner = self.inner.get_mut();
+            inner.handles -= 1;
+            if inner.handles == 0 && inner.local && !inner.closed && inner.error.is_none() {
+                trace!("Sender link {:?} is dropped, detaching", inner.name);
+                drop(inner.close(None));
+            }
+        }
+    }
 }
 
 impl SenderLink {
     pub(crate) fn new(inner: Cell<SenderLinkInner>) -> SenderLink {
-        SenderLink { inner }
+        SenderLink {
+            inner,
+            owned: false,
+        }
+    }
+
+    /// Create link handle owned by user code
+    ///
+    /// Locally opened link get detached once last owned handle is dropped.
+    pub(crate) fn owned(inner: Cell<SenderLinkInner>) -> SenderLink {
+        inner.get_mut().handles += 1;
+        SenderLink { inner, owned: true }
+    }
+
+    /// Release owned handle without detaching the link
+    pub(crate) fn disown(mut self) {
+        if self.owned {
+            self.owned = false;
+            self.inner.get_mut().handles -= 1;
+        }
     }
 
     pub fn id(&self) -> u32 {
@@ -67,6 +317,16 @@ impl SenderLink {
         self.inner.remote_handle
     }
 
+    /// Target address
+    pub fn address(&self) -> Option<&ByteString> {
+        self.inner.get_ref().address.as_ref()
+    }
+
+    /// Link credit granted by remote peer
+    pub fn credit(&self) -> u32 {
+        self.inner.get_ref().link_credit
+    }
+
     pub fn session(&self) -> &Session {
         &self.inner.get_ref().session
     }
@@ -75,28 +335,383 @@ impl SenderLink {
         &mut self.inner.get_mut().session
     }
 
-    pub fn send<T>(&self, body: T) -> impl Future<Output = Result<Disposition, AmqpTransportError>>
+    /// Current delivery count of the link
+    ///
+    /// Could be passed to `SenderLinkBuilder::initial_delivery_count()`
+    /// to recover link state after reconnect.
+    pub fn delivery_count(&self) -> SequenceNo {
+        self.inner.get_ref().delivery_count
+    }
+
+    /// Negotiated sender settle mode
+    pub fn settle_mode(&self) -> SenderSettleMode {
+        self.inner.get_ref().settle_mode
+    }
+
+    /// Max message size accepted by remote peer
+    ///
+    /// `None` means no limit
+    pub fn max_message_size(&self) -> Option<u64> {
+        self.inner.get_ref().max_message_size
+    }
+
+    /// Message format of the link
+    ///
+    /// `None` means messages of any format could be sent
+    pub fn message_format(&self) -> Option<MessageFormat> {
+        self.inner.get_ref().message_format
+    }
+
+    /// Capabilities offered by remote peer on attach
+    pub fn remote_offered_capabilities(&self) -> Option<&Symbols> {
+        self.inner.get_ref().remote_offered_capabilities.as_ref()
+    }
+
+    /// Capabilities desired by remote peer on attach
+    pub fn remote_desired_capabilities(&self) -> Option<&Symbols> {
+        self.inner.get_ref().remote_desired_capabilities.as_ref()
+    }
+
+    /// Check if remote peer offers capability
+    pub fn is_capability_offered(&self, cap: &str) -> bool {
+        has_capability(self.remote_offered_capabilities(), cap)
+    }
+
+    /// Unsettled deliveries reported by remote peer on attach
+    ///
+    /// Maps delivery tag to the last delivery state known to remote peer,
+    /// deliveries without state should be re-sent on link resume.
+    pub fn remote_unsettled(&self) -> &FxHashMap<Bytes, Option<DeliveryState>> {
+        &self.inner.get_ref().remote_unsettled
+    }
+
+    /// Unsettled deliveries of the link
+    ///
+    /// Maps delivery tag to the last delivery state reported by remote peer.
+    /// Could be persisted and passed to `SenderLinkBuilder::unsettled()`
+    /// to resume deliveries after reconnect.
+    pub fn unsettled(&self) -> FxHashMap<Bytes, Option<DeliveryState>> {
+        let inner = self.inner.get_ref();
+        inner
+            .session
+            .inner
+            .get_ref()
+            .unsettled_deliveries(inner.id as Handle)
+    }
+
+    /// Snapshot of link delivery counters
+    pub fn stats(&self) -> SenderLinkStats {
+        self.inner.get_ref().stats()
+    }
+
+    pub fn send<T>(&self, body: T) -> Delivery
+    where
+        T: Into<TransferBody>,
+    {
+        self.send_delivery(body, TransferOptions::default())
+    }
+
+    /// Wait until remote peer grants at least `credit` link credit
+    ///
+    /// Resolves immediately if link credit is sufficient already.
+    /// Fails if link get detached.
+    pub fn wait_for_credit(
+        &self,
+        credit: u32,
+    ) -> impl Future<Output = Result<(), AmqpTransportError>> {
+        let inner = self.inner.get_mut();
+        let (tx, rx) = oneshot::channel();
+        if let Some(ref err) = inner.error {
+            let _ = tx.send(Err(err.clone()));
+        } else if inner.link_credit >= credit {
+            let _ = tx.send(Ok(()));
+        } else {
+            inner.credit_waiters.push((credit, tx));
+        }
+
+        async move {
+            match rx.await {
+                Ok(res) => res,
+                Err(_) => Err(AmqpTransportError::Disconnected),
+            }
+        }
+    }
+
+    /// Check if transfer could be sent right away
+    ///
+    /// Link is ready if remote peer granted link credit and session window,
+    /// and no transfers are queued. Otherwise current task is woken up
+    /// once capacity is available.
+    pub fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), AmqpTransportError>> {
+        let inner = self.inner.get_ref();
+        if let Some(ref err) = inner.error {
+            Poll::Ready(Err(err.clone()))
+        } else if inner.link_credit > 0
+            && inner.pending_transfers.is_empty()
+            && inner.session.inner.get_ref().queued_transfers().is_none()
+        {
+            Poll::Ready(Ok(()))
+        } else {
+            inner.ready_task.register(cx.waker());
+            Poll::Pending
+        }
+    }
+
+    /// Send message only if transfer could be sent right away
+    ///
+    /// Message is taken from `body` and sent once link is ready, see
+    /// `poll_ready()`. Returns `Poll::Pending` and keeps the message
+    /// if remote peer has no capacity, message is never queued.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `body` is `None`
+    pub fn poll_send<T>(
+        &self,
+        cx: &mut Context<'_>,
+        body: &mut Option<T>,
+    ) -> Poll<Result<Delivery, AmqpTransportError>>
+    where
+        T: Into<TransferBody>,
+    {
+        match self.poll_ready(cx) {
+            Poll::Ready(Ok(_)) => {
+                let body = body.take().expect("Message is sent already");
+                Poll::Ready(Ok(self.send(body)))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Send message with custom delivery tag
+    ///
+    /// Delivery tag must not be longer than 32 bytes.
+    pub fn send_with_tag<T>(&self, body: T, tag: Bytes) -> Delivery
+    where
+        T: Into<TransferBody>,
+    {
+        if tag.len() > MAX_DELIVERY_TAG_SIZE {
+            return Delivery::resolved(Err(tag_size_error(tag.len())));
+        }
+        self.send_delivery(
+            body,
+            TransferOptions {
+                tag: Some(tag),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Send pre-settled message
+    ///
+    /// Delivery resolves immediately with `Accepted` state once transfer is sent.
+    /// Link must not be attached with `SenderSettleMode::Unsettled` mode.
+    pub fn send_settled<T>(&self, body: T) -> Delivery
+    where
+        T: Into<TransferBody>,
+    {
+        self.inner.get_mut().send(
+            body,
+            TransferOptions {
+                settled: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Send batchable message
+    ///
+    /// Transfer is queued and sent together with next non-batchable
+    /// frame or on `SenderLink::flush()` call.
+    pub fn send_batchable<T>(&self, body: T) -> Delivery
     where
         T: Into<TransferBody>,
     {
-        self.inner.get_mut().send(body, None)
+        self.send_delivery(
+            body,
+            TransferOptions {
+                batchable: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Flush batched transfers
+    pub fn flush(&self) {
+        self.inner.get_ref().session.flush()
     }
 
-    pub fn send_with_tag<T>(
+    /// Send message, resolves with `AmqpTransportError::Timeout` error
+    /// if delivery is not settled by remote peer in time
+    pub fn send_with_timeout<T>(
         &self,
         body: T,
-        tag: Bytes,
+        timeout: Duration,
     ) -> impl Future<Output = Result<Disposition, AmqpTransportError>>
     where
         T: Into<TransferBody>,
     {
-        self.inner.get_mut().send(body, Some(tag))
+        let delivery = self.send_delivery(body, TransferOptions::default());
+        let inner = self.inner.clone();
+
+        async move {
+            match time::timeout(timeout, delivery).await {
+                Ok(res) => res,
+                Err(_) => {
+                    trace!("Delivery is not settled in {:?}", timeout);
+                    inner.get_mut().drop_canceled_deliveries();
+                    Err(AmqpTransportError::Timeout)
+                }
+            }
+        }
     }
 
     pub fn settle_message(&self, id: DeliveryNumber, state: DeliveryState) {
         self.inner.get_mut().settle_message(id, state)
     }
 
+    /// Set retry policy for rejected deliveries
+    ///
+    /// Policy applies to unsettled deliveries sent after this call.
+    pub fn set_retry_policy(&self, policy: Option<RetryPolicy>) {
+        self.inner.get_mut().retry_policy = policy;
+    }
+
+    /// Set delivery tag generation strategy
+    ///
+    /// Strategy applies to transfers sent after this call.
+    pub fn set_delivery_tag_strategy(&self, strategy: DeliveryTagStrategy) {
+        self.inner.get_mut().tag_strategy = strategy;
+    }
+
+    /// Send unsettled message, re-send rejected message according to retry policy
+    pub(crate) fn send_delivery<T>(&self, body: T, opts: TransferOptions) -> Delivery
+    where
+        T: Into<TransferBody>,
+    {
+        let inner = self.inner.get_mut();
+        let policy = match inner.retry_policy {
+            Some(ref policy) if inner.settle_mode != SenderSettleMode::Settled => policy.clone(),
+            _ => return inner.send(body, opts),
+        };
+
+        let body = body.into();
+        let mut delivery = inner.send(body.clone(), opts.clone());
+        let (promise, result) = DeliveryPromise::new();
+        let link = self.clone();
+
+        ntex::rt::spawn(async move {
+            let mut backoff = policy.backoff;
+            let mut retries = 0;
+            loop {
+                let res = delivery.await;
+                match res {
+                    Ok(ref disp)
+                        if retries < policy.max_retries
+                            && policy.is_retryable(disp)
+                            && !promise.is_canceled() =>
+                    {
+                        retries += 1;
+                        trace!(
+                            "Delivery is rejected with {:?}, retry {} in {:?}",
+                            disp.rejection_error(),
+                            retries,
+                            backoff
+                        );
+                        time::delay_for(backoff).await;
+                        backoff *= 2;
+                        delivery = link.inner.get_mut().send(body.clone(), opts.clone());
+                    }
+                    res => {
+                        let _ = promise.send(res);
+                        break;
+                    }
+                }
+            }
+        });
+        result
+    }
+
+    /// Re-send delivery that is unsettled on remote peer
+    ///
+    /// If remote peer reported delivery tag in attach unsettled map,
+    /// transfer is sent with `resume` flag set, so remote peer associates
+    /// it with existing delivery. Otherwise message is sent as new delivery.
+    pub fn resume<T>(&self, body: T, tag: Bytes) -> Delivery
+    where
+        T: Into<TransferBody>,
+    {
+        let inner = self.inner.get_mut();
+        let resume = inner.remote_unsettled.remove(&tag).is_some();
+        inner.send(
+            body,
+            TransferOptions {
+                tag: Some(tag),
+                resume,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Abort multi-frame delivery that is in progress
+    ///
+    /// Remote peer discards partially received message, delivery
+    /// resolves with `AmqpTransportError::Aborted` error.
+    /// Returns `false` if delivery is not in progress.
+    pub fn abort(&self, delivery: &Delivery) -> bool {
+        let inner = self.inner.get_ref();
+        inner
+            .session
+            .inner
+            .get_mut()
+            .abort_transfer(inner.id as Handle, delivery)
+    }
+
+    /// Settle delivery locally without waiting for remote peer
+    ///
+    /// Settled disposition with provided outcome is sent to remote peer,
+    /// delivery resolves with this outcome. Returns `false` if delivery is
+    /// already settled or is not sent yet.
+    pub fn settle<T: Into<DeliveryState>>(&self, delivery: &Delivery, outcome: T) -> bool {
+        let inner = self.inner.get_ref();
+        inner
+            .session
+            .inner
+            .get_mut()
+            .settle_transfer(inner.id as Handle, delivery, outcome.into())
+    }
+
+    /// Settle unsettled deliveries without waiting for remote peer
+    ///
+    /// Deliveries with contiguous ids are settled with single `Disposition`
+    /// frame. Multi-frame deliveries in progress are not settled.
+    /// Returns number of settled deliveries.
+    pub fn settle_many<T: Into<DeliveryState>>(
+        &self,
+        deliveries: &[&Delivery],
+        outcome: T,
+    ) -> usize {
+        let inner = self.inner.get_ref();
+        inner.session.inner.get_mut().settle_transfers(
+            inner.id as Handle,
+            Some(deliveries),
+            outcome.into(),
+        )
+    }
+
+    /// Settle all unsettled deliveries of the link without waiting for remote peer
+    ///
+    /// Same as `settle_many()` for all unsettled deliveries of the link.
+    pub fn settle_unsettled<T: Into<DeliveryState>>(&self, outcome: T) -> usize {
+        let inner = self.inner.get_ref();
+        inner
+            .session
+            .inner
+            .get_mut()
+            .settle_transfers(inner.id as Handle, None, outcome.into())
+    }
+
     pub fn close(&self) -> impl Future<Output = Result<(), AmqpTransportError>> {
         self.inner.get_mut().close(None)
     }
@@ -111,28 +726,131 @@ impl SenderLink {
     pub fn on_close(&self) -> condition::Waiter {
         self.inner.get_ref().on_close.wait()
     }
+
+    /// Sink of messages for pipelined sending
+    ///
+    /// Sink accepts new messages while number of unsettled deliveries sent
+    /// through the sink is less than `buffer`. Transfers are queued until
+    /// link credit and session window are available.
+    pub fn sink(&self, buffer: usize) -> SenderLinkSink {
+        SenderLinkSink {
+            link: self.clone(),
+            buffer: std::cmp::max(buffer, 1),
+            deliveries: FuturesUnordered::new(),
+        }
+    }
+}
+
+/// Sink of outgoing messages
+///
+/// Sink fails if link is detached or any of deliveries fails.
+pub struct SenderLinkSink {
+    link: SenderLink,
+    buffer: usize,
+    deliveries: FuturesUnordered<Delivery>,
+}
+
+impl std::fmt::Debug for SenderLinkSink {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("SenderLinkSink")
+            .field("link", &self.link)
+            .field("buffer", &self.buffer)
+            .field("deliveries", &self.deliveries.len())
+            .finish()
+    }
+}
+
+impl SenderLinkSink {
+    /// Number of unsettled deliveries sent through the sink
+    pub fn unsettled(&self) -> usize {
+        self.deliveries.len()
+    }
+
+    fn poll_settled(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), AmqpTransportError>> {
+        loop {
+            match Pin::new(&mut self.deliveries).poll_next(cx) {
+                Poll::Ready(Some(Ok(_))) => continue,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<T: Into<TransferBody>> Sink<T> for SenderLinkSink {
+    type Error = AmqpTransportError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(ref err) = self.link.inner.get_ref().error {
+            return Poll::Ready(Err(err.clone()));
+        }
+        if let Poll::Ready(Err(e)) = self.poll_settled(cx) {
+            return Poll::Ready(Err(e));
+        }
+        if self.deliveries.len() < self.buffer {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let delivery = self.link.send(item);
+        self.deliveries.push(delivery);
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.link.session().flush();
+        self.poll_settled(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.link.session().flush();
+        self.poll_settled(cx)
+    }
 }
 
 impl SenderLinkInner {
     pub(crate) fn new(
         id: usize,
         name: ByteString,
-        handle: Handle,
         delivery_count: SequenceNo,
+        attach: &Attach,
         session: Cell<SessionInner>,
     ) -> SenderLinkInner {
+        let default_outcome = attach
+            .source
+            .as_ref()
+            .and_then(|source| source.default_outcome.clone());
+
         SenderLinkInner {
             id,
             name,
+            address: target_address(attach),
             delivery_count,
-            idx: 0,
             session: Session::new(session),
-            remote_handle: handle,
+            remote_handle: attach.handle(),
             link_credit: 0,
+            default_outcome: default_outcome.unwrap_or_else(|| Outcome::Accepted(Accepted {})),
+            settle_mode: attach.snd_settle_mode(),
+            max_message_size: max_message_size(attach),
+            message_format: None,
+            remote_unsettled: unsettled_map(attach),
+            remote_offered_capabilities: attach.offered_capabilities.clone(),
+            remote_desired_capabilities: attach.desired_capabilities.clone(),
+            retry_policy: None,
+            tag_strategy: DeliveryTagStrategy::default(),
+            stats: SenderLinkStats::default(),
             pending_transfers: VecDeque::new(),
             error: None,
             closed: false,
+            local: true,
             on_close: condition::Condition::new(),
+            credit_waiters: Vec::new(),
+            handles: 0,
+            ready_task: LocalWaker::new(),
         }
     }
 
@@ -144,19 +862,38 @@ impl SenderLinkInner {
             }
         }
         let delivery_count = frame.initial_delivery_count.unwrap_or(0);
+        let default_outcome = frame
+            .source
+            .as_ref()
+            .and_then(|source| source.default_outcome.clone())
+            .unwrap_or_else(|| Outcome::Accepted(Accepted {}));
 
         SenderLinkInner {
             delivery_count,
             id: 0,
-            idx: 0,
             name: name.unwrap_or_else(ByteString::default),
+            address: target_address(frame),
             session: Session::new(session),
             remote_handle: frame.handle(),
             link_credit: 0,
+            default_outcome,
+            settle_mode: frame.snd_settle_mode(),
+            max_message_size: max_message_size(frame),
+            message_format: None,
+            remote_unsettled: unsettled_map(frame),
+            remote_offered_capabilities: frame.offered_capabilities.clone(),
+            remote_desired_capabilities: frame.desired_capabilities.clone(),
+            retry_policy: None,
+            tag_strategy: DeliveryTagStrategy::default(),
+            stats: SenderLinkStats::default(),
             pending_transfers: VecDeque::new(),
             error: None,
             closed: false,
+            local: false,
             on_close: condition::Condition::new(),
+            credit_waiters: Vec::new(),
+            handles: 0,
+            ready_task: LocalWaker::new(),
         }
     }
 
@@ -164,14 +901,43 @@ impl SenderLinkInner {
         self.id as u32
     }
 
-    pub(crate) fn remote_handle(&self) -> Handle {
-        self.remote_handle
-    }
-
     pub(crate) fn name(&self) -> &ByteString {
         &self.name
     }
 
+    /// Link is not closed or detached
+    pub(crate) fn is_opened(&self) -> bool {
+        !self.closed && self.error.is_none()
+    }
+
+    /// Outcome for deliveries settled by remote peer without delivery state
+    pub(crate) fn default_outcome(&self) -> &Outcome {
+        &self.default_outcome
+    }
+
+    pub(crate) fn stats(&self) -> SenderLinkStats {
+        SenderLinkStats {
+            unsettled: self
+                .session
+                .inner
+                .get_ref()
+                .unsettled_deliveries(self.id as Handle)
+                .len(),
+            ..self.stats
+        }
+    }
+
+    /// Record outcome of settled delivery
+    pub(crate) fn delivery_settled(&mut self, state: Option<&DeliveryState>) {
+        match state {
+            Some(state) if state.is_accepted() => self.stats.accepted += 1,
+            Some(state) if state.is_rejected() => self.stats.rejected += 1,
+            Some(state) if state.is_released() => self.stats.released += 1,
+            Some(state) if state.is_modified() => self.stats.modified += 1,
+            _ => (),
+        }
+    }
+
     pub(crate) fn detached(&mut self, err: AmqpTransportError) {
         trace!("Detaching sender link {:?} with error {:?}", self.name, err);
 
@@ -180,8 +946,30 @@ impl SenderLinkInner {
             let _ = tr.promise.send(Err(err.clone()));
         }
 
+        self.drop_credit_waiters(&err);
         self.error = Some(err);
         self.on_close.notify();
+        self.ready_task.wake();
+    }
+
+    fn drop_credit_waiters(&mut self, err: &AmqpTransportError) {
+        for (_, tx) in self.credit_waiters.drain(..) {
+            let _ = tx.send(Err(err.clone()));
+        }
+    }
+
+    /// Notify credit waiters which credit is granted
+    fn notify_credit_waiters(&mut self) {
+        let credit = self.link_credit;
+        let mut idx = 0;
+        while idx < self.credit_waiters.len() {
+            if self.credit_waiters[idx].0 <= credit {
+                let (_, tx) = self.credit_waiters.swap_remove(idx);
+                let _ = tx.send(Ok(()));
+            } else {
+                idx += 1;
+            }
+        }
     }
 
     pub(crate) fn close(
@@ -193,6 +981,15 @@ impl SenderLinkInner {
         } else {
             self.closed = true;
             self.on_close.notify();
+            self.ready_task.wake();
+
+            // drop pending transfers
+            let err = AmqpTransportError::LinkDetached(error.clone());
+            for tr in self.pending_transfers.drain(..) {
+                let _ = tr.promise.send(Err(err.clone()));
+            }
+            self.drop_credit_waiters(&err);
+            self.error = Some(err);
 
             let (tx, rx) = oneshot::channel();
 
@@ -211,7 +1008,11 @@ impl SenderLinkInner {
         }
     }
 
-    pub(crate) fn apply_flow(&mut self, flow: &Flow) {
+    /// Apply link flow received by session
+    ///
+    /// Session is borrowed mutably by the caller, frames are posted
+    /// through provided session instead of link's own session cell.
+    pub(crate) fn apply_flow(&mut self, flow: &Flow, session: &mut SessionInner) {
         // #2.7.6
         if let Some(credit) = flow.link_credit() {
             trace!(
@@ -222,77 +1023,148 @@ impl SenderLinkInner {
                 self.delivery_count
             );
 
-            let delta = flow
-                .delivery_count
-                .unwrap_or(0)
-                .saturating_add(credit)
-                .saturating_sub(self.delivery_count);
-            self.link_credit += delta;
-
-            let session = self.session.inner.get_mut();
+            // link-credit(snd) := delivery-count(rcv) + link-credit(rcv) - delivery-count(snd)
+            // delivery counts are sequence numbers, compare them with serial arithmetic
+            let rcv_count = flow.delivery_count.unwrap_or(self.delivery_count);
+            let in_flight = self.delivery_count.wrapping_sub(rcv_count);
+            self.link_credit = if in_flight <= std::i32::MAX as u32 {
+                credit.saturating_sub(in_flight)
+            } else {
+                credit.saturating_add(rcv_count.wrapping_sub(self.delivery_count))
+            };
 
             // credit became available => drain pending_transfers
             while self.link_credit > 0 {
                 if let Some(transfer) = self.pending_transfers.pop_front() {
                     self.link_credit -= 1;
-                    self.delivery_count = self.delivery_count.saturating_add(1);
+                    self.delivery_count = self.delivery_count.wrapping_add(1);
+                    self.stats.sent += 1;
                     session.send_transfer(
-                        self.remote_handle,
-                        transfer.idx,
+                        self.id as Handle,
                         transfer.body,
                         transfer.promise,
-                        transfer.tag,
-                        transfer.settle,
+                        transfer.opts,
                     );
                 } else {
                     break;
                 }
             }
+            if self.link_credit > 0 {
+                self.ready_task.wake();
+                self.notify_credit_waiters();
+            }
         }
 
-        if flow.echo() {
-            // todo: send flow
+        if flow.drain() {
+            // #2.6.7 use up remaining credit by advancing delivery count
+            self.delivery_count = self.delivery_count.wrapping_add(self.link_credit);
+            self.link_credit = 0;
+            session.link_flow(self.id as Handle, self.delivery_count, 0, true);
+        } else if flow.echo() {
+            session.link_flow(
+                self.id as Handle,
+                self.delivery_count,
+                self.link_credit,
+                false,
+            );
         }
     }
 
-    pub(crate) fn send<T: Into<TransferBody>>(&mut self, body: T, tag: Option<Bytes>) -> Delivery {
+    pub(crate) fn send<T: Into<TransferBody>>(
+        &mut self,
+        body: T,
+        mut opts: TransferOptions,
+    ) -> Delivery {
         if let Some(ref err) = self.error {
-            Delivery::Resolved(Err(err.clone()))
+            Delivery::resolved(Err(err.clone()))
+        } else if opts.settled && self.settle_mode == SenderSettleMode::Unsettled {
+            Delivery::resolved(Err(AmqpTransportError::Protocol(Error {
+                condition: AmqpError::NotAllowed.into(),
+                description: Some(ByteString::from_static(
+                    "Pre-settled transfer on unsettled link",
+                )),
+                info: None,
+            })))
         } else {
             let body = body.into();
-            let (delivery_tx, delivery_rx) = oneshot::channel();
+            if let Some(max) = self.max_message_size {
+                if body.len() as u64 > max {
+                    log::trace!(
+                        "Message size {} exceeds max message size {}",
+                        body.len(),
+                        max
+                    );
+                    return Delivery::resolved(Err(AmqpTransportError::MessageTooLarge));
+                }
+            }
+
+            if let Some(format) = self.message_format {
+                // absent message format is the standard amqp format
+                let msg_format = body.message_format().unwrap_or(0);
+                if msg_format != format {
+                    log::trace!(
+                        "Message format {} does not match link message format {}",
+                        msg_format,
+                        format
+                    );
+                    return Delivery::resolved(Err(AmqpTransportError::MessageFormatMismatch(
+                        msg_format, format,
+                    )));
+                }
+            }
+
+            if let Some(max) = self.session.inner.get_ref().max_pending_transfers() {
+                let queued = if self.link_credit == 0 {
+                    Some(self.pending_transfers.len())
+                } else {
+                    self.session.inner.get_ref().queued_transfers()
+                };
+                if let Some(queued) = queued.filter(|queued| *queued >= max) {
+                    log::trace!("Transfers queue is full, queued transfers: {}", queued);
+                    return Delivery::resolved(Err(AmqpTransportError::Full(queued)));
+                }
+            }
+
+            opts.tag = match opts.tag.take().or_else(|| self.tag_strategy.next_tag()) {
+                Some(tag) if tag.len() > MAX_DELIVERY_TAG_SIZE => {
+                    log::trace!("Generated delivery tag is too long: {}", tag.len());
+                    return Delivery::resolved(Err(tag_size_error(tag.len())));
+                }
+                tag => tag,
+            };
+
+            opts.settled = opts.settled || self.settle_mode == SenderSettleMode::Settled;
+            let (delivery_tx, delivery) = DeliveryPromise::new();
             if self.link_credit == 0 {
                 log::trace!(
                     "Sender link credit is 0, push to pending queue hnd:{} {:?}, queue size: {}",
                     self.remote_handle,
-                    tag,
+                    opts.tag,
                     self.pending_transfers.len()
                 );
                 self.pending_transfers.push_back(PendingTransfer {
-                    tag,
-                    settle: Some(false),
                     body: Some(body),
-                    idx: self.idx,
                     promise: delivery_tx,
+                    opts,
                 });
             } else {
                 let session = self.session.inner.get_mut();
                 self.link_credit -= 1;
-                self.delivery_count = self.delivery_count.saturating_add(1);
-                session.send_transfer(
-                    self.remote_handle,
-                    self.idx,
-                    Some(body),
-                    delivery_tx,
-                    tag,
-                    None,
-                );
+                self.delivery_count = self.delivery_count.wrapping_add(1);
+                self.stats.sent += 1;
+                session.send_transfer(self.id as Handle, Some(body), delivery_tx, opts);
             }
-            self.idx = self.idx.saturating_add(1);
-            Delivery::Pending(delivery_rx)
+            delivery
         }
     }
 
+    /// Forget deliveries which futures are dropped
+    pub(crate) fn drop_canceled_deliveries(&mut self) {
+        self.pending_transfers
+            .retain(|tr| !tr.promise.is_canceled());
+        self.session.inner.get_mut().drop_canceled_deliveries();
+    }
+
     pub(crate) fn settle_message(&mut self, id: DeliveryNumber, state: DeliveryState) {
         let disp = Disposition {
             role: Role::Sender,
@@ -309,6 +1181,10 @@ impl SenderLinkInner {
 pub struct SenderLinkBuilder {
     frame: Attach,
     session: Cell<SessionInner>,
+    timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+    message_format: Option<MessageFormat>,
+    tag_strategy: DeliveryTagStrategy,
 }
 
 impl SenderLinkBuilder {
@@ -329,17 +1205,60 @@ impl SenderLinkBuilder {
             snd_settle_mode: SenderSettleMode::Mixed,
             rcv_settle_mode: ReceiverSettleMode::First,
             source: None,
-            target: Some(target),
+            target: Some(target.into()),
             unsettled: None,
             incomplete_unsettled: false,
-            initial_delivery_count: None,
+            initial_delivery_count: Some(0),
             max_message_size: Some(65536 * 4),
             offered_capabilities: None,
             desired_capabilities: None,
             properties: None,
         };
 
-        SenderLinkBuilder { frame, session }
+        SenderLinkBuilder {
+            frame,
+            session,
+            timeout: None,
+            retry_policy: None,
+            message_format: None,
+            tag_strategy: DeliveryTagStrategy::default(),
+        }
+    }
+
+    /// Set sender settle mode
+    ///
+    /// With `SenderSettleMode::Settled` mode all transfers are sent pre-settled.
+    pub fn settle_mode(mut self, mode: SenderSettleMode) -> Self {
+        self.frame.snd_settle_mode = mode;
+        self
+    }
+
+    /// Set receiver settle mode
+    ///
+    /// By default receiver settle mode is set to `ReceiverSettleMode::First`
+    pub fn receiver_settle_mode(mut self, mode: ReceiverSettleMode) -> Self {
+        self.frame.rcv_settle_mode = mode;
+        self
+    }
+
+    /// Set target terminus durability
+    ///
+    /// By default durability is set to `TerminusDurability::None`
+    pub fn durable(mut self, durability: TerminusDurability) -> Self {
+        if let Some(target) = self.frame.target.as_mut().and_then(|t| t.target_mut()) {
+            target.durable = durability;
+        }
+        self
+    }
+
+    /// Set target terminus expiry policy
+    ///
+    /// By default expiry policy is set to `TerminusExpiryPolicy::SessionEnd`
+    pub fn expiry_policy(mut self, policy: TerminusExpiryPolicy) -> Self {
+        if let Some(target) = self.frame.target.as_mut().and_then(|t| t.target_mut()) {
+            target.expiry_policy = policy;
+        }
+        self
     }
 
     pub fn max_message_size(mut self, size: u64) -> Self {
@@ -347,6 +1266,81 @@ impl SenderLinkBuilder {
         self
     }
 
+    /// Set unsettled deliveries for link resume
+    ///
+    /// Maps delivery tag to the last known delivery state, unsettled map
+    /// is sent to remote peer with `Attach` frame.
+    pub fn unsettled<I>(mut self, deliveries: I) -> Self
+    where
+        I: IntoIterator<Item = (Bytes, Option<DeliveryState>)>,
+    {
+        let map = deliveries
+            .into_iter()
+            .map(|(tag, state)| {
+                let state = state.map_or(Variant::Null, |state| state.to_variant());
+                (Variant::Binary(tag), state)
+            })
+            .collect();
+        self.frame.unsettled = Some(map);
+        self
+    }
+
+    /// Set capabilities offered by this link
+    pub fn offered_capabilities(mut self, caps: Symbols) -> Self {
+        self.frame.offered_capabilities = Some(caps);
+        self
+    }
+
+    /// Set capabilities desired from remote peer
+    pub fn desired_capabilities(mut self, caps: Symbols) -> Self {
+        self.frame.desired_capabilities = Some(caps);
+        self
+    }
+
+    /// Set initial delivery count of the link
+    ///
+    /// By default initial delivery count is set to 0
+    pub fn initial_delivery_count(mut self, count: SequenceNo) -> Self {
+        self.frame.initial_delivery_count = Some(count);
+        self
+    }
+
+    /// Set link open timeout
+    ///
+    /// If remote peer does not confirm link in time, link get detached
+    /// and `open()` fails with `AmqpTransportError::Timeout` error.
+    /// By default timeout is not set.
+    pub fn open_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set retry policy for rejected deliveries
+    ///
+    /// By default rejected deliveries are not retried.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Set message format agreed with remote peer
+    ///
+    /// Sending message of other format fails with
+    /// `AmqpTransportError::MessageFormatMismatch` error.
+    /// By default message format is not checked.
+    pub fn message_format(mut self, format: MessageFormat) -> Self {
+        self.message_format = Some(format);
+        self
+    }
+
+    /// Set delivery tag generation strategy
+    ///
+    /// By default delivery tag is the session delivery id.
+    pub fn delivery_tag_strategy(mut self, strategy: DeliveryTagStrategy) -> Self {
+        self.tag_strategy = strategy;
+        self
+    }
+
     pub fn with_frame<F>(mut self, f: F) -> Self
     where
         F: FnOnce(&mut Attach),
@@ -356,10 +1350,32 @@ impl SenderLinkBuilder {
     }
 
     pub async fn open(self) -> Result<SenderLink, AmqpTransportError> {
-        let result = self.session.get_mut().open_sender_link(self.frame).await;
+        let name = self.frame.name.clone();
+        let fut = self.session.get_mut().open_sender_link(self.frame);
+        let guard = OpeningLink::new(self.session.clone(), name.clone());
+
+        let result = if let Some(timeout) = self.timeout {
+            match time::timeout(timeout, fut).await {
+                Ok(res) => res,
+                Err(_) => {
+                    self.session
+                        .get_mut()
+                        .cancel_opening_link(&name, &self.session, timeout);
+                    return Err(AmqpTransportError::Timeout);
+                }
+            }
+        } else {
+            fut.await
+        };
+        guard.completed();
 
         match result {
-            Ok(Ok(link)) => Ok(link),
+            Ok(Ok(link)) => {
+                link.inner.get_mut().retry_policy = self.retry_policy;
+                link.inner.get_mut().message_format = self.message_format;
+                link.inner.get_mut().tag_strategy = self.tag_strategy;
+                Ok(link)
+            }
             Ok(Err(e)) => Err(e),
             Err(_) => Err(AmqpTransportError::Disconnected),
         }
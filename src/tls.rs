@@ -0,0 +1,116 @@
+//! Tls transport based on rustls
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytestring::ByteString;
+use ntex::connect::{Connect, ConnectError, Connector};
+use ntex::http::Uri;
+use ntex::rt::net::TcpStream;
+use ntex::service::Service;
+use tokio_rustls::rustls::{Certificate, PrivateKey, TLSError};
+use webpki::DNSNameRef;
+
+pub use tokio_rustls::client::TlsStream;
+pub use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+/// Tls connector service
+///
+/// Opens tcp connection and performs tls handshake before amqp protocol
+/// negotiation, could be used with `sasl::connect_service()`. Server
+/// certificate is validated against configured root store.
+#[derive(Clone)]
+pub struct TlsConnector {
+    connector: Connector<Uri>,
+    config: Arc<ClientConfig>,
+    server_name: Option<ByteString>,
+}
+
+impl std::fmt::Debug for TlsConnector {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("TlsConnector")
+            .field("server_name", &self.server_name)
+            .finish()
+    }
+}
+
+impl TlsConnector {
+    /// Create connector that trusts certificates from root store
+    pub fn new(roots: RootCertStore) -> Self {
+        let mut config = ClientConfig::new();
+        config.root_store = roots;
+        Self::from_config(Arc::new(config))
+    }
+
+    /// Create connector with custom rustls client configuration
+    pub fn from_config(config: Arc<ClientConfig>) -> Self {
+        TlsConnector {
+            config,
+            connector: Connector::default(),
+            server_name: None,
+        }
+    }
+
+    /// Set server name for sni and certificate validation
+    ///
+    /// By default uri host is used.
+    pub fn server_name<T: Into<ByteString>>(mut self, name: T) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+
+    /// Set client certificate chain and private key
+    ///
+    /// Certificate is presented to server during handshake,
+    /// could be used for `SASL EXTERNAL` authentication.
+    pub fn client_cert(
+        mut self,
+        certs: Vec<Certificate>,
+        key: PrivateKey,
+    ) -> Result<Self, TLSError> {
+        Arc::make_mut(&mut self.config).set_single_client_cert(certs, key)?;
+        Ok(self)
+    }
+}
+
+impl Service for TlsConnector {
+    type Request = Connect<Uri>;
+    type Response = TlsStream<TcpStream>;
+    type Error = ConnectError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    #[inline]
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.connector.poll_ready(cx)
+    }
+
+    fn call(&self, req: Connect<Uri>) -> Self::Future {
+        let host = self
+            .server_name
+            .as_ref()
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| req.host().to_string());
+        let fut = self.connector.call(req);
+        let connector = tokio_rustls::TlsConnector::from(self.config.clone());
+
+        Box::pin(async move {
+            let io = fut.await?;
+            trace!("Tls handshake start for: {:?}", host);
+
+            let name = DNSNameRef::try_from_ascii_str(&host)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{}", e)))?;
+            match connector.connect(name, io).await {
+                Ok(io) => {
+                    trace!("Tls handshake success: {:?}", host);
+                    Ok(io)
+                }
+                Err(e) => {
+                    trace!("Tls handshake error: {:?}", e);
+                    Err(ConnectError::Io(e))
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,173 @@
+use std::future::Future;
+
+use bytes::Bytes;
+use bytestring::ByteString;
+use ntex_amqp_codec::protocol::{
+    AmqpError, AttachTarget, Coordinator, DeliveryNumber, DeliveryState, Error, TransferBody,
+};
+use ntex_amqp_codec::types::{Descriptor, List, Variant};
+use ntex_amqp_codec::OutMessage;
+use uuid::Uuid;
+
+use crate::errors::AmqpTransportError;
+use crate::rcvlink::ReceiverLink;
+use crate::session::{Session, TransferOptions};
+use crate::sndlink::SenderLink;
+use crate::Delivery;
+
+const DECLARE_CODE: u64 = 0x0000_0000_0000_0031;
+const DISCHARGE_CODE: u64 = 0x0000_0000_0000_0032;
+
+/// Declared transaction
+///
+/// Transaction is declared over sender link to remote transaction coordinator.
+/// Transfers and dispositions get associated with transaction via
+/// `Transaction::send()`, `Transaction::settle_message()` and
+/// `Transaction::settle_range()`. Work is
+/// discharged with `Transaction::commit()` or `Transaction::rollback()`,
+/// transaction that is dropped without discharge is rolled back by coordinator
+/// once controller link get detached.
+pub struct Transaction {
+    id: Bytes,
+    controller: SenderLink,
+}
+
+impl std::fmt::Debug for Transaction {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("Transaction")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl Transaction {
+    /// Open controller link and declare new transaction
+    pub(crate) fn declare(
+        session: &mut Session,
+    ) -> impl Future<Output = Result<Transaction, AmqpTransportError>> {
+        let name = ByteString::from(format!("txn-{}", Uuid::new_v4().to_simple()));
+        let link = session
+            .build_sender_link(name, "")
+            .with_frame(|attach| {
+                attach.target = Some(AttachTarget::Coordinator(Coordinator {
+                    capabilities: None,
+                }))
+            })
+            .open();
+
+        async move {
+            let controller = link.await?;
+            let declare = coordinator_message(DECLARE_CODE, Vec::new());
+            let disp = controller.send(declare).await?;
+
+            match disp.state() {
+                Some(DeliveryState::Declared(declared)) => Ok(Transaction {
+                    id: declared.txn_id.clone(),
+                    controller,
+                }),
+                state => Err(coordinator_error(state, "Transaction is not declared")),
+            }
+        }
+    }
+
+    /// Transaction id assigned by coordinator
+    pub fn id(&self) -> &Bytes {
+        &self.id
+    }
+
+    /// Send message as part of transaction
+    pub fn send<T>(&self, link: &SenderLink, body: T) -> Delivery
+    where
+        T: Into<TransferBody>,
+    {
+        link.send_delivery(
+            body,
+            TransferOptions {
+                txn_id: Some(self.id.clone()),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Settle incoming delivery as part of transaction
+    ///
+    /// Terminal delivery state is wrapped into `TransactionalState`.
+    pub fn settle_message(&self, link: &ReceiverLink, id: DeliveryNumber, state: DeliveryState) {
+        link.inner
+            .get_mut()
+            .settle_message(id, state, Some(self.id.clone()))
+    }
+
+    /// Settle range of incoming deliveries as part of transaction
+    ///
+    /// Same as `ReceiverLink::settle_range()`, terminal delivery state
+    /// is wrapped into `TransactionalState`.
+    pub fn settle_range(
+        &self,
+        link: &ReceiverLink,
+        first: DeliveryNumber,
+        last: DeliveryNumber,
+        state: DeliveryState,
+    ) {
+        link.inner
+            .get_mut()
+            .settle_range(first, last, state, Some(self.id.clone()))
+    }
+
+    /// Commit transaction
+    ///
+    /// Resolves once coordinator settles discharge.
+    pub async fn commit(self) -> Result<(), AmqpTransportError> {
+        self.discharge(false).await
+    }
+
+    /// Rollback transaction
+    ///
+    /// Resolves once coordinator settles discharge.
+    pub async fn rollback(self) -> Result<(), AmqpTransportError> {
+        self.discharge(true).await
+    }
+
+    async fn discharge(self, fail: bool) -> Result<(), AmqpTransportError> {
+        let discharge = coordinator_message(
+            DISCHARGE_CODE,
+            vec![Variant::Binary(self.id.clone()), Variant::Boolean(fail)],
+        );
+        // controller link is closed even if discharge fails
+        let res = self.controller.send(discharge).await;
+        let _ = self.controller.close().await;
+        let disp = res?;
+
+        if disp.is_accepted() {
+            Ok(())
+        } else {
+            Err(coordinator_error(
+                disp.state(),
+                "Transaction discharge is not accepted",
+            ))
+        }
+    }
+}
+
+/// Message with described coordinator performative as amqp-value body
+fn coordinator_message(code: u64, fields: Vec<Variant>) -> OutMessage {
+    OutMessage::with_value(Variant::Described((
+        Descriptor::Ulong(code),
+        Box::new(Variant::List(List(fields))),
+    )))
+}
+
+fn coordinator_error(
+    state: Option<&DeliveryState>,
+    description: &'static str,
+) -> AmqpTransportError {
+    if let Some(err) = state.and_then(|s| s.rejection_error()) {
+        AmqpTransportError::Protocol(err.clone())
+    } else {
+        AmqpTransportError::Protocol(Error {
+            condition: AmqpError::IllegalState.into(),
+            description: Some(ByteString::from_static(description)),
+            info: None,
+        })
+    }
+}
@@ -1,14 +1,36 @@
+use std::cell::RefCell;
 use std::convert::TryFrom;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use futures::future::{err, Ready};
-use futures::Future;
-use ntex::codec::{AsyncRead, AsyncWrite};
+use bytes::Bytes;
+use bytestring::ByteString;
+use futures::future::{err, ok, pending, ready, Ready};
+use futures::{Future, FutureExt, SinkExt, StreamExt};
+use ntex::codec::{AsyncRead, AsyncWrite, BytesCodec, Framed};
 use ntex::connect::Connector;
 use ntex::http::Uri;
-use ntex::server::test_server;
-use ntex::service::{fn_factory_with_config, pipeline_factory, Service};
+use ntex::rt::net::TcpStream;
+use ntex::rt::time::delay_for;
+use ntex::server::{test_server, TestServer};
+use ntex::service::{fn_factory_with_config, fn_service, pipeline_factory, Service};
+use ntex::util::time::LowResTimeService;
+use ntex_amqp::codec::protocol::{
+    Accepted, Attach, Begin, Close, Declared, DeliveryState, Detach, Disposition, End, Fields,
+    Flow, Frame, MessageId, Modified, Outcome, ProtocolId, Received, ReceiverSettleMode, Rejected,
+    Released, Role, SaslCode, SenderSettleMode, Target, TerminusDurability, TerminusExpiryPolicy,
+    TransactionalState, Transfer, TransferBody, TransferNumber,
+};
+use ntex_amqp::codec::types::{Descriptor, List, Multiple, Symbol, Variant};
+use ntex_amqp::codec::{AmqpCodec, AmqpFrame, Decode, InMessage, ProtocolIdCodec};
 use ntex_amqp::server::{self, AmqpError, LinkError};
-use ntex_amqp::{sasl, Configuration};
+use ntex_amqp::{
+    sasl, AmqpTransportError, Configuration, Connection, ConnectionController, DeliveryTagStrategy,
+    Direction, ReconnectPolicy, ReconnectingConnection, RequestReply, Session, SessionEvent,
+    SharedCredit,
+};
 
 fn server(
     link: server::Link<()>,
@@ -19,7 +41,7 @@ fn server(
                     Request = server::Message<()>,
                     Response = server::Outcome,
                     Error = AmqpError,
-                    Future = Ready<Result<server::Message<()>, server::Outcome>>,
+                    Future = Ready<Result<server::Outcome, AmqpError>>,
                 > + 'static,
         >,
         LinkError,
@@ -59,6 +81,7 @@ async fn test_simple() -> std::io::Result<()> {
             authz_id: "".to_string(),
             authn_id: "user1".to_string(),
             password: "password1".to_string(),
+            ..Default::default()
         },
     };
     let res = sasl_srv.call(req).await;
@@ -123,6 +146,7 @@ async fn test_sasl() -> std::io::Result<()> {
             authz_id: "".to_string(),
             authn_id: "user1".to_string(),
             password: "password1".to_string(),
+            ..Default::default()
         },
     };
     let res = sasl_srv.call(req).await;
@@ -130,3 +154,7045 @@ async fn test_sasl() -> std::io::Result<()> {
 
     Ok(())
 }
+
+fn start_server() -> TestServer {
+    test_server(|| {
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(pipeline_factory(sasl_auth).map_err(|e| e.into())),
+        )
+        .finish(
+            server::App::<()>::new()
+                .service("test", fn_factory_with_config(server))
+                .service(
+                    "accept",
+                    fn_factory_with_config(|_: server::Link<()>| {
+                        ok::<_, LinkError>(fn_service(|_: server::Message<()>| {
+                            ok::<_, AmqpError>(server::Outcome::Accept)
+                        }))
+                    }),
+                )
+                .service(
+                    "reject",
+                    fn_factory_with_config(|_: server::Link<()>| {
+                        ok::<_, LinkError>(fn_service(|_: server::Message<()>| {
+                            ok::<_, AmqpError>(server::Outcome::Error(
+                                AmqpError::not_allowed().description("rejected").into(),
+                            ))
+                        }))
+                    }),
+                )
+                .service(
+                    "durable",
+                    fn_factory_with_config(|link: server::Link<()>| {
+                        let target = link.frame().target.as_ref().and_then(|t| t.target());
+                        let durable = target.map_or(false, |target| {
+                            target.durable == TerminusDurability::UnsettledState
+                                && target.expiry_policy == TerminusExpiryPolicy::Never
+                        });
+                        ready(if durable {
+                            Ok(fn_service(|_: server::Message<()>| {
+                                ok::<_, AmqpError>(server::Outcome::Accept)
+                            }))
+                        } else {
+                            Err(LinkError::force_detach().description("durable link is required"))
+                        })
+                    }),
+                )
+                .service(
+                    "hang",
+                    fn_factory_with_config(|_: server::Link<()>| {
+                        ok::<_, LinkError>(fn_service(|_: server::Message<()>| {
+                            pending::<Result<server::Outcome, AmqpError>>()
+                        }))
+                    }),
+                )
+                .finish(),
+        )
+    })
+}
+
+async fn open_session(srv: &TestServer) -> Session {
+    open_session_with_config(srv, Configuration::default()).await
+}
+
+async fn open_session_with_config(srv: &TestServer, config: Configuration) -> Session {
+    let uri = Uri::try_from(format!("amqp://{}:{}", srv.addr().ip(), srv.addr().port())).unwrap();
+    let sasl_srv = sasl::connect_service(Connector::default());
+    let req = sasl::SaslConnect {
+        uri,
+        config,
+        time: None,
+        auth: sasl::SaslAuth {
+            authz_id: "".to_string(),
+            authn_id: "user1".to_string(),
+            password: "password1".to_string(),
+            ..Default::default()
+        },
+    };
+    let mut conn = sasl_srv.call(req).await.unwrap();
+    let session = conn.open_session();
+    ntex::rt::spawn(conn.map(|_| ()));
+    session.await.unwrap()
+}
+
+#[ntex::test]
+async fn test_receiver_link() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session.open_receiver_link("test-receiver", "test").await;
+    assert!(link.is_ok());
+
+    Ok(())
+}
+
+async fn control(frame: server::ControlFrame<()>) -> Result<(), LinkError> {
+    if let server::ControlFrameKind::AttachSender(ref attach, ref link) = frame.frame() {
+        let link = link.clone();
+        if attach.name() == "test-detach" {
+            ntex::rt::spawn(async move {
+                let _ = link.close().await;
+            });
+            return Ok(());
+        }
+        if attach.name() == "test-many" {
+            ntex::rt::spawn(async move {
+                let deliveries: Vec<_> = (0..20)
+                    .map(|idx| link.send(Bytes::from(idx.to_string())))
+                    .collect();
+                let res = futures::future::join_all(deliveries).await;
+                if res.iter().all(|res| res.is_ok()) {
+                    let _ = link.send(Bytes::from_static(b"done")).await;
+                }
+            });
+            return Ok(());
+        }
+        let body = if attach.name() == "test-large" {
+            Bytes::from(vec![b'x'; 4096])
+        } else {
+            Bytes::from_static(b"test message")
+        };
+        ntex::rt::spawn(async move {
+            let _ = link.send(body).await;
+        });
+    }
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_transfer() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(pipeline_factory(sasl_auth).map_err(|e| e.into())),
+        )
+        .control(fn_service(control))
+        .finish(
+            server::App::<()>::new()
+                .service("test", fn_factory_with_config(server))
+                .finish(),
+        )
+    });
+    let mut session = open_session(&srv).await;
+
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(10);
+
+    let transfer = link.next().await.unwrap().unwrap();
+    assert_eq!(
+        transfer.body,
+        Some(TransferBody::Data(Bytes::from_static(b"test message")))
+    );
+    link.settle_message(
+        transfer.delivery_id.unwrap(),
+        DeliveryState::Accepted(Accepted {}),
+    );
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_multi_frame_transfer() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(pipeline_factory(sasl_auth).map_err(|e| e.into())),
+        )
+        .control(fn_service(control))
+        .finish(
+            server::App::<()>::new()
+                .service("test", fn_factory_with_config(server))
+                .finish(),
+        )
+    });
+
+    let mut config = Configuration::default();
+    config.max_frame_size(1024);
+    let mut session = open_session_with_config(&srv, config).await;
+
+    let mut link = session
+        .open_receiver_link("test-large", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(10);
+
+    let transfer = link.next().await.unwrap().unwrap();
+    assert!(!transfer.more);
+    assert_eq!(
+        transfer.body,
+        Some(TransferBody::Data(Bytes::from(vec![b'x'; 4096])))
+    );
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_close() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session.open_receiver_link("test-receiver", "test").await;
+    assert!(link.is_ok());
+
+    let res = session.close().await;
+    assert!(res.is_ok());
+
+    let link = session.open_receiver_link("test-receiver2", "test").await;
+    assert!(link.is_err());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_close_after_settled() -> std::io::Result<()> {
+    let srv = start_server();
+
+    // outstanding deliveries are settled before session end
+    let mut session = open_session(&srv).await;
+    let link = session
+        .build_sender_link("test-sender", "accept")
+        .open()
+        .await
+        .unwrap();
+    let delivery = link.send(Bytes::from_static(b"test message"));
+    let close = session.close_after_settled(Duration::from_secs(5));
+
+    // no new transfers once close begins
+    let res = link.send(Bytes::from_static(b"test message")).await;
+    assert!(matches!(res, Err(AmqpTransportError::SessionEnded(None))));
+
+    assert!(close.await.is_ok());
+    assert!(delivery.await.unwrap().is_accepted());
+
+    // "hang" service never settles deliveries, session closes after timeout
+    let mut session = open_session(&srv).await;
+    let link = session
+        .build_sender_link("test-sender", "hang")
+        .open()
+        .await
+        .unwrap();
+    let delivery = link.send(Bytes::from_static(b"test message"));
+    let start = std::time::Instant::now();
+    assert!(session
+        .close_after_settled(Duration::from_millis(100))
+        .await
+        .is_ok());
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    let res = delivery.await;
+    assert!(matches!(res, Err(AmqpTransportError::SessionEnded(None))));
+
+    // dropped close future does not leave session draining
+    let mut session = open_session(&srv).await;
+    let link = session
+        .build_sender_link("test-sender", "hang")
+        .open()
+        .await
+        .unwrap();
+    let _delivery = link.send(Bytes::from_static(b"test message"));
+    let res = ntex::rt::time::timeout(
+        Duration::from_millis(100),
+        session.close_after_settled(Duration::from_secs(5)),
+    )
+    .await;
+    assert!(res.is_err());
+    drop(session.close_after_settled(Duration::from_secs(5)));
+    let res = ntex::rt::time::timeout(
+        Duration::from_millis(100),
+        link.send(Bytes::from_static(b"test message")),
+    )
+    .await;
+    assert!(res.is_err());
+
+    // unsettled deliveries fail on immediate close
+    let mut session = open_session(&srv).await;
+    let link = session
+        .build_sender_link("test-sender", "hang")
+        .open()
+        .await
+        .unwrap();
+    let delivery = link.send(Bytes::from_static(b"test message"));
+    assert!(session.close().await.is_ok());
+    let res = delivery.await;
+    assert!(matches!(res, Err(AmqpTransportError::SessionEnded(None))));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_detached_by_peer() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "test")
+        .open()
+        .await;
+    assert!(link.is_err());
+
+    // link slot is released, session is still usable
+    let link = session.open_receiver_link("test-receiver", "test").await;
+    assert!(link.is_ok());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_detached_by_peer() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(pipeline_factory(sasl_auth).map_err(|e| e.into())),
+        )
+        .control(fn_service(control))
+        .finish(
+            server::App::<()>::new()
+                .service("test", fn_factory_with_config(server))
+                .finish(),
+        )
+    });
+    let mut session = open_session(&srv).await;
+
+    let mut link = session
+        .open_receiver_link("test-detach", "test")
+        .await
+        .unwrap();
+    assert!(link.next().await.is_none());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_close() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "accept")
+        .open()
+        .await
+        .unwrap();
+    let disp = link.send(Bytes::from_static(b"test message")).await;
+    assert!(disp.is_ok());
+
+    assert!(link.close().await.is_ok());
+    assert!(session.get_sender_link("test-sender").is_none());
+    assert!(link
+        .send(Bytes::from_static(b"test message"))
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_drop() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "accept")
+        .open()
+        .await
+        .unwrap();
+    assert!(session.get_sender_link("test-sender").is_some());
+
+    // link is detached once last user copy is dropped
+    let link2 = link.clone();
+    let _internal = session.get_sender_link("test-sender").cloned();
+    drop(link);
+    assert!(session.get_sender_link("test-sender").is_some());
+    assert!(link2.send(Bytes::from_static(b"test")).await.is_ok());
+
+    drop(link2);
+    assert!(session.get_sender_link("test-sender").is_none());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_detach_all() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link1 = session
+        .open_sender_link("sender-1", "accept")
+        .await
+        .unwrap();
+    let link2 = session
+        .open_sender_link("sender-2", "accept")
+        .await
+        .unwrap();
+
+    let res = session.detach_all(Duration::from_secs(1)).await;
+    assert!(res.is_ok());
+    assert!(session.get_sender_link("sender-1").is_none());
+    assert!(session.get_sender_link("sender-2").is_none());
+    assert!(link1
+        .send(Bytes::from_static(b"test message"))
+        .await
+        .is_err());
+    assert!(link2
+        .send(Bytes::from_static(b"test message"))
+        .await
+        .is_err());
+
+    // nothing to detach
+    assert!(session.detach_all(Duration::from_secs(1)).await.is_ok());
+    assert!(session.close().await.is_ok());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_credit() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "accept")
+        .open()
+        .await
+        .unwrap();
+
+    // server grants 50 credits at a time, the rest of deliveries get queued
+    let deliveries: Vec<_> = (0..120)
+        .map(|_| link.send(Bytes::from_static(b"test message")))
+        .collect();
+    for res in futures::future::join_all(deliveries).await {
+        assert!(res.is_ok());
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_delivery_dropped() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "accept")
+        .open()
+        .await
+        .unwrap();
+
+    // delivery future is dropped before remote peer settles it
+    drop(link.send(Bytes::from_static(b"test message")));
+    let res = link.send(Bytes::from_static(b"test message")).await;
+    assert!(res.is_ok());
+
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(session.stats().unsettled_deliveries, 0);
+    assert!(link.send(Bytes::from_static(b"test message")).await.is_ok());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_sink() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "accept")
+        .open()
+        .await
+        .unwrap();
+
+    // more messages than server credit and sink buffer
+    let mut sink = link.sink(10);
+    let mut messages = futures::stream::iter(0..120)
+        .map(|idx| Ok(ntex_amqp::codec::OutMessage::text(idx.to_string())));
+    sink.send_all(&mut messages).await.unwrap();
+    assert_eq!(sink.unsettled(), 0);
+    assert_eq!(link.stats().accepted, 120);
+
+    // link errors are reported by sink
+    link.close().await.unwrap();
+    let res = sink.send(ntex_amqp::codec::OutMessage::text("test")).await;
+    assert!(matches!(res, Err(AmqpTransportError::LinkDetached(_))));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_poll_send() -> std::io::Result<()> {
+    // raw amqp peer, grants single credit with delay
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            delay_for(Duration::from_millis(100)).await;
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: std::u32::MAX,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(1),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            let frame = framed.next().await.unwrap().unwrap();
+            if let Frame::Transfer(transfer) = frame.performative() {
+                let disp = Disposition {
+                    role: Role::Receiver,
+                    first: transfer.delivery_id().unwrap(),
+                    last: None,
+                    settled: true,
+                    state: Some(DeliveryState::Accepted(Accepted {})),
+                    batchable: false,
+                };
+                framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+            } else {
+                panic!("Transfer is expected: {:?}", frame)
+            }
+
+            let _ = framed.next().await;
+            Ok::<_, ()>(())
+        })
+    });
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+
+    // no credit, message is kept by caller
+    let mut body = Some(Bytes::from_static(b"test message"));
+    let res = futures::future::poll_fn(|cx| {
+        std::task::Poll::Ready(link.poll_send(cx, &mut body).is_pending())
+    })
+    .await;
+    assert!(res);
+    assert!(body.is_some());
+    assert_eq!(session.stats().pending_transfers, 0);
+
+    // task is woken up once credit is granted
+    let delivery = futures::future::poll_fn(|cx| link.poll_send(cx, &mut body))
+        .await
+        .unwrap();
+    assert!(body.is_none());
+    assert!(delivery.await.unwrap().is_accepted());
+
+    // credit is used up
+    let mut body = Some(Bytes::from_static(b"test message"));
+    let res = futures::future::poll_fn(|cx| {
+        std::task::Poll::Ready(link.poll_send(cx, &mut body).is_pending())
+    })
+    .await;
+    assert!(res);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_send_timeout() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "hang")
+        .open()
+        .await
+        .unwrap();
+    let res = link
+        .send_with_timeout(
+            Bytes::from_static(b"test message"),
+            Duration::from_millis(100),
+        )
+        .await;
+    assert!(matches!(res, Err(AmqpTransportError::Timeout)));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_send_settled() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    // "hang" service never settles deliveries, pre-settled sends do not wait for it
+    let link = session
+        .build_sender_link("test-sender", "hang")
+        .open()
+        .await
+        .unwrap();
+    let disp = link
+        .send_settled(Bytes::from_static(b"test message"))
+        .await
+        .unwrap();
+    assert!(disp.settled);
+    assert_eq!(disp.state, Some(DeliveryState::Accepted(Accepted {})));
+
+    let link = session
+        .build_sender_link("test-sender2", "hang")
+        .settle_mode(SenderSettleMode::Settled)
+        .open()
+        .await
+        .unwrap();
+    assert_eq!(link.settle_mode(), SenderSettleMode::Settled);
+    let disp = link
+        .send(Bytes::from_static(b"test message"))
+        .await
+        .unwrap();
+    assert_eq!(disp.state, Some(DeliveryState::Accepted(Accepted {})));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_send_batchable() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "accept")
+        .open()
+        .await
+        .unwrap();
+
+    let deliveries: Vec<_> = (0..10)
+        .map(|_| link.send_batchable(Bytes::from_static(b"test message")))
+        .collect();
+    link.flush();
+    for res in futures::future::join_all(deliveries).await {
+        assert!(res.is_ok());
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_send_with_tag() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "accept")
+        .open()
+        .await
+        .unwrap();
+
+    let res = link
+        .send_with_tag(
+            Bytes::from_static(b"test message"),
+            Bytes::from_static(b"custom-tag"),
+        )
+        .await;
+    assert!(res.is_ok());
+
+    let res = link
+        .send_with_tag(
+            Bytes::from_static(b"test message"),
+            Bytes::from(vec![0; 33]),
+        )
+        .await;
+    assert!(matches!(res, Err(AmqpTransportError::Protocol(_))));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_delivery_tag_strategy() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "accept")
+        .delivery_tag_strategy(DeliveryTagStrategy::Sequential(5))
+        .open()
+        .await
+        .unwrap();
+
+    for tag in 5u64..7 {
+        let id = session.stats().next_outgoing_id;
+        assert!(link.send(Bytes::from_static(b"test")).await.is_ok());
+        let view = session.delivery_state(id).unwrap();
+        assert_eq!(view.tag, Bytes::copy_from_slice(&tag.to_be_bytes()));
+    }
+
+    // explicit tag takes precedence
+    let id = session.stats().next_outgoing_id;
+    let res = link
+        .send_with_tag(Bytes::from_static(b"test"), Bytes::from_static(b"tag"))
+        .await;
+    assert!(res.is_ok());
+    assert_eq!(
+        session.delivery_state(id).unwrap().tag,
+        Bytes::from_static(b"tag")
+    );
+
+    link.set_delivery_tag_strategy(DeliveryTagStrategy::UuidV4);
+    let id = session.stats().next_outgoing_id;
+    assert!(link.send(Bytes::from_static(b"test")).await.is_ok());
+    assert_eq!(session.delivery_state(id).unwrap().tag.len(), 16);
+
+    // generated tag must not exceed 32 bytes
+    link.set_delivery_tag_strategy(DeliveryTagStrategy::Custom(Rc::new(|| {
+        Bytes::from(vec![0; 33])
+    })));
+    let res = link.send(Bytes::from_static(b"test")).await;
+    assert!(matches!(res, Err(AmqpTransportError::Protocol(_))));
+
+    Ok(())
+}
+
+type RawFramed = Framed<TcpStream, AmqpCodec<AmqpFrame>>;
+
+/// Accept connection and session on raw amqp transport,
+/// returns transport and next incoming transfer id
+async fn raw_peer_begin(io: TcpStream, incoming_window: u32) -> (RawFramed, TransferNumber) {
+    raw_peer_begin_with_handle_max(io, incoming_window, std::u32::MAX).await
+}
+
+/// Accept connection and session of raw amqp peer with custom handle-max
+async fn raw_peer_begin_with_handle_max(
+    io: TcpStream,
+    incoming_window: u32,
+    handle_max: u32,
+) -> (RawFramed, TransferNumber) {
+    let mut framed = Framed::new(io, ProtocolIdCodec);
+    let proto = framed.next().await.unwrap().unwrap();
+    framed.send(proto).await.unwrap();
+
+    let mut framed = framed.into_framed(AmqpCodec::<AmqpFrame>::new());
+    let _open = framed.next().await.unwrap().unwrap();
+    framed
+        .send(AmqpFrame::new(0, Configuration::default().to_open().into()))
+        .await
+        .unwrap();
+
+    let frame = framed.next().await.unwrap().unwrap();
+    let next_incoming_id = if let Frame::Begin(begin) = frame.performative() {
+        begin.next_outgoing_id()
+    } else {
+        panic!("Begin is expected: {:?}", frame)
+    };
+    let begin = Begin {
+        remote_channel: Some(frame.channel_id()),
+        next_outgoing_id: 1,
+        incoming_window,
+        outgoing_window: std::u32::MAX,
+        handle_max,
+        offered_capabilities: None,
+        desired_capabilities: None,
+        properties: None,
+    };
+    framed.send(AmqpFrame::new(0, begin.into())).await.unwrap();
+
+    (framed, next_incoming_id)
+}
+
+/// Open session to raw amqp peer without sasl
+async fn open_raw_session(srv: &TestServer) -> Session {
+    let mut conn = connect_raw(srv).await;
+    let session = conn.open_session();
+    ntex::rt::spawn(conn.map(|_| ()));
+    session.await.unwrap()
+}
+
+/// Open connection to raw amqp peer without sasl
+async fn connect_raw(srv: &TestServer) -> Connection<TcpStream> {
+    connect_raw_with_config(srv, Configuration::default()).await
+}
+
+/// Open connection to raw amqp peer with custom configuration
+async fn connect_raw_with_config(srv: &TestServer, config: Configuration) -> Connection<TcpStream> {
+    connect_raw_addr(srv.addr(), config).await
+}
+
+/// Open connection to raw amqp peer by address
+async fn connect_raw_addr(
+    addr: std::net::SocketAddr,
+    config: Configuration,
+) -> Connection<TcpStream> {
+    let io = TcpStream::connect(addr).await.unwrap();
+    let mut framed = Framed::new(io, ProtocolIdCodec);
+    framed.send(ProtocolId::Amqp).await.unwrap();
+    let _ = framed.next().await.unwrap().unwrap();
+
+    let mut framed = framed.into_framed(AmqpCodec::<AmqpFrame>::new());
+    framed
+        .send(AmqpFrame::new(0, config.to_open().into()))
+        .await
+        .unwrap();
+    let frame = framed.next().await.unwrap().unwrap();
+    let remote = if let Frame::Open(open) = frame.performative() {
+        Configuration::from(open)
+    } else {
+        panic!("Open is expected: {:?}", frame)
+    };
+
+    Connection::new(framed, config, remote, None)
+}
+
+#[ntex::test]
+async fn test_session_links_info() -> std::io::Result<()> {
+    // raw amqp peer, confirms attaches with custom handles
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            for handle in &[5, 7] {
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    let role = if attach.role == Role::Sender {
+                        Role::Receiver
+                    } else {
+                        Role::Sender
+                    };
+                    Attach {
+                        handle: *handle,
+                        role,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+            }
+
+            let _ = framed.next().await;
+            Ok::<_, ()>(())
+        })
+    });
+    let mut session = open_raw_session(&srv).await;
+    assert_eq!(session.local_channel(), 0);
+    assert_eq!(session.remote_channel(), 0);
+
+    let _sender = session
+        .open_sender_link("test-sender", "queue-1")
+        .await
+        .unwrap();
+    let _receiver = session
+        .open_receiver_link("test-receiver", "queue-2")
+        .await
+        .unwrap();
+
+    let links = session.links();
+    assert_eq!(
+        links,
+        vec![
+            ntex_amqp::LinkInfo {
+                name: ByteString::from("test-sender"),
+                handle: 0,
+                remote_handle: Some(5),
+                role: Role::Sender,
+                address: Some(ByteString::from("queue-1")),
+            },
+            ntex_amqp::LinkInfo {
+                name: ByteString::from("test-receiver"),
+                handle: 1,
+                remote_handle: Some(7),
+                role: Role::Receiver,
+                address: Some(ByteString::from("queue-2")),
+            },
+        ]
+    );
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_update_flow() -> std::io::Result<()> {
+    let flows = Arc::new(AtomicUsize::new(0));
+    let window = Arc::new(AtomicUsize::new(0));
+    let flows2 = flows.clone();
+    let window2 = window.clone();
+
+    // raw amqp peer, counts session flows
+    let srv = test_server(move || {
+        let flows = flows2.clone();
+        let window = window2.clone();
+        fn_service(move |io: TcpStream| {
+            let flows = flows.clone();
+            let window = window.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    if let Frame::Flow(flow) = frame.performative() {
+                        assert!(flow.handle().is_none());
+                        window.store(flow.incoming_window() as usize, Ordering::Relaxed);
+                        flows.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+    let mut session = open_raw_session(&srv).await;
+
+    session.update_flow();
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(flows.load(Ordering::Relaxed), 1);
+    assert_eq!(window.load(Ordering::Relaxed), std::u32::MAX as usize);
+
+    session.set_incoming_window(10, 5);
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(flows.load(Ordering::Relaxed), 2);
+    assert_eq!(window.load(Ordering::Relaxed), 10);
+    assert_eq!(session.stats().incoming_window, 10);
+
+    session.set_flow_interval(Some(Duration::from_millis(50)));
+    delay_for(Duration::from_millis(300)).await;
+    assert!(flows.load(Ordering::Relaxed) >= 4);
+
+    // periodic flow is stopped
+    session.set_flow_interval(None);
+    delay_for(Duration::from_millis(100)).await;
+    let count = flows.load(Ordering::Relaxed);
+    delay_for(Duration::from_millis(200)).await;
+    assert_eq!(flows.load(Ordering::Relaxed), count);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_remote_detach_drops_pending_transfers() -> std::io::Result<()> {
+    let transmitted = Arc::new(AtomicBool::new(false));
+    let transmitted2 = transmitted.clone();
+
+    // raw amqp peer, grants link credit without session window,
+    // detaches link and then opens session window
+    let srv = test_server(move || {
+        let transmitted = transmitted2.clone();
+        fn_service(move |io: TcpStream| {
+            let transmitted = transmitted.clone();
+            async move {
+                let (mut framed, next_incoming_id) = raw_peer_begin(io, 0).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Receiver,
+                        initial_delivery_count: None,
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id),
+                    incoming_window: 0,
+                    next_outgoing_id: 1,
+                    outgoing_window: std::u32::MAX,
+                    handle: Some(0),
+                    delivery_count: Some(0),
+                    link_credit: Some(10),
+                    available: None,
+                    drain: false,
+                    echo: false,
+                    properties: None,
+                };
+                framed
+                    .send(AmqpFrame::new(0, flow.clone().into()))
+                    .await
+                    .unwrap();
+
+                // transfers get queued waiting for session window
+                delay_for(Duration::from_millis(200)).await;
+                let detach = Detach {
+                    handle: 0,
+                    closed: true,
+                    error: None,
+                };
+                framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+                let frame = framed.next().await.unwrap().unwrap();
+                assert!(matches!(frame.performative(), Frame::Detach(_)));
+
+                let flow = Flow {
+                    incoming_window: 10,
+                    handle: None,
+                    delivery_count: None,
+                    link_credit: None,
+                    ..flow
+                };
+                framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    if let Frame::Transfer(_) = frame.performative() {
+                        transmitted.store(true, Ordering::Relaxed);
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .build_sender_link("test-sender", "test")
+        .open()
+        .await
+        .unwrap();
+    while link.credit() == 0 {
+        delay_for(Duration::from_millis(10)).await;
+    }
+
+    let deliveries: Vec<_> = (0..3)
+        .map(|idx| link.send(Bytes::from(idx.to_string())))
+        .collect();
+    assert_eq!(session.stats().pending_transfers, 3);
+    for res in futures::future::join_all(deliveries).await {
+        assert!(matches!(res, Err(AmqpTransportError::LinkDetached(None))));
+    }
+    assert_eq!(session.stats().pending_transfers, 0);
+
+    delay_for(Duration::from_millis(100)).await;
+    assert!(!transmitted.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_window_transfers_order() -> std::io::Result<()> {
+    const COUNT: usize = 10;
+    const WINDOW: usize = 2;
+
+    // raw amqp peer, starts session with zero incoming window
+    // and then opens window in chunks
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, 0).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: 0,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(COUNT as u32),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed
+                .send(AmqpFrame::new(0, flow.clone().into()))
+                .await
+                .unwrap();
+
+            let mut next_incoming_id = next_incoming_id;
+            let mut received = 0;
+            while received < COUNT {
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id),
+                    incoming_window: WINDOW as u32,
+                    handle: None,
+                    delivery_count: None,
+                    link_credit: None,
+                    ..flow.clone()
+                };
+                framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+                let mut window = WINDOW;
+                while window > 0 && received < COUNT {
+                    let frame = framed.next().await.unwrap().unwrap();
+                    if let Frame::Transfer(transfer) = frame.performative() {
+                        let expected = TransferBody::Data(Bytes::from(received.to_string()));
+                        let state = if transfer.body() == Some(&expected) {
+                            DeliveryState::Accepted(Accepted {})
+                        } else {
+                            DeliveryState::Rejected(Rejected { error: None })
+                        };
+                        let disp = Disposition {
+                            role: Role::Receiver,
+                            first: transfer.delivery_id().unwrap(),
+                            last: None,
+                            settled: true,
+                            state: Some(state),
+                            batchable: false,
+                        };
+                        framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+
+                        next_incoming_id += 1;
+                        received += 1;
+                        window -= 1;
+                    }
+                }
+            }
+
+            let _ = framed.next().await;
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "test")
+        .open()
+        .await
+        .unwrap();
+    let deliveries: Vec<_> = (0..COUNT)
+        .map(|idx| link.send(Bytes::from(idx.to_string())))
+        .collect();
+    for res in futures::future::join_all(deliveries).await {
+        assert_eq!(
+            res.unwrap().state,
+            Some(DeliveryState::Accepted(Accepted {}))
+        );
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_options() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let err = session
+        .open_sender_link("test-sender", "durable")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.remote_condition().unwrap().as_str(),
+        "amqp:link:detach-forced"
+    );
+    assert_eq!(err.remote_description(), Some("durable link is required"));
+
+    let link = session
+        .build_sender_link("test-sender2", "durable")
+        .durable(TerminusDurability::UnsettledState)
+        .expiry_policy(TerminusExpiryPolicy::Never)
+        .receiver_settle_mode(ReceiverSettleMode::First)
+        .desired_capabilities(vec![Symbol::from("test-capability")].into())
+        .open()
+        .await
+        .unwrap();
+    let res = link.send(Bytes::from_static(b"test message")).await;
+    assert!(res.is_ok());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_dynamic() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                let mut source = attach.source.clone().unwrap();
+                assert!(source.dynamic);
+                assert!(source.address.is_none());
+                source.address = Some(ByteString::from_static("dynamic-address"));
+                Attach {
+                    handle: 0,
+                    role: Role::Sender,
+                    source: Some(source),
+                    initial_delivery_count: Some(0),
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let _ = framed.next().await;
+            Ok::<_, ()>(())
+        })
+    });
+    let mut session = open_raw_session(&srv).await;
+
+    let link = session
+        .build_receiver_link("test-receiver", "")
+        .dynamic(None)
+        .open()
+        .await
+        .unwrap();
+    assert_eq!(link.address().unwrap(), "dynamic-address");
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_link_capabilities() -> std::io::Result<()> {
+    // raw amqp peer, offers "shared-subs" capability if it is desired
+    let srv = test_server(move || {
+        fn_service(move |io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            for handle in 0..2 {
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    let desired = attach.desired_capabilities.as_ref().unwrap();
+                    assert_eq!(desired.iter().next().unwrap().as_str(), "shared-subs");
+                    let offered = attach.offered_capabilities.as_ref().unwrap();
+                    assert_eq!(offered.iter().next().unwrap().as_str(), "local-cap");
+                    Attach {
+                        handle,
+                        role: if attach.role == Role::Sender {
+                            Role::Receiver
+                        } else {
+                            Role::Sender
+                        },
+                        initial_delivery_count: Some(0),
+                        offered_capabilities: Some(Multiple(vec![Symbol::from_static(
+                            "shared-subs",
+                        )])),
+                        desired_capabilities: None,
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+            }
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let desired = Multiple(vec![Symbol::from_static("shared-subs")]);
+    let offered = Multiple(vec![Symbol::from_static("local-cap")]);
+    let mut session = open_raw_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "test")
+        .desired_capabilities(desired.clone())
+        .offered_capabilities(offered.clone())
+        .open()
+        .await
+        .unwrap();
+    assert!(link.is_capability_offered("shared-subs"));
+    assert!(!link.is_capability_offered("local-cap"));
+    assert_eq!(link.remote_offered_capabilities().unwrap().len(), 1);
+    assert!(link.remote_desired_capabilities().is_none());
+
+    let link = session
+        .build_receiver_link("test-receiver", "test")
+        .desired_capabilities(desired)
+        .offered_capabilities(offered)
+        .open()
+        .await
+        .unwrap();
+    assert!(link.is_capability_offered("shared-subs"));
+    assert!(!link.is_capability_offered("local-cap"));
+    assert_eq!(link.remote_offered_capabilities().unwrap().len(), 1);
+    assert!(link.remote_desired_capabilities().is_none());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_source_filter() -> std::io::Result<()> {
+    // raw amqp peer, supports selector filter only
+    let srv = test_server(move || {
+        fn_service(move |io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                let mut source = attach.source().unwrap().clone();
+                assert_eq!(source.durable, TerminusDurability::UnsettledState);
+                assert_eq!(source.expiry_policy, TerminusExpiryPolicy::Never);
+                let caps = source.capabilities.as_ref().unwrap();
+                assert_eq!(caps.iter().next().unwrap().as_str(), "shared");
+
+                let filter = source.filter.as_mut().unwrap();
+                assert_eq!(filter.len(), 2);
+                let selector = filter
+                    .get(&Symbol::from("apache.org:selector-filter:string"))
+                    .unwrap();
+                assert_eq!(
+                    selector,
+                    &Some(Variant::Described((
+                        Descriptor::Ulong(0x0000_468C_0000_0004),
+                        Box::new(Variant::from("color = 'red'")),
+                    )))
+                );
+                let binding = filter
+                    .remove(&Symbol::from("apache.org:legacy-amqp-topic-binding:string"))
+                    .unwrap();
+                assert_eq!(
+                    binding,
+                    Some(Variant::Described((
+                        Descriptor::Ulong(0x0000_468C_0000_0001),
+                        Box::new(Variant::from("news.#")),
+                    )))
+                );
+
+                Attach {
+                    handle: 0,
+                    role: Role::Sender,
+                    initial_delivery_count: Some(0),
+                    source: Some(source),
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .build_receiver_link("test-subscription", "topic")
+        .durable(TerminusDurability::UnsettledState)
+        .expiry_policy(TerminusExpiryPolicy::Never)
+        .source_capabilities(Multiple(vec![Symbol::from_static("shared")]))
+        .selector("color = 'red'")
+        .topic_binding("news.#")
+        .open()
+        .await
+        .unwrap();
+
+    // topic binding filter is not honored by remote peer
+    let filter = link.remote_source().unwrap().filter().unwrap();
+    assert_eq!(filter.len(), 1);
+    assert!(filter.contains_key(&Symbol::from("apache.org:selector-filter:string")));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_link_rejected_by_peer() -> std::io::Result<()> {
+    let confirmed = Arc::new(AtomicUsize::new(0));
+    let confirmed2 = confirmed.clone();
+
+    // raw amqp peer, rejects links with null terminus and detach
+    let srv = test_server(move || {
+        let confirmed = confirmed2.clone();
+        fn_service(move |io: TcpStream| {
+            let confirmed = confirmed.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                for handle in 0..2 {
+                    let frame = framed.next().await.unwrap().unwrap();
+                    let attach = if let Frame::Attach(attach) = frame.performative() {
+                        Attach {
+                            handle,
+                            role: if attach.role == Role::Sender {
+                                Role::Receiver
+                            } else {
+                                Role::Sender
+                            },
+                            source: None,
+                            target: None,
+                            initial_delivery_count: Some(0),
+                            ..attach.clone()
+                        }
+                    } else {
+                        panic!("Attach is expected: {:?}", frame)
+                    };
+                    framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                    let detach = Detach {
+                        handle,
+                        closed: true,
+                        error: Some(AmqpError::not_found().description("no such node").into()),
+                    };
+                    framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+
+                    let frame = framed.next().await.unwrap().unwrap();
+                    if let Frame::Detach(detach) = frame.performative() {
+                        assert!(detach.closed);
+                        assert!(detach.error.is_none());
+                        confirmed.fetch_add(1, Ordering::Relaxed);
+                    } else {
+                        panic!("Detach is expected: {:?}", frame)
+                    }
+                }
+
+                while let Some(Ok(_)) = framed.next().await {}
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let res = session.open_sender_link("test-sender", "unknown").await;
+    if let Err(AmqpTransportError::Remote {
+        condition,
+        description,
+    }) = res
+    {
+        assert_eq!(condition, "amqp:not-found");
+        assert_eq!(description.as_deref(), Some("no such node"));
+    } else {
+        panic!("Remote error is expected: {:?}", res.map(|_| ()));
+    }
+
+    let res = session.open_receiver_link("test-receiver", "unknown").await;
+    if let Err(AmqpTransportError::Remote {
+        condition,
+        description,
+    }) = res
+    {
+        assert_eq!(condition, "amqp:not-found");
+        assert_eq!(description.as_deref(), Some("no such node"));
+    } else {
+        panic!("Remote error is expected: {:?}", res.map(|_| ()));
+    }
+
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(confirmed.load(Ordering::Relaxed), 2);
+    assert!(session.links().is_empty());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_link_name_collision() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let fut1 = session.open_sender_link("test-sender", "accept");
+    let fut2 = session.open_sender_link("test-sender", "accept");
+    let (res1, res2) = futures::future::join(fut1, fut2).await;
+    assert!(res1.is_ok());
+    assert!(matches!(res2, Err(AmqpTransportError::LinkNameInUse(_))));
+
+    let res = session.open_receiver_link("test-sender", "test").await;
+    assert!(matches!(res, Err(AmqpTransportError::LinkNameInUse(_))));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_open_timeout() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            // do not confirm first attach
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+
+            // link is detached after timeout, confirm attach and detach
+            let frame = framed.next().await.unwrap().unwrap();
+            if let Frame::Detach(detach) = frame.performative() {
+                assert!(detach.closed);
+            } else {
+                panic!("Detach is expected: {:?}", frame)
+            }
+            framed
+                .send(AmqpFrame::new(0, attach.clone().into()))
+                .await
+                .unwrap();
+            let detach = Detach {
+                handle: 0,
+                closed: true,
+                error: None,
+            };
+            framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+
+            // link name is released
+            let frame = framed.next().await.unwrap().unwrap();
+            if let Frame::Attach(_) = frame.performative() {
+                let attach = Attach {
+                    handle: 1,
+                    ..attach
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            }
+
+            let _ = framed.next().await;
+            Ok::<_, ()>(())
+        })
+    });
+    let mut session = open_raw_session(&srv).await;
+
+    let res = session
+        .build_sender_link("test-sender", "test")
+        .open_timeout(Duration::from_millis(100))
+        .open()
+        .await;
+    assert!(matches!(res, Err(AmqpTransportError::Timeout)));
+
+    delay_for(Duration::from_millis(100)).await;
+    let res = session.open_sender_link("test-sender", "test").await;
+    assert!(res.is_ok());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_link_open_timeout_detach_not_confirmed() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            // do not confirm attach and detach
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                attach.clone()
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            let frame = framed.next().await.unwrap().unwrap();
+            if let Frame::Detach(detach) = frame.performative() {
+                assert!(detach.closed);
+            } else {
+                panic!("Detach is expected: {:?}", frame)
+            }
+
+            // link slot is released
+            let frame = framed.next().await.unwrap().unwrap();
+            if let Frame::Attach(_) = frame.performative() {
+                let attach = Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            }
+
+            let _ = framed.next().await;
+            Ok::<_, ()>(())
+        })
+    });
+    let mut session = open_raw_session(&srv).await;
+
+    let res = session
+        .build_sender_link("test-sender", "test")
+        .open_timeout(Duration::from_millis(100))
+        .open()
+        .await;
+    assert!(matches!(res, Err(AmqpTransportError::Timeout)));
+
+    let res = session.open_sender_link("test-sender", "test").await;
+    assert!(matches!(res, Err(AmqpTransportError::LinkNameInUse(_))));
+
+    delay_for(Duration::from_millis(200)).await;
+    let res = session.open_sender_link("test-sender", "test").await;
+    assert!(res.is_ok());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_link_open_future_dropped() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            for role in &[Role::Receiver, Role::Sender] {
+                // do not confirm first attach
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: *role,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+
+                // open future is dropped, link is detached
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Detach(detach) = frame.performative() {
+                    assert!(detach.closed);
+                } else {
+                    panic!("Detach is expected: {:?}", frame)
+                }
+                framed
+                    .send(AmqpFrame::new(0, attach.clone().into()))
+                    .await
+                    .unwrap();
+                let detach = Detach {
+                    handle: 0,
+                    closed: true,
+                    error: None,
+                };
+                framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+
+                // link name is released
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Attach(_) = frame.performative() {
+                    let attach = Attach {
+                        handle: 1,
+                        ..attach
+                    };
+                    framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                }
+            }
+
+            let _ = framed.next().await;
+            Ok::<_, ()>(())
+        })
+    });
+    let mut session = open_raw_session(&srv).await;
+
+    let res = ntex::rt::time::timeout(
+        Duration::from_millis(50),
+        session.open_sender_link("test-sender", "test"),
+    )
+    .await;
+    assert!(res.is_err());
+
+    delay_for(Duration::from_millis(100)).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    // link slot is released
+    assert_eq!(link.id(), 0);
+
+    let res = ntex::rt::time::timeout(
+        Duration::from_millis(50),
+        session.open_receiver_link("test-receiver", "test"),
+    )
+    .await;
+    assert!(res.is_err());
+
+    delay_for(Duration::from_millis(100)).await;
+    let link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    assert_eq!(link.handle(), 1);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_settle_range_shared_session() -> std::io::Result<()> {
+    let settled = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let settled2 = settled.clone();
+
+    // raw amqp peer, interleaves deliveries of two links
+    let srv = test_server(move || {
+        let settled = settled2.clone();
+        fn_service(move |io: TcpStream| {
+            let settled = settled.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                for handle in 0..2 {
+                    let frame = framed.next().await.unwrap().unwrap();
+                    let attach = if let Frame::Attach(attach) = frame.performative() {
+                        Attach {
+                            handle,
+                            role: Role::Sender,
+                            initial_delivery_count: Some(0),
+                            ..attach.clone()
+                        }
+                    } else {
+                        panic!("Attach is expected: {:?}", frame)
+                    };
+                    framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+                }
+
+                for id in 0..4 {
+                    let transfer = Transfer {
+                        handle: id % 2,
+                        delivery_id: Some(id),
+                        delivery_tag: Some(Bytes::from(format!("tag-{}", id))),
+                        message_format: None,
+                        settled: Some(false),
+                        more: false,
+                        rcv_settle_mode: None,
+                        state: None,
+                        resume: false,
+                        aborted: false,
+                        batchable: false,
+                        body: Some(TransferBody::Data(Bytes::from_static(b"test"))),
+                    };
+                    framed
+                        .send(AmqpFrame::new(0, transfer.into()))
+                        .await
+                        .unwrap();
+                }
+
+                let mut dispositions = Vec::new();
+                while dispositions.len() < 4 {
+                    let frame = framed.next().await.unwrap().unwrap();
+                    if let Frame::Disposition(disp) = frame.performative() {
+                        dispositions.push((disp.first, disp.last));
+                    }
+                }
+                *settled.lock().unwrap() = dispositions;
+
+                while let Some(Ok(_)) = framed.next().await {}
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut link1 = session
+        .open_receiver_link("test-receiver1", "test")
+        .await
+        .unwrap();
+    let mut link2 = session
+        .open_receiver_link("test-receiver2", "test")
+        .await
+        .unwrap();
+    link1.set_link_credit(10);
+    link2.set_link_credit(10);
+
+    for _ in 0..2 {
+        link1.next().await.unwrap().unwrap();
+        link2.next().await.unwrap().unwrap();
+    }
+
+    // only deliveries of the link are settled
+    link1.settle_range(0, 3, DeliveryState::Accepted(Accepted {}));
+    // reversed range is ignored
+    link2.settle_range(3, 1, DeliveryState::Accepted(Accepted {}));
+    link2.settle_range(1, 3, DeliveryState::Accepted(Accepted {}));
+
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(
+        *settled.lock().unwrap(),
+        vec![(0, None), (2, None), (1, None), (3, None)]
+    );
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_auto_credit() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(pipeline_factory(sasl_auth).map_err(|e| e.into())),
+        )
+        .control(fn_service(control))
+        .finish(
+            server::App::<()>::new()
+                .service("test", fn_factory_with_config(server))
+                .finish(),
+        )
+    });
+    let mut session = open_session(&srv).await;
+
+    let mut link = session
+        .open_receiver_link("test-many", "test")
+        .await
+        .unwrap();
+    link.set_auto_credit(4);
+    assert_eq!(link.credit(), 4);
+
+    for idx in 0..20 {
+        let transfer = link.next().await.unwrap().unwrap();
+        assert_eq!(
+            transfer.body,
+            Some(TransferBody::Data(Bytes::from(idx.to_string())))
+        );
+        assert!(link.credit() <= 4);
+        link.settle_message(
+            transfer.delivery_id.unwrap(),
+            DeliveryState::Accepted(Accepted {}),
+        );
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_settle() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(pipeline_factory(sasl_auth).map_err(|e| e.into())),
+        )
+        .control(fn_service(control))
+        .finish(
+            server::App::<()>::new()
+                .service("test", fn_factory_with_config(server))
+                .finish(),
+        )
+    });
+    let mut session = open_session(&srv).await;
+
+    let mut link = session
+        .open_receiver_link("test-many", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(21);
+
+    let mut ids = Vec::new();
+    for _ in 0..20 {
+        let transfer = link.next().await.unwrap().unwrap();
+        ids.push(transfer.delivery_id.unwrap());
+    }
+    link.accept(ids[0]);
+    link.reject(ids[1], None);
+    link.release(ids[2]);
+    link.modify(
+        ids[3],
+        Modified {
+            delivery_failed: Some(true),
+            undeliverable_here: None,
+            message_annotations: None,
+        },
+    );
+    link.settle_range(ids[4], ids[19], DeliveryState::Accepted(Accepted {}));
+
+    // server sends last message once all deliveries are settled
+    let transfer = link.next().await.unwrap().unwrap();
+    assert_eq!(
+        transfer.body,
+        Some(TransferBody::Data(Bytes::from_static(b"done")))
+    );
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_drain() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(pipeline_factory(sasl_auth).map_err(|e| e.into())),
+        )
+        .control(fn_service(control))
+        .finish(
+            server::App::<()>::new()
+                .service("test", fn_factory_with_config(server))
+                .finish(),
+        )
+    });
+    let mut session = open_session(&srv).await;
+
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(10);
+
+    let transfer = link.next().await.unwrap().unwrap();
+    link.accept(transfer.delivery_id.unwrap());
+
+    // server has nothing to send, remaining credit is used up
+    link.drain().await.unwrap();
+    assert_eq!(link.credit(), 0);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_incoming_window() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(pipeline_factory(sasl_auth).map_err(|e| e.into())),
+        )
+        .control(fn_service(control))
+        .finish(
+            server::App::<()>::new()
+                .service("test", fn_factory_with_config(server))
+                .finish(),
+        )
+    });
+    let mut config = Configuration::default();
+    config.session_window(4, 1);
+    let mut session = open_session_with_config(&srv, config).await;
+
+    let mut link = session
+        .open_receiver_link("test-many", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(21);
+
+    // server waits for new session window after every 3 transfers
+    for _ in 0..20 {
+        let transfer = link.next().await.unwrap().unwrap();
+        link.accept(transfer.delivery_id.unwrap());
+    }
+    let transfer = link.next().await.unwrap().unwrap();
+    assert_eq!(
+        transfer.body,
+        Some(TransferBody::Data(Bytes::from_static(b"done")))
+    );
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_begin_session() -> std::io::Result<()> {
+    // raw amqp peer, responds to begin with expected windows only
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let mut framed = Framed::new(io, ProtocolIdCodec);
+            let proto = framed.next().await.unwrap().unwrap();
+            framed.send(proto).await.unwrap();
+
+            let mut framed = framed.into_framed(AmqpCodec::<AmqpFrame>::new());
+            let _open = framed.next().await.unwrap().unwrap();
+            framed
+                .send(AmqpFrame::new(0, Configuration::default().to_open().into()))
+                .await
+                .unwrap();
+
+            let frame = framed.next().await.unwrap().unwrap();
+            if let Frame::Begin(begin) = frame.performative() {
+                if begin.incoming_window() == 8
+                    && begin.outgoing_window() == 16
+                    && begin.next_outgoing_id() == 0
+                {
+                    let begin = Begin {
+                        remote_channel: Some(frame.channel_id()),
+                        next_outgoing_id: 1,
+                        incoming_window: 16,
+                        outgoing_window: 8,
+                        handle_max: std::u32::MAX,
+                        offered_capabilities: None,
+                        desired_capabilities: None,
+                        properties: None,
+                    };
+                    framed.send(AmqpFrame::new(0, begin.into())).await.unwrap();
+                }
+            }
+            let _ = framed.next().await;
+            Ok::<_, ()>(())
+        })
+    });
+
+    let conn = connect_raw(&srv).await;
+    let session = conn.controller().begin_session(8, 16);
+    ntex::rt::spawn(conn.map(|_| ()));
+
+    let res = ntex::rt::time::timeout(Duration::from_secs(1), session).await;
+    assert!(res.unwrap().is_ok());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_begin_invalid_remote_channel() -> std::io::Result<()> {
+    // raw amqp peer, limits channels and maps begin to out of range channel
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let mut framed = Framed::new(io, ProtocolIdCodec);
+            let proto = framed.next().await.unwrap().unwrap();
+            framed.send(proto).await.unwrap();
+
+            let mut framed = framed.into_framed(AmqpCodec::<AmqpFrame>::new());
+            let _open = framed.next().await.unwrap().unwrap();
+            let mut config = Configuration::default();
+            config.channel_max(1);
+            framed
+                .send(AmqpFrame::new(0, config.to_open().into()))
+                .await
+                .unwrap();
+
+            // second begin is mapped to invalid channel
+            let mut channels = vec![0, 5000, 1].into_iter();
+            while let Some(Ok(frame)) = framed.next().await {
+                if let Frame::Begin(_) = frame.performative() {
+                    let begin = Begin {
+                        remote_channel: Some(frame.channel_id()),
+                        next_outgoing_id: 1,
+                        incoming_window: std::u32::MAX,
+                        outgoing_window: std::u32::MAX,
+                        handle_max: std::u32::MAX,
+                        offered_capabilities: None,
+                        desired_capabilities: None,
+                        properties: None,
+                    };
+                    let channel = channels.next().unwrap();
+                    framed
+                        .send(AmqpFrame::new(channel, begin.into()))
+                        .await
+                        .unwrap();
+                }
+            }
+            Ok::<_, ()>(())
+        })
+    });
+
+    let conn = connect_raw(&srv).await;
+    let controller = conn.controller();
+    ntex::rt::spawn(conn.map(|_| ()));
+
+    let _session1 = controller.begin_session(10, 10).await.unwrap();
+    match controller.begin_session(10, 10).await {
+        Err(AmqpTransportError::InvalidRemoteChannel(5000)) => (),
+        res => panic!("Invalid remote channel is expected: {:?}", res.map(|_| ())),
+    }
+
+    // remote peer allows only two channels
+    let _session2 = controller.begin_session(10, 10).await.unwrap();
+    match controller.begin_session(10, 10).await {
+        Err(AmqpTransportError::TooManyChannels) => (),
+        res => panic!("Too many channels is expected: {:?}", res.map(|_| ())),
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_idle_timeout() -> std::io::Result<()> {
+    let heartbeats = Arc::new(AtomicUsize::new(0));
+
+    // raw amqp peer, requires heartbeats and never sends any frame
+    let hb = heartbeats.clone();
+    let srv = test_server(move || {
+        let hb = hb.clone();
+        fn_service(move |io: TcpStream| {
+            let hb = hb.clone();
+            async move {
+                let mut framed = Framed::new(io, ProtocolIdCodec);
+                let proto = framed.next().await.unwrap().unwrap();
+                framed.send(proto).await.unwrap();
+
+                let mut framed = framed.into_framed(AmqpCodec::<AmqpFrame>::new());
+                let _open = framed.next().await.unwrap().unwrap();
+                let mut config = Configuration::default();
+                config.idle_timeout(200);
+                framed
+                    .send(AmqpFrame::new(0, config.to_open().into()))
+                    .await
+                    .unwrap();
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    if let Frame::Empty = frame.performative() {
+                        hb.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let io = TcpStream::connect(srv.addr()).await.unwrap();
+    let mut framed = Framed::new(io, ProtocolIdCodec);
+    framed.send(ProtocolId::Amqp).await.unwrap();
+    let _ = framed.next().await.unwrap().unwrap();
+
+    let mut config = Configuration::default();
+    config.idle_timeout(500);
+    let mut framed = framed.into_framed(AmqpCodec::<AmqpFrame>::new());
+    framed
+        .send(AmqpFrame::new(0, config.to_open().into()))
+        .await
+        .unwrap();
+    let frame = framed.next().await.unwrap().unwrap();
+    let remote = if let Frame::Open(open) = frame.performative() {
+        Configuration::from(open)
+    } else {
+        panic!("Open is expected: {:?}", frame)
+    };
+
+    let conn = Connection::new(
+        framed,
+        config,
+        remote,
+        Some(LowResTimeService::with(Duration::from_millis(10))),
+    );
+    let controller = conn.controller();
+
+    let res = ntex::rt::time::timeout(Duration::from_secs(2), conn).await;
+    assert!(res.is_ok());
+    assert!(heartbeats.load(Ordering::Relaxed) >= 2);
+
+    match controller.begin_session(10, 10).await {
+        Err(AmqpTransportError::IdleTimeout) => (),
+        res => panic!("Idle timeout is expected: {:?}", res.map(|_| ())),
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_outcome() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .open_sender_link("test-sender", "accept")
+        .await
+        .unwrap();
+    let disp = link
+        .send(Bytes::from_static(b"test message"))
+        .await
+        .unwrap();
+    assert!(disp.is_accepted());
+    assert!(!disp.is_rejected());
+    assert!(disp.rejection_error().is_none());
+    assert!(disp.rejection_condition().is_none());
+
+    let link = session
+        .open_sender_link("test-sender2", "reject")
+        .await
+        .unwrap();
+    let disp = link
+        .send(Bytes::from_static(b"test message"))
+        .await
+        .unwrap();
+    assert!(disp.is_rejected());
+    let err = disp.rejection_error().unwrap();
+    assert_eq!(
+        err.description.as_ref().map(|d| d.as_ref()),
+        Some("rejected")
+    );
+    assert_eq!(
+        disp.rejection_condition().map(|c| c.to_symbol()),
+        Some(Symbol::from_static("amqp:not-allowed"))
+    );
+    assert!(disp.modified().is_none());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_send() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    // link to each address is opened on first send and reused afterwards
+    for _ in 0..3 {
+        let disp = session
+            .send("accept", Bytes::from_static(b"test message"))
+            .await
+            .unwrap();
+        assert!(disp.is_accepted());
+
+        let disp = session
+            .send("reject", Bytes::from_static(b"test message"))
+            .await
+            .unwrap();
+        assert!(disp.is_rejected());
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_cached_sender_link() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link1 = session.cached_sender_link("accept").await.unwrap();
+    let link2 = session.cached_sender_link("accept").await.unwrap();
+    assert_eq!(link1.name(), link2.name());
+
+    let link3 = session.cached_sender_link("reject").await.unwrap();
+    assert_ne!(link1.name(), link3.name());
+
+    // detached link is removed from cache
+    link1.close().await.unwrap();
+    let link4 = session.cached_sender_link("accept").await.unwrap();
+    assert_ne!(link1.name(), link4.name());
+    let disp = link4
+        .send(Bytes::from_static(b"test message"))
+        .await
+        .unwrap();
+    assert!(disp.is_accepted());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_max_message_size() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .open_sender_link("test-sender", "accept")
+        .await
+        .unwrap();
+    assert_eq!(link.max_message_size(), Some(65536));
+
+    match link.send(Bytes::from(vec![0u8; 65537])).await {
+        Err(AmqpTransportError::MessageTooLarge) => (),
+        res => panic!("Message too large is expected: {:?}", res),
+    }
+
+    let disp = link.send(Bytes::from(vec![0u8; 1024])).await.unwrap();
+    assert!(disp.is_accepted());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_connection_lost() -> std::io::Result<()> {
+    // raw amqp peer, receives 3 transfers, drops connection on next attach
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: std::u32::MAX,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(3),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            while let Some(Ok(frame)) = framed.next().await {
+                if let Frame::Attach(_) = frame.performative() {
+                    break;
+                }
+            }
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+
+    // 3 deliveries are in flight, 2 wait for credit
+    let deliveries: Vec<_> = (0..5)
+        .map(|idx| link.send(Bytes::from(idx.to_string())))
+        .collect();
+    let opening = session.open_sender_link("test-sender2", "test");
+
+    let res = ntex::rt::time::timeout(Duration::from_secs(1), async move {
+        assert!(opening.await.is_err());
+        for delivery in deliveries {
+            assert!(delivery.await.is_err());
+        }
+    })
+    .await;
+    assert!(res.is_ok());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_decode_error() -> std::io::Result<()> {
+    let closed = Arc::new(AtomicBool::new(false));
+    let closed2 = closed.clone();
+
+    // raw amqp peer, sends malformed frame after open
+    let srv = test_server(move || {
+        let closed = closed2.clone();
+        fn_service(move |io: TcpStream| {
+            let closed = closed.clone();
+            async move {
+                let mut framed = Framed::new(io, ProtocolIdCodec);
+                let proto = framed.next().await.unwrap().unwrap();
+                framed.send(proto).await.unwrap();
+
+                let mut framed = framed.into_framed(AmqpCodec::<AmqpFrame>::new());
+                let _open = framed.next().await.unwrap().unwrap();
+                framed
+                    .send(AmqpFrame::new(0, Configuration::default().to_open().into()))
+                    .await
+                    .unwrap();
+
+                // size, doff, type, channel 1, unknown descriptor
+                let mut framed = framed.into_framed(BytesCodec);
+                framed
+                    .send(Bytes::from_static(&[
+                        0, 0, 0, 12, 2, 0, 0, 1, 0x00, 0x53, 0xFF, 0xFF,
+                    ]))
+                    .await
+                    .unwrap();
+
+                let mut framed = framed.into_framed(AmqpCodec::<AmqpFrame>::new());
+                while let Some(Ok(frame)) = framed.next().await {
+                    if let Frame::Close(close) = frame.performative() {
+                        let condition = close.error.as_ref().map(|e| e.condition.clone());
+                        if condition
+                            == Some(ntex_amqp_codec::protocol::AmqpError::DecodeError.into())
+                        {
+                            closed.store(true, Ordering::Relaxed);
+                        }
+                        break;
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let conn = connect_raw(&srv).await;
+    let controller = conn.controller();
+    let res = ntex::rt::time::timeout(Duration::from_secs(1), conn).await;
+    assert!(res.is_ok());
+    delay_for(Duration::from_millis(100)).await;
+    assert!(closed.load(Ordering::Relaxed));
+
+    let res = controller.begin_session(10, 10).await;
+    match res {
+        Err(AmqpTransportError::DecodeError(_)) => (),
+        _ => panic!("Decode error is expected"),
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_incoming_links() -> std::io::Result<()> {
+    let results = Arc::new(AtomicUsize::new(0));
+    let results2 = results.clone();
+
+    // raw amqp peer, attaches links to client session
+    let srv = test_server(move || {
+        let results = results2.clone();
+        fn_service(move |io: TcpStream| {
+            let results = results.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let attach = |name: &str, handle, role| Attach {
+                    name: ByteString::from(name),
+                    handle,
+                    role,
+                    snd_settle_mode: SenderSettleMode::Mixed,
+                    rcv_settle_mode: ReceiverSettleMode::First,
+                    source: None,
+                    target: None,
+                    unsettled: None,
+                    incomplete_unsettled: false,
+                    initial_delivery_count: Some(0),
+                    max_message_size: None,
+                    offered_capabilities: None,
+                    desired_capabilities: None,
+                    properties: None,
+                };
+
+                // accepted by client
+                let frm = attach("incoming-sender", 0, Role::Sender);
+                framed.send(AmqpFrame::new(0, frm.into())).await.unwrap();
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Attach(attach) = frame.performative() {
+                    if attach.role == Role::Receiver && attach.name() == "incoming-sender" {
+                        results.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+
+                // rejected by client
+                let frm = attach("incoming-receiver", 1, Role::Receiver);
+                framed.send(AmqpFrame::new(0, frm.into())).await.unwrap();
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Detach(detach) = frame.performative() {
+                    if detach.handle() == 1 && detach.error.is_some() {
+                        results.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+
+                // handle 0 is in use
+                let frm = attach("incoming-sender2", 0, Role::Sender);
+                framed.send(AmqpFrame::new(0, frm.into())).await.unwrap();
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::End(end) = frame.performative() {
+                    let condition = end.error.as_ref().map(|e| e.condition.clone());
+                    if condition
+                        == Some(ntex_amqp_codec::protocol::SessionError::HandleInUse.into())
+                    {
+                        results.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut links = session.incoming_links();
+
+    let link = links.next().await.unwrap();
+    assert!(!link.is_sender());
+    assert_eq!(link.name(), "incoming-sender");
+    match link.accept() {
+        ntex_amqp::AcceptedLink::Receiver(_) => (),
+        ntex_amqp::AcceptedLink::Sender(_) => panic!("Receiver link is expected"),
+    }
+
+    let link = links.next().await.unwrap();
+    assert!(link.is_sender());
+    link.reject(AmqpError::not_allowed().description("rejected"));
+
+    delay_for(Duration::from_millis(200)).await;
+    assert_eq!(results.load(Ordering::Relaxed), 3);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_duplicate_disposition() -> std::io::Result<()> {
+    // raw amqp peer, settles first delivery twice
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: std::u32::MAX,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(2),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            let mut first = None;
+            while let Some(Ok(frame)) = framed.next().await {
+                if let Frame::Transfer(transfer) = frame.performative() {
+                    let id = transfer.delivery_id().unwrap();
+                    let disp = Disposition {
+                        role: Role::Receiver,
+                        first: id,
+                        last: Some(id),
+                        settled: true,
+                        state: Some(DeliveryState::Accepted(Accepted {})),
+                        batchable: false,
+                    };
+                    framed
+                        .send(AmqpFrame::new(0, disp.clone().into()))
+                        .await
+                        .unwrap();
+
+                    if first.is_none() {
+                        first = Some(id);
+                        // same range again, with different outcome
+                        let disp = Disposition {
+                            state: Some(DeliveryState::Rejected(Rejected { error: None })),
+                            ..disp
+                        };
+                        framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+                    }
+                }
+            }
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+
+    let disp = link.send(Bytes::from_static(b"1")).await.unwrap();
+    assert!(disp.is_accepted());
+
+    // duplicate disposition is ignored
+    let disp = link.send(Bytes::from_static(b"2")).await.unwrap();
+    assert!(disp.is_accepted());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_report_received() -> std::io::Result<()> {
+    let received = Arc::new(AtomicUsize::new(0));
+    let received2 = received.clone();
+
+    // raw amqp peer, sends one unsettled transfer
+    let srv = test_server(move || {
+        let received = received2.clone();
+        fn_service(move |io: TcpStream| {
+            let received = received.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Sender,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let transfer = Transfer {
+                    handle: 0,
+                    delivery_id: Some(1),
+                    delivery_tag: Some(Bytes::from_static(b"tag")),
+                    message_format: None,
+                    settled: Some(false),
+                    more: false,
+                    rcv_settle_mode: None,
+                    state: None,
+                    resume: false,
+                    aborted: false,
+                    batchable: false,
+                    body: Some(TransferBody::Data(Bytes::from_static(b"data"))),
+                };
+                framed
+                    .send(AmqpFrame::new(0, transfer.into()))
+                    .await
+                    .unwrap();
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    if let Frame::Disposition(disp) = frame.performative() {
+                        match disp.state() {
+                            Some(DeliveryState::Received(state))
+                                if !disp.settled
+                                    && state.section_number == 1
+                                    && state.section_offset == 2 =>
+                            {
+                                received.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Some(DeliveryState::Accepted(_)) if disp.settled => {
+                                received.fetch_add(1, Ordering::Relaxed);
+                                break;
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(1);
+
+    let transfer = link.next().await.unwrap().unwrap();
+    let id = transfer.delivery_id.unwrap();
+    link.report_received(id, 1, 2);
+    link.accept(id);
+
+    // delivery is settled, state is not reported
+    link.report_received(id, 3, 4);
+
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(received.load(Ordering::Relaxed), 2);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_open_config() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        let mut config = Configuration::default();
+        config.container_id("test-server");
+
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(
+                pipeline_factory(|auth: server::Sasl<_>| async move {
+                    let init = auth.mechanism("PLAIN").init().await?;
+                    let conn = init.outcome(SaslCode::Ok).await?.open().await?;
+
+                    // container id and properties are sent by client
+                    let open = conn.frame();
+                    let prop = open
+                        .properties
+                        .as_ref()
+                        .and_then(|p| p.get(&Symbol::from("client")).cloned());
+                    if open.container_id != "test-client"
+                        || prop != Some(Variant::from(ByteString::from("ntex")))
+                    {
+                        return Err(server::ServerError::Disconnected);
+                    }
+                    Ok::<_, server::ServerError<()>>(conn.ack(()))
+                })
+                .map_err(|e| e.into()),
+            ),
+        )
+        .config(config)
+        .finish(
+            server::App::<()>::new()
+                .service("test", fn_factory_with_config(server))
+                .finish(),
+        )
+    });
+
+    let mut properties = Fields::default();
+    properties.insert(
+        Symbol::from("client"),
+        Variant::from(ByteString::from("ntex")),
+    );
+    let mut config = Configuration::default();
+    config.container_id("test-client").properties(properties);
+
+    let session = open_session_with_config(&srv, config).await;
+    assert_eq!(
+        session.remote_config().container_id,
+        Some(ByteString::from("test-server"))
+    );
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sasl_mechanism_not_supported() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(
+                pipeline_factory(|auth: server::Sasl<_>| async move {
+                    let init = auth.mechanism("ANONYMOUS").init().await?;
+                    let conn = init.outcome(SaslCode::Ok).await?.open().await?;
+                    Ok::<_, server::ServerError<()>>(conn.ack(()))
+                })
+                .map_err(|e| e.into()),
+            ),
+        )
+        .finish(
+            server::App::<()>::new()
+                .service("test", fn_factory_with_config(server))
+                .finish(),
+        )
+    });
+
+    let uri = Uri::try_from(format!("amqp://{}:{}", srv.addr().ip(), srv.addr().port())).unwrap();
+    let sasl_srv = sasl::connect_service(Connector::default());
+    let req = sasl::SaslConnect {
+        uri,
+        config: Configuration::default(),
+        time: None,
+        auth: sasl::SaslAuth {
+            authz_id: "".to_string(),
+            authn_id: "user1".to_string(),
+            password: "password1".to_string(),
+            ..Default::default()
+        },
+    };
+    match sasl_srv.call(req).await {
+        Err(either::Either::Left(sasl::SaslConnectError::MechanismNotSupported(mech))) => {
+            assert_eq!(mech, vec![sasl::SaslMechanism::Plain])
+        }
+        _ => panic!("Sasl mechanism error is expected"),
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sasl_mechanism_preference() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(
+                pipeline_factory(|auth: server::Sasl<_>| async move {
+                    let init = auth
+                        .mechanism("ANONYMOUS")
+                        .mechanism("EXTERNAL")
+                        .init()
+                        .await?;
+                    let code = if init.mechanism() == "EXTERNAL"
+                        && init.initial_response() == Some(b"client-identity".as_ref())
+                    {
+                        SaslCode::Ok
+                    } else {
+                        SaslCode::Auth
+                    };
+                    let conn = init.outcome(code).await?.open().await?;
+                    Ok::<_, server::ServerError<()>>(conn.ack(()))
+                })
+                .map_err(|e| e.into()),
+            ),
+        )
+        .finish(
+            server::App::<()>::new()
+                .service("test", fn_factory_with_config(server))
+                .finish(),
+        )
+    });
+
+    let uri = Uri::try_from(format!("amqp://{}:{}", srv.addr().ip(), srv.addr().port())).unwrap();
+    let sasl_srv = sasl::connect_service(Connector::default());
+    let req = sasl::SaslConnect {
+        uri,
+        config: Configuration::default(),
+        time: None,
+        auth: sasl::SaslAuth {
+            authz_id: "client-identity".to_string(),
+            mechanisms: vec![
+                sasl::SaslMechanism::Plain,
+                sasl::SaslMechanism::External,
+                sasl::SaslMechanism::Anonymous,
+            ],
+            ..Default::default()
+        },
+    };
+    assert!(sasl_srv.call(req).await.is_ok());
+
+    Ok(())
+}
+
+/// Decode amqp-value of raw transfer body
+fn transfer_value(transfer: &Transfer) -> Variant {
+    match transfer.body() {
+        Some(TransferBody::Data(data)) => {
+            InMessage::decode(data).unwrap().1.value().cloned().unwrap()
+        }
+        body => panic!("Data body is expected: {:?}", body),
+    }
+}
+
+#[ntex::test]
+async fn test_transaction() -> std::io::Result<()> {
+    let discharged = Arc::new(AtomicBool::new(false));
+    let discharged2 = discharged.clone();
+
+    // raw amqp peer, acts as transaction coordinator
+    let srv = test_server(move || {
+        let discharged = discharged2.clone();
+        fn_service(move |io: TcpStream| {
+            let discharged = discharged.clone();
+            async move {
+                let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+                let txn_id = Bytes::from_static(b"txn-1");
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    match frame.performative() {
+                        Frame::Attach(attach) => {
+                            let handle = attach.handle();
+                            let target = attach.target().unwrap();
+                            assert_eq!(handle == 0, target.coordinator().is_some());
+                            let reply = Attach {
+                                role: Role::Receiver,
+                                initial_delivery_count: None,
+                                ..attach.clone()
+                            };
+                            framed.send(AmqpFrame::new(0, reply.into())).await.unwrap();
+
+                            let flow = Flow {
+                                next_incoming_id: Some(next_incoming_id),
+                                incoming_window: std::u32::MAX,
+                                next_outgoing_id: 1,
+                                outgoing_window: std::u32::MAX,
+                                handle: Some(handle),
+                                delivery_count: Some(0),
+                                link_credit: Some(10),
+                                available: None,
+                                drain: false,
+                                echo: false,
+                                properties: None,
+                            };
+                            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+                        }
+                        Frame::Transfer(transfer) => {
+                            let is_coordinator = transfer.handle() == 0;
+                            let state = if is_coordinator {
+                                match transfer_value(transfer) {
+                                    Variant::Described((Descriptor::Ulong(0x31), _)) => {
+                                        DeliveryState::Declared(Declared {
+                                            txn_id: txn_id.clone(),
+                                        })
+                                    }
+                                    Variant::Described((Descriptor::Ulong(0x32), fields)) => {
+                                        assert_eq!(
+                                            *fields,
+                                            Variant::List(List(vec![
+                                                Variant::Binary(txn_id.clone()),
+                                                Variant::Boolean(false)
+                                            ]))
+                                        );
+                                        discharged.store(true, Ordering::Relaxed);
+                                        DeliveryState::Accepted(Accepted {})
+                                    }
+                                    value => panic!("Unexpected coordinator message {:?}", value),
+                                }
+                            } else {
+                                // transactional transfer
+                                assert_eq!(
+                                    transfer.state(),
+                                    Some(&DeliveryState::TransactionalState(TransactionalState {
+                                        txn_id: txn_id.clone(),
+                                        outcome: None,
+                                    }))
+                                );
+                                DeliveryState::TransactionalState(TransactionalState {
+                                    txn_id: txn_id.clone(),
+                                    outcome: Some(Outcome::Accepted(Accepted {})),
+                                })
+                            };
+
+                            let disp = Disposition {
+                                role: Role::Receiver,
+                                first: transfer.delivery_id().unwrap(),
+                                last: None,
+                                settled: true,
+                                state: Some(state),
+                                batchable: false,
+                            };
+                            framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+                        }
+                        Frame::Detach(detach) => {
+                            let detach = Detach {
+                                error: None,
+                                ..detach.clone()
+                            };
+                            framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+                        }
+                        _ => (),
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let txn = session.transaction().await.unwrap();
+    assert_eq!(txn.id(), &Bytes::from_static(b"txn-1"));
+
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    let disp = txn
+        .send(&link, Bytes::from_static(b"test message"))
+        .await
+        .unwrap();
+    match disp.state() {
+        Some(DeliveryState::TransactionalState(state)) => {
+            assert_eq!(state.txn_id(), txn.id());
+            assert!(matches!(state.outcome(), Some(Outcome::Accepted(_))));
+        }
+        state => panic!("Transactional state is expected: {:?}", state),
+    }
+
+    txn.commit().await.unwrap();
+    assert!(discharged.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_transaction_settle_range() -> std::io::Result<()> {
+    let settled = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let settled2 = settled.clone();
+
+    // raw amqp peer, acts as transaction coordinator and as sender
+    let srv = test_server(move || {
+        let settled = settled2.clone();
+        fn_service(move |io: TcpStream| {
+            let settled = settled.clone();
+            async move {
+                let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    match frame.performative() {
+                        Frame::Attach(attach) if attach.role == Role::Sender => {
+                            let reply = Attach {
+                                role: Role::Receiver,
+                                initial_delivery_count: None,
+                                ..attach.clone()
+                            };
+                            framed.send(AmqpFrame::new(0, reply.into())).await.unwrap();
+
+                            let flow = Flow {
+                                next_incoming_id: Some(next_incoming_id),
+                                incoming_window: std::u32::MAX,
+                                next_outgoing_id: 1,
+                                outgoing_window: std::u32::MAX,
+                                handle: Some(attach.handle()),
+                                delivery_count: Some(0),
+                                link_credit: Some(10),
+                                available: None,
+                                drain: false,
+                                echo: false,
+                                properties: None,
+                            };
+                            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+                        }
+                        Frame::Attach(attach) => {
+                            let reply = Attach {
+                                role: Role::Sender,
+                                initial_delivery_count: Some(0),
+                                ..attach.clone()
+                            };
+                            framed.send(AmqpFrame::new(0, reply.into())).await.unwrap();
+
+                            for id in 0..3 {
+                                let transfer = Transfer {
+                                    handle: attach.handle(),
+                                    delivery_id: Some(id),
+                                    delivery_tag: Some(Bytes::from(format!("tag-{}", id))),
+                                    message_format: None,
+                                    settled: Some(false),
+                                    more: false,
+                                    rcv_settle_mode: None,
+                                    state: None,
+                                    resume: false,
+                                    aborted: false,
+                                    batchable: false,
+                                    body: Some(TransferBody::Data(Bytes::from_static(b"test"))),
+                                };
+                                framed
+                                    .send(AmqpFrame::new(0, transfer.into()))
+                                    .await
+                                    .unwrap();
+                            }
+                        }
+                        Frame::Transfer(transfer) => {
+                            let disp = Disposition {
+                                role: Role::Receiver,
+                                first: transfer.delivery_id().unwrap(),
+                                last: None,
+                                settled: true,
+                                state: Some(DeliveryState::Declared(Declared {
+                                    txn_id: Bytes::from_static(b"txn-1"),
+                                })),
+                                batchable: false,
+                            };
+                            framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+                        }
+                        Frame::Disposition(disp) => {
+                            settled.lock().unwrap().push(disp.clone());
+                        }
+                        _ => (),
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let txn = session.transaction().await.unwrap();
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(10);
+    for _ in 0..3 {
+        link.next().await.unwrap().unwrap();
+    }
+
+    txn.settle_range(&link, 0, 2, DeliveryState::Accepted(Accepted {}));
+    delay_for(Duration::from_millis(100)).await;
+
+    let settled = settled.lock().unwrap();
+    assert_eq!(settled.len(), 1);
+    assert_eq!(settled[0].first, 0);
+    assert_eq!(settled[0].last, Some(2));
+    assert!(settled[0].settled);
+    assert_eq!(
+        settled[0].state,
+        Some(DeliveryState::TransactionalState(TransactionalState {
+            txn_id: Bytes::from_static(b"txn-1"),
+            outcome: Some(Outcome::Accepted(Accepted {})),
+        }))
+    );
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_transaction_discharge_failure() -> std::io::Result<()> {
+    let detached = Arc::new(AtomicBool::new(false));
+    let detached2 = detached.clone();
+
+    // raw amqp peer, coordinator limits message size,
+    // discharge with long transaction id does not fit
+    let srv = test_server(move || {
+        let detached = detached2.clone();
+        fn_service(move |io: TcpStream| {
+            let detached = detached.clone();
+            async move {
+                let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    match frame.performative() {
+                        Frame::Attach(attach) => {
+                            let reply = Attach {
+                                role: Role::Receiver,
+                                initial_delivery_count: None,
+                                max_message_size: Some(64),
+                                ..attach.clone()
+                            };
+                            framed.send(AmqpFrame::new(0, reply.into())).await.unwrap();
+
+                            let flow = Flow {
+                                next_incoming_id: Some(next_incoming_id),
+                                incoming_window: std::u32::MAX,
+                                next_outgoing_id: 1,
+                                outgoing_window: std::u32::MAX,
+                                handle: Some(attach.handle()),
+                                delivery_count: Some(0),
+                                link_credit: Some(10),
+                                available: None,
+                                drain: false,
+                                echo: false,
+                                properties: None,
+                            };
+                            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+                        }
+                        Frame::Transfer(transfer) => {
+                            let disp = Disposition {
+                                role: Role::Receiver,
+                                first: transfer.delivery_id().unwrap(),
+                                last: None,
+                                settled: true,
+                                state: Some(DeliveryState::Declared(Declared {
+                                    txn_id: Bytes::from(vec![b'x'; 128]),
+                                })),
+                                batchable: false,
+                            };
+                            framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+                        }
+                        Frame::Detach(detach) => {
+                            assert!(detach.closed);
+                            detached.store(true, Ordering::Relaxed);
+                            framed
+                                .send(AmqpFrame::new(0, detach.clone().into()))
+                                .await
+                                .unwrap();
+                        }
+                        _ => (),
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let txn = session.transaction().await.unwrap();
+
+    // controller link is closed on failed discharge
+    let res = txn.rollback().await;
+    assert!(matches!(res, Err(AmqpTransportError::MessageTooLarge)));
+    assert!(detached.load(Ordering::Relaxed));
+    assert!(session.links().is_empty());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_management_client() -> std::io::Result<()> {
+    // raw amqp management node, responds to requests in reverse order
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let mut requests = Vec::new();
+            while let Some(Ok(frame)) = framed.next().await {
+                match frame.performative() {
+                    Frame::Attach(attach) if attach.role == Role::Sender => {
+                        let target = attach.target().and_then(|t| t.target()).unwrap();
+                        assert_eq!(target.address, Some(ByteString::from("$management")));
+                        let attach = Attach {
+                            handle: 0,
+                            role: Role::Receiver,
+                            initial_delivery_count: None,
+                            ..attach.clone()
+                        };
+                        framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+                        let flow = Flow {
+                            next_incoming_id: Some(0),
+                            incoming_window: std::u32::MAX,
+                            next_outgoing_id: 0,
+                            outgoing_window: std::u32::MAX,
+                            handle: Some(0),
+                            delivery_count: Some(0),
+                            link_credit: Some(10),
+                            available: None,
+                            drain: false,
+                            echo: false,
+                            properties: None,
+                        };
+                        framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+                    }
+                    Frame::Attach(attach) => {
+                        // assign address of dynamic reply node
+                        let mut source = attach.source.clone().unwrap();
+                        assert!(source.dynamic);
+                        source.address = Some(ByteString::from("reply-1"));
+                        let attach = Attach {
+                            handle: 1,
+                            role: Role::Sender,
+                            initial_delivery_count: Some(0),
+                            source: Some(source),
+                            ..attach.clone()
+                        };
+                        framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+                    }
+                    Frame::Transfer(transfer) => {
+                        let msg = match transfer.body() {
+                            Some(TransferBody::Data(data)) => InMessage::decode(data).unwrap().1,
+                            body => panic!("Data body is expected: {:?}", body),
+                        };
+                        let disp = Disposition {
+                            role: Role::Receiver,
+                            first: transfer.delivery_id().unwrap(),
+                            last: None,
+                            settled: true,
+                            state: Some(DeliveryState::Accepted(Accepted {})),
+                            batchable: false,
+                        };
+                        framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+                        requests.push(msg);
+                        if requests.len() < 2 {
+                            continue;
+                        }
+
+                        for (id, req) in requests.drain(..).rev().enumerate() {
+                            let props = req.properties().unwrap();
+                            assert_eq!(props.reply_to(), Some(&ByteString::from("reply-1")));
+                            assert_eq!(req.app_property("type").unwrap(), "queue");
+
+                            let mut resp = ntex_amqp::codec::OutMessage::with_value(
+                                req.app_property("name").cloned().unwrap(),
+                            );
+                            resp.set_correlation_id(props.message_id().cloned().unwrap())
+                                .set_app_property("statusCode", 200)
+                                .set_app_property(
+                                    "operation",
+                                    req.app_property("operation").cloned().unwrap(),
+                                );
+                            let transfer = Transfer {
+                                handle: 1,
+                                delivery_id: Some(id as u32),
+                                delivery_tag: Some(Bytes::from(format!("tag-{}", id))),
+                                message_format: None,
+                                settled: Some(true),
+                                more: false,
+                                rcv_settle_mode: None,
+                                state: None,
+                                resume: false,
+                                aborted: false,
+                                batchable: false,
+                                body: Some(TransferBody::MessageOut(resp)),
+                            };
+                            framed
+                                .send(AmqpFrame::new(0, transfer.into()))
+                                .await
+                                .unwrap();
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let client = session.management("$management").await.unwrap();
+    assert_eq!(client.reply_to(), "reply-1");
+
+    let read = client.request(
+        "READ",
+        "queue",
+        Some(ByteString::from("queue-1")),
+        ntex_amqp::codec::OutMessage::default(),
+    );
+    let create = client.request(
+        "CREATE",
+        "queue",
+        Some(ByteString::from("queue-2")),
+        ntex_amqp::codec::OutMessage::default(),
+    );
+    let (read, create) = futures::future::join(read, create).await;
+
+    let read = read.unwrap();
+    assert_eq!(read.app_property("statusCode"), Some(&Variant::Int(200)));
+    assert_eq!(read.app_property("operation").unwrap(), "READ");
+    assert_eq!(read.value().unwrap(), "queue-1");
+    let create = create.unwrap();
+    assert_eq!(create.app_property("operation").unwrap(), "CREATE");
+    assert_eq!(create.value().unwrap(), "queue-2");
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_request_reply() -> std::io::Result<()> {
+    let detached = Arc::new(AtomicUsize::new(0));
+    let detached2 = detached.clone();
+
+    // raw amqp peer, responds to "ping" requests only
+    let srv = test_server(move || {
+        let detached = detached2.clone();
+        fn_service(move |io: TcpStream| {
+            let detached = detached.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let mut reply_id = 0;
+                while let Some(Ok(frame)) = framed.next().await {
+                    match frame.performative() {
+                        Frame::Attach(attach) if attach.role == Role::Sender => {
+                            let attach = Attach {
+                                handle: 0,
+                                role: Role::Receiver,
+                                initial_delivery_count: None,
+                                ..attach.clone()
+                            };
+                            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+                            let flow = Flow {
+                                next_incoming_id: Some(0),
+                                incoming_window: std::u32::MAX,
+                                next_outgoing_id: 0,
+                                outgoing_window: std::u32::MAX,
+                                handle: Some(0),
+                                delivery_count: Some(0),
+                                link_credit: Some(10),
+                                available: None,
+                                drain: false,
+                                echo: false,
+                                properties: None,
+                            };
+                            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+                        }
+                        Frame::Attach(attach) => {
+                            let attach = Attach {
+                                handle: 1,
+                                role: Role::Sender,
+                                initial_delivery_count: Some(0),
+                                ..attach.clone()
+                            };
+                            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+                        }
+                        Frame::Transfer(transfer) => {
+                            let req = match transfer.body() {
+                                Some(TransferBody::Data(data)) => {
+                                    InMessage::decode(data).unwrap().1
+                                }
+                                body => panic!("Data body is expected: {:?}", body),
+                            };
+                            let disp = Disposition {
+                                role: Role::Receiver,
+                                first: transfer.delivery_id().unwrap(),
+                                last: None,
+                                settled: true,
+                                state: Some(DeliveryState::Accepted(Accepted {})),
+                                batchable: false,
+                            };
+                            framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+
+                            let props = req.properties().unwrap();
+                            assert_eq!(props.reply_to(), Some(&ByteString::from("replies")));
+                            if req.value().unwrap() != "ping" {
+                                continue;
+                            }
+
+                            // unrelated message is ignored
+                            let mut other = ntex_amqp::codec::OutMessage::with_value("other");
+                            other.set_correlation_id("unknown");
+                            let mut resp = req.reply_message();
+                            resp.set_value("pong");
+
+                            for msg in [other, resp] {
+                                let transfer = Transfer {
+                                    handle: 1,
+                                    delivery_id: Some(reply_id),
+                                    delivery_tag: Some(Bytes::from(format!("tag-{}", reply_id))),
+                                    message_format: None,
+                                    settled: Some(false),
+                                    more: false,
+                                    rcv_settle_mode: None,
+                                    state: None,
+                                    resume: false,
+                                    aborted: false,
+                                    batchable: false,
+                                    body: Some(TransferBody::MessageOut(msg)),
+                                };
+                                reply_id += 1;
+                                framed
+                                    .send(AmqpFrame::new(0, transfer.into()))
+                                    .await
+                                    .unwrap();
+                            }
+                        }
+                        Frame::Detach(detach) => {
+                            detached.fetch_add(1, Ordering::Relaxed);
+                            framed
+                                .send(AmqpFrame::new(0, detach.clone().into()))
+                                .await
+                                .unwrap();
+                        }
+                        _ => (),
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let sender = session
+        .open_sender_link("test-sender", "requests")
+        .await
+        .unwrap();
+    let receiver = session
+        .open_receiver_link("test-receiver", "replies")
+        .await
+        .unwrap();
+    let rr = RequestReply::new(sender, receiver).timeout(Duration::from_millis(200));
+    assert_eq!(rr.reply_to(), Some(&ByteString::from("replies")));
+
+    let mut req = ntex_amqp::codec::OutMessage::with_value("ping");
+    req.set_message_id("request-1");
+    let resp = rr.request(req).await.unwrap();
+    assert_eq!(resp.value().unwrap(), "pong");
+    assert_eq!(
+        resp.properties().unwrap().correlation_id(),
+        Some(&MessageId::from("request-1"))
+    );
+
+    // message id is generated
+    let resp = rr
+        .request(ntex_amqp::codec::OutMessage::with_value("ping"))
+        .await
+        .unwrap();
+    assert_eq!(resp.value().unwrap(), "pong");
+
+    let res = rr
+        .request(ntex_amqp::codec::OutMessage::with_value("hello"))
+        .await;
+    assert!(matches!(res, Err(AmqpTransportError::Timeout)));
+
+    // request with message id of pending request is rejected
+    let mut req = ntex_amqp::codec::OutMessage::with_value("hello");
+    req.set_message_id("request-2");
+    let pending = rr.request(req);
+    let mut req = ntex_amqp::codec::OutMessage::with_value("ping");
+    req.set_message_id("request-2");
+    let res = rr.request(req).await;
+    assert!(matches!(res, Err(AmqpTransportError::Protocol(_))));
+    assert!(matches!(pending.await, Err(AmqpTransportError::Timeout)));
+
+    // dropped helper closes both links
+    drop(rr);
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(detached.load(Ordering::Relaxed), 2);
+    assert!(session.links().is_empty());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_stats() -> std::io::Result<()> {
+    // raw amqp peer, grants link credit but opens session window with delay
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, 0).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: 0,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(10),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed
+                .send(AmqpFrame::new(0, flow.clone().into()))
+                .await
+                .unwrap();
+
+            delay_for(Duration::from_millis(300)).await;
+            let flow = Flow {
+                incoming_window: 10,
+                handle: None,
+                delivery_count: None,
+                link_credit: None,
+                ..flow
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            while let Some(Ok(frame)) = framed.next().await {
+                if let Frame::Transfer(transfer) = frame.performative() {
+                    let disp = Disposition {
+                        role: Role::Receiver,
+                        first: transfer.delivery_id().unwrap(),
+                        last: None,
+                        settled: true,
+                        state: Some(DeliveryState::Accepted(Accepted {})),
+                        batchable: false,
+                    };
+                    framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+                }
+            }
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    let stats = session.stats();
+    assert_eq!(stats.outgoing_window, 0);
+    assert_eq!(stats.pending_transfers, 0);
+    assert_eq!(stats.unsettled_deliveries, 0);
+    let next_outgoing_id = stats.next_outgoing_id;
+
+    // remote window is closed, transfers are queued by session
+    delay_for(Duration::from_millis(100)).await;
+    let d1 = link.send(Bytes::from_static(b"1"));
+    let d2 = link.send(Bytes::from_static(b"2"));
+    let stats = session.stats();
+    assert_eq!(stats.pending_transfers, 2);
+    assert_eq!(stats.next_outgoing_id, next_outgoing_id);
+
+    assert!(d1.await.unwrap().is_accepted());
+    assert!(d2.await.unwrap().is_accepted());
+    let stats = session.stats();
+    assert_eq!(stats.pending_transfers, 0);
+    assert_eq!(stats.unsettled_deliveries, 0);
+    assert_eq!(stats.next_outgoing_id, next_outgoing_id + 2);
+    assert_eq!(stats.outgoing_window, 8);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_max_pending_transfers() -> std::io::Result<()> {
+    // raw amqp peer, grants link credit but never opens session window
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, 0).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: 0,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(10),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut config = Configuration::default();
+    config.max_pending_transfers(2);
+    let mut conn = connect_raw_with_config(&srv, config).await;
+    let session = conn.open_session();
+    ntex::rt::spawn(conn.map(|_| ()));
+    let mut session = session.await.unwrap();
+
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(100)).await;
+
+    let _d1 = link.send(Bytes::from_static(b"1"));
+    let _d2 = link.send(Bytes::from_static(b"2"));
+    let res = link.send(Bytes::from_static(b"3")).await;
+    assert!(matches!(res, Err(AmqpTransportError::Full(2))));
+    assert_eq!(session.stats().pending_transfers, 2);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_settle_mode_second() -> std::io::Result<()> {
+    let settled = Arc::new(AtomicBool::new(false));
+    let settled2 = settled.clone();
+
+    // raw amqp peer, receiver with rcv-settle-mode second
+    let srv = test_server(move || {
+        let settled = settled2.clone();
+        fn_service(move |io: TcpStream| {
+            let settled = settled.clone();
+            async move {
+                let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    assert_eq!(attach.rcv_settle_mode(), ReceiverSettleMode::Second);
+                    Attach {
+                        handle: 0,
+                        role: Role::Receiver,
+                        initial_delivery_count: None,
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id),
+                    incoming_window: std::u32::MAX,
+                    next_outgoing_id: 1,
+                    outgoing_window: std::u32::MAX,
+                    handle: Some(0),
+                    delivery_count: Some(0),
+                    link_credit: Some(1),
+                    available: None,
+                    drain: false,
+                    echo: false,
+                    properties: None,
+                };
+                framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let id = if let Frame::Transfer(transfer) = frame.performative() {
+                    transfer.delivery_id().unwrap()
+                } else {
+                    panic!("Transfer is expected: {:?}", frame)
+                };
+
+                // first phase, outcome without settlement
+                let disp = Disposition {
+                    role: Role::Receiver,
+                    first: id,
+                    last: None,
+                    settled: false,
+                    state: Some(DeliveryState::Accepted(Accepted {})),
+                    batchable: false,
+                };
+                framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+
+                // sender settles delivery
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Disposition(disp) = frame.performative() {
+                    assert_eq!(disp.role, Role::Sender);
+                    assert_eq!(disp.first, id);
+                    assert!(disp.settled);
+                    assert!(disp.is_accepted());
+                    settled.store(true, Ordering::Relaxed);
+                } else {
+                    panic!("Disposition is expected: {:?}", frame)
+                }
+
+                // second phase, receiver settles delivery
+                let disp = Disposition {
+                    role: Role::Receiver,
+                    first: id,
+                    last: None,
+                    settled: true,
+                    state: Some(DeliveryState::Accepted(Accepted {})),
+                    batchable: false,
+                };
+                framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+
+                while let Some(Ok(_)) = framed.next().await {}
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .build_sender_link("test-sender", "test")
+        .receiver_settle_mode(ReceiverSettleMode::Second)
+        .open()
+        .await
+        .unwrap();
+
+    let disp = link.send(Bytes::from_static(b"test")).await.unwrap();
+    assert!(disp.is_accepted());
+    assert_eq!(session.stats().unsettled_deliveries, 0);
+
+    // final settlement of already settled delivery is ignored
+    delay_for(Duration::from_millis(100)).await;
+    assert!(settled.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_unsettled() -> std::io::Result<()> {
+    // raw amqp peer, reports its unsettled deliveries on attach
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                let unsettled = attach.unsettled().unwrap();
+                assert_eq!(unsettled.len(), 2);
+                assert_eq!(
+                    unsettled.get(&Variant::Binary(Bytes::from_static(b"t1"))),
+                    Some(&Variant::Null)
+                );
+
+                let mut remote = ntex_amqp::codec::protocol::Map::default();
+                remote.insert(
+                    Variant::Binary(Bytes::from_static(b"t1")),
+                    DeliveryState::Accepted(Accepted {}).to_variant(),
+                );
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    unsettled: Some(remote),
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: std::u32::MAX,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(10),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            // deliveries are not settled
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .build_sender_link("test-sender", "test")
+        .unsettled(vec![
+            (Bytes::from_static(b"t1"), None),
+            (
+                Bytes::from_static(b"t2"),
+                Some(DeliveryState::Released(Released {})),
+            ),
+        ])
+        .open()
+        .await
+        .unwrap();
+
+    // remote peer accepted t1, t2 is unknown to remote peer
+    let remote = link.remote_unsettled();
+    assert_eq!(remote.len(), 1);
+    assert_eq!(
+        remote.get(&Bytes::from_static(b"t1")),
+        Some(&Some(DeliveryState::Accepted(Accepted {})))
+    );
+
+    // re-send t2
+    delay_for(Duration::from_millis(100)).await;
+    let _delivery = link.send_with_tag(Bytes::from_static(b"2"), Bytes::from_static(b"t2"));
+    let unsettled = link.unsettled();
+    assert_eq!(unsettled.len(), 1);
+    assert_eq!(unsettled.get(&Bytes::from_static(b"t2")), Some(&None));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_abort() -> std::io::Result<()> {
+    let aborted = Arc::new(AtomicBool::new(false));
+    let aborted2 = aborted.clone();
+
+    // raw amqp peer, session window allows only first frame of delivery
+    let srv = test_server(move || {
+        let aborted = aborted2.clone();
+        fn_service(move |io: TcpStream| {
+            let aborted = aborted.clone();
+            async move {
+                let (mut framed, next_incoming_id) = raw_peer_begin(io, 1).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Receiver,
+                        initial_delivery_count: None,
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id),
+                    incoming_window: 1,
+                    next_outgoing_id: 1,
+                    outgoing_window: std::u32::MAX,
+                    handle: Some(0),
+                    delivery_count: Some(0),
+                    link_credit: Some(10),
+                    available: None,
+                    drain: false,
+                    echo: false,
+                    properties: None,
+                };
+                framed
+                    .send(AmqpFrame::new(0, flow.clone().into()))
+                    .await
+                    .unwrap();
+
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Transfer(transfer) = frame.performative() {
+                    assert!(transfer.more());
+                    assert!(!transfer.aborted());
+                } else {
+                    panic!("Transfer is expected: {:?}", frame)
+                }
+
+                // open window after delivery is aborted
+                delay_for(Duration::from_millis(200)).await;
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id + 1),
+                    incoming_window: 10,
+                    handle: None,
+                    delivery_count: None,
+                    link_credit: None,
+                    ..flow
+                };
+                framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Transfer(transfer) = frame.performative() {
+                    assert!(transfer.aborted());
+                    assert!(!transfer.more());
+                    assert!(transfer.body().is_none());
+                    aborted.store(true, Ordering::Relaxed);
+                } else {
+                    panic!("Transfer is expected: {:?}", frame)
+                }
+
+                while let Some(Ok(_)) = framed.next().await {}
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(100)).await;
+
+    let delivery = link.send(Bytes::from(vec![b'x'; 200 * 1024]));
+    assert!(link.abort(&delivery));
+    assert!(!link.abort(&delivery));
+    assert!(matches!(delivery.await, Err(AmqpTransportError::Aborted)));
+
+    delay_for(Duration::from_millis(300)).await;
+    assert!(aborted.load(Ordering::Relaxed));
+    assert_eq!(session.stats().unsettled_deliveries, 0);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_settle_partial() -> std::io::Result<()> {
+    // raw amqp peer, session window allows only first frame of second delivery
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, 2).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: 2,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(10),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(100)).await;
+
+    let small = link.send(Bytes::from_static(b"test"));
+    let large = link.send(Bytes::from(vec![b'x'; 200 * 1024]));
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(session.stats().unsettled_deliveries, 2);
+
+    // sent delivery could not be aborted
+    assert!(!link.abort(&small));
+    assert_eq!(session.stats().unsettled_deliveries, 2);
+
+    // multi-frame delivery in progress could be aborted only
+    assert!(!link.settle(&large, DeliveryState::Accepted(Accepted {})));
+    assert_eq!(session.stats().unsettled_deliveries, 2);
+    assert_eq!(
+        link.settle_many(&[&large], DeliveryState::Accepted(Accepted {})),
+        0
+    );
+    assert_eq!(session.stats().unsettled_deliveries, 2);
+
+    assert!(link.settle(&small, DeliveryState::Accepted(Accepted {})));
+    assert!(small.await.unwrap().is_accepted());
+    assert_eq!(session.stats().unsettled_deliveries, 1);
+    assert_eq!(
+        link.settle_unsettled(DeliveryState::Accepted(Accepted {})),
+        0
+    );
+    assert_eq!(session.stats().unsettled_deliveries, 1);
+
+    assert!(link.abort(&large));
+    assert!(matches!(large.await, Err(AmqpTransportError::Aborted)));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_retry_policy() -> std::io::Result<()> {
+    use ntex_amqp::codec::protocol::{AmqpError as ErrorCode, Error};
+    use ntex_amqp::RetryPolicy;
+
+    let transfers = Arc::new(AtomicUsize::new(0));
+    let transfers2 = transfers.clone();
+
+    // raw amqp peer, rejects first attempts of deliveries
+    let srv = test_server(move || {
+        let transfers = transfers2.clone();
+        fn_service(move |io: TcpStream| {
+            let transfers = transfers.clone();
+            async move {
+                let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Receiver,
+                        initial_delivery_count: None,
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id),
+                    incoming_window: std::u32::MAX,
+                    next_outgoing_id: 1,
+                    outgoing_window: std::u32::MAX,
+                    handle: Some(0),
+                    delivery_count: Some(0),
+                    link_credit: Some(10),
+                    available: None,
+                    drain: false,
+                    echo: false,
+                    properties: None,
+                };
+                framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    let (id, body) = if let Frame::Transfer(transfer) = frame.performative() {
+                        (transfer.delivery_id().unwrap(), transfer.body().cloned())
+                    } else {
+                        continue;
+                    };
+                    let num = transfers.fetch_add(1, Ordering::Relaxed);
+
+                    // "retry" is accepted on second attempt, "fail" is never accepted
+                    let retry = TransferBody::Data(Bytes::from_static(b"retry"));
+                    let state = if body == Some(retry) && num > 0 {
+                        DeliveryState::Accepted(Accepted {})
+                    } else {
+                        DeliveryState::Rejected(Rejected {
+                            error: Some(Error {
+                                condition: ErrorCode::ResourceLimitExceeded.into(),
+                                description: None,
+                                info: None,
+                            }),
+                        })
+                    };
+                    let disp = Disposition {
+                        role: Role::Receiver,
+                        first: id,
+                        last: None,
+                        settled: true,
+                        state: Some(state),
+                        batchable: false,
+                    };
+                    framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .build_sender_link("test-sender", "test")
+        .retry_policy(
+            RetryPolicy::new(2)
+                .condition(ErrorCode::ResourceLimitExceeded)
+                .backoff(Duration::from_millis(10)),
+        )
+        .open()
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(100)).await;
+
+    // rejected once, accepted on retry
+    let disp = link.send(Bytes::from_static(b"retry")).await.unwrap();
+    assert!(disp.is_accepted());
+    assert_eq!(transfers.load(Ordering::Relaxed), 2);
+
+    // retries are exhausted
+    let disp = link.send(Bytes::from_static(b"fail")).await.unwrap();
+    assert!(disp.is_rejected());
+    assert_eq!(transfers.load(Ordering::Relaxed), 5);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_handle_max() -> std::io::Result<()> {
+    // raw amqp peer, allows single link handle
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin_with_handle_max(io, std::u32::MAX, 0).await;
+
+            while let Some(Ok(frame)) = framed.next().await {
+                match frame.performative() {
+                    Frame::Attach(attach) => {
+                        let attach = Attach {
+                            role: Role::Receiver,
+                            initial_delivery_count: None,
+                            ..attach.clone()
+                        };
+                        framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+                    }
+                    Frame::Detach(detach) => {
+                        let detach = detach.clone();
+                        framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+                    }
+                    _ => (),
+                }
+            }
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session.open_sender_link("test-1", "test").await.unwrap();
+
+    let res = session.open_sender_link("test-2", "test").await;
+    assert!(matches!(res, Err(AmqpTransportError::HandleMaxExceeded(0))));
+    let res = session.open_receiver_link("test-3", "test").await;
+    assert!(matches!(res, Err(AmqpTransportError::HandleMaxExceeded(0))));
+
+    // handle is released once detach is confirmed
+    link.close().await.unwrap();
+    let link = session.open_sender_link("test-2", "test").await.unwrap();
+    assert_eq!(link.id(), 0);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_settle() -> std::io::Result<()> {
+    let settled = Arc::new(AtomicBool::new(false));
+    let settled2 = settled.clone();
+
+    // raw amqp peer, never settles deliveries
+    let srv = test_server(move || {
+        let settled = settled2.clone();
+        fn_service(move |io: TcpStream| {
+            let settled = settled.clone();
+            async move {
+                let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Receiver,
+                        initial_delivery_count: None,
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id),
+                    incoming_window: std::u32::MAX,
+                    next_outgoing_id: 1,
+                    outgoing_window: std::u32::MAX,
+                    handle: Some(0),
+                    delivery_count: Some(0),
+                    link_credit: Some(1),
+                    available: None,
+                    drain: false,
+                    echo: false,
+                    properties: None,
+                };
+                framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let id = if let Frame::Transfer(transfer) = frame.performative() {
+                    transfer.delivery_id().unwrap()
+                } else {
+                    panic!("Transfer is expected: {:?}", frame)
+                };
+
+                // sender settles delivery
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Disposition(disp) = frame.performative() {
+                    assert_eq!(disp.role, Role::Sender);
+                    assert_eq!(disp.first, id);
+                    assert!(disp.settled);
+                    assert!(disp.is_released());
+                    settled.store(true, Ordering::Relaxed);
+                } else {
+                    panic!("Disposition is expected: {:?}", frame)
+                }
+
+                while let Some(Ok(_)) = framed.next().await {}
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(100)).await;
+
+    let delivery = link.send(Bytes::from_static(b"test"));
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(session.stats().unsettled_deliveries, 1);
+
+    assert!(link.settle(&delivery, Outcome::Released(Released {})));
+    assert!(!link.settle(&delivery, Outcome::Released(Released {})));
+    assert_eq!(session.stats().unsettled_deliveries, 0);
+
+    let disp = delivery.await.unwrap();
+    assert!(disp.is_released());
+
+    delay_for(Duration::from_millis(100)).await;
+    assert!(settled.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_wait_for_credit() -> std::io::Result<()> {
+    // raw amqp peer, grants more credit later and detaches link
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            for credit in &[1, 5] {
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id),
+                    incoming_window: std::u32::MAX,
+                    next_outgoing_id: 1,
+                    outgoing_window: std::u32::MAX,
+                    handle: Some(0),
+                    delivery_count: Some(0),
+                    link_credit: Some(*credit),
+                    available: None,
+                    drain: false,
+                    echo: false,
+                    properties: None,
+                };
+                framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+                delay_for(Duration::from_millis(200)).await;
+            }
+
+            let detach = Detach {
+                handle: 0,
+                closed: true,
+                error: None,
+            };
+            framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+
+    link.wait_for_credit(1).await.unwrap();
+    assert!(link.credit() >= 1);
+    assert!(link.wait_for_credit(0).now_or_never().is_some());
+
+    // peer grants more credit later
+    let res = ntex::rt::time::timeout(Duration::from_millis(50), link.wait_for_credit(3)).await;
+    assert!(res.is_err());
+    link.wait_for_credit(3).await.unwrap();
+    assert_eq!(link.credit(), 5);
+    assert!(matches!(
+        link.wait_for_credit(5).now_or_never(),
+        Some(Ok(()))
+    ));
+
+    // waiters fail once link is detached
+    let res = link.wait_for_credit(10).await;
+    assert!(matches!(res, Err(AmqpTransportError::LinkDetached(_))));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_settle_many() -> std::io::Result<()> {
+    let dispositions = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let dispositions2 = dispositions.clone();
+
+    // raw amqp peer, never settles deliveries
+    let srv = test_server(move || {
+        let dispositions = dispositions2.clone();
+        fn_service(move |io: TcpStream| {
+            let dispositions = dispositions.clone();
+            async move {
+                let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Receiver,
+                        initial_delivery_count: None,
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id),
+                    incoming_window: std::u32::MAX,
+                    next_outgoing_id: 1,
+                    outgoing_window: std::u32::MAX,
+                    handle: Some(0),
+                    delivery_count: Some(0),
+                    link_credit: Some(5),
+                    available: None,
+                    drain: false,
+                    echo: false,
+                    properties: None,
+                };
+                framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    if let Frame::Disposition(disp) = frame.performative() {
+                        assert_eq!(disp.role, Role::Sender);
+                        assert!(disp.settled);
+                        assert!(disp.is_released());
+                        dispositions.lock().unwrap().push((
+                            disp.first - next_incoming_id,
+                            disp.last.map(|l| l - next_incoming_id),
+                        ));
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(100)).await;
+
+    let deliveries: Vec<_> = (0..5)
+        .map(|_| link.send(Bytes::from_static(b"test")))
+        .collect();
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(session.stats().unsettled_deliveries, 5);
+
+    // contiguous deliveries are settled with single disposition
+    let settled = link.settle_many(
+        &[&deliveries[3], &deliveries[0], &deliveries[1]],
+        Outcome::Released(Released {}),
+    );
+    assert_eq!(settled, 3);
+    assert_eq!(session.stats().unsettled_deliveries, 2);
+    assert_eq!(
+        link.settle_many(&[&deliveries[0]], Outcome::Released(Released {})),
+        0
+    );
+
+    assert_eq!(link.settle_unsettled(Outcome::Released(Released {})), 2);
+    assert_eq!(session.stats().unsettled_deliveries, 0);
+
+    for (idx, res) in futures::future::join_all(deliveries)
+        .await
+        .into_iter()
+        .enumerate()
+    {
+        let disp = res.unwrap();
+        assert!(disp.is_released());
+        if idx < 2 {
+            assert_eq!(disp.last, Some(disp.first + 1));
+        } else {
+            assert!(disp.last.is_none());
+        }
+    }
+
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(
+        *dispositions.lock().unwrap(),
+        vec![(0, Some(1)), (3, None), (2, None), (4, None)]
+    );
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_flow_drains_pending() -> std::io::Result<()> {
+    // raw amqp peer, grants credit after transfers are queued
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            // let sender queue transfers
+            delay_for(Duration::from_millis(100)).await;
+
+            let flow = |credit, echo| Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: std::u32::MAX,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(credit),
+                available: None,
+                drain: false,
+                echo,
+                properties: None,
+            };
+            framed
+                .send(AmqpFrame::new(0, flow(2, true).into()))
+                .await
+                .unwrap();
+
+            let mut ids = Vec::new();
+            let mut echoed = false;
+            while ids.len() < 2 || !echoed {
+                let frame = framed.next().await.unwrap().unwrap();
+                match frame.performative() {
+                    Frame::Transfer(transfer) => ids.push(transfer.delivery_id().unwrap()),
+                    Frame::Flow(flow) => {
+                        // echoed flow, queued transfers used up credit
+                        assert_eq!(flow.delivery_count(), Some(2));
+                        assert_eq!(flow.link_credit(), Some(0));
+                        echoed = true;
+                    }
+                    _ => panic!("Transfer is expected: {:?}", frame),
+                }
+            }
+            framed
+                .send(AmqpFrame::new(0, flow(3, false).into()))
+                .await
+                .unwrap();
+            while let Some(Ok(frame)) = framed.next().await {
+                if let Frame::Transfer(transfer) = frame.performative() {
+                    ids.push(transfer.delivery_id().unwrap());
+                }
+                if ids.len() == 3 {
+                    break;
+                }
+            }
+
+            let disp = Disposition {
+                role: Role::Receiver,
+                first: ids[0],
+                last: Some(ids[2]),
+                settled: true,
+                state: Some(DeliveryState::Accepted(Accepted {})),
+                batchable: false,
+            };
+            framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+
+    let deliveries: Vec<_> = (0..3)
+        .map(|_| link.send(Bytes::from_static(b"test message")))
+        .collect();
+    for res in futures::future::join_all(deliveries).await {
+        assert!(res.unwrap().is_accepted());
+    }
+    assert_eq!(session.stats().unsettled_deliveries, 0);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_disposition_range() -> std::io::Result<()> {
+    // raw amqp peer, settles deliveries with invalid and wrapped ranges
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: std::u32::MAX,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(3),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            let mut ids = Vec::new();
+            while ids.len() < 3 {
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Transfer(transfer) = frame.performative() {
+                    ids.push(transfer.delivery_id().unwrap());
+                }
+            }
+            assert_eq!(ids, vec![0, 1, 2]);
+
+            let disp = |first, last| Disposition {
+                role: Role::Receiver,
+                first,
+                last: Some(last),
+                settled: true,
+                state: Some(DeliveryState::Accepted(Accepted {})),
+                batchable: false,
+            };
+            // last precedes first, ignored
+            framed
+                .send(AmqpFrame::new(0, disp(1, 0).into()))
+                .await
+                .unwrap();
+            // range wraps around max delivery id
+            framed
+                .send(AmqpFrame::new(0, disp(std::u32::MAX - 1, 0).into()))
+                .await
+                .unwrap();
+            delay_for(Duration::from_millis(100)).await;
+            // range is wider than outstanding deliveries
+            framed
+                .send(AmqpFrame::new(0, disp(1, std::u32::MAX / 2).into()))
+                .await
+                .unwrap();
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+
+    let mut deliveries: Vec<_> = (0..3)
+        .map(|_| link.send(Bytes::from_static(b"test message")))
+        .collect();
+    assert!((&mut deliveries[0]).await.unwrap().is_accepted());
+    assert_eq!(session.stats().unsettled_deliveries, 2);
+
+    for res in futures::future::join_all(deliveries.drain(1..)).await {
+        assert!(res.unwrap().is_accepted());
+    }
+    assert_eq!(session.stats().unsettled_deliveries, 0);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_delivery_id_wraparound() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut config = Configuration::default();
+    config.initial_outgoing_id(std::u32::MAX - 1);
+    let mut session = open_session_with_config(&srv, config).await;
+    assert_eq!(session.stats().next_outgoing_id, std::u32::MAX - 1);
+
+    let link = session
+        .open_sender_link("test-sender", "accept")
+        .await
+        .unwrap();
+
+    // delivery ids cross max delivery id
+    let deliveries: Vec<_> = (0..4)
+        .map(|_| link.send(Bytes::from_static(b"test message")))
+        .collect();
+    for res in futures::future::join_all(deliveries).await {
+        assert!(res.unwrap().is_accepted());
+    }
+    let stats = session.stats();
+    assert_eq!(stats.next_outgoing_id, 2);
+    assert_eq!(stats.unsettled_deliveries, 0);
+
+    let res = link.send(Bytes::from_static(b"test message")).await;
+    assert!(res.unwrap().is_accepted());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_initial_delivery_count() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .open_sender_link("test-sender", "accept")
+        .await
+        .unwrap();
+    assert_eq!(link.delivery_count(), 0);
+
+    // delivery count wraps around, remote peer keeps granting credit
+    let link = session
+        .build_sender_link("test-sender2", "accept")
+        .initial_delivery_count(std::u32::MAX - 1)
+        .open()
+        .await
+        .unwrap();
+    assert_eq!(link.delivery_count(), std::u32::MAX - 1);
+
+    for _ in 0..4 {
+        let res = link.send(Bytes::from_static(b"test message")).await;
+        assert!(res.unwrap().is_accepted());
+    }
+    assert_eq!(link.delivery_count(), 2);
+    assert!(link.credit() > 0);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_deliveries() -> std::io::Result<()> {
+    let replenished = Arc::new(AtomicBool::new(false));
+    let replenished2 = replenished.clone();
+
+    // raw amqp peer, sends two unsettled transfers and detaches link
+    let srv = test_server(move || {
+        let replenished = replenished2.clone();
+        fn_service(move |io: TcpStream| {
+            let replenished = replenished.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Sender,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                for id in 0..2 {
+                    let body = ntex_amqp::codec::OutMessage::with_value(Variant::String(
+                        format!("message-{}", id).into(),
+                    ));
+                    let transfer = Transfer {
+                        handle: 0,
+                        delivery_id: Some(id),
+                        delivery_tag: Some(Bytes::from(format!("tag-{}", id))),
+                        message_format: None,
+                        settled: Some(false),
+                        more: false,
+                        rcv_settle_mode: None,
+                        state: None,
+                        resume: false,
+                        aborted: false,
+                        batchable: false,
+                        body: Some(TransferBody::MessageOut(body)),
+                    };
+                    framed
+                        .send(AmqpFrame::new(0, transfer.into()))
+                        .await
+                        .unwrap();
+                }
+
+                let mut settled = Vec::new();
+                while settled.len() < 2 || !replenished.load(Ordering::Relaxed) {
+                    let frame = framed.next().await.unwrap().unwrap();
+                    match frame.performative() {
+                        Frame::Disposition(disp) if disp.settled && disp.is_accepted() => {
+                            settled.push(disp.first)
+                        }
+                        // credit is replenished once stream is polled
+                        Frame::Flow(flow)
+                            if flow.delivery_count() == Some(2)
+                                && flow.link_credit() == Some(2) =>
+                        {
+                            replenished.store(true, Ordering::Relaxed);
+                        }
+                        _ => (),
+                    }
+                }
+                assert_eq!(settled, vec![0, 1]);
+
+                let detach = Detach {
+                    handle: 0,
+                    closed: true,
+                    error: None,
+                };
+                framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+
+                while let Some(Ok(_)) = framed.next().await {}
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_auto_credit(2);
+
+    let mut messages = Vec::new();
+    let mut deliveries = link.deliveries();
+    while let Some(delivery) = deliveries.next().await {
+        let delivery = delivery.unwrap();
+        assert!(!delivery.is_settled());
+        messages.push(delivery.message().value().cloned().unwrap());
+        delivery.accept();
+    }
+    assert_eq!(
+        messages,
+        vec![
+            Variant::String("message-0".into()),
+            Variant::String("message-1".into())
+        ]
+    );
+    assert!(replenished.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_deliveries_malformed() -> std::io::Result<()> {
+    let rejected = Arc::new(AtomicBool::new(false));
+    let rejected2 = rejected.clone();
+
+    // raw amqp peer, sends unsettled transfer with malformed message
+    let srv = test_server(move || {
+        let rejected = rejected2.clone();
+        fn_service(move |io: TcpStream| {
+            let rejected = rejected.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Sender,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let transfer = Transfer {
+                    handle: 0,
+                    delivery_id: Some(0),
+                    delivery_tag: Some(Bytes::from_static(b"tag-0")),
+                    message_format: None,
+                    settled: Some(false),
+                    more: false,
+                    rcv_settle_mode: None,
+                    state: None,
+                    resume: false,
+                    aborted: false,
+                    batchable: false,
+                    body: Some(TransferBody::Data(Bytes::from_static(&[0x00, 0x53, 0xFF]))),
+                };
+                framed
+                    .send(AmqpFrame::new(0, transfer.into()))
+                    .await
+                    .unwrap();
+
+                loop {
+                    let frame = framed.next().await.unwrap().unwrap();
+                    if let Frame::Disposition(disp) = frame.performative() {
+                        assert_eq!(disp.first, 0);
+                        assert!(disp.settled);
+                        if let Some(DeliveryState::Rejected(ref rej)) = disp.state {
+                            let condition = rej.error.as_ref().map(|e| e.condition.clone());
+                            if condition
+                                == Some(ntex_amqp_codec::protocol::AmqpError::DecodeError.into())
+                            {
+                                rejected.store(true, Ordering::Relaxed);
+                            }
+                        }
+                        break;
+                    }
+                }
+
+                let detach = Detach {
+                    handle: 0,
+                    closed: true,
+                    error: None,
+                };
+                framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+
+                while let Some(Ok(_)) = framed.next().await {}
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(1);
+
+    let mut deliveries = link.deliveries();
+    let res = deliveries.next().await.unwrap();
+    assert!(matches!(res, Err(AmqpTransportError::DecodeError(_))));
+    assert!(deliveries.next().await.is_none());
+    assert!(rejected.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_message_format() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+
+    let link = session
+        .build_sender_link("test-sender", "accept")
+        .message_format(0x0114_0001)
+        .open()
+        .await
+        .unwrap();
+    assert_eq!(link.message_format(), Some(0x0114_0001));
+
+    // standard message format is rejected locally
+    let res = link.send(ntex_amqp::codec::OutMessage::text("test")).await;
+    assert!(matches!(
+        res,
+        Err(AmqpTransportError::MessageFormatMismatch(0, 0x0114_0001))
+    ));
+
+    let mut msg = ntex_amqp::codec::OutMessage::text("test");
+    msg.message_format = Some(0x0114_0001);
+    assert!(link.send(msg).await.is_ok());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_delivery_message_format() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Sender,
+                    initial_delivery_count: Some(0),
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            for (id, format) in vec![None, Some(0x0114_0001)].into_iter().enumerate() {
+                let transfer = Transfer {
+                    handle: 0,
+                    delivery_id: Some(id as u32),
+                    delivery_tag: Some(Bytes::from(format!("tag-{}", id))),
+                    message_format: format,
+                    settled: Some(true),
+                    more: false,
+                    rcv_settle_mode: None,
+                    state: None,
+                    resume: false,
+                    aborted: false,
+                    batchable: false,
+                    body: Some(TransferBody::MessageOut(
+                        ntex_amqp::codec::OutMessage::text("test"),
+                    )),
+                };
+                framed
+                    .send(AmqpFrame::new(0, transfer.into()))
+                    .await
+                    .unwrap();
+            }
+
+            let detach = Detach {
+                handle: 0,
+                closed: true,
+                error: None,
+            };
+            framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(10);
+
+    let mut formats = Vec::new();
+    let mut deliveries = link.deliveries();
+    while let Some(delivery) = deliveries.next().await {
+        formats.push(delivery.unwrap().message_format());
+    }
+    assert_eq!(formats, vec![None, Some(0x0114_0001)]);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_dedup() -> std::io::Result<()> {
+    let released = Arc::new(AtomicBool::new(false));
+    let released2 = released.clone();
+
+    // raw amqp peer, redelivers first message with the same delivery tag
+    let srv = test_server(move || {
+        let released = released2.clone();
+        fn_service(move |io: TcpStream| {
+            let released = released.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Sender,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                for (id, tag) in vec!["tag-0", "tag-1", "tag-0"].into_iter().enumerate() {
+                    let transfer = Transfer {
+                        handle: 0,
+                        delivery_id: Some(id as u32),
+                        delivery_tag: Some(Bytes::from(tag)),
+                        message_format: None,
+                        settled: Some(false),
+                        more: false,
+                        rcv_settle_mode: None,
+                        state: None,
+                        resume: false,
+                        aborted: false,
+                        batchable: false,
+                        body: Some(TransferBody::Data(Bytes::from(id.to_string()))),
+                    };
+                    framed
+                        .send(AmqpFrame::new(0, transfer.into()))
+                        .await
+                        .unwrap();
+                }
+
+                // duplicate is released by receiver
+                loop {
+                    let frame = framed.next().await.unwrap().unwrap();
+                    if let Frame::Disposition(disp) = frame.performative() {
+                        if disp.first == 2 {
+                            assert_eq!(disp.state, Some(DeliveryState::Released(Released {})));
+                            assert!(disp.settled);
+                            released.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                    }
+                }
+
+                let detach = Detach {
+                    handle: 0,
+                    closed: true,
+                    error: None,
+                };
+                framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+
+                while let Some(Ok(_)) = framed.next().await {}
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_dedup_window(16);
+    link.set_link_credit(10);
+
+    let mut ids = Vec::new();
+    while let Some(transfer) = link.next().await {
+        let transfer = transfer.unwrap();
+        ids.push(transfer.delivery_id.unwrap());
+        link.accept(transfer.delivery_id.unwrap());
+    }
+    assert_eq!(ids, vec![0, 1]);
+    assert!(released.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_expire_messages() -> std::io::Result<()> {
+    let expired = Arc::new(AtomicUsize::new(0));
+    let expired2 = expired.clone();
+
+    // raw amqp peer, sends two expired messages and one live message
+    let srv = test_server(move || {
+        let expired = expired2.clone();
+        fn_service(move |io: TcpStream| {
+            let expired = expired.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Sender,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let now = chrono::Utc::now();
+                let mut messages = Vec::new();
+                // absolute expiry time has passed
+                let mut msg = ntex_amqp::codec::OutMessage::with_value("expired-0");
+                msg.set_properties(|props| {
+                    props.absolute_expiry_time = Some(now - chrono::Duration::seconds(10))
+                });
+                messages.push(msg);
+                // creation time plus ttl has passed
+                let mut msg = ntex_amqp::codec::OutMessage::with_value("expired-1");
+                msg.set_ttl(1000).set_properties(|props| {
+                    props.creation_time = Some(now - chrono::Duration::seconds(10))
+                });
+                messages.push(msg);
+                let mut msg = ntex_amqp::codec::OutMessage::with_value("live");
+                msg.set_ttl(60_000).set_properties(|props| {
+                    props.creation_time = Some(now);
+                    props.absolute_expiry_time = Some(now + chrono::Duration::seconds(60))
+                });
+                messages.push(msg);
+
+                for (id, msg) in messages.into_iter().enumerate() {
+                    let transfer = Transfer {
+                        handle: 0,
+                        delivery_id: Some(id as u32),
+                        delivery_tag: Some(Bytes::from(format!("tag-{}", id))),
+                        message_format: None,
+                        settled: Some(false),
+                        more: false,
+                        rcv_settle_mode: None,
+                        state: None,
+                        resume: false,
+                        aborted: false,
+                        batchable: false,
+                        body: Some(TransferBody::MessageOut(msg)),
+                    };
+                    framed
+                        .send(AmqpFrame::new(0, transfer.into()))
+                        .await
+                        .unwrap();
+                }
+
+                // expired deliveries are settled by receiver
+                loop {
+                    let frame = framed.next().await.unwrap().unwrap();
+                    if let Frame::Disposition(disp) = frame.performative() {
+                        if disp.first == 2 {
+                            assert!(disp.is_accepted());
+                            break;
+                        }
+                        assert!(disp.settled);
+                        assert_eq!(
+                            disp.state,
+                            Some(DeliveryState::Modified(Modified {
+                                delivery_failed: Some(false),
+                                undeliverable_here: Some(true),
+                                message_annotations: None,
+                            }))
+                        );
+                        expired.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+
+                let detach = Detach {
+                    handle: 0,
+                    closed: true,
+                    error: None,
+                };
+                framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+
+                while let Some(Ok(_)) = framed.next().await {}
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_expire_messages(true);
+    link.set_link_credit(10);
+
+    let mut values = Vec::new();
+    let mut deliveries = link.deliveries();
+    while let Some(delivery) = deliveries.next().await {
+        let delivery = delivery.unwrap();
+        values.push(delivery.message().value().cloned().unwrap());
+        delivery.accept();
+    }
+    assert_eq!(values, vec![Variant::from("live")]);
+    assert_eq!(expired.load(Ordering::Relaxed), 2);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_release() -> std::io::Result<()> {
+    let dispositions = Arc::new(AtomicUsize::new(0));
+    let dispositions2 = dispositions.clone();
+
+    // raw amqp peer, redelivers released message
+    let srv = test_server(move || {
+        let dispositions = dispositions2.clone();
+        fn_service(move |io: TcpStream| {
+            let dispositions = dispositions.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Sender,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let transfer = |id| Transfer {
+                    handle: 0,
+                    delivery_id: Some(id),
+                    delivery_tag: Some(Bytes::from(format!("tag-{}", id))),
+                    message_format: None,
+                    settled: Some(false),
+                    more: false,
+                    rcv_settle_mode: None,
+                    state: None,
+                    resume: false,
+                    aborted: false,
+                    batchable: false,
+                    body: Some(TransferBody::Data(Bytes::from_static(b"data"))),
+                };
+                framed
+                    .send(AmqpFrame::new(0, transfer(1).into()))
+                    .await
+                    .unwrap();
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    if let Frame::Disposition(disp) = frame.performative() {
+                        dispositions.fetch_add(1, Ordering::Relaxed);
+                        assert!(disp.settled);
+                        if disp.first == 1 {
+                            // released delivery is redelivered
+                            assert!(disp.is_released());
+                            framed
+                                .send(AmqpFrame::new(0, transfer(2).into()))
+                                .await
+                                .unwrap();
+                        } else {
+                            assert_eq!(disp.first, 2);
+                            assert!(disp.is_accepted());
+                        }
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(2);
+
+    let transfer = link.next().await.unwrap().unwrap();
+    link.release(transfer.delivery_id.unwrap());
+
+    // released delivery is not tracked anymore
+    link.release(transfer.delivery_id.unwrap());
+    link.accept(transfer.delivery_id.unwrap());
+
+    let transfer = link.next().await.unwrap().unwrap();
+    assert_eq!(transfer.delivery_id, Some(2));
+    assert_eq!(
+        transfer.body,
+        Some(TransferBody::Data(Bytes::from_static(b"data")))
+    );
+    link.accept(2);
+
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(dispositions.load(Ordering::Relaxed), 2);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_modify() -> std::io::Result<()> {
+    let modified = Arc::new(AtomicBool::new(false));
+    let modified2 = modified.clone();
+
+    // raw amqp peer, checks modified outcome
+    let srv = test_server(move || {
+        let modified = modified2.clone();
+        fn_service(move |io: TcpStream| {
+            let modified = modified.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Sender,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let transfer = Transfer {
+                    handle: 0,
+                    delivery_id: Some(1),
+                    delivery_tag: Some(Bytes::from_static(b"tag")),
+                    message_format: None,
+                    settled: Some(false),
+                    more: false,
+                    rcv_settle_mode: None,
+                    state: None,
+                    resume: false,
+                    aborted: false,
+                    batchable: false,
+                    body: Some(TransferBody::Data(Bytes::from_static(b"data"))),
+                };
+                framed
+                    .send(AmqpFrame::new(0, transfer.into()))
+                    .await
+                    .unwrap();
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    if let Frame::Disposition(disp) = frame.performative() {
+                        assert!(disp.settled);
+                        let state = disp.modified().unwrap();
+                        assert_eq!(state.delivery_failed, Some(true));
+                        assert_eq!(state.undeliverable_here, Some(false));
+                        let reason = state
+                            .message_annotations
+                            .as_ref()
+                            .and_then(|a| a.get(&Symbol::from("x-opt-reason")).cloned());
+                        assert_eq!(reason, Some(Variant::String("invalid".into())));
+                        modified.store(true, Ordering::Relaxed);
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(1);
+
+    let transfer = link.next().await.unwrap().unwrap();
+    let mut annotations = Fields::default();
+    annotations.insert(
+        Symbol::from("x-opt-reason"),
+        Variant::String("invalid".into()),
+    );
+    link.modify_with(
+        transfer.delivery_id.unwrap(),
+        true,
+        false,
+        Some(annotations),
+    );
+
+    delay_for(Duration::from_millis(100)).await;
+    assert!(modified.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_flow_state() -> std::io::Result<()> {
+    // raw amqp peer, reports available messages and advances delivery count
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Sender,
+                    initial_delivery_count: Some(0),
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            // wait for link credit
+            loop {
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Flow(_) = frame.performative() {
+                    break;
+                }
+            }
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: std::u32::MAX,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(3),
+                link_credit: Some(7),
+                available: Some(25),
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    assert_eq!(link.delivery_count(), 0);
+    link.set_link_credit(10);
+
+    delay_for(Duration::from_millis(100)).await;
+    assert_eq!(link.delivery_count(), 3);
+    assert_eq!(link.credit(), 7);
+    assert_eq!(link.available(), 25);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_connection_remote_close() -> std::io::Result<()> {
+    let replied = Arc::new(AtomicBool::new(false));
+    let replied2 = replied.clone();
+
+    // raw amqp peer, closes connection after session is opened
+    let srv = test_server(move || {
+        let replied = replied2.clone();
+        fn_service(move |io: TcpStream| {
+            let replied = replied.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let close = Close { error: None };
+                framed.send(AmqpFrame::new(0, close.into())).await.unwrap();
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    if let Frame::Close(close) = frame.performative() {
+                        assert!(close.error.is_none());
+                        replied.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut conn = connect_raw(&srv).await;
+    let session = conn.open_session();
+    let (tx, rx) = ntex::channel::oneshot::channel();
+    ntex::rt::spawn(conn.map(move |res| {
+        let _ = tx.send(res.is_ok());
+    }));
+    let _session = session.await.unwrap();
+
+    let res = ntex::rt::time::timeout(Duration::from_secs(1), rx).await;
+    assert_eq!(res.ok().and_then(|res| res.ok()), Some(true));
+    delay_for(Duration::from_millis(100)).await;
+    assert!(replied.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_connection_close() -> std::io::Result<()> {
+    use ntex_amqp::codec::protocol::{AmqpError as ErrorCode, Error};
+
+    let frames = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let frames2 = frames.clone();
+
+    // raw amqp peer, confirms session end and connection close
+    let srv = test_server(move || {
+        let frames = frames2.clone();
+        fn_service(move |io: TcpStream| {
+            let frames = frames.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    match frame.performative() {
+                        Frame::End(_) => {
+                            frames.lock().unwrap().push("end");
+                            let end = End { error: None };
+                            framed.send(AmqpFrame::new(0, end.into())).await.unwrap();
+                        }
+                        Frame::Close(close) => {
+                            assert!(close.error.is_some());
+                            frames.lock().unwrap().push("close");
+                            let close = Close { error: None };
+                            framed.send(AmqpFrame::new(0, close.into())).await.unwrap();
+                        }
+                        _ => (),
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut conn = connect_raw(&srv).await;
+    let controller = conn.controller();
+    let session = conn.open_session();
+    ntex::rt::spawn(conn.map(|_| ()));
+    let mut session = session.await.unwrap();
+
+    let err = Error {
+        condition: ErrorCode::NotAllowed.into(),
+        description: Some(ByteString::from_static("shutdown")),
+        info: None,
+    };
+    controller
+        .close(Some(err), Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(*frames.lock().unwrap(), vec!["end", "close"]);
+    assert!(session.open_sender_link("test", "test").await.is_err());
+
+    // close with `Connection` api
+    frames.lock().unwrap().clear();
+    let mut conn = connect_raw(&srv).await;
+    let session = conn.open_session();
+    let _session = match futures::future::select(&mut conn, Box::pin(session)).await {
+        futures::future::Either::Right((session, _)) => session.unwrap(),
+        _ => panic!("Session is expected"),
+    };
+    let close = conn.close_with_error(Error {
+        condition: ErrorCode::NotAllowed.into(),
+        description: None,
+        info: None,
+    });
+    let res =
+        ntex::rt::time::timeout(Duration::from_secs(1), futures::future::join(conn, close)).await;
+    assert!(matches!(res, Ok((_, Ok(())))));
+    assert_eq!(*frames.lock().unwrap(), vec!["end", "close"]);
+
+    // remote peer does not respond with close
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+    let mut conn = connect_raw(&srv).await;
+    let controller = conn.controller();
+    let session = conn.open_session();
+    ntex::rt::spawn(conn.map(|_| ()));
+    let _session = session.await.unwrap();
+
+    let res = controller.close(None, Duration::from_millis(100)).await;
+    assert!(matches!(res, Err(AmqpTransportError::Timeout)));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_disposition_role() -> std::io::Result<()> {
+    let dispositions = Arc::new(AtomicUsize::new(0));
+    let dispositions2 = dispositions.clone();
+
+    // raw amqp peer, receiver for first link and sender for second link
+    let srv = test_server(move || {
+        let dispositions = dispositions2.clone();
+        fn_service(move |io: TcpStream| {
+            let dispositions = dispositions.clone();
+            async move {
+                let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Receiver,
+                        initial_delivery_count: None,
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id),
+                    incoming_window: std::u32::MAX,
+                    next_outgoing_id: 0,
+                    outgoing_window: std::u32::MAX,
+                    handle: Some(0),
+                    delivery_count: Some(0),
+                    link_credit: Some(1),
+                    available: None,
+                    drain: false,
+                    echo: false,
+                    properties: None,
+                };
+                framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 1,
+                        role: Role::Sender,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let mut outgoing = None;
+                while let Some(Ok(frame)) = framed.next().await {
+                    match frame.performative() {
+                        Frame::Transfer(transfer) => {
+                            let id = transfer.delivery_id().unwrap();
+                            outgoing = Some(id);
+
+                            // incoming delivery with the same delivery id
+                            let transfer = Transfer {
+                                handle: 1,
+                                delivery_id: Some(id),
+                                delivery_tag: Some(Bytes::from_static(b"tag")),
+                                message_format: None,
+                                settled: Some(false),
+                                more: false,
+                                rcv_settle_mode: None,
+                                state: None,
+                                resume: false,
+                                aborted: false,
+                                batchable: false,
+                                body: Some(TransferBody::Data(Bytes::from_static(b"data"))),
+                            };
+                            framed
+                                .send(AmqpFrame::new(0, transfer.into()))
+                                .await
+                                .unwrap();
+
+                            // sender settles incoming delivery
+                            let disp = Disposition {
+                                role: Role::Sender,
+                                first: id,
+                                last: None,
+                                settled: true,
+                                state: Some(DeliveryState::Accepted(Accepted {})),
+                                batchable: false,
+                            };
+                            framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+                        }
+                        Frame::Disposition(disp) => {
+                            assert_eq!(disp.role, Role::Sender);
+                            dispositions.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Frame::Attach(_) => panic!("Unexpected attach"),
+                        _ => (),
+                    }
+                    if let Some(id) = outgoing.take() {
+                        // settle outgoing delivery later, after incoming delivery
+                        delay_for(Duration::from_millis(100)).await;
+                        let disp = Disposition {
+                            role: Role::Receiver,
+                            first: id,
+                            last: None,
+                            settled: true,
+                            state: Some(DeliveryState::Released(Released {})),
+                            batchable: false,
+                        };
+                        framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let sender = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    let mut receiver = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    receiver.set_link_credit(1);
+    delay_for(Duration::from_millis(100)).await;
+
+    let delivery = sender.send(Bytes::from_static(b"test"));
+    let transfer = receiver.next().await.unwrap().unwrap();
+
+    // sender role disposition does not settle outgoing delivery
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(session.stats().unsettled_deliveries, 1);
+
+    // incoming delivery is settled by remote sender
+    receiver.accept(transfer.delivery_id.unwrap());
+
+    let disp = delivery.await.unwrap();
+    assert!(disp.is_released());
+    assert_eq!(dispositions.load(Ordering::Relaxed), 0);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_disposition_wrapped_range() -> std::io::Result<()> {
+    let dispositions = Arc::new(AtomicUsize::new(0));
+    let dispositions2 = dispositions.clone();
+
+    // raw amqp peer, settles incoming delivery with range that wraps around
+    let srv = test_server(move || {
+        let dispositions = dispositions2.clone();
+        fn_service(move |io: TcpStream| {
+            let dispositions = dispositions.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Sender,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    match frame.performative() {
+                        Frame::Flow(_) => {
+                            let transfer = Transfer {
+                                handle: 0,
+                                delivery_id: Some(std::u32::MAX),
+                                delivery_tag: Some(Bytes::from_static(b"tag")),
+                                message_format: None,
+                                settled: Some(false),
+                                more: false,
+                                rcv_settle_mode: None,
+                                state: None,
+                                resume: false,
+                                aborted: false,
+                                batchable: false,
+                                body: Some(TransferBody::Data(Bytes::from_static(b"data"))),
+                            };
+                            framed
+                                .send(AmqpFrame::new(0, transfer.into()))
+                                .await
+                                .unwrap();
+
+                            let disp = Disposition {
+                                role: Role::Sender,
+                                first: std::u32::MAX - 1,
+                                last: Some(1),
+                                settled: true,
+                                state: Some(DeliveryState::Accepted(Accepted {})),
+                                batchable: false,
+                            };
+                            framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+                        }
+                        Frame::Disposition(_) => {
+                            dispositions.fetch_add(1, Ordering::Relaxed);
+                        }
+                        _ => (),
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut receiver = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    receiver.set_link_credit(1);
+
+    let transfer = receiver.next().await.unwrap().unwrap();
+    delay_for(Duration::from_millis(50)).await;
+
+    // delivery is settled by remote sender, disposition is not sent
+    receiver.accept(transfer.delivery_id.unwrap());
+    delay_for(Duration::from_millis(50)).await;
+    assert_eq!(dispositions.load(Ordering::Relaxed), 0);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_request_flow_echo() -> std::io::Result<()> {
+    // remote peer echoes session flow
+    let srv = start_server();
+    let session = open_session(&srv).await;
+    let flow = session
+        .request_flow_echo(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert!(flow.handle().is_none());
+    assert!(!flow.echo());
+
+    // raw amqp peer, ignores flow echo request
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+    let mut session = open_raw_session(&srv).await;
+    let res = session.request_flow_echo(Duration::from_millis(100)).await;
+    assert!(matches!(res, Err(AmqpTransportError::Timeout)));
+
+    // ended session fails right away
+    let _ = ntex::rt::time::timeout(Duration::from_millis(100), session.close()).await;
+    let res = session.request_flow_echo(Duration::from_secs(5)).await;
+    assert!(matches!(res, Err(AmqpTransportError::SessionEnded(_))));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_request_flow_echo_link_flow() -> std::io::Result<()> {
+    // raw amqp peer, sends link flow before echoing session flow
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let frame = framed.next().await.unwrap().unwrap();
+            if let Frame::Flow(flow) = frame.performative() {
+                assert!(flow.handle().is_none());
+                assert!(flow.echo());
+            } else {
+                panic!("Flow is expected: {:?}", frame)
+            }
+
+            for handle in &[Some(0), None] {
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id),
+                    incoming_window: if handle.is_some() { 5 } else { 7 },
+                    next_outgoing_id: 1,
+                    outgoing_window: std::u32::MAX,
+                    handle: *handle,
+                    delivery_count: handle.map(|_| 0),
+                    link_credit: handle.map(|_| 5),
+                    available: None,
+                    drain: false,
+                    echo: false,
+                    properties: None,
+                };
+                framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+            }
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+
+    let flow = session
+        .request_flow_echo(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert!(flow.handle().is_none());
+    assert_eq!(flow.incoming_window(), 7);
+    assert_eq!(link.credit(), 5);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_flow_echo() -> std::io::Result<()> {
+    // raw amqp peer, requests flow state of session and link
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Sender,
+                    initial_delivery_count: Some(0),
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            // link credit
+            let frame = framed.next().await.unwrap().unwrap();
+            assert!(matches!(frame.performative(), Frame::Flow(_)));
+
+            // transfer-ids 1, 2 and 3
+            for id in 0..3 {
+                let transfer = Transfer {
+                    handle: 0,
+                    delivery_id: Some(id),
+                    delivery_tag: Some(Bytes::from(format!("tag-{}", id))),
+                    message_format: None,
+                    settled: Some(true),
+                    more: false,
+                    rcv_settle_mode: None,
+                    state: None,
+                    resume: false,
+                    aborted: false,
+                    batchable: false,
+                    body: Some(TransferBody::Data(Bytes::from_static(b"data"))),
+                };
+                framed
+                    .send(AmqpFrame::new(0, transfer.into()))
+                    .await
+                    .unwrap();
+            }
+
+            let flow = |handle, delivery_count| Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: std::u32::MAX,
+                next_outgoing_id: 4,
+                outgoing_window: std::u32::MAX,
+                handle,
+                delivery_count,
+                link_credit: delivery_count.map(|_| 0),
+                available: None,
+                drain: false,
+                echo: true,
+                properties: None,
+            };
+
+            // #2.5.6 session state after three incoming transfers
+            framed
+                .send(AmqpFrame::new(0, flow(None, None).into()))
+                .await
+                .unwrap();
+            let frame = framed.next().await.unwrap().unwrap();
+            if let Frame::Flow(flow) = frame.performative() {
+                assert_eq!(flow.handle(), None);
+                assert_eq!(flow.next_incoming_id(), Some(4));
+                assert_eq!(flow.incoming_window(), 7);
+                assert_eq!(flow.next_outgoing_id(), next_incoming_id);
+            } else {
+                panic!("Flow is expected: {:?}", frame)
+            }
+
+            // link flow is echoed once, with session state
+            framed
+                .send(AmqpFrame::new(0, flow(Some(0), Some(3)).into()))
+                .await
+                .unwrap();
+            let detach = Detach {
+                handle: 0,
+                closed: true,
+                error: None,
+            };
+            framed.send(AmqpFrame::new(0, detach.into())).await.unwrap();
+
+            let frame = framed.next().await.unwrap().unwrap();
+            if let Frame::Flow(flow) = frame.performative() {
+                assert_eq!(flow.handle(), Some(0));
+                assert_eq!(flow.next_incoming_id(), Some(4));
+                assert_eq!(flow.delivery_count(), Some(3));
+                assert_eq!(flow.link_credit(), Some(0));
+            } else {
+                panic!("Flow is expected: {:?}", frame)
+            }
+            let frame = framed.next().await.unwrap().unwrap();
+            assert!(matches!(frame.performative(), Frame::Detach(_)));
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut config = Configuration::default();
+    config.session_window(10, 2);
+    let mut conn = connect_raw_with_config(&srv, config).await;
+    let session = conn.open_session();
+    ntex::rt::spawn(conn.map(|_| ()));
+    let mut session = session.await.unwrap();
+
+    let mut link = session
+        .open_receiver_link("test-receiver", "test")
+        .await
+        .unwrap();
+    link.set_link_credit(3);
+
+    let mut received = 0;
+    while let Some(Ok(_)) = link.next().await {
+        received += 1;
+    }
+    assert_eq!(received, 3);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_delivery_state() -> std::io::Result<()> {
+    // raw amqp peer, reports received state before settling delivery
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: std::u32::MAX,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(1),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let id = if let Frame::Transfer(transfer) = frame.performative() {
+                transfer.delivery_id().unwrap()
+            } else {
+                panic!("Transfer is expected: {:?}", frame)
+            };
+
+            let mut disp = Disposition {
+                role: Role::Receiver,
+                first: id,
+                last: None,
+                settled: false,
+                state: Some(DeliveryState::Received(Received {
+                    section_number: 0,
+                    section_offset: 2,
+                })),
+                batchable: false,
+            };
+            framed
+                .send(AmqpFrame::new(0, disp.clone().into()))
+                .await
+                .unwrap();
+
+            delay_for(Duration::from_millis(100)).await;
+            disp.settled = true;
+            disp.state = Some(DeliveryState::Accepted(Accepted {}));
+            framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(100)).await;
+
+    let id = session.stats().next_outgoing_id;
+    let delivery = link.send_with_tag(Bytes::from_static(b"test"), Bytes::from_static(b"tag"));
+    delay_for(Duration::from_millis(50)).await;
+
+    let view = session.delivery_state(id).unwrap();
+    assert!(!view.settled);
+    assert_eq!(view.handle, link.id());
+    assert_eq!(view.tag, Bytes::from_static(b"tag"));
+    assert!(matches!(view.state, Some(DeliveryState::Received(_))));
+
+    assert!(delivery.await.unwrap().is_accepted());
+    let view = session.delivery_state(id).unwrap();
+    assert!(view.settled);
+    assert!(matches!(view.state, Some(DeliveryState::Accepted(_))));
+    assert!(session.delivery_state(id.wrapping_add(1)).is_none());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_resume() -> std::io::Result<()> {
+    // raw amqp peer, reports unsettled delivery on attach
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                let mut remote = ntex_amqp::codec::protocol::Map::default();
+                remote.insert(Variant::Binary(Bytes::from_static(b"t1")), Variant::Null);
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    unsettled: Some(remote),
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: std::u32::MAX,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(10),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            for (tag, resume) in &[(&b"t1"[..], true), (&b"t2"[..], false)] {
+                let frame = framed.next().await.unwrap().unwrap();
+                let id = if let Frame::Transfer(transfer) = frame.performative() {
+                    assert_eq!(transfer.delivery_tag.as_ref().unwrap().as_ref(), *tag);
+                    assert_eq!(transfer.resume, *resume);
+                    transfer.delivery_id().unwrap()
+                } else {
+                    panic!("Transfer is expected: {:?}", frame)
+                };
+                let disp = Disposition {
+                    role: Role::Receiver,
+                    first: id,
+                    last: None,
+                    settled: true,
+                    state: Some(DeliveryState::Accepted(Accepted {})),
+                    batchable: false,
+                };
+                framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+            }
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .build_sender_link("test-sender", "test")
+        .unsettled(vec![
+            (Bytes::from_static(b"t1"), None),
+            (Bytes::from_static(b"t2"), None),
+        ])
+        .open()
+        .await
+        .unwrap();
+    assert_eq!(link.remote_unsettled().len(), 1);
+    delay_for(Duration::from_millis(100)).await;
+
+    // t1 is known to remote peer, t2 is sent as new delivery
+    let d1 = link.resume(Bytes::from_static(b"1"), Bytes::from_static(b"t1"));
+    let d2 = link.resume(Bytes::from_static(b"2"), Bytes::from_static(b"t2"));
+    assert!(d1.await.unwrap().is_accepted());
+    assert!(d2.await.unwrap().is_accepted());
+    assert!(link.remote_unsettled().is_empty());
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_connection_frame_logger() -> std::io::Result<()> {
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let frames = Rc::new(RefCell::new(Vec::new()));
+    let frames2 = frames.clone();
+
+    let mut conn = connect_raw(&srv).await;
+    conn.controller()
+        .set_frame_logger(move |direction, frame: &AmqpFrame| {
+            frames2
+                .borrow_mut()
+                .push((direction, frame.performative().clone()))
+        });
+    let session = conn.open_session();
+    ntex::rt::spawn(conn.map(|_| ()));
+    let _session = session.await.unwrap();
+
+    {
+        let frames = frames.borrow();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].0, Direction::Outgoing);
+        assert!(matches!(frames[0].1, Frame::Begin(_)));
+        assert_eq!(frames[1].0, Direction::Incoming);
+        assert!(
+            matches!(frames[1].1, Frame::Begin(ref begin) if begin.remote_channel() == Some(0))
+        );
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_stats() -> std::io::Result<()> {
+    // raw amqp peer, accepts first delivery, rejects second, keeps third unsettled
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let attach = if let Frame::Attach(attach) = frame.performative() {
+                Attach {
+                    handle: 0,
+                    role: Role::Receiver,
+                    initial_delivery_count: None,
+                    ..attach.clone()
+                }
+            } else {
+                panic!("Attach is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+            let flow = Flow {
+                next_incoming_id: Some(next_incoming_id),
+                incoming_window: std::u32::MAX,
+                next_outgoing_id: 1,
+                outgoing_window: std::u32::MAX,
+                handle: Some(0),
+                delivery_count: Some(0),
+                link_credit: Some(10),
+                available: None,
+                drain: false,
+                echo: false,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            let states = vec![
+                Some(DeliveryState::Accepted(Accepted {})),
+                Some(DeliveryState::Rejected(Rejected { error: None })),
+                None,
+            ];
+            for state in states {
+                let frame = framed.next().await.unwrap().unwrap();
+                let id = if let Frame::Transfer(transfer) = frame.performative() {
+                    transfer.delivery_id().unwrap()
+                } else {
+                    panic!("Transfer is expected: {:?}", frame)
+                };
+                if state.is_some() {
+                    let disp = Disposition {
+                        role: Role::Receiver,
+                        first: id,
+                        last: None,
+                        settled: true,
+                        state,
+                        batchable: false,
+                    };
+                    framed.send(AmqpFrame::new(0, disp.into())).await.unwrap();
+                }
+            }
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(100)).await;
+
+    let d1 = link.send(Bytes::from_static(b"1"));
+    let d2 = link.send(Bytes::from_static(b"2"));
+    let _d3 = link.send(Bytes::from_static(b"3"));
+    assert!(d1.await.unwrap().is_accepted());
+    assert!(d2.await.unwrap().is_rejected());
+
+    let stats = link.stats();
+    assert_eq!(stats.sent, 3);
+    assert_eq!(stats.accepted, 1);
+    assert_eq!(stats.rejected, 1);
+    assert_eq!(stats.released, 0);
+    assert_eq!(stats.modified, 0);
+    assert_eq!(stats.unsettled, 1);
+    assert_eq!(session.sender_link_stats(), stats);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_sender_link_flow_echo() -> std::io::Result<()> {
+    let echoed = Arc::new(AtomicBool::new(false));
+    let echoed2 = echoed.clone();
+
+    // raw amqp peer, grants link credit and requests echo in the same flow
+    let srv = test_server(move || {
+        let echoed = echoed2.clone();
+        fn_service(move |io: TcpStream| {
+            let echoed = echoed.clone();
+            async move {
+                let (mut framed, next_incoming_id) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle: 0,
+                        role: Role::Receiver,
+                        initial_delivery_count: None,
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let flow = Flow {
+                    next_incoming_id: Some(next_incoming_id),
+                    incoming_window: std::u32::MAX,
+                    next_outgoing_id: 1,
+                    outgoing_window: std::u32::MAX,
+                    handle: Some(0),
+                    delivery_count: Some(0),
+                    link_credit: Some(5),
+                    available: None,
+                    drain: false,
+                    echo: true,
+                    properties: None,
+                };
+                framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Flow(flow) = frame.performative() {
+                    assert_eq!(flow.handle(), Some(0));
+                    assert_eq!(flow.delivery_count(), Some(0));
+                    assert_eq!(flow.link_credit(), Some(5));
+                    assert!(!flow.echo());
+                    echoed.store(true, Ordering::Relaxed);
+                } else {
+                    panic!("Flow is expected: {:?}", frame)
+                }
+
+                while let Some(Ok(_)) = framed.next().await {}
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let link = session
+        .open_sender_link("test-sender", "test")
+        .await
+        .unwrap();
+    delay_for(Duration::from_millis(100)).await;
+
+    assert_eq!(link.credit(), 5);
+    assert!(echoed.load(Ordering::Relaxed));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_receiver_link_shared_credit() -> std::io::Result<()> {
+    // raw amqp peer, sends transfers once first link gets credit
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            for handle in 0..2 {
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    Attach {
+                        handle,
+                        role: Role::Sender,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+            }
+
+            loop {
+                let frame = framed.next().await.unwrap().unwrap();
+                if let Frame::Flow(flow) = frame.performative() {
+                    if flow.handle() == Some(0) && flow.link_credit() == Some(4) {
+                        break;
+                    }
+                }
+            }
+            for id in 0..4 {
+                let transfer = Transfer {
+                    handle: 0,
+                    delivery_id: Some(id),
+                    delivery_tag: Some(Bytes::from(format!("tag-{}", id))),
+                    message_format: None,
+                    settled: Some(true),
+                    more: false,
+                    rcv_settle_mode: None,
+                    state: None,
+                    resume: false,
+                    aborted: false,
+                    batchable: false,
+                    body: Some(TransferBody::Data(Bytes::from_static(b"data"))),
+                };
+                framed
+                    .send(AmqpFrame::new(0, transfer.into()))
+                    .await
+                    .unwrap();
+            }
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut session = open_raw_session(&srv).await;
+    let mut link1 = session.open_receiver_link("test1", "test1").await.unwrap();
+    let mut link2 = session.open_receiver_link("test2", "test2").await.unwrap();
+
+    let shared = SharedCredit::new(4);
+    link1.set_shared_credit(&shared, 4);
+    assert_eq!(link1.credit(), 4);
+    assert_eq!(shared.available(), 0);
+
+    // budget is used up by first link
+    link2.set_shared_credit(&shared, 4);
+    assert_eq!(link2.credit(), 0);
+
+    // consumed transfers return credit, links share the budget
+    for _ in 0..3 {
+        link1.next().await.unwrap().unwrap();
+    }
+    assert_eq!(link1.credit(), 1);
+    assert_eq!(shared.available(), 2);
+
+    let res = ntex::rt::time::timeout(Duration::from_millis(50), link2.next()).await;
+    assert!(res.is_err());
+    assert_eq!(link2.credit(), 2);
+    assert_eq!(shared.available(), 0);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_transfer_before_link_opened() -> std::io::Result<()> {
+    // link service is created after transfer is received
+    let srv = test_server(|| {
+        server::Server::new(
+            server::Handshake::new(|conn: server::Connect<_>| async move {
+                let conn = conn.open().await.unwrap();
+                Ok::<_, server::Error>(conn.ack(()))
+            })
+            .sasl(pipeline_factory(sasl_auth).map_err(|e| e.into())),
+        )
+        .finish(
+            server::App::<()>::new()
+                .service(
+                    "accept",
+                    fn_factory_with_config(|_: server::Link<()>| async {
+                        delay_for(Duration::from_millis(100)).await;
+                        Ok::<_, LinkError>(fn_service(|_: server::Message<()>| {
+                            ok::<_, AmqpError>(server::Outcome::Accept)
+                        }))
+                    }),
+                )
+                .finish(),
+        )
+    });
+
+    // raw amqp client, sends transfer right after attach
+    let io = TcpStream::connect(srv.addr()).await.unwrap();
+    let mut framed = Framed::new(io, ProtocolIdCodec);
+    framed.send(ProtocolId::Amqp).await.unwrap();
+    let _ = framed.next().await.unwrap().unwrap();
+
+    let mut framed = framed.into_framed(AmqpCodec::<AmqpFrame>::new());
+    framed
+        .send(AmqpFrame::new(0, Configuration::default().to_open().into()))
+        .await
+        .unwrap();
+    let frame = framed.next().await.unwrap().unwrap();
+    assert!(matches!(frame.performative(), Frame::Open(_)));
+
+    let begin = Begin {
+        remote_channel: None,
+        next_outgoing_id: 1,
+        incoming_window: std::u32::MAX,
+        outgoing_window: std::u32::MAX,
+        handle_max: std::u32::MAX,
+        offered_capabilities: None,
+        desired_capabilities: None,
+        properties: None,
+    };
+    framed.send(AmqpFrame::new(0, begin.into())).await.unwrap();
+    let frame = framed.next().await.unwrap().unwrap();
+    assert!(matches!(frame.performative(), Frame::Begin(_)));
+
+    let attach = Attach {
+        name: ByteString::from("eager-sender"),
+        handle: 0,
+        role: Role::Sender,
+        snd_settle_mode: SenderSettleMode::Mixed,
+        rcv_settle_mode: ReceiverSettleMode::First,
+        source: None,
+        target: Some(
+            Target {
+                address: Some(ByteString::from("accept")),
+                durable: TerminusDurability::None,
+                expiry_policy: TerminusExpiryPolicy::SessionEnd,
+                timeout: 0,
+                dynamic: false,
+                dynamic_node_properties: None,
+                capabilities: None,
+            }
+            .into(),
+        ),
+        unsettled: None,
+        incomplete_unsettled: false,
+        initial_delivery_count: Some(0),
+        max_message_size: None,
+        offered_capabilities: None,
+        desired_capabilities: None,
+        properties: None,
+    };
+    let transfer = Transfer {
+        handle: 0,
+        delivery_id: Some(0),
+        delivery_tag: Some(Bytes::from_static(b"tag")),
+        message_format: None,
+        settled: Some(false),
+        more: false,
+        rcv_settle_mode: None,
+        state: None,
+        resume: false,
+        aborted: false,
+        batchable: false,
+        body: Some(TransferBody::Data(Bytes::from_static(b"data"))),
+    };
+    framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+    framed
+        .send(AmqpFrame::new(0, transfer.into()))
+        .await
+        .unwrap();
+
+    // transfer is delivered to link service once link is opened
+    let mut attached = false;
+    loop {
+        let frame = framed.next().await.unwrap().unwrap();
+        match frame.performative() {
+            Frame::Attach(_) => attached = true,
+            Frame::Flow(flow) => {
+                assert_eq!(flow.delivery_count(), Some(1));
+            }
+            Frame::Disposition(disp) => {
+                assert!(attached);
+                assert_eq!(disp.first, 0);
+                assert!(disp.settled);
+                assert!(matches!(disp.state, Some(DeliveryState::Accepted(_))));
+                break;
+            }
+            frame => panic!("Unexpected frame: {:?}", frame),
+        }
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_dropped_with_links() -> std::io::Result<()> {
+    let srv = start_server();
+    let mut session = open_session(&srv).await;
+    let mut events = session.events();
+    assert!(matches!(events.next().await, Some(SessionEvent::Begun)));
+
+    let link = session
+        .build_sender_link("test-sender", "accept")
+        .open()
+        .await
+        .unwrap();
+
+    // session is not ended while links are attached
+    let link_session = link.session().clone();
+    drop(session);
+    drop(link_session);
+    assert!(link.send(Bytes::from_static(b"test")).await.is_ok());
+
+    // last link is detached, dropped session ends
+    drop(link);
+    let res = ntex::rt::time::timeout(Duration::from_secs(1), events.next()).await;
+    assert!(matches!(res, Ok(Some(SessionEvent::LocalEnd(None)))));
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_session_events() -> std::io::Result<()> {
+    use ntex_amqp::codec::protocol::{AmqpError as ErrorCode, Error};
+
+    // raw amqp peer, confirms local end of first session, ends second session
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            assert!(matches!(frame.performative(), Frame::End(_)));
+            framed
+                .send(AmqpFrame::new(0, End { error: None }.into()))
+                .await
+                .unwrap();
+
+            let frame = framed.next().await.unwrap().unwrap();
+            assert!(matches!(frame.performative(), Frame::Begin(_)));
+            let begin = Begin {
+                remote_channel: Some(frame.channel_id()),
+                next_outgoing_id: 1,
+                incoming_window: std::u32::MAX,
+                outgoing_window: std::u32::MAX,
+                handle_max: std::u32::MAX,
+                offered_capabilities: None,
+                desired_capabilities: None,
+                properties: None,
+            };
+            framed.send(AmqpFrame::new(1, begin.into())).await.unwrap();
+
+            delay_for(Duration::from_millis(50)).await;
+            let end = End {
+                error: Some(Error {
+                    condition: ErrorCode::InternalError.into(),
+                    description: Some("session failure".into()),
+                    info: None,
+                }),
+            };
+            framed.send(AmqpFrame::new(1, end.into())).await.unwrap();
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut conn = connect_raw(&srv).await;
+    let controller = conn.controller();
+    let session = conn.open_session();
+    ntex::rt::spawn(conn.map(|_| ()));
+    let mut session = session.await.unwrap();
+
+    // dropped session is ended locally
+    let mut events = session.events();
+    assert!(matches!(events.next().await, Some(SessionEvent::Begun)));
+    drop(session);
+    assert!(matches!(
+        events.next().await,
+        Some(SessionEvent::LocalEnd(None))
+    ));
+    assert!(events.next().await.is_none());
+
+    let mut session = controller
+        .begin_session(std::u32::MAX, std::u32::MAX)
+        .await
+        .unwrap();
+    let mut events = session.events();
+    assert!(matches!(events.next().await, Some(SessionEvent::Begun)));
+    match events.next().await {
+        Some(SessionEvent::RemoteEnd(Some(err))) => {
+            assert_eq!(err.condition, ErrorCode::InternalError.into())
+        }
+        ev => panic!("Remote end is expected: {:?}", ev),
+    }
+    assert!(events.next().await.is_none());
+
+    // remote end error is reported as `Remote` error
+    let res = session.request_flow_echo(Duration::from_secs(1)).await;
+    if let Err(AmqpTransportError::Remote {
+        condition,
+        description,
+    }) = res
+    {
+        assert_eq!(condition, "amqp:internal-error");
+        assert_eq!(description.as_deref(), Some("session failure"));
+    } else {
+        panic!("Remote error is expected: {:?}", res.map(|_| ()));
+    }
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_reconnecting_connection() -> std::io::Result<()> {
+    let srv = start_server();
+    let addr = srv.addr();
+    let controllers = Rc::new(RefCell::new(Vec::<ConnectionController>::new()));
+
+    let ctrls = controllers.clone();
+    let policy =
+        ReconnectPolicy::new().backoff(Duration::from_millis(10), Duration::from_millis(50));
+    let conn = ReconnectingConnection::new(policy, move || {
+        let ctrls = ctrls.clone();
+        async move {
+            let uri = Uri::try_from(format!("amqp://{}:{}", addr.ip(), addr.port())).unwrap();
+            let sasl_srv = sasl::connect_service(Connector::default());
+            let req = sasl::SaslConnect {
+                uri,
+                config: Configuration::default(),
+                time: None,
+                auth: sasl::SaslAuth {
+                    authz_id: "".to_string(),
+                    authn_id: "user1".to_string(),
+                    password: "password1".to_string(),
+                    ..Default::default()
+                },
+            };
+            let conn = sasl_srv.call(req).await.map_err(|_| ())?;
+            ctrls.borrow_mut().push(conn.controller());
+            Ok::<_, ()>(conn)
+        }
+    });
+
+    let sender = conn.sender_link("test-sender", "accept");
+    let res = sender.send(Bytes::from_static(b"test message")).await;
+    assert!(res.unwrap().is_accepted());
+    assert!(conn.is_connected());
+    assert_eq!(controllers.borrow().len(), 1);
+
+    // link is re-attached over new connection
+    controllers.borrow_mut()[0].drop_connection();
+    while controllers.borrow().len() < 2 {
+        delay_for(Duration::from_millis(10)).await;
+    }
+    conn.connected().await.unwrap();
+    let res = sender.send(Bytes::from_static(b"test message")).await;
+    assert!(res.unwrap().is_accepted());
+    assert_eq!(controllers.borrow().len(), 2);
+    assert!(sender.link().is_some());
+
+    // in-flight send fails without retry
+    let hang = conn.sender_link("test-hang", "hang");
+    let delivery = hang.send(Bytes::from_static(b"test message"));
+    let ctrls = controllers.clone();
+    ntex::rt::spawn(async move {
+        delay_for(Duration::from_millis(100)).await;
+        ctrls.borrow_mut()[1].drop_connection();
+    });
+    assert!(matches!(
+        delivery.await,
+        Err(AmqpTransportError::Disconnected)
+    ));
+    while controllers.borrow().len() < 3 {
+        delay_for(Duration::from_millis(10)).await;
+    }
+    conn.connected().await.unwrap();
+
+    let res = sender.send(Bytes::from_static(b"test message")).await;
+    assert!(res.unwrap().is_accepted());
+    assert_eq!(controllers.borrow().len(), 3);
+
+    let _ = conn.close(Duration::from_secs(1)).await;
+    let res = sender.send(Bytes::from_static(b"test message")).await;
+    assert!(matches!(res, Err(AmqpTransportError::Disconnected)));
+
+    // reconnect attempts are exhausted
+    let attempts = Rc::new(RefCell::new(0));
+    let cnt = attempts.clone();
+    let policy = ReconnectPolicy::new()
+        .backoff(Duration::from_millis(1), Duration::from_millis(5))
+        .max_attempts(3);
+    let conn = ReconnectingConnection::new(policy, move || {
+        *cnt.borrow_mut() += 1;
+        err::<Connection<TcpStream>, _>(())
+    });
+    assert!(matches!(
+        conn.connected().await,
+        Err(AmqpTransportError::Disconnected)
+    ));
+    assert_eq!(*attempts.borrow(), 3);
+
+    Ok(())
+}
+
+#[ntex::test]
+async fn test_reconnecting_receiver() -> std::io::Result<()> {
+    let connections = Arc::new(AtomicUsize::new(0));
+    let settled = Arc::new(AtomicBool::new(false));
+    let released = Arc::new(AtomicBool::new(false));
+    let connections2 = connections.clone();
+    let settled2 = settled.clone();
+    let released2 = released.clone();
+
+    // raw amqp peer, drops first connection with unsettled delivery,
+    // redelivers settled delivery and resumes unsettled delivery
+    // over second connection
+    let srv = test_server(move || {
+        let connections = connections2.clone();
+        let settled = settled2.clone();
+        let released = released2.clone();
+        fn_service(move |io: TcpStream| {
+            let connection = connections.fetch_add(1, Ordering::Relaxed);
+            let settled = settled.clone();
+            let released = released.clone();
+            async move {
+                let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+                let frame = framed.next().await.unwrap().unwrap();
+                let attach = if let Frame::Attach(attach) = frame.performative() {
+                    let tags: Vec<_> = attach
+                        .unsettled()
+                        .map(|map| map.keys().cloned().collect())
+                        .unwrap_or_default();
+                    if connection == 0 {
+                        assert!(tags.is_empty());
+                    } else {
+                        assert_eq!(tags, vec![Variant::Binary(Bytes::from_static(b"t1"))]);
+                    }
+                    Attach {
+                        handle: 0,
+                        role: Role::Sender,
+                        initial_delivery_count: Some(0),
+                        ..attach.clone()
+                    }
+                } else {
+                    panic!("Attach is expected: {:?}", frame)
+                };
+                framed.send(AmqpFrame::new(0, attach.into())).await.unwrap();
+
+                let frame = framed.next().await.unwrap().unwrap();
+                assert!(matches!(frame.performative(), Frame::Flow(_)));
+                let transfer = |id, tag, resume| Transfer {
+                    handle: 0,
+                    delivery_id: Some(id),
+                    delivery_tag: Some(Bytes::from_static(tag)),
+                    message_format: None,
+                    settled: Some(false),
+                    more: false,
+                    rcv_settle_mode: None,
+                    state: None,
+                    resume,
+                    aborted: false,
+                    batchable: false,
+                    body: Some(TransferBody::Data(Bytes::from_static(b"data"))),
+                };
+                let transfers = if connection == 0 {
+                    vec![transfer(1, b"t1", false), transfer(2, b"t2", false)]
+                } else {
+                    // settled delivery is redelivered
+                    vec![transfer(2, b"t2", false), transfer(1, b"t1", true)]
+                };
+                for transfer in transfers {
+                    framed
+                        .send(AmqpFrame::new(0, transfer.into()))
+                        .await
+                        .unwrap();
+                }
+                if connection == 0 {
+                    // drop connection
+                    delay_for(Duration::from_millis(50)).await;
+                    return Ok::<_, ()>(());
+                }
+
+                while let Some(Ok(frame)) = framed.next().await {
+                    if let Frame::Disposition(disp) = frame.performative() {
+                        assert!(disp.settled);
+                        if disp.first == 2 {
+                            assert!(matches!(disp.state, Some(DeliveryState::Released(_))));
+                            released.store(true, Ordering::Relaxed);
+                        } else {
+                            assert_eq!(disp.first, 1);
+                            settled.store(true, Ordering::Relaxed);
+                        }
+                    }
+                }
+                Ok::<_, ()>(())
+            }
+        })
+    });
+
+    let addr = srv.addr();
+    let policy =
+        ReconnectPolicy::new().backoff(Duration::from_millis(10), Duration::from_millis(50));
+    let conn = ReconnectingConnection::new(policy, move || async move {
+        Ok::<_, ()>(connect_raw_addr(addr, Configuration::default()).await)
+    });
+
+    // delivery is not settled before connection loss
+    let receiver = conn.receiver_link("test-receiver", "test", 10);
+    receiver.set_dedup_window(10);
+    let transfer = receiver.recv().await.unwrap();
+    assert_eq!(transfer.delivery_tag, Some(Bytes::from_static(b"t1")));
+    assert!(!transfer.resume);
+    let transfer = receiver.recv().await.unwrap();
+    assert_eq!(transfer.delivery_tag, Some(Bytes::from_static(b"t2")));
+    receiver.accept(&transfer);
+
+    // link is re-attached with unsettled map, redelivered delivery
+    // is released, unsettled delivery is resumed
+    let transfer = receiver.recv().await.unwrap();
+    assert_eq!(transfer.delivery_tag, Some(Bytes::from_static(b"t1")));
+    assert!(transfer.resume);
+    assert_eq!(connections.load(Ordering::Relaxed), 2);
+
+    receiver.accept(&transfer);
+    delay_for(Duration::from_millis(50)).await;
+    assert!(settled.load(Ordering::Relaxed));
+    assert!(released.load(Ordering::Relaxed));
+
+    let _ = conn.close(Duration::from_secs(1)).await;
+    assert!(matches!(
+        receiver.recv().await,
+        Err(AmqpTransportError::Disconnected)
+    ));
+
+    Ok(())
+}
+
+#[cfg(feature = "raw-frames")]
+#[ntex::test]
+async fn test_connection_raw_frames() -> std::io::Result<()> {
+    // raw amqp peer, responds to injected session flow
+    let srv = test_server(|| {
+        fn_service(|io: TcpStream| async move {
+            let (mut framed, _) = raw_peer_begin(io, std::u32::MAX).await;
+
+            let frame = framed.next().await.unwrap().unwrap();
+            let flow = if let Frame::Flow(flow) = frame.performative() {
+                assert!(flow.echo);
+                assert!(flow.handle.is_none());
+                Flow {
+                    echo: false,
+                    next_outgoing_id: 7,
+                    ..flow.clone()
+                }
+            } else {
+                panic!("Flow is expected: {:?}", frame)
+            };
+            framed.send(AmqpFrame::new(0, flow.into())).await.unwrap();
+
+            while let Some(Ok(_)) = framed.next().await {}
+            Ok::<_, ()>(())
+        })
+    });
+
+    let mut conn = connect_raw(&srv).await;
+    let mut controller = conn.controller();
+    let mut frames = controller.subscribe_frames(0);
+    let session = conn.open_session();
+    ntex::rt::spawn(conn.map(|_| ()));
+    let _session = session.await.unwrap();
+
+    let frame = frames.next().await.unwrap();
+    assert!(matches!(frame.performative(), Frame::Begin(_)));
+
+    let flow = Flow {
+        next_incoming_id: Some(1),
+        incoming_window: std::u32::MAX,
+        next_outgoing_id: 0,
+        outgoing_window: std::u32::MAX,
+        handle: None,
+        delivery_count: None,
+        link_credit: None,
+        available: None,
+        drain: false,
+        echo: true,
+        properties: None,
+    };
+    controller.inject_frame(AmqpFrame::new(0, flow.into()));
+
+    let frame = frames.next().await.unwrap();
+    if let Frame::Flow(flow) = frame.performative() {
+        assert!(!flow.echo);
+        assert_eq!(flow.next_outgoing_id, 7);
+    } else {
+        panic!("Flow is expected: {:?}", frame)
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "rustls")]
+#[ntex::test]
+async fn test_tls_handshake_failed() -> std::io::Result<()> {
+    use ntex_amqp::tls::{RootCertStore, TlsConnector};
+
+    let srv = start_server();
+    let uri = Uri::try_from(format!("amqp://{}:{}", srv.addr().ip(), srv.addr().port())).unwrap();
+    let connector = TlsConnector::new(RootCertStore::empty()).server_name("localhost");
+    let sasl_srv = sasl::connect_service(connector);
+    let req = sasl::SaslConnect {
+        uri,
+        config: Configuration::default(),
+        time: None,
+        auth: sasl::SaslAuth {
+            mechanisms: vec![sasl::SaslMechanism::External],
+            ..Default::default()
+        },
+    };
+
+    // plaintext server does not respond with tls handshake
+    let res = ntex::rt::time::timeout(Duration::from_secs(5), sasl_srv.call(req)).await;
+    assert!(matches!(res, Ok(Err(either::Either::Right(_)))));
+
+    Ok(())
+}